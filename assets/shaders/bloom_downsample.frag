@@ -0,0 +1,65 @@
+#version 460
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout (set = 0, binding = 0) uniform sampler2D sourceImage;
+
+layout( push_constant ) uniform constants
+{
+    // Negative for every level but the first, which extracts the bright parts of the scene
+    float threshold;
+    float knee;
+    vec2 padding;
+} pushConstants;
+
+// Keeps the part of a colour brighter than the threshold, easing in over the knee below it
+vec3 Prefilter(vec3 colour)
+{
+    if (pushConstants.threshold < 0.0) {
+        return colour;
+    }
+
+    float brightness = dot(colour, vec3(0.2126, 0.7152, 0.0722));
+    float knee = pushConstants.knee;
+    float soft = clamp(brightness - pushConstants.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.00001);
+    float contribution = max(soft, brightness - pushConstants.threshold) / max(brightness, 0.00001);
+    return colour * contribution;
+}
+
+vec3 Sample(vec2 offset)
+{
+    return texture(sourceImage, inTexCoords + offset).rgb;
+}
+
+// 13 tap downsample from "Next Generation Post Processing in Call of Duty: Advanced Warfare"
+// (Jimenez 2014), halving the size without the flickering of a single bilinear tap
+void main()
+{
+    vec2 texel = 1.0 / vec2(textureSize(sourceImage, 0));
+    float x = texel.x;
+    float y = texel.y;
+
+    vec3 a = Sample(vec2(-2.0 * x, 2.0 * y));
+    vec3 b = Sample(vec2(0.0, 2.0 * y));
+    vec3 c = Sample(vec2(2.0 * x, 2.0 * y));
+    vec3 d = Sample(vec2(-2.0 * x, 0.0));
+    vec3 e = Sample(vec2(0.0, 0.0));
+    vec3 f = Sample(vec2(2.0 * x, 0.0));
+    vec3 g = Sample(vec2(-2.0 * x, -2.0 * y));
+    vec3 h = Sample(vec2(0.0, -2.0 * y));
+    vec3 i = Sample(vec2(2.0 * x, -2.0 * y));
+    vec3 j = Sample(vec2(-x, y));
+    vec3 k = Sample(vec2(x, y));
+    vec3 l = Sample(vec2(-x, -y));
+    vec3 m = Sample(vec2(x, -y));
+
+    vec3 result = e * 0.125;
+    result += (a + c + g + i) * 0.03125;
+    result += (b + d + f + h) * 0.0625;
+    result += (j + k + l + m) * 0.125;
+
+    outFragColor = vec4(Prefilter(result), 1.0);
+}
@@ -0,0 +1,31 @@
+#version 460
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+
+// This level of the downsampled chain
+layout (set = 0, binding = 0) uniform sampler2D levelImage;
+// The level below it, half the size, with every smaller level already added in
+layout (set = 0, binding = 1) uniform sampler2D smallerImage;
+
+// Upsamples the smaller level with a 3x3 tent filter and adds it to this level
+void main()
+{
+    vec2 texel = 1.0 / vec2(textureSize(smallerImage, 0));
+    float x = texel.x;
+    float y = texel.y;
+
+    vec3 upsampled = texture(smallerImage, inTexCoords).rgb * 4.0;
+    upsampled += (texture(smallerImage, inTexCoords + vec2(0.0, y)).rgb
+        + texture(smallerImage, inTexCoords + vec2(0.0, -y)).rgb
+        + texture(smallerImage, inTexCoords + vec2(x, 0.0)).rgb
+        + texture(smallerImage, inTexCoords + vec2(-x, 0.0)).rgb) * 2.0;
+    upsampled += texture(smallerImage, inTexCoords + vec2(x, y)).rgb
+        + texture(smallerImage, inTexCoords + vec2(-x, y)).rgb
+        + texture(smallerImage, inTexCoords + vec2(x, -y)).rgb
+        + texture(smallerImage, inTexCoords + vec2(-x, -y)).rgb;
+    upsampled /= 16.0;
+
+    outFragColor = vec4(texture(levelImage, inTexCoords).rgb + upsampled, 1.0);
+}
@@ -7,11 +7,22 @@ layout (location = 0) out vec4 outFragColor;
 layout (set = 0, binding = 0) uniform sampler2D forwardImage;
 layout (set = 0, binding = 1) uniform sampler2D bloomImage;
 
+// Adapted by the auto exposure pass, or 1 while it's disabled
+layout(std430, set = 1, binding = 0) readonly buffer ExposureBuffer{
+    float luminance;
+    float exposure;
+} exposureData;
+
+layout( push_constant ) uniform constants
+{
+    float bloomIntensity;
+} pushConstants;
+
 void main()
 {
     vec3 forwardColour = texture(forwardImage, inTexCoords).rgb;
-    vec3 bloomColour = texture(bloomImage, inTexCoords).rgb;
-    vec3 combineResult = forwardColour + bloomColour;
+    vec3 bloomColour = texture(bloomImage, inTexCoords).rgb * pushConstants.bloomIntensity;
+    vec3 combineResult = (forwardColour + bloomColour) * exposureData.exposure;
 
     outFragColor = vec4(combineResult,1.0f);
 }
\ No newline at end of file
@@ -0,0 +1,155 @@
+#version 460
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout (set = 0, binding = 0) uniform sampler2D forwardImage;
+layout (set = 0, binding = 1) uniform sampler2D bloomImage;
+
+// Adapted by the auto exposure pass, or 1 while it's disabled
+layout(std430, set = 1, binding = 0) readonly buffer ExposureBuffer{
+    float luminance;
+    float exposure;
+} exposureData;
+
+layout( push_constant ) uniform constants
+{
+    float edgeThreshold;
+    float edgeThresholdMin;
+    float subpixelQuality;
+    float bloomIntensity;
+} fxaa;
+
+const int SEARCH_STEPS = 8;
+
+// Same result as combine.frag, clamped to what ends up on screen
+vec3 Combine(vec2 texCoords)
+{
+    vec3 forwardColour = texture(forwardImage, texCoords).rgb;
+    vec3 bloomColour = texture(bloomImage, texCoords).rgb * fxaa.bloomIntensity;
+    return clamp((forwardColour + bloomColour) * exposureData.exposure, 0.0, 1.0);
+}
+
+float Luma(vec3 colour)
+{
+    return sqrt(dot(colour, vec3(0.299, 0.587, 0.114)));
+}
+
+float LumaAt(vec2 texCoords)
+{
+    return Luma(Combine(texCoords));
+}
+
+void main()
+{
+    vec2 texelSize = 1.0 / vec2(textureSize(forwardImage, 0));
+    vec3 colourCentre = Combine(inTexCoords);
+
+    float lumaCentre = Luma(colourCentre);
+    float lumaDown = LumaAt(inTexCoords + vec2(0.0, -texelSize.y));
+    float lumaUp = LumaAt(inTexCoords + vec2(0.0, texelSize.y));
+    float lumaLeft = LumaAt(inTexCoords + vec2(-texelSize.x, 0.0));
+    float lumaRight = LumaAt(inTexCoords + vec2(texelSize.x, 0.0));
+
+    float lumaMin = min(lumaCentre, min(min(lumaDown, lumaUp), min(lumaLeft, lumaRight)));
+    float lumaMax = max(lumaCentre, max(max(lumaDown, lumaUp), max(lumaLeft, lumaRight)));
+    float lumaRange = lumaMax - lumaMin;
+
+    // Not an edge, or too dark to notice
+    if (lumaRange < max(fxaa.edgeThresholdMin, lumaMax * fxaa.edgeThreshold)) {
+        outFragColor = vec4(colourCentre, 1.0);
+        return;
+    }
+
+    float lumaDownLeft = LumaAt(inTexCoords + vec2(-texelSize.x, -texelSize.y));
+    float lumaUpRight = LumaAt(inTexCoords + vec2(texelSize.x, texelSize.y));
+    float lumaUpLeft = LumaAt(inTexCoords + vec2(-texelSize.x, texelSize.y));
+    float lumaDownRight = LumaAt(inTexCoords + vec2(texelSize.x, -texelSize.y));
+
+    float lumaDownUp = lumaDown + lumaUp;
+    float lumaLeftRight = lumaLeft + lumaRight;
+    float lumaLeftCorners = lumaDownLeft + lumaUpLeft;
+    float lumaDownCorners = lumaDownLeft + lumaDownRight;
+    float lumaRightCorners = lumaDownRight + lumaUpRight;
+    float lumaUpCorners = lumaUpRight + lumaUpLeft;
+
+    float edgeHorizontal = abs(-2.0 * lumaLeft + lumaLeftCorners) + abs(-2.0 * lumaCentre + lumaDownUp) * 2.0 + abs(-2.0 * lumaRight + lumaRightCorners);
+    float edgeVertical = abs(-2.0 * lumaUp + lumaUpCorners) + abs(-2.0 * lumaCentre + lumaLeftRight) * 2.0 + abs(-2.0 * lumaDown + lumaDownCorners);
+    bool isHorizontal = edgeHorizontal >= edgeVertical;
+
+    // Pick the side of the pixel the edge is on
+    float luma1 = isHorizontal ? lumaDown : lumaLeft;
+    float luma2 = isHorizontal ? lumaUp : lumaRight;
+    float gradient1 = luma1 - lumaCentre;
+    float gradient2 = luma2 - lumaCentre;
+    bool is1Steepest = abs(gradient1) >= abs(gradient2);
+    float gradientScaled = 0.25 * max(abs(gradient1), abs(gradient2));
+
+    float stepLength = isHorizontal ? texelSize.y : texelSize.x;
+    float lumaLocalAverage;
+    if (is1Steepest) {
+        stepLength = -stepLength;
+        lumaLocalAverage = 0.5 * (luma1 + lumaCentre);
+    } else {
+        lumaLocalAverage = 0.5 * (luma2 + lumaCentre);
+    }
+
+    vec2 currentUv = inTexCoords;
+    if (isHorizontal) {
+        currentUv.y += stepLength * 0.5;
+    } else {
+        currentUv.x += stepLength * 0.5;
+    }
+
+    // Walk along the edge in both directions until its end is found
+    vec2 offset = isHorizontal ? vec2(texelSize.x, 0.0) : vec2(0.0, texelSize.y);
+    vec2 uv1 = currentUv - offset;
+    vec2 uv2 = currentUv + offset;
+    float lumaEnd1 = LumaAt(uv1) - lumaLocalAverage;
+    float lumaEnd2 = LumaAt(uv2) - lumaLocalAverage;
+    bool reached1 = abs(lumaEnd1) >= gradientScaled;
+    bool reached2 = abs(lumaEnd2) >= gradientScaled;
+
+    for (int i = 1; i < SEARCH_STEPS && !(reached1 && reached2); i++) {
+        float stepScale = i < 4 ? 1.0 : 2.0;
+        if (!reached1) {
+            uv1 -= offset * stepScale;
+            lumaEnd1 = LumaAt(uv1) - lumaLocalAverage;
+            reached1 = abs(lumaEnd1) >= gradientScaled;
+        }
+        if (!reached2) {
+            uv2 += offset * stepScale;
+            lumaEnd2 = LumaAt(uv2) - lumaLocalAverage;
+            reached2 = abs(lumaEnd2) >= gradientScaled;
+        }
+    }
+
+    float distance1 = isHorizontal ? (inTexCoords.x - uv1.x) : (inTexCoords.y - uv1.y);
+    float distance2 = isHorizontal ? (uv2.x - inTexCoords.x) : (uv2.y - inTexCoords.y);
+    bool isDirection1 = distance1 < distance2;
+    float distanceFinal = min(distance1, distance2);
+    float edgeThickness = distance1 + distance2;
+    float pixelOffset = -distanceFinal / edgeThickness + 0.5;
+
+    // Only blend if the luma at the edge end moves the same way as the centre
+    bool isLumaCentreSmaller = lumaCentre < lumaLocalAverage;
+    bool correctVariation = ((isDirection1 ? lumaEnd1 : lumaEnd2) < 0.0) != isLumaCentreSmaller;
+    float finalOffset = correctVariation ? pixelOffset : 0.0;
+
+    // Subpixel antialiasing for thin lines and single pixel details
+    float lumaAverage = (1.0 / 12.0) * (2.0 * (lumaDownUp + lumaLeftRight) + lumaLeftCorners + lumaRightCorners);
+    float subPixelOffset1 = clamp(abs(lumaAverage - lumaCentre) / lumaRange, 0.0, 1.0);
+    float subPixelOffset2 = (-2.0 * subPixelOffset1 + 3.0) * subPixelOffset1 * subPixelOffset1;
+    float subPixelOffsetFinal = subPixelOffset2 * subPixelOffset2 * fxaa.subpixelQuality;
+    finalOffset = max(finalOffset, subPixelOffsetFinal);
+
+    vec2 finalUv = inTexCoords;
+    if (isHorizontal) {
+        finalUv.y += finalOffset * stepLength;
+    } else {
+        finalUv.x += finalOffset * stepLength;
+    }
+
+    outFragColor = vec4(Combine(finalUv), 1.0);
+}
@@ -0,0 +1,10 @@
+#version 450
+
+layout (location = 0) in vec3 inColour;
+
+layout (location = 0) out vec4 outFragColor;
+
+void main()
+{
+    outFragColor = vec4(inColour, 1.0f);
+}
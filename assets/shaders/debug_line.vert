@@ -0,0 +1,23 @@
+#version 450
+#include "assets/shaders/library/camera.glsl"
+
+layout (location = 0) out vec3 outColour;
+
+struct DebugLineVertex{
+	vec4 position;
+	vec4 colour;
+};
+
+layout(std140,set = 2, binding = 0) readonly buffer LineVertexBuffer{
+	DebugLineVertex vertices[];
+} lineData;
+
+void main()
+{
+	DebugLineVertex vertex = lineData.vertices[gl_VertexIndex];
+	outColour = vertex.colour.rgb;
+
+	gl_Position = cameraData.proj * cameraData.view * vec4(vertex.position.xyz, 1.0f);
+	// Drawn after the TAA resolve, so undo the jitter to keep lines steady
+	gl_Position.xy -= cameraData.jitter.xy * gl_Position.w;
+}
@@ -0,0 +1,15 @@
+#version 460
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout (set = 0, binding = 0) uniform sampler2D shadowMap;
+
+void main()
+{
+    // The sun uses an orthographic projection, so depth is already linear between the near and far planes
+    float depth = texture(shadowMap, inTexCoords).r;
+
+    outFragColor = vec4(vec3(depth), 1.0f);
+}
@@ -4,6 +4,7 @@
 #include "assets/shaders/library/lighting.glsl"
 #include "assets/shaders/library/camera.glsl"
 #include "assets/shaders/library/object.glsl"
+#include "assets/shaders/library/alpha_test.glsl"
 
 //shader input
 layout (location = 0) in vec3 inColor;
@@ -13,10 +14,13 @@ layout (location = 3) in vec3 inWorldPos;
 layout (location = 4) in mat3 inTBN;
 layout (location = 7) in vec4 inShadowCoord;
 layout (location = 8) in flat int inInstanceIndex;
+layout (location = 9) in vec4 inClipPos;
+layout (location = 10) in vec4 inPrevClipPos;
 
 layout (location = 0) out vec4 gPosition;
 layout (location = 1) out vec4 gNormal;
 layout (location = 2) out vec4 gAlbedoSpec;
+layout (location = 3) out vec2 gVelocity;
 
 layout (set = 1, binding = 4) uniform sampler2DShadow sceneShadowMap;
 
@@ -26,13 +30,24 @@ void main()
     MaterialParameters material = materialData.materials[instance.material_handle];
     int diffuseTexIndex = material.textures.r;
     int normalTexIndex = material.textures.g;
+    int metallicRoughnessTexIndex = material.textures.b;
     int emissiveTexIndex = material.textures_two.r;
+    int materialFlags = material.textures_two.g;
+    int samplerIndex = material.textures_two.b;
+    float lodBias = material.uvScroll.w;
 
-    vec4 diffuseTexture = SampleBindlessTexture(0, diffuseTexIndex, inTexCoords);
-    vec3 emissiveTexture = SampleBindlessTexture(0, emissiveTexIndex, inTexCoords).rgb;
+    vec2 scrolledTexCoords = inTexCoords + material.uvScroll.xy;
+    vec4 diffuseTexture = SampleBindlessTextureBias(samplerIndex, diffuseTexIndex, scrolledTexCoords, lodBias);
+    vec3 emissiveTexture = SampleBindlessTextureBias(samplerIndex, emissiveTexIndex, scrolledTexCoords, lodBias).rgb;
+    if (AlphaTestDiscards(material, scrolledTexCoords)){
+        discard;
+    }
 
     // Ambient
-    vec3 objectColour = inColor;
+    vec3 objectColour = vec3(1.0);
+    if ((materialFlags & MATERIAL_FLAG_VERTEX_COLOR) != 0){
+        objectColour = inColor;
+    }
     if (diffuseTexIndex > 0) {
         if (diffuseTexture.a == 0){
             discard;
@@ -43,18 +58,36 @@ void main()
     }
 
     vec3 normal = normalize(inNormal);
-    if (normalTexIndex > 0){
-        vec3 normalTexture = SampleBindlessTexture(0, normalTexIndex, inTexCoords).rgb;
+    if ((materialFlags & MATERIAL_FLAG_NORMAL_MAP) != 0){
+        vec3 normalTexture = SampleBindlessTextureBias(samplerIndex, normalTexIndex, inTexCoords, lodBias).rgb;
         normal = normalize(inTBN * normalize(normalTexture * 2.0 - 1.0));
     }
+    if ((materialFlags & MATERIAL_FLAG_DOUBLE_SIDED) != 0 && !gl_FrontFacing){
+        normal = -normal;
+    }
+
+    // Matches glTF, roughness in green and metallic in blue. Untextured materials are fully rough dielectrics
+    float roughness = 1.0;
+    float metallic = 0.0;
+    if (metallicRoughnessTexIndex > 0) {
+        vec3 metallicRoughness = SampleBindlessTextureBias(samplerIndex, metallicRoughnessTexIndex, scrolledTexCoords, lodBias).rgb;
+        roughness = metallicRoughness.g;
+        metallic = metallicRoughness.b;
+    }
 
     vec3 emissive = material.emissive.rgb;
     if (emissiveTexIndex > 0) {
         emissive *= emissiveTexture.rgb * emissive;
     }
+    emissive *= material.emissive.w;
 
     gPosition = vec4(emissive, 1.0f);
-    gNormal = vec4(normal, 1.0f);
+    gNormal = vec4(normal, roughness);
     gAlbedoSpec.rgb = objectColour;
-    gAlbedoSpec.a = 1.0;
+    gAlbedoSpec.a = metallic;
+
+    // Movement in texture coordinates since last frame, without this frame's jitter
+    vec2 currentPos = inClipPos.xy / inClipPos.w - cameraData.jitter.xy;
+    vec2 previousPos = inPrevClipPos.xy / inPrevClipPos.w;
+    gVelocity = (currentPos - previousPos) * 0.5;
 }
\ No newline at end of file
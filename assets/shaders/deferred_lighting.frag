@@ -3,6 +3,9 @@
 #include "assets/shaders/library/shadow.glsl"
 #include "assets/shaders/library/lighting.glsl"
 #include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/cluster.glsl"
+#include "assets/shaders/library/area_light.glsl"
+#include "assets/shaders/library/sky.glsl"
 
 layout (location = 0) in vec2 inTexCoords;
 
@@ -15,6 +18,35 @@ layout (set = 2, binding = 0) uniform sampler2D positionImage;
 layout (set = 2, binding = 1) uniform sampler2D normalImage;
 layout (set = 2, binding = 2) uniform sampler2D albedoSpecImage;
 layout (set = 2, binding = 3) uniform sampler2D depthImage;
+layout (set = 2, binding = 4) uniform sampler2D ssaoImage;
+
+layout( push_constant ) uniform constants
+{
+    vec4 colour;
+    int type;
+    int skyboxHandle;
+    int shadowFilterRadius;
+    float shadowBias;
+    int irradianceHandle;
+    int prefilteredHandle;
+    int brdfLutHandle;
+    float iblIntensity;
+    int ltcMatrixHandle;
+    int ltcAmplitudeHandle;
+    float skyboxIntensity;
+    int padding;
+    // Inverse of the skybox rotation
+    vec4 skyboxRotation;
+    // Ground albedo in rgb, turbidity in a
+    vec4 skyParams;
+} background;
+
+const int BACKGROUND_COLOUR = 0;
+const int BACKGROUND_SKYBOX = 1;
+const int BACKGROUND_PROCEDURAL = 2;
+
+// Must match PREFILTERED_MIP_LEVELS in ibl.rs
+const float PREFILTERED_MIP_LEVELS = 5.0;
 
 const mat4 biasMat = mat4(
 0.5, 0.0, 0.0, 0.0,
@@ -22,6 +54,36 @@ const mat4 biasMat = mat4(
 0.0, 0.0, 1.0, 0.0,
 0.5, 0.5, 0.0, 1.0 );
 
+// Direction the skybox and the maps convolved from it are sampled in
+vec3 SkyboxDirection(vec3 dir)
+{
+    vec4 q = background.skyboxRotation;
+    return dir + 2.0 * cross(q.xyz, cross(q.xyz, dir) + q.w * dir);
+}
+
+vec3 BackgroundColour(vec3 viewDir)
+{
+    if (background.type == BACKGROUND_SKYBOX) {
+        return SampleBindlessSkybox(3, background.skyboxHandle, SkyboxDirection(viewDir)) * background.skyboxIntensity;
+    } else if (background.type == BACKGROUND_PROCEDURAL) {
+        return ProceduralSky(viewDir, -cameraData.directionalLightDirection.xyz, cameraData.directionalLightColour, background.skyParams.a, background.skyParams.rgb);
+    }
+    return background.colour.rgb;
+}
+
+float FogAmount(float fogDistance)
+{
+    if (cameraData.fogMode == FOG_LINEAR) {
+        return clamp((fogDistance - cameraData.fogStart) / max(cameraData.fogEnd - cameraData.fogStart, 0.0001), 0.0, 1.0);
+    } else if (cameraData.fogMode == FOG_EXP) {
+        return 1.0 - exp(-cameraData.fogDensity * fogDistance);
+    } else if (cameraData.fogMode == FOG_EXP2) {
+        float fogDepth = cameraData.fogDensity * fogDistance;
+        return 1.0 - exp(-fogDepth * fogDepth);
+    }
+    return 0.0;
+}
+
 void main()
 {
     float depth = texture(depthImage, inTexCoords).r;
@@ -30,46 +92,88 @@ void main()
     vec3 fragPos = clip.xyz / clip.www;
 
     vec3 emissive = texture(positionImage, inTexCoords).rgb;
-    vec3 normal = texture(normalImage, inTexCoords).rgb;
+    vec4 normalRoughness = texture(normalImage, inTexCoords);
+    vec3 normal = normalRoughness.rgb;
+    float roughness = normalRoughness.a;
     vec3 albedo = texture(albedoSpecImage, inTexCoords).rgb;
-    float specular = texture(albedoSpecImage, inTexCoords).a;
+    float metallic = texture(albedoSpecImage, inTexCoords).a;
 
-    vec3 ambient = cameraData.ambientLight.w * cameraData.ambientLight.rgb;
+    float ambientOcclusion = texture(ssaoImage, inTexCoords).r;
+    vec3 ambient = cameraData.ambientLight.w * cameraData.ambientLight.rgb * ambientOcclusion;
 
     // calculate shadow
     vec4 inShadowCoord = biasMat * cameraData.sunProj * cameraData.sunView * vec4(fragPos, 1.0f);
-    float shadow = ShadowCalculation(sceneShadowMap, inShadowCoord / inShadowCoord.w);
+    float shadow = ShadowCalculation(sceneShadowMap, inShadowCoord / inShadowCoord.w, background.shadowFilterRadius, background.shadowBias);
 
     // ----------------- Lighting Calculations -----------------------
     // Directional Light
-    vec3 dirLight = CalculateDirectionalLight(normal, fragPos,cameraData.cameraPos.xyz, -cameraData.directionalLightDirection.xyz,cameraData.directionalLightColour,cameraData.directionalLightStrength);
+    vec3 dirLight = CalculateDirectionalLight(normal, fragPos,GetViewPosition(fragPos), -cameraData.directionalLightDirection.xyz,cameraData.directionalLightColour,cameraData.directionalLightStrength);
     vec3 lighting = (1.0 - shadow) * (dirLight);
 
-    // Point lights
+    // Point lights, only the ones reaching this pixel's cluster
     vec3 pointLightsResult = vec3(0);
-    for (int i = 0; i < cameraData.pointLightCount; i++){
+    uint clusterIndex = ClusterIndexAt(fragPos);
+    uint clusterLightCount = clusterData.lightCounts[clusterIndex];
+    for (uint i = 0; i < clusterLightCount; i++){
         // Diffuse
-        Light currentLight = lightData.lights[i];
-        pointLightsResult += CalculatePointLight(normal, fragPos,cameraData.cameraPos.xyz, currentLight);
+        Light currentLight = lightData.lights[clusterData.lightIndices[clusterIndex * MAX_LIGHTS_PER_CLUSTER + i]];
+        float pointShadow = PointShadowCalculation(currentLight.shadowIndex, fragPos, currentLight.position, cameraData.pointShadowFarPlane);
+        pointLightsResult += (1.0 - pointShadow) * CalculateLight(normal, fragPos,GetViewPosition(fragPos), currentLight);
     }
     lighting += pointLightsResult;
     vec3 result = albedo * (ambient + lighting);
+
+    // Area lights, which aren't culled so are all shaded here
+    if (cameraData.areaLightCount > 0) {
+        vec3 viewPos = GetViewPosition(fragPos);
+        vec2 ltcUv = LtcUv(roughness, dot(normal, normalize(viewPos - fragPos)));
+        vec4 ltcMatrix = SampleBindlessTexture(3, background.ltcMatrixHandle, ltcUv);
+        vec4 ltcAmplitude = SampleBindlessTexture(3, background.ltcAmplitudeHandle, ltcUv);
+        for (int i = 0; i < cameraData.areaLightCount; i++) {
+            result += CalculateAreaLight(normal, fragPos, viewPos, albedo, metallic, ltcMatrix, ltcAmplitude, areaLightData.areaLights[i]);
+        }
+    }
+
+    // Image based lighting, ambient diffuse and specular light from the skybox
+    if (background.irradianceHandle > 0) {
+        vec3 viewDir = normalize(GetViewPosition(fragPos) - fragPos);
+        float NdotV = max(dot(normal, viewDir), 0.0001);
+        vec3 F0 = mix(vec3(0.04), albedo, metallic);
+        vec3 fresnel = F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(1.0 - NdotV, 5.0);
+
+        vec3 irradiance = SampleBindlessSkybox(3, background.irradianceHandle, SkyboxDirection(normal));
+        vec3 diffuseIbl = (1.0 - fresnel) * (1.0 - metallic) * irradiance * albedo;
+
+        float lod = roughness * (PREFILTERED_MIP_LEVELS - 1.0);
+        vec3 prefiltered = SampleBindlessSkyboxLod(3, background.prefilteredHandle, SkyboxDirection(reflect(-viewDir, normal)), lod);
+        vec2 brdf = SampleBindlessTexture(3, background.brdfLutHandle, vec2(NdotV, roughness)).rg;
+        vec3 specularIbl = prefiltered * (fresnel * brdf.x + brdf.y);
+
+        result += (diffuseIbl + specularIbl) * ambientOcclusion * background.iblIntensity * background.skyboxIntensity;
+    }
     // ----------------- Lighting Calculations -----------------------
 
     result += emissive;
 
+    vec3 viewDir = fragPos - GetViewPosition(fragPos);
+    // Nothing was drawn here, so fill in the background
     if (depth == 1){
-        result = albedo;
+        result = BackgroundColour(viewDir);
+    } else if (cameraData.fogMode != FOG_NONE) {
+        // Measured from the near plane, which also works for orthographic cameras
+        vec4 nearClip = cameraData.invProjView * vec4(ndc.xy, 0.0, 1.0);
+        vec4 farClip = cameraData.invProjView * vec4(ndc.xy, 1.0, 1.0);
+        vec3 nearPos = nearClip.xyz / nearClip.w;
+        float fogDistance = length(fragPos - nearPos);
+        float farDistance = length(farClip.xyz / farClip.w - nearPos);
+
+        // Fades into the background towards the far plane, so the horizon has no visible edge
+        vec3 fogColour = mix(cameraData.fogColour.rgb, BackgroundColour(viewDir), smoothstep(0.5, 1.0, fogDistance / farDistance));
+        result = mix(result, fogColour, FogAmount(fogDistance));
     }
 
     outFragColor = vec4(result,1.0f);
 
-    // Bright Colours
-    float brightness = dot(outFragColor.rgb, vec3(0.2126, 0.7152, 0.0722));
-    if(brightness > 1.0) {
-        outBrightColor = vec4(outFragColor.rgb, 1.0);
-    }
-    else {
-        outBrightColor = vec4(0.0, 0.0, 0.0, 1.0);
-    }
+    // Thresholded by the first bloom pass
+    outBrightColor = vec4(outFragColor.rgb, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+#version 460
+#include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/dof.glsl"
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout (set = 2, binding = 0) uniform sampler2D depthImage;
+layout (set = 2, binding = 1) uniform sampler2D farImage;
+layout (set = 2, binding = 2) uniform sampler2D nearImage;
+
+void main()
+{
+    float coc = CircleOfConfusion(inTexCoords, texture(depthImage, inTexCoords).r);
+    vec4 far = texture(farImage, inTexCoords);
+    vec4 near = texture(nearImage, inTexCoords);
+
+    // The far layer only replaces pixels that are themselves behind the focus distance, so it never
+    // covers sharp foreground edges
+    float farAmount = far.a > 0.0001 ? max(coc, 0.0) : 0.0;
+    vec3 farColour = far.rgb / max(far.a, 0.0001);
+
+    // The near layer spreads past the edges of foreground objects, blurring their silhouettes over
+    // whatever is behind them
+    float nearAmount = clamp(max(-coc, near.a * 2.0), 0.0, 1.0);
+    vec3 nearColour = near.rgb / max(near.a, 0.0001);
+    if (near.a < 0.0001) {
+        nearAmount = 0.0;
+    }
+
+    // Far layer over the sharp scene, then the near layer over both, as a single blend
+    float alpha = 1.0 - (1.0 - farAmount) * (1.0 - nearAmount);
+    if (alpha < 0.0001) {
+        discard;
+    }
+    vec3 colour = (farColour * farAmount * (1.0 - nearAmount) + nearColour * nearAmount) / alpha;
+    outFragColor = vec4(colour, alpha);
+}
@@ -0,0 +1,24 @@
+#version 460
+#include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/dof.glsl"
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFar;
+layout (location = 1) out vec4 outNear;
+
+layout (set = 2, binding = 0) uniform sampler2D forwardImage;
+layout (set = 2, binding = 1) uniform sampler2D depthImage;
+
+void main()
+{
+    vec3 colour = texture(forwardImage, inTexCoords).rgb;
+    float coc = CircleOfConfusion(inTexCoords, texture(depthImage, inTexCoords).r);
+
+    // Premultiplied by how out of focus each pixel is, so in focus pixels add nothing to either
+    // layer and can't be smeared into blurred areas
+    float farWeight = max(coc, 0.0);
+    float nearWeight = max(-coc, 0.0);
+    outFar = vec4(colour * farWeight, farWeight);
+    outNear = vec4(colour * nearWeight, nearWeight);
+}
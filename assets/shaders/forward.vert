@@ -17,6 +17,8 @@ layout (location = 3) out vec3 outWorldPos;
 layout (location = 4) out mat3 outTBN;
 layout (location = 7) out vec4 outShadowCoord;
 layout (location = 8) out int outInstanceIndex;
+layout (location = 9) out vec4 outClipPos;
+layout (location = 10) out vec4 outPrevClipPos;
 
 const mat4 biasMat = mat4(
 0.5, 0.0, 0.0, 0.0,
@@ -26,8 +28,9 @@ const mat4 biasMat = mat4(
 
 void main()
 {
-	InstanceParameters instance = instanceData.instance[gl_InstanceIndex];
-	outInstanceIndex = gl_InstanceIndex;
+	int instanceIndex = int(visibleInstanceData.instances[gl_InstanceIndex]);
+	InstanceParameters instance = instanceData.instance[instanceIndex];
+	outInstanceIndex = instanceIndex;
 	mat4 modelMatrix = modelData.models[instance.transform_handle].model;
 	mat3 normalMatrix = mat3(modelData.models[instance.transform_handle].normal);
 	vec3 worldPos = vec3(modelMatrix * vec4(vPosition, 1.0f));
@@ -46,4 +49,6 @@ void main()
 	outTBN = mat3(T, B, N);
 
 	gl_Position = cameraData.proj * cameraData.view * modelMatrix * vec4(vPosition, 1.0f);
+	outClipPos = gl_Position;
+	outPrevClipPos = cameraData.prevProjView * modelData.models[instance.transform_handle].prevModel * vec4(vPosition, 1.0f);
 }
\ No newline at end of file
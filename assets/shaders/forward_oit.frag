@@ -0,0 +1,26 @@
+#version 460
+#include "assets/shaders/library/texture.glsl"
+#include "assets/shaders/library/shadow.glsl"
+#include "assets/shaders/library/lighting.glsl"
+#include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/cluster.glsl"
+#include "assets/shaders/library/object.glsl"
+#include "assets/shaders/library/alpha_test.glsl"
+#include "assets/shaders/library/forward_shading.glsl"
+
+// Weighted blended order independent transparency (McGuire and Bavoil 2013). Summed with additive
+// blending, then divided through by the total weight in oit_composite.frag
+layout (location = 0) out vec4 outAccumulation;
+// 1 - product of (1 - alpha), so it can be cleared to zero along with the accumulation
+layout (location = 1) out vec4 outCoverage;
+
+void main()
+{
+	vec4 colour = ShadeForward();
+
+	// Closer and more opaque surfaces count for more, the depth falloff keeps the weight in range
+	// of the 16 bit float target
+	float weight = clamp(pow(min(1.0, colour.a * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 1e-2, 3e3);
+	outAccumulation = vec4(colour.rgb * colour.a, colour.a) * weight;
+	outCoverage = vec4(colour.a);
+}
@@ -0,0 +1,23 @@
+// Needs texture.glsl and object.glsl to be included first
+
+// Lower mips average each texel's alpha with the transparent texels around it, so cutouts would
+// thin out and disappear with distance. Raising alpha with the mip level keeps coverage roughly
+// the same at every level.
+const float ALPHA_TEST_MIP_SCALE = 0.25;
+
+// True when an alpha tested material is transparent at these coordinates
+bool AlphaTestDiscards(MaterialParameters material, vec2 texCoords)
+{
+    float cutoff = material.uvScroll.z;
+    int diffuseTexIndex = material.textures.r;
+    if (cutoff < 0.0 || diffuseTexIndex <= 0) {
+        return false;
+    }
+
+    int samplerIndex = material.textures_two.b;
+    float lodBias = material.uvScroll.w;
+    float lod = textureQueryLod(sampler2D(bindlessTextures[nonuniformEXT(diffuseTexIndex - 1)], samplers[nonuniformEXT(samplerIndex)]), texCoords).x + lodBias;
+    float alpha = SampleBindlessTextureBias(samplerIndex, diffuseTexIndex, texCoords, lodBias).a * material.diffuse.a;
+    alpha *= 1.0 + max(lod, 0.0) * ALPHA_TEST_MIP_SCALE;
+    return alpha < cutoff;
+}
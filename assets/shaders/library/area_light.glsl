@@ -0,0 +1,167 @@
+// Rectangular area lights, shaded with linearly transformed cosines
+// (Heitz et al. 2016, "Real-Time Polygonal-Light Shading with Linearly Transformed Cosines")
+
+struct AreaLight{
+    // Wound clockwise as seen from the lit side
+    vec4 corners[4];
+    vec3 colour;
+    float intensity;
+};
+
+// Sized by RendererSettings::max_area_lights
+layout(std140,set = 1, binding = 10) readonly buffer AreaLightBuffer{
+    AreaLight areaLights[];
+} areaLightData;
+
+// Must match the size the LTC tables were fitted at
+const float LTC_LUT_SIZE = 64.0;
+const float LTC_TWO_PI = 6.28318530718;
+
+// Coordinates of the fitted LTC tables for a surface's roughness and view angle
+vec2 LtcUv(float roughness, float NdotV)
+{
+    vec2 uv = vec2(roughness, sqrt(1.0 - clamp(NdotV, 0.0, 1.0)));
+    return uv * (LTC_LUT_SIZE - 1.0) / LTC_LUT_SIZE + 0.5 / LTC_LUT_SIZE;
+}
+
+// Inverse LTC transform from the matrix table's four non-trivial entries
+mat3 LtcMatrix(vec4 entries)
+{
+    return mat3(
+        vec3(entries.x, 0.0, entries.y),
+        vec3(0.0, 1.0, 0.0),
+        vec3(entries.z, 0.0, entries.w)
+    );
+}
+
+float IntegrateEdge(vec3 v1, vec3 v2)
+{
+    float cosTheta = clamp(dot(v1, v2), -0.9999, 0.9999);
+    float theta = acos(cosTheta);
+    return cross(v1, v2).z * ((theta > 0.001) ? theta / sin(theta) : 1.0);
+}
+
+// Clips the quad to the upper hemisphere, leaving between 3 and 5 vertices, or none when the
+// quad is entirely below the horizon
+void ClipQuadToHorizon(inout vec3 L[5], out int n)
+{
+    int config = 0;
+    if (L[0].z > 0.0) config += 1;
+    if (L[1].z > 0.0) config += 2;
+    if (L[2].z > 0.0) config += 4;
+    if (L[3].z > 0.0) config += 8;
+
+    n = 0;
+    if (config == 1) {
+        n = 3;
+        L[1] = -L[1].z * L[0] + L[0].z * L[1];
+        L[2] = -L[3].z * L[0] + L[0].z * L[3];
+    } else if (config == 2) {
+        n = 3;
+        L[0] = -L[0].z * L[1] + L[1].z * L[0];
+        L[2] = -L[2].z * L[1] + L[1].z * L[2];
+    } else if (config == 3) {
+        n = 4;
+        L[2] = -L[2].z * L[1] + L[1].z * L[2];
+        L[3] = -L[3].z * L[0] + L[0].z * L[3];
+    } else if (config == 4) {
+        n = 3;
+        L[0] = -L[3].z * L[2] + L[2].z * L[3];
+        L[1] = -L[1].z * L[2] + L[2].z * L[1];
+    } else if (config == 6) {
+        n = 4;
+        L[0] = -L[0].z * L[1] + L[1].z * L[0];
+        L[3] = -L[3].z * L[2] + L[2].z * L[3];
+    } else if (config == 7) {
+        n = 5;
+        L[4] = -L[3].z * L[0] + L[0].z * L[3];
+        L[3] = -L[3].z * L[2] + L[2].z * L[3];
+    } else if (config == 8) {
+        n = 3;
+        L[0] = -L[0].z * L[3] + L[3].z * L[0];
+        L[1] = -L[2].z * L[3] + L[3].z * L[2];
+        L[2] = L[3];
+    } else if (config == 9) {
+        n = 4;
+        L[1] = -L[1].z * L[0] + L[0].z * L[1];
+        L[2] = -L[2].z * L[3] + L[3].z * L[2];
+    } else if (config == 11) {
+        n = 5;
+        L[4] = L[3];
+        L[3] = -L[2].z * L[3] + L[3].z * L[2];
+        L[2] = -L[2].z * L[1] + L[1].z * L[2];
+    } else if (config == 12) {
+        n = 4;
+        L[1] = -L[1].z * L[2] + L[2].z * L[1];
+        L[0] = -L[0].z * L[3] + L[3].z * L[0];
+    } else if (config == 13) {
+        n = 5;
+        L[4] = L[3];
+        L[3] = L[2];
+        L[2] = -L[1].z * L[2] + L[2].z * L[1];
+        L[1] = -L[1].z * L[0] + L[0].z * L[1];
+    } else if (config == 14) {
+        n = 5;
+        L[4] = -L[0].z * L[3] + L[3].z * L[0];
+        L[0] = -L[0].z * L[1] + L[1].z * L[0];
+    } else if (config == 15) {
+        n = 4;
+    }
+
+    // Close the polygon
+    if (n == 3) L[3] = L[0];
+    if (n == 4) L[4] = L[0];
+}
+
+// Integral of the cosine lobe transformed by minv over the light's rectangle, as seen from worldPos
+float LtcEvaluate(vec3 normal, vec3 viewDir, vec3 worldPos, mat3 minv, AreaLight light)
+{
+    // Orthonormal frame around the normal, with the view direction in the xz plane. Any tangent
+    // works when looking straight down the normal.
+    vec3 tangent = viewDir - normal * dot(viewDir, normal);
+    if (dot(tangent, tangent) > 0.000001) {
+        tangent = normalize(tangent);
+    } else {
+        tangent = normalize(cross(normal, abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0)));
+    }
+    vec3 bitangent = cross(normal, tangent);
+    minv = minv * transpose(mat3(tangent, bitangent, normal));
+
+    vec3 L[5];
+    for (int i = 0; i < 4; i++) {
+        L[i] = minv * (light.corners[i].xyz - worldPos);
+    }
+    L[4] = L[3];
+
+    int n;
+    ClipQuadToHorizon(L, n);
+    if (n == 0) {
+        return 0.0;
+    }
+
+    for (int i = 0; i < 5; i++) {
+        L[i] = normalize(L[i]);
+    }
+
+    float sum = IntegrateEdge(L[0], L[1]) + IntegrateEdge(L[1], L[2]) + IntegrateEdge(L[2], L[3]);
+    if (n >= 4) sum += IntegrateEdge(L[3], L[4]);
+    if (n == 5) sum += IntegrateEdge(L[4], L[0]);
+
+    // Negative when seen from behind the light
+    return max(sum, 0.0) / LTC_TWO_PI;
+}
+
+// Light reflected towards the viewer from one area light. ltcMatrix and ltcAmplitude are the
+// LTC tables sampled at LtcUv.
+vec3 CalculateAreaLight(vec3 normal, vec3 worldPos, vec3 cameraPos, vec3 albedo, float metallic, vec4 ltcMatrix, vec4 ltcAmplitude, AreaLight light)
+{
+    vec3 viewDir = normalize(cameraPos - worldPos);
+    vec3 F0 = mix(vec3(0.04), albedo, metallic);
+
+    float diffuse = LtcEvaluate(normal, viewDir, worldPos, mat3(1.0), light);
+    float specular = LtcEvaluate(normal, viewDir, worldPos, LtcMatrix(ltcMatrix), light);
+    vec3 specularColour = F0 * ltcAmplitude.x + (1.0 - F0) * ltcAmplitude.y;
+
+    vec3 radiance = light.colour * light.intensity;
+    return radiance * (diffuse * albedo * (1.0 - metallic) + specular * specularColour);
+}
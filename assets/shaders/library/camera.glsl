@@ -9,6 +9,39 @@ layout(std140,set = 1, binding = 0) uniform  CameraBuffer{
     vec4 directionalLightDirection;
     mat4 sunProj;
     mat4 sunView;
+    vec4 frustumPlanes[6];
     int pointLightCount;
-    int padding[3];
+    float time;
+    int orthographic;
+    int areaLightCount;
+    vec4 fogColour;
+    float fogDensity;
+    float fogStart;
+    float fogEnd;
+    int fogMode;
+    mat4 prevProjView;
+    vec4 jitter;
+    // Projection the light clusters are laid out in, before viewport fitting and jitter
+    mat4 clusterProj;
+    // View space distances to the near and far planes in xy
+    vec4 clusterDepth;
+    // Distance the point light cube shadow maps are rendered out to
+    float pointShadowFarPlane;
 } cameraData;
+
+// Must match fog_mode in CameraUniform::update_fog
+const int FOG_NONE = 0;
+const int FOG_LINEAR = 1;
+const int FOG_EXP = 2;
+const int FOG_EXP2 = 3;
+
+// Position a surface is viewed from. Orthographic cameras view every surface along the same direction.
+vec3 GetViewPosition(vec3 worldPos) {
+    if (cameraData.orthographic == 0) {
+        return cameraData.cameraPos.xyz;
+    }
+    vec4 nearPoint = cameraData.invProjView * vec4(0.0, 0.0, -1.0, 1.0);
+    vec4 farPoint = cameraData.invProjView * vec4(0.0, 0.0, 1.0, 1.0);
+    vec3 forward = normalize(farPoint.xyz / farPoint.w - nearPoint.xyz / nearPoint.w);
+    return worldPos - forward;
+}
@@ -0,0 +1,53 @@
+// Needs camera.glsl to be included first
+
+// Must match CLUSTER_GRID and MAX_LIGHTS_PER_CLUSTER in renderer.rs
+const uvec3 CLUSTER_GRID = uvec3(16, 9, 24);
+const uint CLUSTER_COUNT = CLUSTER_GRID.x * CLUSTER_GRID.y * CLUSTER_GRID.z;
+const uint MAX_LIGHTS_PER_CLUSTER = 64;
+
+// Filled in by light_cull.comp. Each cluster's lights are stored in its own run of
+// MAX_LIGHTS_PER_CLUSTER indices.
+layout(std430,set = 1, binding = 9) buffer LightClusterBuffer{
+    uint lightCounts[CLUSTER_COUNT];
+    uint lightIndices[];
+} clusterData;
+
+// View space distance in front of the camera where a depth slice starts. Slices are spread
+// exponentially for perspective cameras, so near slices are as thin on screen as far ones.
+float ClusterSliceDepth(uint slice)
+{
+    float near = cameraData.clusterDepth.x;
+    float far = cameraData.clusterDepth.y;
+    float t = float(slice) / float(CLUSTER_GRID.z);
+    if (cameraData.orthographic == 0) {
+        return near * pow(far / near, t);
+    }
+    return mix(near, far, t);
+}
+
+uint ClusterIndex(uvec3 cluster)
+{
+    return cluster.x + CLUSTER_GRID.x * (cluster.y + CLUSTER_GRID.y * cluster.z);
+}
+
+// Cluster containing a world space position
+uint ClusterIndexAt(vec3 worldPos)
+{
+    vec4 viewPos = cameraData.view * vec4(worldPos, 1.0);
+    vec4 clip = cameraData.clusterProj * viewPos;
+    vec2 uv = clip.xy / clip.w * 0.5 + 0.5;
+    uvec2 tile = uvec2(clamp(uv * vec2(CLUSTER_GRID.xy), vec2(0.0), vec2(CLUSTER_GRID.xy) - 1.0));
+
+    float near = cameraData.clusterDepth.x;
+    float far = cameraData.clusterDepth.y;
+    float depth = -viewPos.z;
+    float slice;
+    if (cameraData.orthographic == 0) {
+        slice = log(max(depth, near) / near) / log(far / near);
+    } else {
+        slice = (depth - near) / (far - near);
+    }
+    uint z = uint(clamp(slice * float(CLUSTER_GRID.z), 0.0, float(CLUSTER_GRID.z) - 1.0));
+
+    return ClusterIndex(uvec3(tile, z));
+}
@@ -0,0 +1,24 @@
+layout( push_constant ) uniform constants
+{
+    float focusDistance;
+    float focusRange;
+} dof;
+
+vec3 WorldPosition(vec2 texCoords, float depth)
+{
+    vec4 ndc = vec4(texCoords * 2.0 - 1.0, depth, 1.0);
+    vec4 clip = cameraData.invProjView * ndc;
+    return clip.xyz / clip.w;
+}
+
+// Signed circle of confusion, from -1 (fully blurred, in front of the focus distance) to 1 (fully
+// blurred, behind it). The background counts as infinitely far away.
+float CircleOfConfusion(vec2 texCoords, float depth)
+{
+    if (depth >= 1.0) {
+        return 1.0;
+    }
+    vec3 worldPos = WorldPosition(texCoords, depth);
+    float distanceToCamera = distance(worldPos, GetViewPosition(worldPos));
+    return clamp((distanceToCamera - dof.focusDistance) / dof.focusRange, -1.0, 1.0);
+}
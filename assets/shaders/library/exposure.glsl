@@ -0,0 +1,23 @@
+// Shared by the auto exposure compute shaders. Must match HISTOGRAM_BINS and SAMPLE_GRID in exposure.rs
+const uint HISTOGRAM_BINS = 256;
+const uint SAMPLE_GRID = 256;
+
+layout (set = 0, binding = 0) uniform sampler2D sceneImage;
+
+// Bin 0 counts samples too dark to measure, the rest split the log luminance range evenly
+layout(std430, set = 0, binding = 1) buffer HistogramBuffer{
+    uint bins[HISTOGRAM_BINS];
+} histogram;
+
+layout(std430, set = 0, binding = 2) buffer ExposureBuffer{
+    float luminance;
+    float exposure;
+} exposureData;
+
+layout( push_constant ) uniform constants
+{
+    float minLogLuminance;
+    float logLuminanceRange;
+    float adaptation;
+    float padding;
+} pushConstants;
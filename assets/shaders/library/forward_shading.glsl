@@ -0,0 +1,92 @@
+// Lighting for transparent surfaces drawn in a forward pass. Needs texture.glsl, shadow.glsl,
+// lighting.glsl, camera.glsl, cluster.glsl, object.glsl and alpha_test.glsl to be included first
+
+//shader input
+layout (location = 0) in vec3 inColor;
+layout (location = 1) in vec2 inTexCoords;
+layout (location = 2) in vec3 inNormal;
+layout (location = 3) in vec3 inWorldPos;
+layout (location = 4) in mat3 inTBN;
+layout (location = 7) in vec4 inShadowCoord;
+layout (location = 8) in flat int inInstanceIndex;
+
+layout (set = 1, binding = 4) uniform sampler2DShadow sceneShadowMap;
+
+// Lit colour in rgb, coverage in a
+vec4 ShadeForward()
+{
+	InstanceParameters instance = instanceData.instance[inInstanceIndex];
+	MaterialParameters material = materialData.materials[instance.material_handle];
+	int diffuseTexIndex = material.textures.r;
+	int normalTexIndex = material.textures.g;
+	int emissiveTexIndex = material.textures_two.r;
+	int materialFlags = material.textures_two.g;
+	int samplerIndex = material.textures_two.b;
+	float lodBias = material.uvScroll.w;
+
+	vec2 scrolledTexCoords = inTexCoords + material.uvScroll.xy;
+	vec4 diffuseTexture = SampleBindlessTextureBias(samplerIndex, diffuseTexIndex, scrolledTexCoords, lodBias);
+	vec3 emissiveTexture = SampleBindlessTextureBias(samplerIndex, emissiveTexIndex, scrolledTexCoords, lodBias).rgb;
+	if (AlphaTestDiscards(material, scrolledTexCoords)){
+		discard;
+	}
+
+	// Ambient
+	vec3 objectColour = vec3(1.0);
+	if ((materialFlags & MATERIAL_FLAG_VERTEX_COLOR) != 0){
+		objectColour = inColor;
+	}
+	if (diffuseTexIndex > 0) {
+		if (diffuseTexture.a == 0){
+			discard;
+		}
+		objectColour *= diffuseTexture.rgb * material.diffuse.rgb;
+	} else {
+		objectColour *= material.diffuse.rgb;
+	}
+	vec3 ambient = cameraData.ambientLight.w * cameraData.ambientLight.rgb;
+
+	vec3 normal = normalize(inNormal);
+	if ((materialFlags & MATERIAL_FLAG_NORMAL_MAP) != 0){
+		vec3 normalTexture = SampleBindlessTextureBias(samplerIndex, normalTexIndex, inTexCoords, lodBias).rgb;
+		normal = normalize(inTBN * normalize(normalTexture * 2.0 - 1.0));
+	}
+	if ((materialFlags & MATERIAL_FLAG_DOUBLE_SIDED) != 0 && !gl_FrontFacing){
+		normal = -normal;
+	}
+
+	// calculate shadow
+	float shadow = ShadowCalculation(sceneShadowMap, inShadowCoord / inShadowCoord.w, 1, 0.001);
+
+	// ----------------- Lighting Calculations -----------------------
+	// Directional Light
+	vec3 dirLight = CalculateDirectionalLight(normal, inWorldPos,GetViewPosition(inWorldPos), -cameraData.directionalLightDirection.xyz,cameraData.directionalLightColour,cameraData.directionalLightStrength);
+	vec3 lighting = (1.0 - shadow) * (dirLight);
+
+	// Point lights, only the ones reaching this fragment's cluster
+	vec3 pointLightsResult = vec3(0);
+	uint clusterIndex = ClusterIndexAt(inWorldPos);
+	uint clusterLightCount = clusterData.lightCounts[clusterIndex];
+	for (uint i = 0; i < clusterLightCount; i++){
+		// Diffuse
+		Light currentLight = lightData.lights[clusterData.lightIndices[clusterIndex * MAX_LIGHTS_PER_CLUSTER + i]];
+		float pointShadow = PointShadowCalculation(currentLight.shadowIndex, inWorldPos, currentLight.position, cameraData.pointShadowFarPlane);
+		pointLightsResult += (1.0 - pointShadow) * CalculateLight(normal, inWorldPos,GetViewPosition(inWorldPos), currentLight);
+	}
+	lighting += pointLightsResult;
+	vec3 result = objectColour * (ambient + lighting);
+	// ----------------- Lighting Calculations -----------------------
+
+	// Emissive
+	if (emissiveTexIndex > 0){
+		result += emissiveTexture * material.emissive.rgb * material.emissive.w;
+	} else {
+		result += material.emissive.rgb * material.emissive.w;
+	}
+
+	float alpha = material.diffuse.a;
+	if (diffuseTexIndex > 0) {
+		alpha *= diffuseTexture.a;
+	}
+	return vec4(result, alpha);
+}
@@ -0,0 +1,46 @@
+// Shared by the GPU particle simulation and drawing
+
+struct GpuParticle{
+	// w is the remaining life
+	vec4 position;
+	// w is the life the particle spawned with
+	vec4 velocity;
+};
+
+struct DrawCommand{
+	uint indexCount;
+	uint instanceCount;
+	uint firstIndex;
+	int vertexOffset;
+	uint firstInstance;
+};
+
+layout(std430,set = 2, binding = 0) readonly buffer ParticleParamBuffer{
+	mat4 emitter;
+	vec4 velocityMin;
+	vec4 velocityMax;
+	// w is the time step
+	vec4 gravity;
+	vec4 startColour;
+	vec4 endColour;
+	float startSize;
+	float endSize;
+	float lifetime;
+	int textureIndex;
+	uint spawnStart;
+	uint spawnCount;
+	uint capacity;
+	uint seed;
+} params;
+
+layout(std430,set = 2, binding = 1) buffer GpuParticleBuffer{
+	GpuParticle particles[];
+} particleData;
+
+layout(std430,set = 2, binding = 2) buffer AliveBuffer{
+	uint indices[];
+} aliveData;
+
+layout(std430,set = 2, binding = 3) buffer ParticleDrawBuffer{
+	DrawCommand draw;
+} drawData;
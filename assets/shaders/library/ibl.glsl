@@ -0,0 +1,51 @@
+const float PI = 3.14159265359;
+
+// Direction through the centre of a cubemap texel, following the Vulkan face order (+X, -X, +Y, -Y, +Z, -Z)
+vec3 CubeDirection(ivec3 texel, int size)
+{
+    vec2 uv = (vec2(texel.xy) + 0.5) / float(size) * 2.0 - 1.0;
+    vec3 dir;
+    switch (texel.z) {
+        case 0: dir = vec3(1.0, -uv.y, -uv.x); break;
+        case 1: dir = vec3(-1.0, -uv.y, uv.x); break;
+        case 2: dir = vec3(uv.x, 1.0, uv.y); break;
+        case 3: dir = vec3(uv.x, -1.0, -uv.y); break;
+        case 4: dir = vec3(uv.x, -uv.y, 1.0); break;
+        default: dir = vec3(-uv.x, -uv.y, -1.0); break;
+    }
+    return normalize(dir);
+}
+
+vec2 Hammersley(uint i, uint count)
+{
+    uint bits = i;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return vec2(float(i) / float(count), float(bits) * 2.3283064365386963e-10);
+}
+
+// Samples a half vector around the normal, distributed by the GGX lobe for the given roughness
+vec3 ImportanceSampleGGX(vec2 xi, vec3 normal, float roughness)
+{
+    float a = roughness * roughness;
+    float phi = 2.0 * PI * xi.x;
+    float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+    vec3 halfVector = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+
+    vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(up, normal));
+    vec3 bitangent = cross(normal, tangent);
+    return normalize(tangent * halfVector.x + bitangent * halfVector.y + normal * halfVector.z);
+}
+
+float DistributionGGX(float NdotH, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denom = NdotH * NdotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
@@ -1,11 +1,22 @@
 struct Light{
-    vec4 position;
+    vec3 position;
+    int shadowIndex;
     vec3 colour;
     float intensity;
+    vec3 direction;
+    float range;
+    float innerCone;
+    float outerCone;
+    int lightType;
+    int padding;
 };
 
-layout(std140,set = 1, binding = 1) uniform LightBuffer{
-    Light lights[4];
+#define LIGHT_TYPE_POINT 0
+#define LIGHT_TYPE_SPOT 1
+
+// Sized by RendererSettings::max_lights
+layout(std140,set = 1, binding = 1) readonly buffer LightBuffer{
+    Light lights[];
 } lightData;
 
 vec3 CalculateDirectionalLight(vec3 normal, vec3 worldPos, vec3 cameraPos, vec3 lightDir, vec3 lightColour, float lightStrength) {
@@ -36,15 +47,32 @@ vec3 CalculatePointLight(vec3 normal, vec3 worldPos, vec3 cameraPos, Light light
     float spec = pow(max(dot(normal, halfwayDir), 0.0), shininess);
     vec3 specular = specularStrength * spec * (light.colour * light.intensity);
 
-    // attenuation
-    float distance    = length(light.position.xyz - worldPos);
-    float lightConstant = 1.0;
-    float lightLinear = 0.09;
-    float lightQuadratic = 0.032;
-    float attenuation = 1.0 / (lightConstant + lightLinear * distance + lightQuadratic * (distance * distance));
+    // Inverse square falloff, faded smoothly to zero at the light's range
+    float distance = length(light.position.xyz - worldPos);
+    float rangeFalloff = clamp(1.0 - pow(distance / max(light.range, 0.0001), 4.0), 0.0, 1.0);
+    // Clamped to 1cm so surfaces right next to the light don't blow out
+    float attenuation = rangeFalloff * rangeFalloff / max(distance * distance, 0.0001);
 
     diffuse *= attenuation;
     specular *= attenuation;
 
     return diffuse + specular;
-}
\ No newline at end of file
+}
+
+vec3 CalculateSpotLight(vec3 normal, vec3 worldPos, vec3 cameraPos, Light light) {
+    vec3 lightDir = normalize(light.position.xyz - worldPos);
+
+    // Cone falloff, innerCone and outerCone are the cosines of the cone half angles
+    float theta = dot(lightDir, normalize(-light.direction));
+    float epsilon = max(light.innerCone - light.outerCone, 0.0001);
+    float coneFalloff = clamp((theta - light.outerCone) / epsilon, 0.0, 1.0);
+
+    return CalculatePointLight(normal, worldPos, cameraPos, light) * coneFalloff;
+}
+
+vec3 CalculateLight(vec3 normal, vec3 worldPos, vec3 cameraPos, Light light) {
+    if (light.lightType == LIGHT_TYPE_SPOT) {
+        return CalculateSpotLight(normal, worldPos, cameraPos, light);
+    }
+    return CalculatePointLight(normal, worldPos, cameraPos, light);
+}
@@ -0,0 +1,109 @@
+// Writes one mip level from the level above it. Included after the outputImage declaration, whose
+// format qualifier has to match the image. Define ENCODE_SRGB when writing an sRGB image through a
+// UNORM view.
+#include "assets/shaders/library/texture.glsl"
+
+layout (local_size_x = 8, local_size_y = 8) in;
+
+layout( push_constant ) uniform constants
+{
+    int sourceHandle;
+    int sourceLevel;
+    int sourceWidth;
+    int sourceHeight;
+    int width;
+    int height;
+    int filterType;
+} mip;
+
+// Must match MipFilter
+const int FILTER_KAISER = 2;
+// Half the width of the Kaiser window, in destination texels
+const float KAISER_RADIUS = 1.5;
+const float KAISER_ALPHA = 4.0;
+const float PI = 3.14159265359;
+
+vec3 LinearToSrgb(vec3 linear)
+{
+    bvec3 cutoff = lessThan(linear, vec3(0.0031308));
+    vec3 lower = linear * 12.92;
+    vec3 higher = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(higher, lower, cutoff);
+}
+
+vec4 FetchSource(ivec2 texel)
+{
+    texel = clamp(texel, ivec2(0), ivec2(mip.sourceWidth, mip.sourceHeight) - 1);
+    return texelFetch(sampler2D(bindlessTextures[nonuniformEXT(mip.sourceHandle - 1)], samplers[0]), texel, mip.sourceLevel);
+}
+
+// Zeroth order modified Bessel function of the first kind
+float BesselI0(float x)
+{
+    float sum = 1.0;
+    float term = 1.0;
+    for (int k = 1; k < 10; k++) {
+        float factor = x * 0.5 / float(k);
+        term *= factor * factor;
+        sum += term;
+    }
+    return sum;
+}
+
+// A sinc windowed by a Kaiser window. Distance is in destination texels.
+float KaiserWeight(float distance)
+{
+    float x = distance / KAISER_RADIUS;
+    if (abs(x) >= 1.0) {
+        return 0.0;
+    }
+    float window = BesselI0(KAISER_ALPHA * sqrt(1.0 - x * x)) / BesselI0(KAISER_ALPHA);
+    float t = PI * distance;
+    float sinc = abs(t) < 0.0001 ? 1.0 : sin(t) / t;
+    return sinc * window;
+}
+
+// Offset is from the destination texel's centre to a source texel's centre, in source texels
+float FilterWeight(float offset, float ratio)
+{
+    if (mip.filterType == FILTER_KAISER) {
+        return KaiserWeight(offset / ratio);
+    }
+    // Box, weighted by how much of the source texel lies inside the destination texel
+    float halfWidth = 0.5 * ratio;
+    return clamp(min(halfWidth, offset + 0.5) - max(-halfWidth, offset - 0.5), 0.0, 1.0);
+}
+
+void main()
+{
+    ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+    if (texel.x >= mip.width || texel.y >= mip.height) {
+        return;
+    }
+
+    // Odd sized levels don't halve exactly, so the footprint isn't always two texels across
+    vec2 ratio = vec2(mip.sourceWidth, mip.sourceHeight) / vec2(mip.width, mip.height);
+    vec2 centre = (vec2(texel) + 0.5) * ratio;
+    vec2 radius = ratio * (mip.filterType == FILTER_KAISER ? KAISER_RADIUS : 0.5);
+    ivec2 first = ivec2(floor(centre - radius));
+    ivec2 last = ivec2(ceil(centre + radius)) - 1;
+
+    vec4 result = vec4(0.0);
+    float totalWeight = 0.0;
+    for (int y = first.y; y <= last.y; y++) {
+        float weightY = FilterWeight(float(y) + 0.5 - centre.y, ratio.y);
+        for (int x = first.x; x <= last.x; x++) {
+            float weight = weightY * FilterWeight(float(x) + 0.5 - centre.x, ratio.x);
+            result += FetchSource(ivec2(x, y)) * weight;
+            totalWeight += weight;
+        }
+    }
+
+    // The Kaiser filter's negative lobes can ring below zero around sharp edges
+    vec4 filtered = max(result / totalWeight, vec4(0.0));
+#ifdef ENCODE_SRGB
+    // Sources are decoded to linear when fetched, so the filtering is done in linear space
+    filtered.rgb = LinearToSrgb(filtered.rgb);
+#endif
+    imageStore(outputImage, texel, filtered);
+}
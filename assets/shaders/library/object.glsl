@@ -1,13 +1,24 @@
 struct ModelMatrix{
     mat4 model;
     mat4 normal;
+    mat4 prevModel;
+    vec4 params;
 };
 
+// Must match the MATERIAL_FLAG_ constants in renderer.rs
+const int MATERIAL_FLAG_NORMAL_MAP = 1;
+const int MATERIAL_FLAG_VERTEX_COLOR = 2;
+const int MATERIAL_FLAG_DOUBLE_SIDED = 4;
+
 struct MaterialParameters {
     vec4 diffuse;
+    // rgb is the colour, w the strength multiplying it
     vec4 emissive;
     ivec4 textures;
     ivec4 textures_two;
+    // xy offsets the texture coordinates, z is the alpha cutoff, negative when not alpha tested,
+    // w is the LOD bias
+    vec4 uvScroll;
 };
 
 struct InstanceParameters {
@@ -27,4 +38,14 @@ layout(std140,set = 1, binding = 3) readonly buffer MaterialBuffer{
 
 layout(std140,set = 1, binding = 5) readonly buffer InstanceBuffer{
     InstanceParameters instance[];
-} instanceData;
\ No newline at end of file
+} instanceData;
+
+// Values set per render model with Renderer::set_render_model_params, e.g. a tint or dissolve amount
+vec4 objectParams(int instanceIndex) {
+    return modelData.models[instanceData.instance[instanceIndex].transform_handle].params;
+}
+
+// Instance indices that survived culling, indexed by gl_InstanceIndex
+layout(std430,set = 1, binding = 8) readonly buffer VisibleInstanceBuffer{
+    uint instances[];
+} visibleInstanceData;
@@ -1,12 +1,10 @@
-float ShadowCalculation(sampler2DShadow shadowMap, vec4 projCoords)
+// Samples outside the shadow map read the white border colour and count as lit.
+float ShadowCalculation(sampler2DShadow shadowMap, vec4 projCoords, int offset, float bias)
 {
-    float closestDepth = texture(shadowMap, projCoords.xyz).r;
     float currentDepth = projCoords.z;
-    float bias = 0.001;
     float ambient = 0.0;
     float shadow = 0.0;
     vec2 texelSize = 1.0 / textureSize(shadowMap, 0);
-    int offset = 1;
     for(int x = -offset; x <= offset; ++x)
     {
         for(int y = -offset; y <= offset; ++y)
@@ -23,4 +21,22 @@ float ShadowCalculation(sampler2DShadow shadowMap, vec4 projCoords)
     }
 
     return shadow;
+}
+
+// farPlane is the distance the cube shadow maps were rendered out to, cameraData.pointShadowFarPlane
+float PointShadowCalculation(int shadowIndex, vec3 worldPos, vec3 lightPos, float farPlane)
+{
+    if (shadowIndex <= 0) {
+        return 0.0;
+    }
+
+    vec3 lightToFrag = worldPos - lightPos;
+    float currentDepth = length(lightToFrag) / farPlane;
+    if (currentDepth > 1.0) {
+        return 0.0;
+    }
+
+    float closestDepth = texture(samplerCube(bindlessCubeTextures[nonuniformEXT(shadowIndex - 1)], samplers[1]), lightToFrag).r;
+    float bias = 0.005;
+    return currentDepth - bias > closestDepth ? 1.0 : 0.0;
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+// Analytic daylight sky from Preetham et al. 1999, "A Practical Analytic Model for Daylight"
+
+// Brings the model's luminance, in thousands of candela per square metre, to the scene's scale
+const float SKY_LUMINANCE_SCALE = 0.1;
+
+// Perez distribution of sky brightness with the angle from the zenith and from the sun
+float Perez(float cosTheta, float gamma, float cosGamma, float A, float B, float C, float D, float E)
+{
+    return (1.0 + A * exp(B / cosTheta)) * (1.0 + C * exp(D * gamma) + E * cosGamma * cosGamma);
+}
+
+vec3 PerezXyY(float cosTheta, float gamma, float cosGamma, float turbidity)
+{
+    float T = turbidity;
+    float Y = Perez(cosTheta, gamma, cosGamma, 0.1787 * T - 1.4630, -0.3554 * T + 0.4275, -0.0227 * T + 5.3251, 0.1206 * T - 2.5771, -0.0670 * T + 0.3703);
+    float x = Perez(cosTheta, gamma, cosGamma, -0.0193 * T - 0.2592, -0.0665 * T + 0.0008, -0.0004 * T + 0.2125, -0.0641 * T - 0.8989, -0.0033 * T + 0.0452);
+    float y = Perez(cosTheta, gamma, cosGamma, -0.0167 * T - 0.2608, -0.0950 * T + 0.0092, -0.0079 * T + 0.2102, -0.0441 * T - 1.6537, -0.0109 * T + 0.0529);
+    return vec3(x, y, Y);
+}
+
+// Chromaticity and luminance straight up, for the sun thetaSun radians from the zenith
+vec3 ZenithXyY(float thetaSun, float turbidity)
+{
+    float T = turbidity;
+    float chi = (4.0 / 9.0 - T / 120.0) * (3.14159265359 - 2.0 * thetaSun);
+    float Y = (4.0453 * T - 4.9710) * tan(chi) - 0.2155 * T + 2.4192;
+
+    vec4 thetas = vec4(thetaSun * thetaSun * thetaSun, thetaSun * thetaSun, thetaSun, 1.0);
+    float x = T * T * dot(vec4(0.00166, -0.00375, 0.00209, 0.0), thetas)
+        + T * dot(vec4(-0.02903, 0.06377, -0.03202, 0.00394), thetas)
+        + dot(vec4(0.11693, -0.21196, 0.06052, 0.25886), thetas);
+    float y = T * T * dot(vec4(0.00275, -0.00610, 0.00317, 0.0), thetas)
+        + T * dot(vec4(-0.04214, 0.08970, -0.04153, 0.00516), thetas)
+        + dot(vec4(0.15346, -0.26756, 0.06670, 0.26688), thetas);
+    return vec3(x, y, Y);
+}
+
+vec3 XyYToLinearRgb(vec3 xyY)
+{
+    float Y = xyY.z;
+    float X = xyY.x / max(xyY.y, 0.0001) * Y;
+    float Z = (1.0 - xyY.x - xyY.y) / max(xyY.y, 0.0001) * Y;
+    vec3 rgb = mat3(
+        3.2406, -0.9689, 0.0557,
+        -1.5372, 1.8758, -0.2040,
+        -0.4986, 0.0415, 1.0570
+    ) * vec3(X, Y, Z);
+    return max(rgb, vec3(0.0));
+}
+
+// Sky above the horizon, viewed along the normalized dir
+vec3 PreethamSky(vec3 dir, vec3 sunDir, float turbidity)
+{
+    // The model only holds for the sun above the horizon
+    vec3 sun = normalize(vec3(sunDir.x, max(sunDir.y, 0.001), sunDir.z));
+    float thetaSun = acos(sun.y);
+    float cosTheta = max(dir.y, 0.01);
+    float cosGamma = clamp(dot(dir, sun), -1.0, 1.0);
+    float gamma = acos(cosGamma);
+
+    vec3 zenith = ZenithXyY(thetaSun, turbidity);
+    vec3 ratio = PerezXyY(cosTheta, gamma, cosGamma, turbidity) / PerezXyY(1.0, thetaSun, sun.y, turbidity);
+    vec3 xyY = zenith * ratio;
+    xyY.z *= SKY_LUMINANCE_SCALE;
+
+    // Fades to night as the sun sets
+    return XyYToLinearRgb(xyY) * smoothstep(-0.1, 0.05, sunDir.y);
+}
+
+vec3 ProceduralSky(vec3 viewDir, vec3 sunDir, vec3 sunColour, float turbidity, vec3 groundAlbedo)
+{
+    vec3 dir = normalize(viewDir);
+    vec3 sunDirection = normalize(sunDir);
+
+    vec3 sky = PreethamSky(dir, sunDirection, turbidity);
+    if (dir.y < 0.0) {
+        // Ground lit by the sky overhead, blended into the horizon to hide the seam
+        vec3 ground = groundAlbedo * PreethamSky(vec3(0.0, 1.0, 0.0), sunDirection, turbidity);
+        sky = mix(sky, ground, clamp(-dir.y * 20.0, 0.0, 1.0));
+    }
+
+    float sunAmount = max(dot(dir, sunDirection), 0.0);
+    sky += sunColour * pow(sunAmount, 512.0);
+    sky += sunColour * 0.25 * pow(sunAmount, 8.0);
+
+    return sky;
+}
@@ -13,6 +13,15 @@ vec4 SampleBindlessTexture(int samplerHandle, int handle, vec2 texCoords)
     return result;
 }
 
+vec4 SampleBindlessTextureBias(int samplerHandle, int handle, vec2 texCoords, float bias)
+{
+    vec4 result = vec4(0);
+    if (handle > 0){
+        result = texture(sampler2D(bindlessTextures[nonuniformEXT(handle - 1)], samplers[nonuniformEXT(samplerHandle)]), texCoords, bias);
+    }
+    return result;
+}
+
 vec3 SampleBindlessSkybox(int samplerHandle, int handle, vec3 viewDir)
 {
     vec3 result = vec3(0);
@@ -20,4 +29,31 @@ vec3 SampleBindlessSkybox(int samplerHandle, int handle, vec3 viewDir)
         result = texture(samplerCube(bindlessCubeTextures[nonuniformEXT(handle - 1)], samplers[nonuniformEXT(samplerHandle)]), normalize(viewDir)).rgb;
     }
     return result;
-}
\ No newline at end of file
+}
+
+vec3 SampleBindlessSkyboxLod(int samplerHandle, int handle, vec3 viewDir, float lod)
+{
+    vec3 result = vec3(0);
+    if (handle > 0){
+        result = textureLod(samplerCube(bindlessCubeTextures[nonuniformEXT(handle - 1)], samplers[nonuniformEXT(samplerHandle)]), normalize(viewDir), lod).rgb;
+    }
+    return result;
+}
+
+int BindlessSkyboxSize(int samplerHandle, int handle)
+{
+    int result = 0;
+    if (handle > 0){
+        result = textureSize(samplerCube(bindlessCubeTextures[nonuniformEXT(handle - 1)], samplers[nonuniformEXT(samplerHandle)]), 0).x;
+    }
+    return result;
+}
+
+vec4 SampleBindlessTextureLod(int samplerHandle, int handle, vec2 texCoords, float lod)
+{
+    vec4 result = vec4(0);
+    if (handle > 0){
+        result = textureLod(sampler2D(bindlessTextures[nonuniformEXT(handle - 1)], samplers[nonuniformEXT(samplerHandle)]), texCoords, lod);
+    }
+    return result;
+}
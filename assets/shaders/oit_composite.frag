@@ -0,0 +1,25 @@
+#version 460
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+layout (location = 1) out vec4 outBrightColor;
+
+layout (set = 0, binding = 0) uniform sampler2D accumulationImage;
+layout (set = 0, binding = 1) uniform sampler2D coverageImage;
+
+void main()
+{
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    float coverage = texelFetch(coverageImage, texel, 0).r;
+    if (coverage < 0.00001) {
+        discard;
+    }
+
+    // Weighted average of every transparent surface over this pixel, blended over the opaque scene
+    vec4 accumulation = texelFetch(accumulationImage, texel, 0);
+    vec3 averageColour = accumulation.rgb / max(accumulation.a, 0.00001);
+    outFragColor = vec4(averageColour, coverage);
+    // Thresholded by the first bloom pass
+    outBrightColor = vec4(averageColour, coverage);
+}
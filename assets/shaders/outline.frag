@@ -0,0 +1,35 @@
+#version 460
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout (set = 0, binding = 0) uniform sampler2D maskImage;
+
+layout (push_constant) uniform constants {
+    vec4 colour;
+    int width;
+} outlineData;
+
+void main()
+{
+    if (texture(maskImage, inTexCoords).r > 0.5f) {
+        discard;
+    }
+
+    // Outlines pixels outside of the mask that are within the width of it
+    vec2 texelSize = 1.0f / vec2(textureSize(maskImage, 0));
+    int width = outlineData.width;
+    for (int y = -width; y <= width; y++) {
+        for (int x = -width; x <= width; x++) {
+            if (x * x + y * y > width * width) {
+                continue;
+            }
+            if (texture(maskImage, inTexCoords + vec2(x, y) * texelSize).r > 0.5f) {
+                outFragColor = outlineData.colour;
+                return;
+            }
+        }
+    }
+    discard;
+}
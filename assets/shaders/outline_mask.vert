@@ -0,0 +1,16 @@
+#version 450
+#extension GL_EXT_nonuniform_qualifier: enable
+#include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/object.glsl"
+
+layout (location = 0) in vec3 vPosition;
+
+void main()
+{
+	// Drawn one instance at a time without culling, so the instance index is used directly
+	InstanceParameters instance = instanceData.instance[gl_InstanceIndex];
+	mat4 modelMatrix = modelData.models[instance.transform_handle].model;
+	gl_Position = cameraData.proj * cameraData.view * modelMatrix * vec4(vPosition, 1.0f);
+	// The outline is drawn over the resolved image, so undo the TAA jitter to keep it steady
+	gl_Position.xy -= cameraData.jitter.xy * gl_Position.w;
+}
@@ -14,7 +14,7 @@ struct Particle{
 	mat4 model;
 	vec4 colour;
 	int textureIndex;
-	float padding;
+	int billboard;
 	float padding_two;
 	float padding_three;
 };
@@ -34,12 +34,6 @@ void main()
 
 	outFragColor = colour;
 
-	// Bright Colours
-	float brightness = dot(outFragColor.rgb, vec3(0.2126, 0.7152, 0.0722));
-	if(brightness > 1.0) {
-		outBrightColor = vec4(outFragColor.rgb, 1.0);
-	}
-	else {
-		outBrightColor = vec4(0.0, 0.0, 0.0, 1.0);
-	}
+	// Thresholded by the first bloom pass
+	outBrightColor = vec4(outFragColor.rgb, 1.0);
 }
\ No newline at end of file
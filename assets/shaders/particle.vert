@@ -16,7 +16,7 @@ struct Particle{
 	mat4 model;
 	vec4 colour;
 	int textureIndex;
-	float padding;
+	int billboard;
 	float padding_two;
 	float padding_three;
 };
@@ -32,5 +32,14 @@ void main()
 
 	outTexCoords = vTexCoords;
 
-	gl_Position = cameraData.proj * cameraData.view * self.model * vec4(vPosition, 1.0f);
+	vec4 worldPosition;
+	if (self.billboard != 0) {
+		// Roll and scale are applied in view space, so the quad always faces the camera
+		vec3 offset = mat3(self.model) * vPosition;
+		worldPosition = vec4(self.model[3].xyz + transpose(mat3(cameraData.view)) * offset, 1.0f);
+	} else {
+		worldPosition = self.model * vec4(vPosition, 1.0f);
+	}
+
+	gl_Position = cameraData.proj * cameraData.view * worldPosition;
 }
\ No newline at end of file
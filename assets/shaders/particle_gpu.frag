@@ -0,0 +1,22 @@
+#version 460
+#include "assets/shaders/library/texture.glsl"
+#include "assets/shaders/library/gpu_particle.glsl"
+
+layout (location = 0) in vec2 inTexCoords;
+layout (location = 1) in vec4 inColour;
+
+layout (location = 0) out vec4 outFragColor;
+layout (location = 1) out vec4 outBrightColor;
+
+void main()
+{
+	vec4 colour = inColour;
+	if (params.textureIndex > 0) {
+		colour *= SampleBindlessTexture(0, params.textureIndex, inTexCoords);
+	}
+
+	outFragColor = colour;
+
+	// Thresholded by the first bloom pass
+	outBrightColor = vec4(outFragColor.rgb, 1.0);
+}
@@ -0,0 +1,28 @@
+#version 450
+#include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/gpu_particle.glsl"
+
+layout (location = 0) in vec3 vPosition;
+layout (location = 1) in vec2 vTexCoords;
+layout (location = 2) in vec3 vNormal;
+layout (location = 3) in vec3 vColor;
+layout (location = 4) in vec4 vTangent;
+
+layout (location = 0) out vec2 outTexCoords;
+layout (location = 1) out vec4 outColour;
+
+void main()
+{
+	// Instances are the live particles listed by particle_simulate.comp
+	GpuParticle particle = particleData.particles[aliveData.indices[gl_InstanceIndex]];
+	float age = 1.0 - particle.position.w / particle.velocity.w;
+
+	outTexCoords = vTexCoords;
+	outColour = mix(params.startColour, params.endColour, age);
+
+	// Always faces the camera
+	float size = mix(params.startSize, params.endSize, age);
+	vec3 worldPosition = particle.position.xyz + transpose(mat3(cameraData.view)) * (vPosition * size);
+
+	gl_Position = cameraData.proj * cameraData.view * vec4(worldPosition, 1.0f);
+}
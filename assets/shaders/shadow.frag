@@ -1,6 +1,17 @@
 #version 460
+#include "assets/shaders/library/texture.glsl"
+#include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/object.glsl"
+#include "assets/shaders/library/alpha_test.glsl"
+
+layout (location = 0) in vec2 inTexCoords;
+layout (location = 1) in flat int inInstanceIndex;
 
 void main()
 {
-
-}
\ No newline at end of file
+    InstanceParameters instance = instanceData.instance[inInstanceIndex];
+    MaterialParameters material = materialData.materials[instance.material_handle];
+    if (AlphaTestDiscards(material, inTexCoords + material.uvScroll.xy)){
+        discard;
+    }
+}
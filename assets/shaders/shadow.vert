@@ -10,9 +10,14 @@ layout (location = 2) in vec3 vNormal;
 layout (location = 3) in vec3 vColor;
 layout (location = 4) in vec4 vTangent;
 
+layout (location = 0) out vec2 outTexCoords;
+layout (location = 1) out int outInstanceIndex;
+
 void main()
 {
 	InstanceParameters instance = instanceData.instance[gl_InstanceIndex];
+	outTexCoords = vTexCoords;
+	outInstanceIndex = gl_InstanceIndex;
 	mat4 modelMatrix = modelData.models[instance.transform_handle].model;
 	gl_Position = cameraData.sunProj * cameraData.sunView * modelMatrix * vec4(vPosition, 1.0f);
 }
\ No newline at end of file
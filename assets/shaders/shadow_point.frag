@@ -0,0 +1,27 @@
+#version 460
+#include "assets/shaders/library/texture.glsl"
+#include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/object.glsl"
+#include "assets/shaders/library/alpha_test.glsl"
+
+layout (location = 0) in vec3 inWorldPos;
+layout (location = 1) in vec2 inTexCoords;
+layout (location = 2) in flat int inInstanceIndex;
+
+layout( push_constant ) uniform constants
+{
+    mat4 viewProj;
+    vec4 lightPosition;
+} shadowData;
+
+void main()
+{
+    InstanceParameters instance = instanceData.instance[inInstanceIndex];
+    MaterialParameters material = materialData.materials[instance.material_handle];
+    if (AlphaTestDiscards(material, inTexCoords + material.uvScroll.xy)){
+        discard;
+    }
+
+    // Store linear distance to the light so the cube map can be compared against in any direction
+    gl_FragDepth = length(inWorldPos - shadowData.lightPosition.xyz) / shadowData.lightPosition.w;
+}
\ No newline at end of file
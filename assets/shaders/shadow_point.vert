@@ -0,0 +1,32 @@
+//we will be using glsl version 4.5 syntax
+#version 450
+#extension GL_EXT_nonuniform_qualifier: enable
+#include "assets/shaders/library/camera.glsl"
+#include "assets/shaders/library/object.glsl"
+
+layout (location = 0) in vec3 vPosition;
+layout (location = 1) in vec2 vTexCoords;
+layout (location = 2) in vec3 vNormal;
+layout (location = 3) in vec3 vColor;
+layout (location = 4) in vec4 vTangent;
+
+layout (location = 0) out vec3 outWorldPos;
+layout (location = 1) out vec2 outTexCoords;
+layout (location = 2) out int outInstanceIndex;
+
+layout( push_constant ) uniform constants
+{
+	mat4 viewProj;
+	vec4 lightPosition;
+} shadowData;
+
+void main()
+{
+	InstanceParameters instance = instanceData.instance[gl_InstanceIndex];
+	outTexCoords = vTexCoords;
+	outInstanceIndex = gl_InstanceIndex;
+	mat4 modelMatrix = modelData.models[instance.transform_handle].model;
+	vec4 worldPos = modelMatrix * vec4(vPosition, 1.0f);
+	outWorldPos = worldPos.xyz;
+	gl_Position = shadowData.viewProj * worldPos;
+}
\ No newline at end of file
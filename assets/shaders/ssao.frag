@@ -0,0 +1,83 @@
+#version 460
+#include "assets/shaders/library/camera.glsl"
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outOcclusion;
+
+layout (set = 2, binding = 0) uniform sampler2D normalImage;
+layout (set = 2, binding = 1) uniform sampler2D depthImage;
+
+layout( push_constant ) uniform constants
+{
+    float radius;
+    float bias;
+} ssao;
+
+const int KERNEL_SIZE = 16;
+
+float Hash(vec2 p)
+{
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+// Spreads samples over a hemisphere around +Z, packed closer to the centre so nearby geometry counts more
+vec3 KernelSample(int i)
+{
+    float z = 1.0 - (float(i) + 0.5) / float(KERNEL_SIZE);
+    float r = sqrt(1.0 - z * z);
+    float phi = float(i) * 2.3999632;
+    float scale = float(i + 1) / float(KERNEL_SIZE);
+    scale = mix(0.1, 1.0, scale * scale);
+    return vec3(cos(phi) * r, sin(phi) * r, z) * scale;
+}
+
+vec3 WorldPosition(vec2 texCoords, float depth)
+{
+    vec4 ndc = vec4(texCoords * 2.0 - 1.0, depth, 1.0);
+    vec4 clip = cameraData.invProjView * ndc;
+    return clip.xyz / clip.w;
+}
+
+void main()
+{
+    float depth = texture(depthImage, inTexCoords).r;
+    if (depth == 1.0) {
+        outOcclusion = vec4(1.0);
+        return;
+    }
+
+    vec3 fragPos = WorldPosition(inTexCoords, depth);
+    vec3 normal = normalize(texture(normalImage, inTexCoords).rgb);
+
+    // Rotate the kernel per pixel, the blur passes smooth out the resulting noise
+    vec3 randomVec = normalize(vec3(Hash(gl_FragCoord.xy), Hash(gl_FragCoord.yx + 17.0), Hash(gl_FragCoord.xy + 31.0)) * 2.0 - 1.0);
+    vec3 tangent = randomVec - normal * dot(randomVec, normal);
+    if (dot(tangent, tangent) < 0.0001) {
+        tangent = abs(normal.x) < 0.9 ? vec3(1.0, 0.0, 0.0) : vec3(0.0, 1.0, 0.0);
+        tangent -= normal * dot(tangent, normal);
+    }
+    tangent = normalize(tangent);
+    mat3 TBN = mat3(tangent, cross(normal, tangent), normal);
+
+    mat4 projView = cameraData.proj * cameraData.view;
+    vec3 origin = fragPos + normal * ssao.bias;
+    float occlusion = 0.0;
+    for (int i = 0; i < KERNEL_SIZE; i++) {
+        vec3 samplePos = origin + TBN * KernelSample(i) * ssao.radius;
+
+        vec4 clip = projView * vec4(samplePos, 1.0);
+        vec3 ndc = clip.xyz / clip.w;
+        vec2 sampleCoords = ndc.xy * 0.5 + 0.5;
+        if (any(lessThan(sampleCoords, vec2(0.0))) || any(greaterThan(sampleCoords, vec2(1.0)))) {
+            continue;
+        }
+
+        float sceneDepth = texture(depthImage, sampleCoords).r;
+        vec3 scenePos = WorldPosition(sampleCoords, sceneDepth);
+        float rangeCheck = smoothstep(0.0, 1.0, ssao.radius / max(length(scenePos - fragPos), 0.0001));
+        occlusion += (sceneDepth < ndc.z ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    outOcclusion = vec4(vec3(1.0 - occlusion / float(KERNEL_SIZE)), 1.0);
+}
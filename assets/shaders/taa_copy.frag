@@ -0,0 +1,15 @@
+#version 460
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+layout (location = 1) out vec4 outHistory;
+
+layout (set = 0, binding = 0) uniform sampler2D resolvedImage;
+
+void main()
+{
+    vec4 resolved = texelFetch(resolvedImage, ivec2(gl_FragCoord.xy), 0);
+    outFragColor = resolved;
+    outHistory = resolved;
+}
@@ -0,0 +1,74 @@
+#version 460
+#include "assets/shaders/library/camera.glsl"
+
+layout (location = 0) in vec2 inTexCoords;
+
+layout (location = 0) out vec4 outFragColor;
+
+layout (set = 2, binding = 0) uniform sampler2D currentImage;
+layout (set = 2, binding = 1) uniform sampler2D velocityImage;
+layout (set = 2, binding = 2) uniform sampler2D depthImage;
+layout (set = 2, binding = 3) uniform sampler2D historyImage;
+
+layout( push_constant ) uniform constants
+{
+    int historyValid;
+} taa;
+
+// Weight of the current frame, lower values smooth more but take longer to settle
+const float CURRENT_WEIGHT = 0.1;
+
+void main()
+{
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    ivec2 maxTexel = textureSize(currentImage, 0) - 1;
+    vec3 current = texelFetch(currentImage, texel, 0).rgb;
+    if (taa.historyValid == 0) {
+        outFragColor = vec4(current, 1.0);
+        return;
+    }
+
+    // Colour range of the neighbourhood, and the closest surface in it so edges follow the
+    // object in front
+    vec3 minColour = current;
+    vec3 maxColour = current;
+    float closestDepth = 1.0;
+    ivec2 closestTexel = texel;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            ivec2 neighbour = clamp(texel + ivec2(x, y), ivec2(0), maxTexel);
+            vec3 colour = texelFetch(currentImage, neighbour, 0).rgb;
+            minColour = min(minColour, colour);
+            maxColour = max(maxColour, colour);
+
+            float depth = texelFetch(depthImage, neighbour, 0).r;
+            if (depth < closestDepth) {
+                closestDepth = depth;
+                closestTexel = neighbour;
+            }
+        }
+    }
+
+    vec2 velocity = texelFetch(velocityImage, closestTexel, 0).rg;
+    if (closestDepth >= 1.0) {
+        // Nothing drawn, so the background only moves with the camera
+        vec4 ndc = vec4(inTexCoords * 2.0 - 1.0, 1.0, 1.0);
+        vec4 worldPos = cameraData.invProjView * ndc;
+        vec4 prevClip = cameraData.prevProjView * vec4(worldPos.xyz / worldPos.w, 1.0);
+        vec2 currentPos = ndc.xy - cameraData.jitter.xy;
+        velocity = (currentPos - prevClip.xy / prevClip.w) * 0.5;
+    }
+
+    vec2 historyCoords = inTexCoords - velocity;
+    if (any(lessThan(historyCoords, vec2(0.0))) || any(greaterThan(historyCoords, vec2(1.0)))) {
+        outFragColor = vec4(current, 1.0);
+        return;
+    }
+
+    // History that falls outside the neighbourhood no longer matches the scene, so is pulled back
+    // towards it to stop ghosting
+    vec3 history = texture(historyImage, historyCoords).rgb;
+    history = clamp(history, minColour, maxColour);
+
+    outFragColor = vec4(mix(history, current, CURRENT_WEIGHT), 1.0);
+}
@@ -2,10 +2,9 @@ use std::collections::HashMap;
 use std::error::Error;
 
 use anyhow::{anyhow, Result};
-use cgmath::Matrix4;
+use cgmath::{Matrix4, Vector4};
 use gltf::image::Source;
-use image::EncodableLayout;
-use log::info;
+use log::{info, warn};
 
 use jb_gfx::prelude::*;
 use jb_gfx::renderer::MaterialInstanceHandle;
@@ -22,13 +21,12 @@ impl AssetManager {
         file: impl AsRef<std::path::Path>,
         format: &ImageFormatType,
     ) -> Result<ImageHandle> {
-        if let Some(texture) = self.loaded_textures.get(file.as_ref().to_str().unwrap()) {
+        let file = path_to_str(file.as_ref())?;
+        if let Some(texture) = self.loaded_textures.get(file) {
             Ok(*texture)
-        } else if let Ok(loaded_texture) =
-            renderer.load_texture(file.as_ref().to_str().unwrap(), format)
-        {
+        } else if let Ok(loaded_texture) = renderer.load_texture(file, format) {
             self.loaded_textures
-                .insert(file.as_ref().to_str().unwrap().to_string(), loaded_texture);
+                .insert(file.to_string(), loaded_texture);
             Ok(loaded_texture)
         } else {
             Err(anyhow!("Cant load texture or find it!"))
@@ -41,8 +39,15 @@ impl AssetManager {
         buffers: &[gltf::buffer::Data],
         image: &gltf::Image,
         view: &gltf::buffer::View,
+        asset_name: &str,
+        format: &ImageFormatType,
     ) -> Result<ImageHandle> {
-        if let Some(texture) = self.loaded_textures.get(image.name().unwrap()) {
+        // Embedded images don't have to be named, so fall back to their index in the file
+        let key = match image.name() {
+            Some(name) => name.to_string(),
+            None => format!("{}#image{}", asset_name, image.index()),
+        };
+        if let Some(texture) = self.loaded_textures.get(&key) {
             Ok(*texture)
         } else {
             let data = &buffers[view.buffer().index()];
@@ -50,22 +55,20 @@ impl AssetManager {
             let length = view.length();
             let end = offset + length;
             let image_slice = &data[offset..end];
-            let img = image::load_from_memory(image_slice).unwrap();
+            let img = image::load_from_memory(image_slice)?;
 
-            let rgba_img = img.to_rgba8();
-            let img_bytes = rgba_img.as_bytes();
+            let img_bytes = format.image_bytes(&img);
             let mip_levels = (img.width().max(img.height()) as f32).log2().floor() as u32 + 1u32;
 
             if let Ok(loaded_texture) = renderer.load_texture_from_bytes(
-                img_bytes,
+                &img_bytes,
                 img.width(),
                 img.height(),
-                &ImageFormatType::Default,
+                format,
                 mip_levels,
                 1,
             ) {
-                self.loaded_textures
-                    .insert(image.name().unwrap().to_string(), loaded_texture);
+                self.loaded_textures.insert(key, loaded_texture);
                 Ok(loaded_texture)
             } else {
                 Err(anyhow!("Cant load texture or find it!"))
@@ -73,20 +76,57 @@ impl AssetManager {
         }
     }
 
+    /// Loads the image used by a material texture, whether it is embedded in the file or next to it.
+    fn load_material_texture(
+        &mut self,
+        renderer: &mut Renderer,
+        buffers: &[gltf::buffer::Data],
+        source_folder: &str,
+        asset_name: &str,
+        texture: &gltf::Texture,
+        format: &ImageFormatType,
+    ) -> Result<ImageHandle> {
+        match texture.source().source() {
+            Source::View { mime_type: _, view } => self.load_embedded_texture(
+                renderer,
+                buffers,
+                &texture.source(),
+                &view,
+                asset_name,
+                format,
+            ),
+            Source::Uri { uri, .. } => {
+                let image_asset = String::from(source_folder) + "/" + uri;
+                self.load_texture(renderer, &image_asset, format)
+            }
+        }
+    }
+
+    /// Loads every mesh referenced by a node in a glTF file, with a material instance for each primitive.
+    ///
+    /// Missing normals and tangents are generated. Extensions are not supported, so any used by the
+    /// file are ignored with a warning.
     pub fn load_gltf(
         &mut self,
         renderer: &mut Renderer,
         file: impl AsRef<std::path::Path>,
     ) -> Result<Vec<Model>> {
         profiling::scope!("Load GLTF Asset");
-        let file = file.as_ref().to_str().unwrap();
+        let file = path_to_str(file.as_ref())?;
 
         let (gltf, buffers, _) = {
             profiling::scope!("Load GLTF Asset: Import File");
             gltf::import(file)?
         };
 
-        let (source_folder, asset_name) = file.rsplit_once('/').unwrap();
+        let (source_folder, asset_name) = file.rsplit_once('/').unwrap_or((".", file));
+
+        for extension in gltf.extensions_used() {
+            warn!(
+                "GLTF extension {} used by {} is not supported and will be ignored",
+                extension, asset_name
+            );
+        }
 
         // TODO : Add image load to vec when iterating through materials, then for normal maps upload them as normal
         for image in gltf.images() {
@@ -109,6 +149,14 @@ impl AssetManager {
                 for primitive in mesh.primitives() {
                     profiling::scope!("Load GLTF Asset: Primitive");
 
+                    if primitive.mode() != gltf::mesh::Mode::Triangles {
+                        warn!(
+                            "Skipping primitive in mesh {} as only triangle lists are supported",
+                            mesh.index()
+                        );
+                        continue;
+                    }
+
                     let mut positions = Vec::new();
                     let mut tex_coords = Vec::new();
                     let mut normals = Vec::new();
@@ -149,135 +197,71 @@ impl AssetManager {
                     }
 
                     let material = primitive.material();
-                    let diffuse_tex = {
-                        if let Some(info) = material.pbr_metallic_roughness().base_color_texture() {
-                            match info.texture().source().source() {
-                                Source::View { mime_type: _, view } => {
-                                    Some(self.load_embedded_texture(
-                                        renderer,
-                                        &buffers,
-                                        &info.texture().source(),
-                                        &view,
-                                    )?)
-                                }
-                                Source::Uri { uri, .. } => {
-                                    let image_asset = String::from(source_folder) + "/" + uri;
-                                    Some(self.load_texture(
-                                        renderer,
-                                        &image_asset,
-                                        &ImageFormatType::Default,
-                                    )?)
-                                }
-                            }
-                        } else {
-                            None
-                        }
+                    let diffuse_tex = match material.pbr_metallic_roughness().base_color_texture() {
+                        Some(info) => Some(self.load_material_texture(
+                            renderer,
+                            &buffers,
+                            source_folder,
+                            asset_name,
+                            &info.texture(),
+                            &ImageFormatType::Default,
+                        )?),
+                        None => None,
                     };
-                    let normal_tex = {
-                        if let Some(info) = material.normal_texture() {
-                            match info.texture().source().source() {
-                                Source::View { mime_type: _, view } => {
-                                    Some(self.load_embedded_texture(
-                                        renderer,
-                                        &buffers,
-                                        &info.texture().source(),
-                                        &view,
-                                    )?)
-                                }
-                                Source::Uri { uri, .. } => {
-                                    let image_asset = String::from(source_folder) + "/" + uri;
-                                    Some(self.load_texture(
-                                        renderer,
-                                        &image_asset,
-                                        &ImageFormatType::Normal,
-                                    )?)
-                                }
-                            }
-                        } else {
-                            None
-                        }
+                    let normal_tex = match material.normal_texture() {
+                        Some(info) => Some(self.load_material_texture(
+                            renderer,
+                            &buffers,
+                            source_folder,
+                            asset_name,
+                            &info.texture(),
+                            &ImageFormatType::Normal,
+                        )?),
+                        None => None,
                     };
-                    let metallic_roughness_tex = {
-                        if let Some(info) = material
-                            .pbr_metallic_roughness()
-                            .metallic_roughness_texture()
-                        {
-                            match info.texture().source().source() {
-                                Source::View { mime_type: _, view } => {
-                                    Some(self.load_embedded_texture(
-                                        renderer,
-                                        &buffers,
-                                        &info.texture().source(),
-                                        &view,
-                                    )?)
-                                }
-                                Source::Uri { uri, .. } => {
-                                    let image_asset = String::from(source_folder) + "/" + uri;
-                                    Some(self.load_texture(
-                                        renderer,
-                                        &image_asset,
-                                        &ImageFormatType::Default,
-                                    )?)
-                                }
-                            }
-                        } else {
-                            None
-                        }
+                    // Metallic roughness and occlusion store linear data
+                    let metallic_roughness_tex = match material
+                        .pbr_metallic_roughness()
+                        .metallic_roughness_texture()
+                    {
+                        Some(info) => Some(self.load_material_texture(
+                            renderer,
+                            &buffers,
+                            source_folder,
+                            asset_name,
+                            &info.texture(),
+                            &ImageFormatType::Linear,
+                        )?),
+                        None => None,
                     };
-                    let occlusion_tex = {
-                        if let Some(occlusion) = material.occlusion_texture() {
-                            match occlusion.texture().source().source() {
-                                Source::View { mime_type: _, view } => {
-                                    Some(self.load_embedded_texture(
-                                        renderer,
-                                        &buffers,
-                                        &occlusion.texture().source(),
-                                        &view,
-                                    )?)
-                                }
-                                Source::Uri { uri, .. } => {
-                                    let image_asset = String::from(source_folder) + "/" + uri;
-                                    Some(self.load_texture(
-                                        renderer,
-                                        &image_asset,
-                                        &ImageFormatType::Default,
-                                    )?)
-                                }
-                            }
-                        } else {
-                            None
-                        }
+                    let occlusion_tex = match material.occlusion_texture() {
+                        Some(info) => Some(self.load_material_texture(
+                            renderer,
+                            &buffers,
+                            source_folder,
+                            asset_name,
+                            &info.texture(),
+                            &ImageFormatType::Linear,
+                        )?),
+                        None => None,
                     };
-                    let emissive_tex = {
-                        if let Some(emissive) = material.emissive_texture() {
-                            match emissive.texture().source().source() {
-                                Source::View { mime_type: _, view } => {
-                                    Some(self.load_embedded_texture(
-                                        renderer,
-                                        &buffers,
-                                        &emissive.texture().source(),
-                                        &view,
-                                    )?)
-                                }
-                                Source::Uri { uri, .. } => {
-                                    let image_asset = String::from(source_folder) + "/" + uri;
-                                    Some(self.load_texture(
-                                        renderer,
-                                        &image_asset,
-                                        &ImageFormatType::Default,
-                                    )?)
-                                }
-                            }
-                        } else {
-                            None
-                        }
+                    let emissive_tex = match material.emissive_texture() {
+                        Some(info) => Some(self.load_material_texture(
+                            renderer,
+                            &buffers,
+                            source_folder,
+                            asset_name,
+                            &info.texture(),
+                            &ImageFormatType::Default,
+                        )?),
+                        None => None,
                     };
 
                     let mut vertices = Vec::new();
                     for i in 0..positions.len() {
                         let position = *positions.get(i).unwrap();
-                        let tex_coords = *tex_coords.get(i).unwrap();
-                        let normal = *normals.get(i).unwrap();
+                        let tex_coords = tex_coords.get(i).copied().unwrap_or([0f32, 0f32]);
+                        let normal = normals.get(i).copied().unwrap_or([0f32, 0f32, 0f32]);
                         let tangent = {
                             if let Some(tang) = tangents.get(i) {
                                 *tang
@@ -306,13 +290,21 @@ impl AssetManager {
 
                     let faces = {
                         let mut faces = Vec::new();
-                        for i in 0..possible_indices.len() / 3 {
-                            let index = i * 3;
-                            faces.push([
-                                possible_indices[index],
-                                possible_indices[index + 1],
-                                possible_indices[index + 2],
-                            ]);
+                        if possible_indices.is_empty() {
+                            // Non indexed geometry, every three vertices make a triangle
+                            for i in 0..vertices.len() / 3 {
+                                let index = (i * 3) as u32;
+                                faces.push([index, index + 1, index + 2]);
+                            }
+                        } else {
+                            for i in 0..possible_indices.len() / 3 {
+                                let index = i * 3;
+                                faces.push([
+                                    possible_indices[index],
+                                    possible_indices[index + 1],
+                                    possible_indices[index + 2],
+                                ]);
+                            }
                         }
                         faces
                     };
@@ -330,6 +322,10 @@ impl AssetManager {
                         indices,
                         faces,
                     };
+                    // Tangents are generated from the normals, so those have to exist first
+                    if normals.is_empty() {
+                        mesh_data.recompute_normals();
+                    }
                     if tangents.is_empty() {
                         let _ret = mesh_data.generate_tangents();
                     }
@@ -343,8 +339,18 @@ impl AssetManager {
                         normal_texture: normal_tex,
                         metallic_roughness_texture: metallic_roughness_tex,
                         occlusion_texture: occlusion_tex,
+                        // glTF multiplies the base colour by COLOR_0 when it's present
+                        use_vertex_color: !colors.is_empty(),
+                        alpha_cutoff: match material.alpha_mode() {
+                            gltf::material::AlphaMode::Mask => {
+                                Some(material.alpha_cutoff().unwrap_or(0.5f32))
+                            }
+                            _ => None,
+                        },
+                        transparent: material.alpha_mode() == gltf::material::AlphaMode::Blend,
+                        ..Default::default()
                     };
-                    let material_instance = renderer.add_material_instance(material_instance);
+                    let material_instance = renderer.add_material_instance(material_instance)?;
 
                     let model = SubMesh {
                         mesh: mesh_handle,
@@ -402,6 +408,157 @@ impl AssetManager {
 
         Ok(models)
     }
+
+    /// Loads every model in an OBJ file, with a material instance for each made from its MTL file.
+    ///
+    /// Faces are triangulated, and normals are generated when the file has none.
+    /// Models without a material share a default material instance.
+    pub fn load_obj(
+        &mut self,
+        renderer: &mut Renderer,
+        file: impl AsRef<std::path::Path>,
+    ) -> Result<Vec<SubMesh>> {
+        profiling::scope!("Load OBJ Asset");
+        let file = path_to_str(file.as_ref())?;
+        let (source_folder, asset_name) = file.rsplit_once('/').unwrap_or((".", file));
+
+        let (models, materials) = tobj::load_obj(
+            file,
+            &tobj::LoadOptions {
+                single_index: true,
+                triangulate: true,
+                ..Default::default()
+            },
+        )?;
+        let materials = materials.unwrap_or_else(|err| {
+            warn!("Unable to load materials for {}: {}", asset_name, err);
+            Vec::new()
+        });
+
+        let mut material_instances = Vec::new();
+        for material in materials.iter() {
+            let diffuse_texture = if material.diffuse_texture.is_empty() {
+                None
+            } else {
+                let image_asset = String::from(source_folder) + "/" + &material.diffuse_texture;
+                Some(self.load_texture(renderer, &image_asset, &ImageFormatType::Default)?)
+            };
+            let normal_texture = if material.normal_texture.is_empty() {
+                None
+            } else {
+                let image_asset = String::from(source_folder) + "/" + &material.normal_texture;
+                Some(self.load_texture(renderer, &image_asset, &ImageFormatType::Normal)?)
+            };
+
+            let [r, g, b] = material.diffuse;
+            let material_instance = MaterialInstance {
+                diffuse: Vector4::new(r, g, b, material.dissolve),
+                diffuse_texture,
+                normal_texture,
+                ..Default::default()
+            };
+            material_instances.push(renderer.add_material_instance(material_instance)?);
+        }
+
+        let mut default_material = None;
+        let mut submeshes = Vec::new();
+        for model in models.iter() {
+            profiling::scope!("Load OBJ Asset: Model");
+            let mesh = &model.mesh;
+
+            let mut vertices = Vec::new();
+            for i in 0..mesh.positions.len() / 3 {
+                let position = [
+                    mesh.positions[i * 3],
+                    mesh.positions[i * 3 + 1],
+                    mesh.positions[i * 3 + 2],
+                ];
+                let normal = if mesh.normals.is_empty() {
+                    [0f32, 0f32, 0f32]
+                } else {
+                    [
+                        mesh.normals[i * 3],
+                        mesh.normals[i * 3 + 1],
+                        mesh.normals[i * 3 + 2],
+                    ]
+                };
+                // OBJ texture coordinates start from the bottom left
+                let tex_coords = if mesh.texcoords.is_empty() {
+                    [0f32, 0f32]
+                } else {
+                    [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+                };
+                let color = if mesh.vertex_color.is_empty() {
+                    [1f32, 1f32, 1f32]
+                } else {
+                    [
+                        mesh.vertex_color[i * 3],
+                        mesh.vertex_color[i * 3 + 1],
+                        mesh.vertex_color[i * 3 + 2],
+                    ]
+                };
+
+                vertices.push(Vertex {
+                    position,
+                    tex_coords,
+                    normal,
+                    color,
+                    tangent: [0f32, 0f32, 0f32, 0f32],
+                });
+            }
+
+            let faces = mesh
+                .indices
+                .chunks_exact(3)
+                .map(|face| [face[0], face[1], face[2]])
+                .collect();
+
+            let mut mesh_data = MeshData {
+                vertices,
+                indices: Some(mesh.indices.clone()),
+                faces,
+            };
+            if mesh.normals.is_empty() {
+                mesh_data.recompute_normals();
+            }
+            let _ret = mesh_data.generate_tangents();
+
+            let mesh_handle = renderer.load_mesh(&mesh_data)?;
+            let material_instance = match mesh.material_id.and_then(|id| material_instances.get(id))
+            {
+                Some(&material_instance) => material_instance,
+                None => match default_material {
+                    Some(material_instance) => material_instance,
+                    None => {
+                        let material_instance =
+                            renderer.add_material_instance(MaterialInstance::default())?;
+                        default_material = Some(material_instance);
+                        material_instance
+                    }
+                },
+            };
+
+            submeshes.push(SubMesh {
+                mesh: mesh_handle,
+                material_instance,
+            });
+        }
+
+        info!(
+            "Loaded OBJ Model. Name: [{}], Submeshes: [{}], Materials: [{}]",
+            asset_name,
+            submeshes.len(),
+            material_instances.len(),
+        );
+
+        Ok(submeshes)
+    }
+}
+
+/// Asset paths are used as texture keys and passed on as strings, so have to be valid UTF-8.
+fn path_to_str(path: &std::path::Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!("Asset path {} is not valid UTF-8", path.display()))
 }
 
 #[derive(Clone)]
@@ -1,11 +1,19 @@
+use std::collections::VecDeque;
 use std::time::Instant;
 
+/// Number of frame times kept for [FrameTimer::average_frame_time].
+const FRAME_HISTORY_SIZE: usize = 240;
+/// Weight given to the newest frame in the moving average used by [FrameTimer::smoothed_fps].
+const SMOOTHING_FACTOR: f32 = 0.1;
+
 pub struct FrameTimer {
     frame_start_time: Instant,
     frame_time: f32,
     delta_time: f32,
     target_frame_time: f32,
     total_time_elapsed: f32,
+    smoothed_frame_time: f32,
+    frame_history: VecDeque<f32>,
 }
 
 impl FrameTimer {
@@ -14,8 +22,47 @@ impl FrameTimer {
     }
 
     pub fn update(&mut self) {
-        self.frame_time = self.frame_start_time.elapsed().as_secs_f32();
+        let frame_time = self.frame_start_time.elapsed().as_secs_f32();
         self.frame_start_time = Instant::now();
+        self.update_with_frame_time(frame_time);
+    }
+
+    /// Starts the next frame as if the last one took `frame_time` seconds, instead of reading the
+    /// clock. Useful for replays and fixed step tools.
+    pub fn update_with_frame_time(&mut self, frame_time: f32) {
+        self.frame_time = frame_time;
+
+        if self.frame_history.is_empty() {
+            self.smoothed_frame_time = frame_time;
+        } else {
+            self.smoothed_frame_time += (frame_time - self.smoothed_frame_time) * SMOOTHING_FACTOR;
+        }
+
+        if self.frame_history.len() == FRAME_HISTORY_SIZE {
+            self.frame_history.pop_front();
+        }
+        self.frame_history.push_back(frame_time);
+    }
+
+    /// Frames per second from an exponential moving average of the frame time.
+    /// Steadier than the instantaneous value, so better suited for display.
+    pub fn smoothed_fps(&self) -> f32 {
+        if self.smoothed_frame_time > 0.0f32 {
+            1.0f32 / self.smoothed_frame_time
+        } else {
+            0.0f32
+        }
+    }
+
+    /// Mean frame time in seconds over the last `window` frames.
+    /// Uses every recorded frame if fewer than `window` have been recorded, up to 240.
+    pub fn average_frame_time(&self, window: usize) -> f32 {
+        let count = window.min(self.frame_history.len());
+        if count == 0 {
+            return 0.0f32;
+        }
+
+        self.frame_history.iter().rev().take(count).sum::<f32>() / count as f32
     }
 
     pub fn sub_frame_update(&mut self) -> bool {
@@ -49,6 +96,84 @@ impl Default for FrameTimer {
             delta_time: 0.0,
             target_frame_time: 1.0 / 120.0,
             total_time_elapsed: 0.0,
+            smoothed_frame_time: 0.0,
+            frame_history: VecDeque::with_capacity(FRAME_HISTORY_SIZE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn nothing_recorded_reads_as_zero() {
+        let timer = FrameTimer::new();
+        assert_eq!(timer.smoothed_fps(), 0.0);
+        assert_eq!(timer.average_frame_time(10), 0.0);
+    }
+
+    #[test]
+    fn average_frame_time_covers_the_most_recent_frames() {
+        let mut timer = FrameTimer::new();
+        for frame_time in [0.01, 0.02, 0.03, 0.04] {
+            timer.update_with_frame_time(frame_time);
+        }
+
+        assert_close(timer.average_frame_time(1), 0.04);
+        assert_close(timer.average_frame_time(2), 0.035);
+        // Fewer frames than the window averages every recorded frame
+        assert_close(timer.average_frame_time(10), 0.025);
+        assert_eq!(timer.average_frame_time(0), 0.0);
+    }
+
+    #[test]
+    fn average_frame_time_forgets_frames_past_the_history() {
+        let mut timer = FrameTimer::new();
+        for _ in 0..FRAME_HISTORY_SIZE {
+            timer.update_with_frame_time(0.01);
+        }
+        for _ in 0..FRAME_HISTORY_SIZE {
+            timer.update_with_frame_time(0.02);
+        }
+
+        assert_close(timer.average_frame_time(FRAME_HISTORY_SIZE), 0.02);
+        assert_close(timer.average_frame_time(FRAME_HISTORY_SIZE * 2), 0.02);
+    }
+
+    #[test]
+    fn smoothed_fps_starts_at_the_first_frame() {
+        let mut timer = FrameTimer::new();
+        timer.update_with_frame_time(0.02);
+        assert_close(timer.smoothed_fps(), 50.0);
+    }
+
+    #[test]
+    fn smoothed_fps_moves_a_tenth_of_the_way_each_frame() {
+        let mut timer = FrameTimer::new();
+        timer.update_with_frame_time(0.02);
+        timer.update_with_frame_time(0.04);
+
+        // 0.02 + (0.04 - 0.02) * 0.1
+        assert_close(timer.smoothed_fps(), 1.0 / 0.022);
+    }
+
+    #[test]
+    fn smoothed_fps_settles_on_a_steady_frame_rate() {
+        let mut timer = FrameTimer::new();
+        timer.update_with_frame_time(0.01);
+        for _ in 0..200 {
+            timer.update_with_frame_time(0.04);
         }
+        assert_close(timer.smoothed_fps(), 25.0);
     }
 }
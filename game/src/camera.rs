@@ -1,5 +1,11 @@
-use cgmath::{Deg, EuclideanSpace, Euler, Matrix4, Point3, Quaternion, Rotation3, Vector3};
+use cgmath::{
+    Deg, EuclideanSpace, Euler, InnerSpace, Matrix4, Point3, Quaternion, Rotation3, Vector3, Zero,
+};
+use winit::event::{MouseButton, VirtualKeyCode};
 
+use crate::input::Input;
+
+#[derive(Copy, Clone)]
 pub struct Camera {
     pub position: Point3<f32>,
     pub rotation: Vector3<f32>,
@@ -32,3 +38,270 @@ impl jb_gfx::CameraTrait for Camera {
         self.position
     }
 }
+
+/// Fly-through camera for testing scenes. WASD moves, Q and E move down and up, shift sprints,
+/// and holding the right mouse button looks around.
+pub struct FreeCamera {
+    pub position: Point3<f32>,
+    /// Degrees turned right from looking down -z.
+    pub yaw: f32,
+    /// Degrees above the horizon.
+    pub pitch: f32,
+    pub aspect: f32,
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    /// Units per second.
+    pub move_speed: f32,
+    pub sprint_multiplier: f32,
+    /// Degrees turned per pixel the mouse moves.
+    pub sensitivity: f32,
+    /// Seconds for movement and looking to catch up with input. 0 disables smoothing.
+    pub smoothing: f32,
+    velocity: Vector3<f32>,
+    target_yaw: f32,
+    target_pitch: f32,
+    last_mouse_pos: Option<(f32, f32)>,
+}
+
+impl FreeCamera {
+    pub fn new(position: Point3<f32>, aspect: f32) -> Self {
+        Self {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            aspect,
+            fovy: 90.0,
+            znear: 0.1,
+            zfar: 4000.0,
+            move_speed: 10.0,
+            sprint_multiplier: 4.0,
+            sensitivity: 0.2,
+            smoothing: 0.1,
+            velocity: Vector3::zero(),
+            target_yaw: 0.0,
+            target_pitch: 0.0,
+            last_mouse_pos: None,
+        }
+    }
+
+    /// Moves the camera from this frame's input. Pass `ui_has_focus` from
+    /// [crate::egui_context::EguiContext::wants_input] so typing into the UI doesn't move it.
+    pub fn update(&mut self, input: &Input, ui_has_focus: bool, delta_time: f32) {
+        let accepts_input = !ui_has_focus;
+
+        let mouse_pos = input.get_mouse_pos();
+        if accepts_input && input.is_mouse_down(MouseButton::Right) {
+            if let Some(last_mouse_pos) = self.last_mouse_pos {
+                self.target_yaw += (mouse_pos.0 - last_mouse_pos.0) * self.sensitivity;
+                self.target_pitch -= (mouse_pos.1 - last_mouse_pos.1) * self.sensitivity;
+                self.target_pitch = self.target_pitch.clamp(-89.0, 89.0);
+            }
+            self.last_mouse_pos = Some(mouse_pos);
+        } else {
+            self.last_mouse_pos = None;
+        }
+
+        let mut direction = Vector3::zero();
+        if accepts_input {
+            let forward = self.forward();
+            let right = forward.cross(Vector3::unit_y()).normalize();
+            let key_axis = |positive: VirtualKeyCode, negative: VirtualKeyCode| -> f32 {
+                input.is_down(positive) as i32 as f32 - input.is_down(negative) as i32 as f32
+            };
+            direction += forward * key_axis(VirtualKeyCode::W, VirtualKeyCode::S);
+            direction += right * key_axis(VirtualKeyCode::D, VirtualKeyCode::A);
+            direction += Vector3::unit_y() * key_axis(VirtualKeyCode::E, VirtualKeyCode::Q);
+        }
+        let mut target_velocity = Vector3::zero();
+        if direction.magnitude2() > 0.0 {
+            let mut speed = self.move_speed;
+            if input.is_down(VirtualKeyCode::LShift) {
+                speed *= self.sprint_multiplier;
+            }
+            target_velocity = direction.normalize() * speed;
+        }
+
+        self.velocity = damp_vector(self.velocity, target_velocity, self.smoothing, delta_time);
+        self.yaw = damp(self.yaw, self.target_yaw, self.smoothing, delta_time);
+        self.pitch = damp(self.pitch, self.target_pitch, self.smoothing, delta_time);
+        self.position += self.velocity * delta_time;
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        Vector3::new(
+            yaw.sin() * pitch.cos(),
+            pitch.sin(),
+            -yaw.cos() * pitch.cos(),
+        )
+    }
+}
+
+impl jb_gfx::CameraTrait for FreeCamera {
+    fn build_projection_matrix(&self) -> Matrix4<f32> {
+        cgmath::perspective(Deg(self.fovy), self.aspect, self.znear, self.zfar)
+    }
+
+    fn build_view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.position, self.forward(), Vector3::unit_y())
+    }
+
+    fn position(&self) -> Point3<f32> {
+        self.position
+    }
+}
+
+/// Fraction of the way to move towards a target this frame, for exponential smoothing that eases
+/// the same at any frame rate. `smoothing` is roughly the seconds taken to catch up, 0 snaps
+/// straight to the target.
+fn damp_blend(smoothing: f32, delta_time: f32) -> f32 {
+    if smoothing > 0.0 {
+        1.0 - (-delta_time / smoothing).exp()
+    } else {
+        1.0
+    }
+}
+
+/// Moves `current` towards `target`, see [damp_blend] for `smoothing`.
+pub fn damp(current: f32, target: f32, smoothing: f32, delta_time: f32) -> f32 {
+    current + (target - current) * damp_blend(smoothing, delta_time)
+}
+
+/// Like [damp], for angles in degrees. Turns the shortest way round.
+pub fn damp_angle(current: f32, target: f32, smoothing: f32, delta_time: f32) -> f32 {
+    let difference = (target - current + 180.0).rem_euclid(360.0) - 180.0;
+    current + difference * damp_blend(smoothing, delta_time)
+}
+
+pub fn damp_vector(
+    current: Vector3<f32>,
+    target: Vector3<f32>,
+    smoothing: f32,
+    delta_time: f32,
+) -> Vector3<f32> {
+    current + (target - current) * damp_blend(smoothing, delta_time)
+}
+
+/// Like [damp_angle] for each of a camera's euler angles.
+pub fn damp_rotation(
+    current: Vector3<f32>,
+    target: Vector3<f32>,
+    smoothing: f32,
+    delta_time: f32,
+) -> Vector3<f32> {
+    Vector3::new(
+        damp_angle(current.x, target.x, smoothing, delta_time),
+        damp_angle(current.y, target.y, smoothing, delta_time),
+        damp_angle(current.z, target.z, smoothing, delta_time),
+    )
+}
+
+/// Shakes a camera with smooth noise that dies down over the shake's duration.
+pub struct CameraShake {
+    /// Furthest the camera moves at full intensity, in units.
+    pub max_offset: f32,
+    /// Furthest the camera turns at full intensity, in degrees.
+    pub max_angle: f32,
+    /// How many times a second the shake changes direction.
+    pub frequency: f32,
+    intensity: f32,
+    duration: f32,
+    remaining: f32,
+    time: f32,
+}
+
+impl CameraShake {
+    pub fn new() -> Self {
+        Self {
+            max_offset: 0.25,
+            max_angle: 2.0,
+            frequency: 15.0,
+            intensity: 0.0,
+            duration: 0.0,
+            remaining: 0.0,
+            time: 0.0,
+        }
+    }
+
+    /// Starts shaking at `intensity`, usually from 0 to 1, fading out over `duration` seconds.
+    /// A weaker shake doesn't interrupt a stronger one that's still going.
+    pub fn shake(&mut self, intensity: f32, duration: f32) {
+        if duration <= 0.0 || intensity < self.strength() {
+            return;
+        }
+        self.intensity = intensity;
+        self.duration = duration;
+        self.remaining = duration;
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.time += delta_time;
+        self.remaining = (self.remaining - delta_time).max(0.0);
+    }
+
+    pub fn is_shaking(&self) -> bool {
+        self.remaining > 0.0
+    }
+
+    /// Current intensity. Squared as it fades, so the tail of a shake settles quickly.
+    fn strength(&self) -> f32 {
+        if self.remaining <= 0.0 {
+            return 0.0;
+        }
+        let fade = self.remaining / self.duration;
+        self.intensity * fade * fade
+    }
+
+    /// Position offset and euler rotation in degrees to add to the camera this frame.
+    pub fn offset(&self) -> (Vector3<f32>, Vector3<f32>) {
+        let strength = self.strength();
+        if strength <= 0.0 {
+            return (Vector3::zero(), Vector3::zero());
+        }
+
+        // Each axis samples its own stretch of noise
+        let t = self.time * self.frequency;
+        let noise = |seed: u32| smooth_noise(t, seed) * strength;
+        let position = Vector3::new(noise(0), noise(1), noise(2)) * self.max_offset;
+        let rotation = Vector3::new(noise(3), noise(4), noise(5)) * self.max_angle;
+        (position, rotation)
+    }
+
+    /// Returns `camera` moved by [CameraShake::offset], ready for
+    /// [jb_gfx::renderer::Renderer::set_camera].
+    pub fn apply(&self, camera: &Camera) -> Camera {
+        let (position, rotation) = self.offset();
+        Camera {
+            position: camera.position + position,
+            rotation: camera.rotation + rotation,
+            ..*camera
+        }
+    }
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Value noise from -1 to 1, smoothly interpolated between random values at whole numbers.
+fn smooth_noise(t: f32, seed: u32) -> f32 {
+    let lattice = |i: i32| -> f32 {
+        let mut x = (i as u32).wrapping_mul(0x9e3779b9) ^ seed.wrapping_mul(0x85ebca6b);
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x7feb352d);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x846ca68b);
+        x ^= x >> 16;
+        x as f32 / u32::MAX as f32 * 2.0 - 1.0
+    };
+
+    let i = t.floor();
+    let fraction = t - i;
+    let smoothed = fraction * fraction * (3.0 - 2.0 * fraction);
+    let a = lattice(i as i32);
+    let b = lattice(i as i32 + 1);
+    a + (b - a) * smoothed
+}
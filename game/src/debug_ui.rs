@@ -38,6 +38,36 @@ pub fn draw_timestamps(ui: &mut egui::Ui, timestamps: jb_gfx::renderer::TimeStam
     });
 }
 
+pub fn draw_memory_stats(ui: &mut egui::Ui, stats: jb_gfx::resource::MemoryStats) {
+    let to_mb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+    ui.horizontal(|ui| {
+        ui.label("Buffers:");
+        ui.label(format!(
+            "{:.2} MB ({})",
+            to_mb(stats.buffer_bytes),
+            stats.buffer_count
+        ));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Images:");
+        ui.label(format!(
+            "{:.2} MB ({})",
+            to_mb(stats.image_bytes),
+            stats.image_count
+        ));
+    });
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label("Total:");
+        ui.label(format!(
+            "{:.2} MB ({} allocations)",
+            to_mb(stats.total_bytes),
+            stats.allocation_count
+        ));
+    });
+}
+
 pub trait DebugPanel {
     fn draw_debug(&mut self, ui: &mut egui::Ui);
 }
@@ -25,6 +25,11 @@ impl EguiContext {
         }
     }
 
+    /// Whether the UI is being interacted with, so game input should be ignored.
+    pub fn wants_input(&self) -> bool {
+        self.egui_ctx.wants_pointer_input() || self.egui_ctx.wants_keyboard_input()
+    }
+
     pub fn run(&mut self, window: &winit::window::Window, run_ui: impl FnOnce(&Context)) {
         let raw_input = self.egui_winit.take_egui_input(window);
         self.last_output = Some(self.egui_ctx.run(raw_input, run_ui));
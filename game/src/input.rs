@@ -1,12 +1,19 @@
-use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
 
 pub struct Input {
     pub now_keys: [bool; 255],
     pub prev_keys: [bool; 255],
     pub(crate) mouse_pos: (f32, f32),
+    /// Left, right and middle.
+    pub(crate) mouse_buttons: [bool; 3],
 }
 
 impl Input {
+    /// Whether the key is down, including the frame it was pressed.
+    pub fn is_down(&self, key: VirtualKeyCode) -> bool {
+        self.now_keys[key as usize]
+    }
+
     pub fn is_just_pressed(&self, key: VirtualKeyCode) -> bool {
         self.now_keys[key as usize] && !self.prev_keys[key as usize]
     }
@@ -22,6 +29,15 @@ impl Input {
     pub fn get_mouse_pos(&self) -> (f32, f32) {
         self.mouse_pos
     }
+
+    pub fn is_mouse_down(&self, button: MouseButton) -> bool {
+        match button {
+            MouseButton::Left => self.mouse_buttons[0],
+            MouseButton::Right => self.mouse_buttons[1],
+            MouseButton::Middle => self.mouse_buttons[2],
+            MouseButton::Other(_) => false,
+        }
+    }
 }
 
 impl Default for Input {
@@ -30,6 +46,7 @@ impl Default for Input {
             now_keys: [false; 255],
             prev_keys: [false; 255],
             mouse_pos: (0.0, 0.0),
+            mouse_buttons: [false; 3],
         }
     }
 }
@@ -61,6 +78,20 @@ impl Input {
                     self.now_keys[*keycode as usize] = false;
                 }
             },
+            WindowEvent::MouseInput { state, button, .. } => {
+                let index = match button {
+                    MouseButton::Left => 0,
+                    MouseButton::Right => 1,
+                    MouseButton::Middle => 2,
+                    MouseButton::Other(_) => return,
+                };
+                self.mouse_buttons[index] = *state == ElementState::Pressed;
+            }
+            WindowEvent::Focused(false) => {
+                // Releases are missed while unfocused
+                self.now_keys = [false; 255];
+                self.mouse_buttons = [false; 3];
+            }
             _ => {}
         }
     }
@@ -19,17 +19,17 @@ use winit::event_loop::EventLoop;
 use winit::window::Window;
 
 use engine::prelude::*;
-use jb_gfx::particle::{ParticleSystem, ParticleSystemState, VectorParameter};
+use jb_gfx::particle::{ParticleSystem, ParticleSystemDesc, VectorParameter};
 use jb_gfx::prelude::*;
 use jb_gfx::renderer::{MaterialInstanceHandle, ParticleSystemHandle, RenderModelHandle};
 
 use crate::collision::CollisionBox;
 use crate::components::LightComponent;
-use crate::debug_ui::{draw_timestamps, DebugPanel};
+use crate::debug_ui::{draw_memory_stats, draw_timestamps, DebugPanel};
 use crate::egui_context::EguiContext;
 use crate::input::Input;
 use crate::turret_game::player::Player;
-use crate::Camera;
+use crate::{Camera, CameraShake, FreeCamera};
 
 pub mod player;
 
@@ -59,6 +59,9 @@ pub struct TurretGame {
     terrain_settings: TerrainSettings,
     particle_systems: Vec<ParticleSystemHandle>,
     gun: Gun,
+    /// Replaces the player's view while debugging, toggled with F4.
+    free_camera: Option<FreeCamera>,
+    camera_shake: CameraShake,
 }
 
 struct Bullet {
@@ -223,16 +226,16 @@ impl TurretGame {
             diffuse: Vector4::new(1.0f32, 1.0f32, 1.0f32, 1.0f32),
             diffuse_texture: Some(grass_texture),
             ..Default::default()
-        });
+        }).unwrap();
         let bullet_material = renderer.add_material_instance(MaterialInstance {
             diffuse: Vector4::new(0.4f32, 0.4f32, 0.4f32, 1.0f32),
             ..Default::default()
-        });
+        }).unwrap();
         let bullet_tracer_material = renderer.add_material_instance(MaterialInstance {
             diffuse: Vector4::new(0.0f32, 0.0f32, 0.0f32, 1.0f32),
             emissive: Vector3::new(2.0f32, 2.0f32, 0.0f32),
             ..Default::default()
-        });
+        }).unwrap();
 
         let terrain_settings = TerrainSettings {
             tile_width: 5,
@@ -320,33 +323,37 @@ impl TurretGame {
             )
             .unwrap();
 
-        let mut particle_system = ParticleSystem::new(500);
-        particle_system.set_state(ParticleSystemState::Running);
-        particle_system.spawn_position = Vector3::new(-1.5, -1.5, 8.7);
-        particle_system.velocity = VectorParameter::Random {
-            min: [8.0, -0.1, -0.1].into(),
-            max: [8.0, 0.1, 0.1].into(),
-        };
-        particle_system.spawn_rate = 60.0;
-        particle_system.initial_colour = [0.8, 0.8, 0.8, 0.8].into();
-        particle_system.texture = Some(smoke_texture);
-        particle_system.scale = 0.5;
-
-        let system_one = renderer.add_particle_system(particle_system);
-
-        let mut particle_system = ParticleSystem::new(500);
-        particle_system.set_state(ParticleSystemState::Running);
-        particle_system.spawn_position = Vector3::new(-1.5, -1.5, -8.7);
-        particle_system.velocity = VectorParameter::Random {
-            min: [8.0, -0.1, -0.1].into(),
-            max: [8.0, 0.1, 0.1].into(),
+        let engine_smoke = ParticleSystemDesc {
+            particle_limit: 500,
+            emission_rate: 60.0,
+            lifetime: 1.6,
+            velocity: VectorParameter::Random {
+                min: [8.0, -0.1, -0.1].into(),
+                max: [8.0, 0.1, 0.1].into(),
+            },
+            start_colour: [0.8, 0.8, 0.8, 0.8].into(),
+            end_colour: [0.8, 0.8, 0.8, 0.0].into(),
+            start_size: 0.5,
+            end_size: 0.5,
+            texture: Some(smoke_texture),
+            ..Default::default()
         };
-        particle_system.spawn_rate = 60.0;
-        particle_system.initial_colour = [0.8, 0.8, 0.8, 0.8].into();
-        particle_system.texture = Some(smoke_texture);
-        particle_system.scale = 0.5;
 
-        let system_two = renderer.add_particle_system(particle_system);
+        let system_one = renderer.create_particle_system(engine_smoke).unwrap();
+        renderer
+            .set_particle_system_transform(
+                system_one,
+                Matrix4::from_translation(Vector3::new(-1.5, -1.5, 8.7)),
+            )
+            .unwrap();
+
+        let system_two = renderer.create_particle_system(engine_smoke).unwrap();
+        renderer
+            .set_particle_system_transform(
+                system_two,
+                Matrix4::from_translation(Vector3::new(-1.5, -1.5, -8.7)),
+            )
+            .unwrap();
 
         Self {
             window,
@@ -373,6 +380,8 @@ impl TurretGame {
             terrain_settings,
             particle_systems: vec![system_one, system_two],
             gun,
+            free_camera: None,
+            camera_shake: CameraShake::new(),
         }
     }
 
@@ -383,7 +392,23 @@ impl TurretGame {
         if self.input.is_just_pressed(VirtualKeyCode::F2) {
             self.renderer.reload_shaders().unwrap();
         }
-        self.handle_player_input();
+        if self.input.is_just_pressed(VirtualKeyCode::F3) {
+            self.renderer.draw_shadow_map_debug = !self.renderer.draw_shadow_map_debug
+        }
+        if self.input.is_just_pressed(VirtualKeyCode::F4) {
+            self.free_camera = match self.free_camera {
+                Some(_) => None,
+                None => Some(FreeCamera::new(
+                    self.player.camera.position,
+                    self.player.camera.aspect,
+                )),
+            };
+        }
+        if let Some(free_camera) = &mut self.free_camera {
+            free_camera.update(&self.input, self.egui.wants_input(), self.delta_time);
+        } else {
+            self.handle_player_input();
+        }
 
         let plane_movement_speed = 50.0f32;
 
@@ -422,6 +447,9 @@ impl TurretGame {
                     }
                 }
             }
+            if !destroy_barrels.is_empty() {
+                self.camera_shake.shake(1.0, 0.5);
+            }
             for &i in destroy_barrels.iter() {
                 let removed_barrel = self.barrels.remove(i);
                 self.renderer
@@ -457,7 +485,13 @@ impl TurretGame {
 
         // Update render objects & then render
         self.update_renderer_object_states();
-        self.renderer.set_camera(&self.player.camera);
+        self.camera_shake.update(self.delta_time);
+        if let Some(free_camera) = &self.free_camera {
+            self.renderer.set_camera(free_camera);
+        } else {
+            self.renderer
+                .set_camera(&self.camera_shake.apply(&self.player.camera));
+        }
     }
 
     fn handle_player_input(&mut self) {
@@ -473,6 +507,7 @@ impl TurretGame {
             && self.player.time_since_fired >= 1.0f32 / self.player.rate_of_fire
         {
             self.player.time_since_fired = 0.0f32;
+            self.camera_shake.shake(0.2, 0.1);
             let tracer = {
                 if self.player.bullets_since_last_tracer >= self.player.tracer_bullet_rate {
                     self.player.bullets_since_last_tracer = 0;
@@ -686,6 +721,13 @@ impl TurretGame {
                         let timestamps = self.renderer.timestamps();
                         draw_timestamps(ui, timestamps);
                     });
+                egui::Window::new("GPU Memory")
+                    .vscroll(false)
+                    .resizable(false)
+                    .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+                    .show(ctx, |ui| {
+                        draw_memory_stats(ui, self.renderer.memory_stats());
+                    });
             });
             self.egui.paint(&mut self.renderer);
         }
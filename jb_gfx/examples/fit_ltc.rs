@@ -0,0 +1,356 @@
+//! Fits the GGX lookup tables used to shade area lights with linearly transformed cosines, as
+//! described in "Real-Time Polygonal-Light Shading with Linearly Transformed Cosines" (Heitz et
+//! al. 2016), and writes them to `assets/textures/ltc`.
+//!
+//! Run from the workspace root with `cargo run --release -p jb_gfx --example fit_ltc`.
+
+use std::f64::consts::PI;
+
+use anyhow::Result;
+use cgmath::{InnerSpace, Matrix3, SquareMatrix, Vector3};
+use image::{DynamicImage, Rgba, Rgba32FImage};
+
+/// Width and height of the tables. Must match LTC_LUT_SIZE in area_light.glsl
+const SIZE: usize = 64;
+/// Samples taken along each axis when integrating the BRDF and the fitting error.
+const SAMPLES: usize = 32;
+/// Roughness is clamped to this so the lobe never collapses to a single direction.
+const MIN_ALPHA: f64 = 0.00001;
+const MATRIX_PATH: &str = "assets/textures/ltc/ltc_1.exr";
+const AMPLITUDE_PATH: &str = "assets/textures/ltc/ltc_2.exr";
+
+fn main() -> Result<()> {
+    let mut matrices = vec![Matrix3::identity(); SIZE * SIZE];
+    let mut amplitudes = vec![(0.0, 0.0); SIZE * SIZE];
+
+    // Each fit starts from a neighbouring one, from rough to smooth and from facing the view to
+    // grazing
+    let mut ltc = Ltc::default();
+    for a in (0..SIZE).rev() {
+        let roughness = a as f64 / (SIZE - 1) as f64;
+        let alpha = (roughness * roughness).max(MIN_ALPHA);
+
+        for t in 0..SIZE {
+            let x = t as f64 / (SIZE - 1) as f64;
+            let theta = (1.0 - x * x).acos().min(1.57);
+            let view = Vector3::new(theta.sin(), 0.0, theta.cos());
+
+            let (magnitude, fresnel, average_direction) = average_terms(view, alpha);
+            ltc.magnitude = magnitude;
+
+            // Looking straight down the normal the lobe is rotationally symmetric around it
+            let isotropic = t == 0;
+            if isotropic {
+                ltc.basis = Matrix3::identity();
+                if a == SIZE - 1 {
+                    ltc.m11 = 1.0;
+                    ltc.m22 = 1.0;
+                } else {
+                    let previous = matrices[a + 1];
+                    ltc.m11 = previous.x.x;
+                    ltc.m22 = previous.y.y;
+                }
+                ltc.m13 = 0.0;
+            } else {
+                let l = average_direction;
+                ltc.basis = Matrix3::from_cols(Vector3::new(l.z, 0.0, -l.x), Vector3::unit_y(), l);
+            }
+            ltc.update();
+
+            fit(&mut ltc, view, alpha, isotropic);
+
+            let mut matrix = ltc.m;
+            // Only the xz plane is transformed, clear anything fitting noise left in the rest
+            matrix.x.y = 0.0;
+            matrix.y.x = 0.0;
+            matrix.z.y = 0.0;
+            matrix.y.z = 0.0;
+            matrices[a + t * SIZE] = matrix;
+            amplitudes[a + t * SIZE] = (magnitude, fresnel);
+        }
+    }
+
+    let mut matrix_table = Rgba32FImage::new(SIZE as u32, SIZE as u32);
+    let mut amplitude_table = Rgba32FImage::new(SIZE as u32, SIZE as u32);
+    for t in 0..SIZE {
+        for a in 0..SIZE {
+            // Only the direction of transformed vectors matters, so the inverse is scaled to keep
+            // the values in half float range
+            let inverse = matrices[a + t * SIZE].invert().unwrap();
+            let inverse = inverse / inverse.y.y;
+            matrix_table.put_pixel(
+                a as u32,
+                t as u32,
+                Rgba([
+                    inverse.x.x as f32,
+                    inverse.x.z as f32,
+                    inverse.z.x as f32,
+                    inverse.z.z as f32,
+                ]),
+            );
+
+            let (magnitude, fresnel) = amplitudes[a + t * SIZE];
+            amplitude_table.put_pixel(
+                a as u32,
+                t as u32,
+                Rgba([magnitude as f32, fresnel as f32, 0.0, 0.0]),
+            );
+        }
+    }
+
+    DynamicImage::ImageRgba32F(matrix_table).save(MATRIX_PATH)?;
+    DynamicImage::ImageRgba32F(amplitude_table).save(AMPLITUDE_PATH)?;
+    Ok(())
+}
+
+/// A clamped cosine lobe transformed by `m`, the product of `basis` and the fitted scale and skew.
+struct Ltc {
+    magnitude: f64,
+    m11: f64,
+    m22: f64,
+    m13: f64,
+    basis: Matrix3<f64>,
+    m: Matrix3<f64>,
+    inverse: Matrix3<f64>,
+    determinant: f64,
+}
+
+impl Default for Ltc {
+    fn default() -> Self {
+        let mut ltc = Self {
+            magnitude: 1.0,
+            m11: 1.0,
+            m22: 1.0,
+            m13: 0.0,
+            basis: Matrix3::identity(),
+            m: Matrix3::identity(),
+            inverse: Matrix3::identity(),
+            determinant: 1.0,
+        };
+        ltc.update();
+        ltc
+    }
+}
+
+impl Ltc {
+    fn update(&mut self) {
+        self.m = self.basis
+            * Matrix3::from_cols(
+                Vector3::new(self.m11, 0.0, 0.0),
+                Vector3::new(0.0, self.m22, 0.0),
+                Vector3::new(self.m13, 0.0, 1.0),
+            );
+        self.inverse = self.m.invert().unwrap();
+        self.determinant = self.m.determinant().abs();
+    }
+
+    /// Value of the lobe in direction `l`, scaled by its magnitude.
+    fn eval(&self, l: Vector3<f64>) -> f64 {
+        let original = (self.inverse * l).normalize();
+        let transformed = self.m * original;
+        let length = transformed.magnitude();
+        let jacobian = self.determinant / (length * length * length);
+        let cosine = original.z.max(0.0) / PI;
+        self.magnitude * cosine / jacobian
+    }
+
+    fn sample(&self, u1: f64, u2: f64) -> Vector3<f64> {
+        let theta = u1.sqrt().acos();
+        let phi = 2.0 * PI * u2;
+        let direction = Vector3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        );
+        (self.m * direction).normalize()
+    }
+}
+
+/// Smith G2 height correlated masking and shadowing Lambda for GGX.
+fn ggx_lambda(alpha: f64, cos_theta: f64) -> f64 {
+    if cos_theta >= 1.0 {
+        return 0.0;
+    }
+    let a = 1.0 / alpha / cos_theta.acos().tan();
+    0.5 * (-1.0 + (1.0 + 1.0 / (a * a)).sqrt())
+}
+
+/// GGX BRDF times the cosine of the light direction, without fresnel, along with the pdf of
+/// sampling `l` with [ggx_sample].
+fn ggx_eval(view: Vector3<f64>, l: Vector3<f64>, alpha: f64) -> (f64, f64) {
+    if view.z <= 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let lambda_view = ggx_lambda(alpha, view.z);
+    let g2 = if l.z <= 0.0 {
+        0.0
+    } else {
+        1.0 / (1.0 + lambda_view + ggx_lambda(alpha, l.z))
+    };
+
+    let h = (view + l).normalize();
+    let slope_x = h.x / h.z;
+    let slope_y = h.y / h.z;
+    let d = 1.0 / (1.0 + (slope_x * slope_x + slope_y * slope_y) / (alpha * alpha));
+    let d = d * d / (PI * alpha * alpha * h.z * h.z * h.z * h.z);
+
+    let pdf = (d * h.z / 4.0 / view.dot(h)).abs();
+    (d * g2 / 4.0 / view.z, pdf)
+}
+
+/// Reflects the view around a sampled GGX normal.
+fn ggx_sample(view: Vector3<f64>, alpha: f64, u1: f64, u2: f64) -> Vector3<f64> {
+    let phi = 2.0 * PI * u1;
+    let r = alpha * (u2 / (1.0 - u2)).sqrt();
+    let n = Vector3::new(r * phi.cos(), r * phi.sin(), 1.0).normalize();
+    -view + n * 2.0 * n.dot(view)
+}
+
+fn sample_points() -> impl Iterator<Item = (f64, f64)> {
+    (0..SAMPLES * SAMPLES).map(|i| {
+        (
+            ((i % SAMPLES) as f64 + 0.5) / SAMPLES as f64,
+            ((i / SAMPLES) as f64 + 0.5) / SAMPLES as f64,
+        )
+    })
+}
+
+/// Integrals of the BRDF over the hemisphere, without and with the Schlick fresnel weight, and
+/// the average direction light is reflected in.
+fn average_terms(view: Vector3<f64>, alpha: f64) -> (f64, f64, Vector3<f64>) {
+    let mut magnitude = 0.0;
+    let mut fresnel = 0.0;
+    let mut direction = Vector3::new(0.0, 0.0, 0.0);
+
+    for (u1, u2) in sample_points() {
+        let l = ggx_sample(view, alpha, u1, u2);
+        let (value, pdf) = ggx_eval(view, l, alpha);
+        if pdf > 0.0 {
+            let weight = value / pdf;
+            let h = (view + l).normalize();
+            magnitude += weight;
+            fresnel += weight * (1.0 - view.dot(h).max(0.0)).powi(5);
+            direction += l * weight;
+        }
+    }
+
+    let count = (SAMPLES * SAMPLES) as f64;
+    // Isotropic lobes are symmetric about the xz plane
+    direction.y = 0.0;
+    (magnitude / count, fresnel / count, direction.normalize())
+}
+
+/// Cubed difference between the lobe and the BRDF, sampled from both with multiple importance
+/// sampling.
+fn fit_error(ltc: &Ltc, view: Vector3<f64>, alpha: f64) -> f64 {
+    let mut error = 0.0;
+    for (u1, u2) in sample_points() {
+        for l in [ltc.sample(u1, u2), ggx_sample(view, alpha, u1, u2)] {
+            let (brdf, brdf_pdf) = ggx_eval(view, l, alpha);
+            let lobe = ltc.eval(l);
+            let lobe_pdf = lobe / ltc.magnitude;
+            let difference = (brdf - lobe).abs();
+            error += difference * difference * difference / (lobe_pdf + brdf_pdf);
+        }
+    }
+    error / (SAMPLES * SAMPLES) as f64
+}
+
+/// Adjusts the scale and skew of the lobe to best match the BRDF seen from `view`.
+fn fit(ltc: &mut Ltc, view: Vector3<f64>, alpha: f64, isotropic: bool) {
+    let apply = |ltc: &mut Ltc, params: [f64; 3]| {
+        ltc.m11 = params[0].max(1e-7);
+        if isotropic {
+            ltc.m22 = ltc.m11;
+            ltc.m13 = 0.0;
+        } else {
+            ltc.m22 = params[1].max(1e-7);
+            ltc.m13 = params[2];
+        }
+        ltc.update();
+    };
+
+    let start = [ltc.m11, ltc.m22, ltc.m13];
+    let best = nelder_mead(start, 0.05, 1e-5, 100, |params| {
+        apply(ltc, params);
+        fit_error(ltc, view, alpha)
+    });
+    apply(ltc, best);
+}
+
+/// Minimises `objective` starting from a simplex around `start` with sides of `delta`.
+fn nelder_mead(
+    start: [f64; 3],
+    delta: f64,
+    tolerance: f64,
+    max_iterations: usize,
+    mut objective: impl FnMut([f64; 3]) -> f64,
+) -> [f64; 3] {
+    const POINTS: usize = 4;
+
+    let mut simplex = [start; POINTS];
+    for (i, point) in simplex.iter_mut().enumerate().skip(1) {
+        point[i - 1] += delta;
+    }
+    let mut values = simplex.map(&mut objective);
+
+    let along = |from: [f64; 3], to: [f64; 3], scale: f64| {
+        [0, 1, 2].map(|i| from[i] + scale * (to[i] - from[i]))
+    };
+
+    for _ in 0..max_iterations {
+        let mut order = [0, 1, 2, 3];
+        order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+        let (lowest, next_highest, highest) = (order[0], order[2], order[3]);
+
+        let (low, high) = (values[lowest].abs(), values[highest].abs());
+        if 2.0 * (low - high).abs() < (low + high) * tolerance {
+            break;
+        }
+
+        // Centroid of every point but the worst
+        let mut centroid = [0.0; 3];
+        for point in order[..3].iter().map(|&i| simplex[i]) {
+            for i in 0..3 {
+                centroid[i] += point[i] / 3.0;
+            }
+        }
+
+        let reflected = along(centroid, simplex[highest], -1.0);
+        let reflected_value = objective(reflected);
+        if reflected_value < values[next_highest] {
+            if reflected_value < values[lowest] {
+                let expanded = along(centroid, simplex[highest], -2.0);
+                let expanded_value = objective(expanded);
+                if expanded_value < reflected_value {
+                    simplex[highest] = expanded;
+                    values[highest] = expanded_value;
+                    continue;
+                }
+            }
+            simplex[highest] = reflected;
+            values[highest] = reflected_value;
+            continue;
+        }
+
+        let contracted = along(centroid, simplex[highest], 0.5);
+        let contracted_value = objective(contracted);
+        if contracted_value < values[highest] {
+            simplex[highest] = contracted;
+            values[highest] = contracted_value;
+            continue;
+        }
+
+        // Shrink towards the best point
+        for &i in order[1..].iter() {
+            simplex[i] = along(simplex[lowest], simplex[i], 0.5);
+            values[i] = objective(simplex[i]);
+        }
+    }
+
+    let lowest = (0..POINTS)
+        .min_by(|&a, &b| values[a].total_cmp(&values[b]))
+        .unwrap();
+    simplex[lowest]
+}
@@ -1,9 +1,19 @@
 use cgmath::{Deg, Matrix4, Point3, Vector3};
 
+/// The kind of projection a camera uses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+}
+
 pub trait CameraTrait {
     fn build_projection_matrix(&self) -> Matrix4<f32>;
     fn build_view_matrix(&self) -> Matrix4<f32>;
     fn position(&self) -> Point3<f32>;
+    fn projection(&self) -> Projection {
+        Projection::Perspective
+    }
 }
 
 pub struct DefaultCamera {
@@ -28,3 +38,43 @@ impl CameraTrait for DefaultCamera {
         self.position
     }
 }
+
+/// Camera with a parallel projection.
+///
+/// Uses the same depth range convention as [`cgmath::perspective`], so it can be swapped with a perspective camera
+/// without changing depth testing.
+pub struct OrthographicCamera {
+    pub position: Point3<f32>,
+    pub direction: Vector3<f32>,
+    pub left: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub top: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl CameraTrait for OrthographicCamera {
+    fn build_projection_matrix(&self) -> Matrix4<f32> {
+        cgmath::ortho(
+            self.left,
+            self.right,
+            self.bottom,
+            self.top,
+            self.znear,
+            self.zfar,
+        )
+    }
+
+    fn build_view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(self.position, self.direction, cgmath::Vector3::unit_y())
+    }
+
+    fn position(&self) -> Point3<f32> {
+        self.position
+    }
+
+    fn projection(&self) -> Projection {
+        Projection::Orthographic
+    }
+}
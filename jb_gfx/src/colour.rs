@@ -27,6 +27,174 @@ impl Colour {
     pub fn black() -> Self {
         Self::new(0f32, 0f32, 0f32)
     }
+
+    /// Hue is in degrees and wraps around, saturation and value are from 0 to 1.
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let hue = hue.rem_euclid(360f32) / 60f32;
+        let saturation = saturation.clamp(0f32, 1f32);
+        let value = value.clamp(0f32, 1f32);
+
+        let chroma = value * saturation;
+        let x = chroma * (1f32 - (hue % 2f32 - 1f32).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0f32),
+            1 => (x, chroma, 0f32),
+            2 => (0f32, chroma, x),
+            3 => (0f32, x, chroma),
+            4 => (x, 0f32, chroma),
+            _ => (chroma, 0f32, x),
+        };
+        let m = value - chroma;
+        Self::new(r + m, g + m, b + m)
+    }
+
+    /// Returns hue in degrees, saturation and value.
+    pub fn to_hsv(&self) -> (f32, f32, f32) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let chroma = max - min;
+
+        let hue = if chroma == 0f32 {
+            0f32
+        } else if max == self.r {
+            60f32 * ((self.g - self.b) / chroma).rem_euclid(6f32)
+        } else if max == self.g {
+            60f32 * ((self.b - self.r) / chroma + 2f32)
+        } else {
+            60f32 * ((self.r - self.g) / chroma + 4f32)
+        };
+        let saturation = if max == 0f32 { 0f32 } else { chroma / max };
+        (hue, saturation, max)
+    }
+
+    /// The sRGB colour of a black body at `kelvin`, such as 2700 for a warm bulb or 6500 for
+    /// daylight. Accurate from 1000K to 40000K, and clamped to that range.
+    pub fn from_kelvin(kelvin: f32) -> Self {
+        // Tanner Helland's fit of the CIE 1964 black body colours
+        let temperature = kelvin.clamp(1000f32, 40000f32) / 100f32;
+
+        let r = if temperature <= 66f32 {
+            1f32
+        } else {
+            329.69873f32 * (temperature - 60f32).powf(-0.13320476f32) / 255f32
+        };
+        let g = if temperature <= 66f32 {
+            (99.4708f32 * temperature.ln() - 161.11957f32) / 255f32
+        } else {
+            288.12216f32 * (temperature - 60f32).powf(-0.07551485f32) / 255f32
+        };
+        let b = if temperature >= 66f32 {
+            1f32
+        } else if temperature <= 19f32 {
+            0f32
+        } else {
+            (138.51773f32 * (temperature - 10f32).ln() - 305.0448f32) / 255f32
+        };
+
+        Self::new(
+            r.clamp(0f32, 1f32),
+            g.clamp(0f32, 1f32),
+            b.clamp(0f32, 1f32),
+        )
+    }
+
+    /// Blends from `a` at 0 to `b` at 1, per channel without any colour space conversion.
+    pub fn lerp(a: Colour, b: Colour, t: f32) -> Self {
+        Self::new(
+            a.r + (b.r - a.r) * t,
+            a.g + (b.g - a.g) * t,
+            a.b + (b.b - a.b) * t,
+        )
+    }
+
+    /// Blends the sRGB colours in linear space, as light would mix. Midpoints come out brighter
+    /// than [Colour::lerp], which darkens them, e.g. red to green through brown instead of yellow.
+    pub fn lerp_srgb(a: Colour, b: Colour, t: f32) -> Self {
+        Self::lerp(a.to_linear(), b.to_linear(), t).to_srgb()
+    }
+
+    /// Converts the sRGB encoded colour into linear space.
+    pub fn to_linear(&self) -> Self {
+        Self::new(
+            srgb_to_linear(self.r),
+            srgb_to_linear(self.g),
+            srgb_to_linear(self.b),
+        )
+    }
+
+    /// Converts the linear colour into sRGB encoding.
+    pub fn to_srgb(&self) -> Self {
+        Self::new(
+            linear_to_srgb(self.r),
+            linear_to_srgb(self.g),
+            linear_to_srgb(self.b),
+        )
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045f32 {
+        value / 12.92f32
+    } else {
+        ((value + 0.055f32) / 1.055f32).powf(2.4f32)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308f32 {
+        value * 12.92f32
+    } else {
+        1.055f32 * value.powf(1f32 / 2.4f32) - 0.055f32
+    }
+}
+
+/// Colours placed along 0 to 1, blended between with [Colour::lerp_srgb].
+#[derive(Clone)]
+pub struct Gradient {
+    /// Sorted by position.
+    stops: Vec<(f32, Colour)>,
+}
+
+impl Gradient {
+    pub fn new() -> Self {
+        Self { stops: Vec::new() }
+    }
+
+    /// Adds a colour at `position`, usually from 0 to 1.
+    pub fn with_stop(mut self, position: f32, colour: Colour) -> Self {
+        self.add_stop(position, colour);
+        self
+    }
+
+    pub fn add_stop(&mut self, position: f32, colour: Colour) {
+        let index = self.stops.partition_point(|(stop, _)| *stop <= position);
+        self.stops.insert(index, (position, colour));
+    }
+
+    /// The colour at `t`. Before the first stop or after the last, that stop's colour is used.
+    /// Black when there are no stops.
+    pub fn sample(&self, t: f32) -> Colour {
+        let next = self.stops.partition_point(|(stop, _)| *stop <= t);
+        if next == 0 {
+            return self
+                .stops
+                .first()
+                .map_or(Colour::black(), |(_, colour)| *colour);
+        }
+        if next == self.stops.len() {
+            return self.stops[next - 1].1;
+        }
+
+        let (start, start_colour) = self.stops[next - 1];
+        let (end, end_colour) = self.stops[next];
+        Colour::lerp_srgb(start_colour, end_colour, (t - start) / (end - start))
+    }
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl From<Vector3<f32>> for Colour {
@@ -40,3 +208,111 @@ impl From<Colour> for Vector3<f32> {
         Vector3::new(value.r, value.g, value.b)
     }
 }
+
+impl From<[f32; 3]> for Colour {
+    fn from(value: [f32; 3]) -> Self {
+        Colour::new(value[0], value[1], value[2])
+    }
+}
+
+impl From<Colour> for [f32; 3] {
+    fn from(value: Colour) -> Self {
+        [value.r, value.g, value.b]
+    }
+}
+
+/// Opaque, with 1 in alpha.
+impl From<Colour> for [f32; 4] {
+    fn from(value: Colour) -> Self {
+        [value.r, value.g, value.b, 1f32]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// sRGB encoding of a linear 0.5.
+    const SRGB_HALF: f32 = 0.735_356_9;
+
+    fn assert_colour(actual: Colour, expected: [f32; 3]) {
+        let actual: [f32; 3] = actual.into();
+        assert!(
+            actual
+                .iter()
+                .zip(expected.iter())
+                .all(|(a, b)| (a - b).abs() < 1e-5),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn lerp_srgb_returns_the_endpoints() {
+        let a = Colour::new(0.2, 0.4, 0.8);
+        let b = Colour::new(0.9, 0.1, 0.3);
+
+        assert_colour(Colour::lerp_srgb(a, b, 0.0), [0.2, 0.4, 0.8]);
+        assert_colour(Colour::lerp_srgb(a, b, 1.0), [0.9, 0.1, 0.3]);
+    }
+
+    #[test]
+    fn lerp_srgb_blends_in_linear_space() {
+        assert_colour(
+            Colour::lerp_srgb(Colour::black(), Colour::new(1.0, 1.0, 1.0), 0.5),
+            [SRGB_HALF; 3],
+        );
+        // Yellow rather than the brown a plain lerp gives
+        let linear = Colour::lerp_srgb(Colour::red(), Colour::green(), 0.5);
+        let plain = Colour::lerp(Colour::red(), Colour::green(), 0.5);
+        assert_colour(linear, [SRGB_HALF, SRGB_HALF, 0.0]);
+        assert_colour(plain, [0.5, 0.5, 0.0]);
+        assert!(linear.r > plain.r + 0.1 && linear.g > plain.g + 0.1);
+    }
+
+    #[test]
+    fn gradient_returns_the_stop_colours_at_the_stops() {
+        let gradient = Gradient::new()
+            .with_stop(0.0, Colour::red())
+            .with_stop(0.5, Colour::green())
+            .with_stop(1.0, Colour::blue());
+
+        assert_colour(gradient.sample(0.0), [1.0, 0.0, 0.0]);
+        assert_colour(gradient.sample(0.5), [0.0, 1.0, 0.0]);
+        assert_colour(gradient.sample(1.0), [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn gradient_blends_between_the_surrounding_stops() {
+        let gradient = Gradient::new()
+            .with_stop(0.0, Colour::red())
+            .with_stop(0.5, Colour::green())
+            .with_stop(1.0, Colour::blue());
+
+        assert_colour(gradient.sample(0.25), [SRGB_HALF, SRGB_HALF, 0.0]);
+        assert_colour(gradient.sample(0.75), [0.0, SRGB_HALF, SRGB_HALF]);
+    }
+
+    #[test]
+    fn gradient_sorts_stops_added_out_of_order() {
+        let mut gradient = Gradient::new();
+        gradient.add_stop(1.0, Colour::new(1.0, 1.0, 1.0));
+        gradient.add_stop(0.0, Colour::black());
+
+        assert_colour(gradient.sample(0.0), [0.0; 3]);
+        assert_colour(gradient.sample(0.5), [SRGB_HALF; 3]);
+        assert_colour(gradient.sample(1.0), [1.0; 3]);
+    }
+
+    #[test]
+    fn gradient_holds_the_end_colours_outside_its_stops() {
+        let gradient = Gradient::new()
+            .with_stop(0.2, Colour::red())
+            .with_stop(0.8, Colour::blue());
+
+        assert_colour(gradient.sample(-1.0), [1.0, 0.0, 0.0]);
+        assert_colour(gradient.sample(2.0), [0.0, 0.0, 1.0]);
+        assert_colour(Gradient::new().sample(0.5), [0.0; 3]);
+    }
+}
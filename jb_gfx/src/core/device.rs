@@ -1,71 +1,181 @@
 use std::cell::RefCell;
 use std::ffi::CString;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::{borrow::Cow, ffi::CStr};
 
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Result};
 use ash::extensions::khr::Synchronization2;
 use ash::extensions::{ext::DebugUtils, khr::DynamicRendering};
+use ash::prelude::VkResult;
 use ash::vk::{
     self, DebugUtilsObjectNameInfoEXT, DeviceSize, Handle, ImageCreateFlags, ImageLayout,
     ObjectType, SurfaceTransformFlagsKHR,
 };
-use log::{error, info};
+use log::{error, info, warn};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
+use crate::mipgen::{downsample_shader, storage_format};
 use crate::renderpass::attachment::AttachmentHandle;
 use crate::renderpass::barrier::{ImageBarrier, ImageBarrierBuilder};
 use crate::resource::{
-    BufferCreateInfo, BufferHandle, BufferStorageType, ImageHandle, ResourceManager,
+    BufferCreateInfo, BufferHandle, BufferStorageType, ImageHandle, MemoryStats, ResourceManager,
 };
 use crate::util::bindless::BindlessManager;
+use crate::util::ktx2::is_bc_format;
 
-pub const FRAMES_IN_FLIGHT: usize = 2usize;
+/// Most frames that can be recorded while the GPU works on earlier ones.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 3usize;
 pub const SHADOWMAP_SIZE: u32 = 4096u32;
-pub const QUERY_COUNT: u32 = 10u32;
+/// Timestamps available per frame. The render graph writes two for every pass it runs.
+pub const QUERY_COUNT: u32 = 128u32;
+/// Most textures the bindless sets grow to when [GraphicsDeviceConfig::max_bindless_capacity]
+/// isn't set.
+pub const DEFAULT_MAX_BINDLESS_CAPACITY: u32 = 16384u32;
+/// Sampled images left out of the bindless capacity for the other sets bound alongside it.
+const RESERVED_SAMPLED_IMAGES: u32 = 64u32;
 
 pub struct GraphicsDevice {
     instance: ash::Instance,
     size: RefCell<PhysicalSize<u32>>,
-    swapchain: RefCell<Swapchain>,
-    surface: RefCell<Surface>,
+    present_target: RefCell<PresentTarget>,
+    present_mode: RefCell<PresentMode>,
     present_index: RefCell<usize>,
     frame_number: RefCell<usize>,
+    frames_in_flight: usize,
+    recording_frame: RefCell<bool>,
+    /// Set when acquire or present report the swapchain no longer matches the surface.
+    swapchain_out_of_date: RefCell<bool>,
+    /// Set while the window has a zero size, during which frames are skipped.
+    minimized: RefCell<bool>,
     pub vk_device: Arc<ash::Device>,
     pdevice: vk::PhysicalDevice,
+    features: vk::PhysicalDeviceFeatures,
     query_pool: vk::QueryPool,
     timestamp_period: f32,
     timestamp_frame_count: RefCell<usize>,
     pub resource_manager: Arc<ResourceManager>,
-    debug_utils_loader: DebugUtils,
-    debug_call_back: vk::DebugUtilsMessengerEXT,
+    /// Loaded when validation is enabled. Debug names and labels are skipped without it. The boxed
+    /// state is used by the messenger callback so must outlive it.
+    debug_utils: Option<(
+        DebugUtils,
+        vk::DebugUtilsMessengerEXT,
+        Box<DebugMessengerState>,
+    )>,
     graphics_queue: vk::Queue,
-    graphics_command_pool: [vk::CommandPool; FRAMES_IN_FLIGHT],
-    graphics_command_buffer: [vk::CommandBuffer; FRAMES_IN_FLIGHT],
-    draw_commands_reuse_fence: [vk::Fence; FRAMES_IN_FLIGHT],
-    rendering_complete_semaphore: [vk::Semaphore; FRAMES_IN_FLIGHT],
-    present_complete_semaphore: [vk::Semaphore; FRAMES_IN_FLIGHT],
+    graphics_queue_family_index: u32,
+    present_queue: vk::Queue,
+    present_context: Option<PresentContext>,
+    transfer_context: Option<TransferContext>,
+    /// Set when this frame's uploads were submitted to the transfer queue, so the frame's
+    /// graphics work has to wait on them.
+    transfer_submitted: RefCell<bool>,
+    graphics_command_pool: Vec<vk::CommandPool>,
+    graphics_command_buffer: Vec<vk::CommandBuffer>,
+    draw_commands_reuse_fence: Vec<vk::Fence>,
+    rendering_complete_semaphore: Vec<vk::Semaphore>,
+    present_complete_semaphore: Vec<vk::Semaphore>,
     upload_context: UploadContext,
     images_to_upload: RefCell<Vec<ImageToUpload>>,
-    buffers_to_delete: RefCell<Vec<(BufferHandle, usize)>>,
+    mip_requests: RefCell<Vec<MipRequest>>,
+    staging_pool: RefCell<StagingPool>,
+    images_to_delete: RefCell<Vec<ImageToDelete>>,
     bindless_descriptor_set_layout: vk::DescriptorSetLayout,
-    bindless_descriptor_set: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
     bindless_manager: RefCell<BindlessManager>,
-    bindless_descriptor_pool: vk::DescriptorPool,
-    default_sampler: vk::Sampler,
+    material_samplers: RefCell<[vk::Sampler; SamplerKind::COUNT]>,
+    /// Material samplers at each fixed anisotropy level, for materials capping their own.
+    capped_material_samplers: Vec<[vk::Sampler; SamplerKind::COUNT]>,
+    anisotropy: RefCell<AnisotropyLevel>,
+    max_sampler_anisotropy: f32,
     shadow_sampler: vk::Sampler,
     ui_sampler: vk::Sampler,
+    linear_clamp_sampler: vk::Sampler,
     skybox_sampler: vk::Sampler,
     timestamps: RefCell<Vec<u64>>,
 }
 
 impl GraphicsDevice {
-    pub fn new(window: &Window) -> Result<Self> {
-        profiling::scope!("GraphicsDevice::new");
+    pub fn new(window: &Window, config: GraphicsDeviceConfig) -> Result<Self> {
+        Self::new_internal(Some(window), window.inner_size(), config)
+    }
+
+    /// Lists the GPUs on the system. The index of each can be passed to [GraphicsDevice::new]
+    /// through [AdapterPreference::Index].
+    pub fn enumerate_adapters() -> Result<Vec<AdapterInfo>> {
+        let entry = ash::Entry::linked();
+        let app_info = vk::ApplicationInfo::builder().api_version(vk::make_api_version(0, 1, 3, 0));
+        let instance_create_info = vk::InstanceCreateInfo::builder().application_info(&app_info);
+        let instance = unsafe { entry.create_instance(&instance_create_info, None) }?;
+
+        let adapters = unsafe { instance.enumerate_physical_devices() }.map(|pdevices| {
+            pdevices
+                .iter()
+                .enumerate()
+                .map(|(index, pdevice)| Self::adapter_info(&instance, index, *pdevice))
+                .collect()
+        });
+        unsafe { instance.destroy_instance(None) };
+        Ok(adapters?)
+    }
+
+    fn adapter_info(
+        instance: &ash::Instance,
+        index: usize,
+        pdevice: vk::PhysicalDevice,
+    ) -> AdapterInfo {
+        let properties = unsafe { instance.get_physical_device_properties(pdevice) };
+        let memory_properties = unsafe { instance.get_physical_device_memory_properties(pdevice) };
+        let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        let adapter_type = match properties.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => AdapterType::Discrete,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => AdapterType::Integrated,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => AdapterType::Virtual,
+            vk::PhysicalDeviceType::CPU => AdapterType::Cpu,
+            _ => AdapterType::Other,
+        };
+        let vram = memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum();
+
+        AdapterInfo {
+            index,
+            name,
+            adapter_type,
+            vram,
+        }
+    }
 
-        let size = window.inner_size();
+    /// Creates a device without a window or swapchain. Frames are rendered into an offscreen image
+    /// of the given size instead, which can be read back with [GraphicsDevice::get_present_image].
+    pub fn new_headless(width: u32, height: u32, config: GraphicsDeviceConfig) -> Result<Self> {
+        ensure!(
+            width > 0 && height > 0,
+            "Headless device size must be greater than zero"
+        );
+        Self::new_internal(None, PhysicalSize::new(width, height), config)
+    }
+
+    fn new_internal(
+        window: Option<&Window>,
+        size: PhysicalSize<u32>,
+        config: GraphicsDeviceConfig,
+    ) -> Result<Self> {
+        profiling::scope!("GraphicsDevice::new");
+        ensure!(
+            (1..=MAX_FRAMES_IN_FLIGHT).contains(&config.frames_in_flight),
+            "Frames in flight must be between 1 and {}, got {}",
+            MAX_FRAMES_IN_FLIGHT,
+            config.frames_in_flight
+        );
+        let frames_in_flight = config.frames_in_flight;
+        let staging_budget = config.staging_budget;
 
         let entry = ash::Entry::linked();
         let app_name = unsafe { CStr::from_bytes_with_nul_unchecked(b"Rust Renderer\0") };
@@ -76,10 +186,16 @@ impl GraphicsDevice {
             .engine_version(vk::make_api_version(0, 0, 1, 0))
             .api_version(vk::make_api_version(0, 1, 3, 0));
 
-        let mut instance_extensions =
-            ash_window::enumerate_required_extensions(window.raw_display_handle())?.to_vec();
+        let mut instance_extensions = match window {
+            Some(window) => {
+                ash_window::enumerate_required_extensions(window.raw_display_handle())?.to_vec()
+            }
+            None => Vec::new(),
+        };
 
-        instance_extensions.push(DebugUtils::name().as_ptr());
+        if config.validation {
+            instance_extensions.push(DebugUtils::name().as_ptr());
+        }
 
         let instance_create_info = vk::InstanceCreateInfo::builder()
             .application_info(&app_info)
@@ -91,31 +207,41 @@ impl GraphicsDevice {
                 .expect("Instance Creation Error")
         };
 
-        let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING, //        | vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
-            )
-            .message_type(
-                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
-                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
-            )
-            .pfn_user_callback(Some(vulkan_debug_callback));
-
-        let debug_utils_loader = DebugUtils::new(&entry, &instance);
-        let debug_call_back =
-            unsafe { debug_utils_loader.create_debug_utils_messenger(&debug_info, None) }?;
-
-        let surface = unsafe {
-            ash_window::create_surface(
-                &entry,
-                &instance,
-                window.raw_display_handle(),
-                window.raw_window_handle(),
-                None,
-            )
-        }?;
+        let debug_utils = if config.validation {
+            let messenger_state = Box::new(DebugMessengerState {
+                suppressed_message_ids: config.suppressed_message_ids,
+                error_count: AtomicUsize::new(0),
+            });
+            let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+                .message_severity(config.validation_severity.severity_flags())
+                .message_type(
+                    vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                        | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                        | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+                )
+                .pfn_user_callback(Some(vulkan_debug_callback))
+                .user_data(messenger_state.as_ref() as *const DebugMessengerState as *mut c_void);
+
+            let debug_utils_loader = DebugUtils::new(&entry, &instance);
+            let debug_call_back =
+                unsafe { debug_utils_loader.create_debug_utils_messenger(&debug_info, None) }?;
+            Some((debug_utils_loader, debug_call_back, messenger_state))
+        } else {
+            None
+        };
+
+        let surface = match window {
+            Some(window) => Some(unsafe {
+                ash_window::create_surface(
+                    &entry,
+                    &instance,
+                    window.raw_display_handle(),
+                    window.raw_window_handle(),
+                    None,
+                )
+            }?),
+            None => None,
+        };
 
         let mut sync_2_feature =
             vk::PhysicalDeviceSynchronization2Features::builder().synchronization2(true);
@@ -126,49 +252,161 @@ impl GraphicsDevice {
         let surface_loader = ash::extensions::khr::Surface::new(&entry, &instance);
         let pdevices =
             unsafe { instance.enumerate_physical_devices() }.expect("Physical device error");
-        let mut timestamp_period = 0.0;
-        let mut max_sampler_anisotropy = 0.0;
-        let (pdevice, queue_family_index) = pdevices
+        let adapters: Vec<AdapterInfo> = pdevices
             .iter()
-            .find_map(|pdevice| {
-                let limits = unsafe { instance.get_physical_device_properties(*pdevice).limits };
-                if limits.timestamp_period == 0.0 {
-                    None
+            .enumerate()
+            .map(|(index, pdevice)| Self::adapter_info(&instance, index, *pdevice))
+            .collect();
+
+        // Returns the graphics and present queue families if the device can be used
+        let queue_families_for = |pdevice: vk::PhysicalDevice| {
+            let limits = unsafe { instance.get_physical_device_properties(pdevice).limits };
+            if limits.timestamp_period == 0.0 {
+                return None;
+            }
+            let queue_families =
+                unsafe { instance.get_physical_device_queue_family_properties(pdevice) };
+            // Without a surface any graphics family will do
+            let supports_surface = |index: usize| match surface {
+                Some(surface) => unsafe {
+                    surface_loader.get_physical_device_surface_support(
+                        pdevice,
+                        index as u32,
+                        surface,
+                    )
+                }
+                .unwrap_or(false),
+                None => true,
+            };
+
+            let graphics_families: Vec<usize> = queue_families
+                .iter()
+                .enumerate()
+                .filter(|(_, info)| info.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+                .map(|(index, _)| index)
+                .collect();
+
+            // Prefer a single family that can do both, otherwise pair up separate families
+            graphics_families
+                .iter()
+                .find(|&&index| supports_surface(index))
+                .map(|&index| (index, index))
+                .or_else(|| {
+                    let graphics_index = *graphics_families.first()?;
+                    let present_index =
+                        (0..queue_families.len()).find(|&index| supports_surface(index))?;
+                    Some((graphics_index, present_index))
+                })
+        };
+
+        // Candidates are tried in order, so a preferred device that can't be used falls back to the
+        // next suitable one
+        let mut candidates: Vec<usize> = (0..pdevices.len()).collect();
+        match config.adapter {
+            AdapterPreference::FirstSuitable => {}
+            AdapterPreference::PreferDiscrete => candidates
+                .sort_by_key(|&index| adapters[index].adapter_type != AdapterType::Discrete),
+            AdapterPreference::Index(preferred) => {
+                if preferred < candidates.len() {
+                    candidates.remove(preferred);
+                    candidates.insert(0, preferred);
                 } else {
-                    timestamp_period = limits.timestamp_period;
-                    max_sampler_anisotropy = limits.max_sampler_anisotropy;
-                    unsafe { instance.get_physical_device_queue_family_properties(*pdevice) }
-                        .iter()
-                        .enumerate()
-                        .find_map(|(index, info)| {
-                            let supports_graphic_and_surface =
-                                info.queue_flags.contains(vk::QueueFlags::GRAPHICS)
-                                    && unsafe {
-                                        surface_loader.get_physical_device_surface_support(
-                                            *pdevice,
-                                            index as u32,
-                                            surface,
-                                        )
-                                    }
-                                    .unwrap();
-                            if supports_graphic_and_surface {
-                                Some((*pdevice, index))
-                            } else {
-                                None
-                            }
-                        })
+                    warn!(
+                        "Adapter index {} is out of range, {} adapters found",
+                        preferred,
+                        adapters.len()
+                    );
                 }
+            }
+        }
+
+        let (pdevice, queue_family_index, present_family_index) = candidates
+            .iter()
+            .find_map(|&index| {
+                let families = queue_families_for(pdevices[index]);
+                if families.is_none() {
+                    warn!("Skipping unsuitable adapter: {}", adapters[index].name);
+                }
+                families.map(|(graphics_index, present_index)| {
+                    (pdevices[index], graphics_index, present_index)
+                })
             })
             .expect("Couldn't find suitable device.");
+        let limits = unsafe { instance.get_physical_device_properties(pdevice).limits };
+        let timestamp_period = limits.timestamp_period;
+        let max_sampler_anisotropy = limits.max_sampler_anisotropy;
+        // The other sets of a pipeline share the limits with the bindless textures
+        let device_bindless_capacity = limits
+            .max_descriptor_set_sampled_images
+            .min(limits.max_per_stage_descriptor_sampled_images)
+            .saturating_sub(RESERVED_SAMPLED_IMAGES);
+        let max_bindless_capacity = match config.max_bindless_capacity {
+            Some(capacity) => {
+                ensure!(
+                    capacity <= device_bindless_capacity,
+                    "Max bindless capacity of {} is above the device's limit of {} sampled images",
+                    capacity,
+                    device_bindless_capacity
+                );
+                capacity
+            }
+            None => device_bindless_capacity.min(DEFAULT_MAX_BINDLESS_CAPACITY),
+        };
+        ensure!(
+            config.bindless_capacity > 0 && max_bindless_capacity > 0,
+            "Bindless capacity must be greater than zero"
+        );
+        if let Some(adapter) = adapters
+            .iter()
+            .find(|adapter| pdevices[adapter.index] == pdevice)
+        {
+            info!(
+                "Adapter selected: {} [{:?}, {} MiB]",
+                adapter.name,
+                adapter.adapter_type,
+                adapter.vram / (1024 * 1024)
+            );
+        }
         let queue_family_index = queue_family_index as u32;
-        let device_extension_names_raw = [
-            ash::extensions::khr::Swapchain::name().as_ptr(),
+        let present_family_index = present_family_index as u32;
+        // A family with transfer but not graphics is backed by dedicated copy hardware, prefer one
+        // without compute too
+        let transfer_family_index = {
+            let families = unsafe { instance.get_physical_device_queue_family_properties(pdevice) };
+            let transfer_only = |excluded: vk::QueueFlags| {
+                families.iter().position(|family| {
+                    family.queue_flags.contains(vk::QueueFlags::TRANSFER)
+                        && !family.queue_flags.intersects(excluded)
+                        && family.queue_count > 0
+                })
+            };
+            transfer_only(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+                .or_else(|| transfer_only(vk::QueueFlags::GRAPHICS))
+                .map(|index| index as u32)
+        };
+        info!(
+            "Queue families selected. [Graphics: {}, Present: {}, Transfer: {:?}]",
+            queue_family_index, present_family_index, transfer_family_index
+        );
+        let mut device_extension_names_raw = vec![
             DynamicRendering::name().as_ptr(),
             Synchronization2::name().as_ptr(),
         ];
+        if surface.is_some() {
+            device_extension_names_raw.push(ash::extensions::khr::Swapchain::name().as_ptr());
+        }
+        let supported_features = unsafe { instance.get_physical_device_features(pdevice) };
+        // Optional features used for debug drawing, compressed textures and batching indirect
+        // draws, enabled when available
         let features = vk::PhysicalDeviceFeatures {
             shader_clip_distance: 1,
-            sampler_anisotropy: vk::TRUE,
+            multi_draw_indirect: supported_features.multi_draw_indirect,
+            // Indirect draws start at each batch's first instance
+            draw_indirect_first_instance: supported_features.draw_indirect_first_instance,
+            sampler_anisotropy: supported_features.sampler_anisotropy,
+            fill_mode_non_solid: supported_features.fill_mode_non_solid,
+            wide_lines: supported_features.wide_lines,
+            texture_compression_bc: supported_features.texture_compression_bc,
             ..Default::default()
         };
         let mut descriptor_indexing_features =
@@ -182,16 +420,30 @@ impl GraphicsDevice {
 
         let priorities = [1.0];
 
-        let queue_info = vk::DeviceQueueCreateInfo::builder()
+        let mut queue_infos = vec![*vk::DeviceQueueCreateInfo::builder()
             .queue_family_index(queue_family_index)
-            .queue_priorities(&priorities);
+            .queue_priorities(&priorities)];
+        if present_family_index != queue_family_index {
+            queue_infos.push(
+                *vk::DeviceQueueCreateInfo::builder()
+                    .queue_family_index(present_family_index)
+                    .queue_priorities(&priorities),
+            );
+        }
+        if let Some(transfer_family_index) = transfer_family_index {
+            queue_infos.push(
+                *vk::DeviceQueueCreateInfo::builder()
+                    .queue_family_index(transfer_family_index)
+                    .queue_priorities(&priorities),
+            );
+        }
 
         let device_create_info = vk::DeviceCreateInfo::builder()
             .push_next(&mut descriptor_indexing_features)
             .push_next(&mut sync_2_feature)
             .push_next(&mut dynamic_rendering_feature)
             .push_next(&mut query_features)
-            .queue_create_infos(std::slice::from_ref(&queue_info))
+            .queue_create_infos(&queue_infos)
             .enabled_extension_names(&device_extension_names_raw)
             .enabled_features(&features);
 
@@ -210,92 +462,99 @@ impl GraphicsDevice {
         }
 
         let resource_manager = ResourceManager::new(&instance, &pdevice, device.clone());
+        resource_manager.create_transient_buffers(frames_in_flight);
 
         let graphics_queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+        let present_queue = unsafe { device.get_device_queue(present_family_index, 0) };
+
+        let present_target = match surface {
+            Some(surface) => {
+                let surface_format = unsafe {
+                    surface_loader.get_physical_device_surface_formats(pdevice, surface)
+                }?
+                .into_iter()
+                .find(|&x| {
+                    (x.format == vk::Format::B8G8R8A8_SRGB || x.format == vk::Format::R8G8B8A8_SRGB)
+                        && x.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+                })
+                .unwrap();
+
+                let surface_capabilities = unsafe {
+                    surface_loader.get_physical_device_surface_capabilities(pdevice, surface)
+                }?;
+                ensure!(surface_capabilities
+                    .supported_usage_flags
+                    .contains(vk::ImageUsageFlags::STORAGE));
+                let mut desired_image_count = surface_capabilities.min_image_count + 1;
+                if surface_capabilities.max_image_count > 0
+                    && desired_image_count > surface_capabilities.max_image_count
+                {
+                    desired_image_count = surface_capabilities.max_image_count;
+                }
+                let surface_resolution = match surface_capabilities.current_extent.width {
+                    u32::MAX => vk::Extent2D {
+                        width: size.width,
+                        height: size.height,
+                    },
+                    _ => surface_capabilities.current_extent,
+                };
+                let pre_transform = if surface_capabilities
+                    .supported_transforms
+                    .contains(vk::SurfaceTransformFlagsKHR::IDENTITY)
+                {
+                    vk::SurfaceTransformFlagsKHR::IDENTITY
+                } else {
+                    surface_capabilities.current_transform
+                };
+                let swapchain_loader = ash::extensions::khr::Swapchain::new(&instance, &device);
 
-        let (surface, swapchain) = {
-            let surface_format =
-                unsafe { surface_loader.get_physical_device_surface_formats(pdevice, surface) }?
-                    .into_iter()
-                    .find(|&x| {
-                        (x.format == vk::Format::B8G8R8A8_SRGB
-                            || x.format == vk::Format::R8G8B8A8_SRGB)
-                            && x.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-                    })
-                    .unwrap();
-
-            let surface_capabilities = unsafe {
-                surface_loader.get_physical_device_surface_capabilities(pdevice, surface)
-            }?;
-            ensure!(surface_capabilities
-                .supported_usage_flags
-                .contains(vk::ImageUsageFlags::STORAGE));
-            let mut desired_image_count = surface_capabilities.min_image_count + 1;
-            if surface_capabilities.max_image_count > 0
-                && desired_image_count > surface_capabilities.max_image_count
-            {
-                desired_image_count = surface_capabilities.max_image_count;
+                let surface = Surface {
+                    surface,
+                    surface_loader,
+                    surface_format,
+                    surface_resolution,
+                };
+                let swapchain = Swapchain::new(
+                    &device,
+                    swapchain_loader,
+                    pdevice,
+                    &surface,
+                    pre_transform,
+                    desired_image_count,
+                    PresentMode::default(),
+                )?;
+                PresentTarget::Window { surface, swapchain }
             }
-            let surface_resolution = match surface_capabilities.current_extent.width {
-                u32::MAX => vk::Extent2D {
+            None => PresentTarget::Offscreen(OffscreenTarget::new(
+                &resource_manager,
+                frames_in_flight,
+                vk::Extent2D {
                     width: size.width,
                     height: size.height,
                 },
-                _ => surface_capabilities.current_extent,
-            };
-            let pre_transform = if surface_capabilities
-                .supported_transforms
-                .contains(vk::SurfaceTransformFlagsKHR::IDENTITY)
-            {
-                vk::SurfaceTransformFlagsKHR::IDENTITY
-            } else {
-                surface_capabilities.current_transform
-            };
-            let swapchain_loader = ash::extensions::khr::Swapchain::new(&instance, &device);
-
-            let surface = Surface {
-                surface,
-                surface_loader,
-                surface_format,
-                surface_resolution,
-            };
-            let swapchain = Swapchain::new(
-                &device,
-                swapchain_loader,
-                pdevice,
-                &surface,
-                pre_transform,
-                desired_image_count,
-            )?;
-            (surface, swapchain)
+            )),
         };
 
         let pool_create_info = vk::CommandPoolCreateInfo::builder()
             .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
             .queue_family_index(queue_family_index);
 
-        let graphics_command_pool = [
-            unsafe { device.create_command_pool(&pool_create_info, None) }?,
-            unsafe { device.create_command_pool(&pool_create_info, None) }?,
-        ];
-
-        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
-            .command_buffer_count(1)
-            .command_pool(graphics_command_pool[0])
-            .level(vk::CommandBufferLevel::PRIMARY);
-
-        let command_buffers =
-            unsafe { device.allocate_command_buffers(&command_buffer_allocate_info) }?;
-
-        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
-            .command_buffer_count(1)
-            .command_pool(graphics_command_pool[1])
-            .level(vk::CommandBufferLevel::PRIMARY);
-
-        let command_buffers_two =
-            unsafe { device.allocate_command_buffers(&command_buffer_allocate_info) }?;
+        let graphics_command_pool = (0..frames_in_flight)
+            .map(|_| unsafe { device.create_command_pool(&pool_create_info, None) })
+            .collect::<VkResult<Vec<_>>>()?;
 
-        let graphics_command_buffer = [command_buffers[0], command_buffers_two[0]];
+        let graphics_command_buffer = graphics_command_pool
+            .iter()
+            .map(|&command_pool| {
+                let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+                    .command_buffer_count(1)
+                    .command_pool(command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY);
+
+                unsafe { device.allocate_command_buffers(&command_buffer_allocate_info) }
+                    .map(|command_buffers| command_buffers[0])
+            })
+            .collect::<VkResult<Vec<_>>>()?;
 
         let upload_command_pool = {
             let pool_create_info = vk::CommandPoolCreateInfo::builder()
@@ -320,10 +579,9 @@ impl GraphicsDevice {
         let fence_create_info =
             vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
 
-        let draw_commands_reuse_fence = [
-            unsafe { device.create_fence(&fence_create_info, None) }.expect("Create fence failed."),
-            unsafe { device.create_fence(&fence_create_info, None) }.expect("Create fence failed."),
-        ];
+        let draw_commands_reuse_fence = (0..frames_in_flight)
+            .map(|_| unsafe { device.create_fence(&fence_create_info, None) })
+            .collect::<VkResult<Vec<_>>>()?;
 
         let upload_fence = {
             let fence_create_info = vk::FenceCreateInfo::builder();
@@ -333,31 +591,82 @@ impl GraphicsDevice {
 
         let semaphore_create_info = vk::SemaphoreCreateInfo::default();
 
-        let present_complete_semaphore = [
-            unsafe { device.create_semaphore(&semaphore_create_info, None) }?,
-            unsafe { device.create_semaphore(&semaphore_create_info, None) }?,
-        ];
-        let rendering_complete_semaphore = [
-            unsafe { device.create_semaphore(&semaphore_create_info, None) }?,
-            unsafe { device.create_semaphore(&semaphore_create_info, None) }?,
-        ];
+        let create_semaphores = || {
+            (0..frames_in_flight)
+                .map(|_| unsafe { device.create_semaphore(&semaphore_create_info, None) })
+                .collect::<VkResult<Vec<_>>>()
+        };
+        let present_complete_semaphore = create_semaphores()?;
+        let rendering_complete_semaphore = create_semaphores()?;
 
-        let default_sampler = {
-            let sampler_info = vk::SamplerCreateInfo::builder()
-                .mag_filter(vk::Filter::NEAREST)
-                .min_filter(vk::Filter::NEAREST)
-                .address_mode_u(vk::SamplerAddressMode::REPEAT)
-                .address_mode_v(vk::SamplerAddressMode::REPEAT)
-                .address_mode_w(vk::SamplerAddressMode::REPEAT)
-                .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-                .min_lod(0.0f32)
-                .max_lod(vk::LOD_CLAMP_NONE)
-                .anisotropy_enable(true)
-                .max_anisotropy(max_sampler_anisotropy);
+        // Swapchain images need to be handed over to the present queue when it is a different family
+        let present_context = if present_family_index != queue_family_index {
+            let pool_create_info = vk::CommandPoolCreateInfo::builder()
+                .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+                .queue_family_index(present_family_index);
+            let command_pool = unsafe { device.create_command_pool(&pool_create_info, None) }?;
 
-            unsafe { device.create_sampler(&sampler_info, None)? }
+            let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .command_buffer_count(frames_in_flight as u32)
+                .command_pool(command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY);
+            let command_buffers =
+                unsafe { device.allocate_command_buffers(&command_buffer_allocate_info) }?;
+
+            Some(PresentContext {
+                queue_family_index: present_family_index,
+                command_pool,
+                command_buffer: command_buffers,
+                fence: (0..frames_in_flight)
+                    .map(|_| unsafe { device.create_fence(&fence_create_info, None) })
+                    .collect::<VkResult<Vec<_>>>()?,
+                ownership_semaphore: create_semaphores()?,
+            })
+        } else {
+            None
+        };
+
+        // Image uploads are copied on the transfer queue when there is one, then handed over to
+        // the graphics queue family
+        let transfer_context = match transfer_family_index {
+            Some(transfer_family_index) => {
+                let pool_create_info = vk::CommandPoolCreateInfo::builder()
+                    .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+                    .queue_family_index(transfer_family_index);
+                let command_pool = unsafe { device.create_command_pool(&pool_create_info, None) }?;
+
+                let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+                    .command_buffer_count(frames_in_flight as u32)
+                    .command_pool(command_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY);
+                let command_buffers =
+                    unsafe { device.allocate_command_buffers(&command_buffer_allocate_info) }?;
+
+                Some(TransferContext {
+                    queue_family_index: transfer_family_index,
+                    queue: unsafe { device.get_device_queue(transfer_family_index, 0) },
+                    command_pool,
+                    command_buffer: command_buffers,
+                    upload_complete_semaphore: create_semaphores()?,
+                })
+            }
+            None => None,
         };
 
+        // Anisotropy can't be used at all without the feature
+        if supported_features.sampler_anisotropy == vk::FALSE {
+            max_sampler_anisotropy = 1.0;
+        }
+        let anisotropy = AnisotropyLevel::default();
+        let material_samplers =
+            create_material_samplers(&device, anisotropy.clamped_samples(max_sampler_anisotropy))?;
+        let capped_material_samplers = AnisotropyLevel::ALL
+            .iter()
+            .map(|level| {
+                create_material_samplers(&device, level.clamped_samples(max_sampler_anisotropy))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         let shadow_sampler = {
             let sampler_info = vk::SamplerCreateInfo::builder()
                 .mag_filter(vk::Filter::LINEAR)
@@ -389,6 +698,20 @@ impl GraphicsDevice {
             unsafe { device.create_sampler(&sampler_info, None)? }
         };
 
+        let linear_clamp_sampler = {
+            let sampler_info = vk::SamplerCreateInfo::builder()
+                .mag_filter(vk::Filter::LINEAR)
+                .min_filter(vk::Filter::LINEAR)
+                .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+                .min_lod(0.0f32)
+                .max_lod(1.0f32);
+
+            unsafe { device.create_sampler(&sampler_info, None)? }
+        };
+
         let skybox_sampler = {
             let sampler_info = vk::SamplerCreateInfo::builder()
                 .mag_filter(vk::Filter::LINEAR)
@@ -399,7 +722,7 @@ impl GraphicsDevice {
                 .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
                 .min_lod(0.0f32)
                 .max_lod(vk::LOD_CLAMP_NONE)
-                .anisotropy_enable(true)
+                .anisotropy_enable(max_sampler_anisotropy > 1.0)
                 .max_anisotropy(max_sampler_anisotropy);
 
             unsafe { device.create_sampler(&sampler_info, None)? }
@@ -412,29 +735,6 @@ impl GraphicsDevice {
             queue: graphics_queue,
         };
 
-        // Create descriptor pool
-
-        let pool_sizes = [
-            *vk::DescriptorPoolSize::builder()
-                .descriptor_count(100u32)
-                .ty(vk::DescriptorType::UNIFORM_BUFFER),
-            *vk::DescriptorPoolSize::builder()
-                .descriptor_count(100u32)
-                .ty(vk::DescriptorType::STORAGE_BUFFER),
-            *vk::DescriptorPoolSize::builder()
-                .descriptor_count(1000u32)
-                .ty(vk::DescriptorType::SAMPLER),
-            *vk::DescriptorPoolSize::builder()
-                .descriptor_count(1000u32)
-                .ty(vk::DescriptorType::SAMPLED_IMAGE),
-        ];
-
-        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
-            .max_sets(4u32)
-            .pool_sizes(&pool_sizes);
-
-        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_create_info, None) }?;
-
         // Create bindless set
 
         let bindless_binding_flags = [
@@ -451,13 +751,13 @@ impl GraphicsDevice {
             *vk::DescriptorSetLayoutBinding::builder()
                 .binding(0u32)
                 .descriptor_type(vk::DescriptorType::SAMPLER)
-                .descriptor_count(4u32)
-                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+                .descriptor_count(BINDLESS_SAMPLER_COUNT as u32)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE),
             *vk::DescriptorSetLayoutBinding::builder()
                 .binding(1u32)
                 .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
-                .descriptor_count(100u32)
-                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+                .descriptor_count(max_bindless_capacity)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE),
         ];
 
         let bindless_descriptor_set_layout_create_info =
@@ -469,32 +769,24 @@ impl GraphicsDevice {
             device.create_descriptor_set_layout(&bindless_descriptor_set_layout_create_info, None)
         }?;
 
-        let bindless_descriptor_set = {
-            let mut descriptor_set_counts =
-                vk::DescriptorSetVariableDescriptorCountAllocateInfo::builder()
-                    .descriptor_counts(&[100u32]);
-
-            let set_layouts = [bindless_descriptor_set_layout];
-            let create_info = vk::DescriptorSetAllocateInfo::builder()
-                .push_next(&mut descriptor_set_counts)
-                .descriptor_pool(descriptor_pool)
-                .set_layouts(&set_layouts);
-
-            let descriptor_sets = unsafe { device.allocate_descriptor_sets(&create_info) }?;
-            let first = *descriptor_sets.get(0).unwrap();
-            let descriptor_sets = unsafe { device.allocate_descriptor_sets(&create_info) }?;
-            let second = *descriptor_sets.get(0).unwrap();
-
-            [first, second]
-        };
-
         let resource_manager = Arc::new(resource_manager);
-        let samplers = vec![default_sampler, shadow_sampler, ui_sampler, skybox_sampler];
+        let mut samplers = vec![
+            material_samplers[0],
+            shadow_sampler,
+            ui_sampler,
+            skybox_sampler,
+            material_samplers[1],
+            material_samplers[2],
+        ];
+        samplers.extend(capped_material_samplers.iter().flatten());
         let bindless_manager = RefCell::new(BindlessManager::new(
             device.clone(),
             resource_manager.clone(),
-            bindless_descriptor_set,
-        ));
+            bindless_descriptor_set_layout,
+            frames_in_flight,
+            config.bindless_capacity.min(max_bindless_capacity),
+            max_bindless_capacity,
+        )?);
         bindless_manager
             .borrow_mut()
             .setup_samplers(&samplers, &device)?;
@@ -502,34 +794,47 @@ impl GraphicsDevice {
         let device = Self {
             instance,
             size: RefCell::new(size),
-            swapchain: RefCell::new(swapchain),
-            surface: RefCell::new(surface),
+            present_target: RefCell::new(present_target),
+            present_mode: RefCell::new(PresentMode::default()),
             present_index: RefCell::new(0),
             vk_device: device,
             pdevice,
+            features,
             query_pool,
             timestamp_period,
             timestamp_frame_count: RefCell::new(0),
             resource_manager,
-            debug_utils_loader,
-            debug_call_back,
+            debug_utils,
             graphics_queue,
+            graphics_queue_family_index: queue_family_index,
+            present_queue,
+            present_context,
+            transfer_context,
+            transfer_submitted: RefCell::new(false),
             graphics_command_pool,
             graphics_command_buffer,
             draw_commands_reuse_fence,
             rendering_complete_semaphore,
             present_complete_semaphore,
             upload_context,
-            default_sampler,
+            material_samplers: RefCell::new(material_samplers),
+            capped_material_samplers,
+            anisotropy: RefCell::new(anisotropy),
+            max_sampler_anisotropy,
             frame_number: RefCell::new(0),
+            frames_in_flight,
+            recording_frame: RefCell::new(false),
+            swapchain_out_of_date: RefCell::new(false),
+            minimized: RefCell::new(false),
             images_to_upload: RefCell::new(Vec::default()),
-            buffers_to_delete: RefCell::new(Vec::default()),
+            mip_requests: RefCell::new(Vec::default()),
+            staging_pool: RefCell::new(StagingPool::new(staging_budget, frames_in_flight)),
+            images_to_delete: RefCell::new(Vec::default()),
             bindless_descriptor_set_layout,
-            bindless_descriptor_set,
             bindless_manager,
-            bindless_descriptor_pool: descriptor_pool,
             shadow_sampler,
             ui_sampler,
+            linear_clamp_sampler,
             skybox_sampler,
             timestamps: RefCell::default(),
         };
@@ -554,29 +859,87 @@ impl GraphicsDevice {
         *self.size.borrow()
     }
 
+    /// The image the current frame is drawn into. This is the offscreen image on headless devices.
     pub fn get_present_image(&self) -> vk::Image {
-        self.swapchain.borrow().present_images[self.present_index()]
+        match &*self.present_target.borrow() {
+            PresentTarget::Window { swapchain, .. } => {
+                swapchain.present_images[self.present_index()]
+            }
+            PresentTarget::Offscreen(offscreen) => self
+                .resource_manager
+                .get_image(offscreen.images[self.present_index()])
+                .unwrap()
+                .image(),
+        }
     }
 
     pub fn get_present_image_view(&self) -> vk::ImageView {
-        self.swapchain.borrow().present_image_views[self.present_index()]
+        match &*self.present_target.borrow() {
+            PresentTarget::Window { swapchain, .. } => {
+                swapchain.present_image_views[self.present_index()]
+            }
+            PresentTarget::Offscreen(offscreen) => self
+                .resource_manager
+                .get_image(offscreen.images[self.present_index()])
+                .unwrap()
+                .image_view(),
+        }
+    }
+
+    /// Size of the swapchain images, which can differ from [GraphicsDevice::size] on some platforms.
+    pub fn swapchain_extent(&self) -> vk::Extent2D {
+        match &*self.present_target.borrow() {
+            PresentTarget::Window { surface, .. } => surface.surface_resolution,
+            PresentTarget::Offscreen(offscreen) => offscreen.extent,
+        }
     }
 
     pub fn surface_format(&self) -> vk::SurfaceFormatKHR {
-        self.surface.borrow().surface_format
+        match &*self.present_target.borrow() {
+            PresentTarget::Window { surface, .. } => surface.surface_format,
+            PresentTarget::Offscreen(_) => vk::SurfaceFormatKHR {
+                format: OFFSCREEN_FORMAT,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            },
+        }
+    }
+
+    /// True when the device was created with [GraphicsDevice::new_headless].
+    pub fn is_headless(&self) -> bool {
+        matches!(*self.present_target.borrow(), PresentTarget::Offscreen(_))
     }
 
     pub fn frame_number(&self) -> usize {
         *self.frame_number.borrow()
     }
 
+    /// Returns true between [GraphicsDevice::start_frame] and [GraphicsDevice::end_frame],
+    /// while the current frame's graphics command buffer is being recorded.
+    pub fn is_recording_frame(&self) -> bool {
+        *self.recording_frame.borrow()
+    }
+
+    /// Frames that can be recorded while the GPU works on earlier ones, see
+    /// [GraphicsDeviceConfig::frames_in_flight].
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight
+    }
+
+    /// Index of the current frame's per-frame resources, below [GraphicsDevice::frames_in_flight].
     pub fn buffered_resource_number(&self) -> usize {
-        self.frame_number() % 2
+        self.frame_number() % self.frames_in_flight
     }
 
-    pub fn start_frame(&self) -> Result<()> {
+    /// Begins recording the next frame. Returns false if the frame has to be skipped, because the
+    /// window is minimized or the swapchain is out of date. See
+    /// [GraphicsDevice::recreate_out_of_date_swapchain].
+    pub fn start_frame(&self) -> Result<bool> {
         profiling::scope!("Start Frame");
 
+        if *self.minimized.borrow() {
+            return Ok(false);
+        }
+
         unsafe {
             self.vk_device.wait_for_fences(
                 &[self.draw_commands_reuse_fence[self.buffered_resource_number()]],
@@ -585,15 +948,40 @@ impl GraphicsDevice {
             )
         }?;
 
-        let (present_index, _) = unsafe {
-            self.swapchain.borrow().swapchain_loader.acquire_next_image(
-                self.swapchain.borrow().swapchain,
-                u64::MAX,
-                self.present_complete_semaphore[self.buffered_resource_number()],
-                vk::Fence::null(),
-            )
-        }?;
-        *self.present_index.borrow_mut() = present_index as usize;
+        let present_index = match &*self.present_target.borrow() {
+            PresentTarget::Window { swapchain, .. } => {
+                let result = unsafe {
+                    swapchain.swapchain_loader.acquire_next_image(
+                        swapchain.swapchain,
+                        u64::MAX,
+                        self.present_complete_semaphore[self.buffered_resource_number()],
+                        vk::Fence::null(),
+                    )
+                };
+                match result {
+                    // A suboptimal image can still be presented, so recreate after this frame
+                    Ok((present_index, suboptimal)) => {
+                        if suboptimal {
+                            *self.swapchain_out_of_date.borrow_mut() = true;
+                        }
+                        present_index as usize
+                    }
+                    Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                        *self.swapchain_out_of_date.borrow_mut() = true;
+                        return Ok(false);
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            // One offscreen image per frame in flight, so it is free once the fence is signalled
+            PresentTarget::Offscreen(_) => self.buffered_resource_number(),
+        };
+        *self.present_index.borrow_mut() = present_index;
+        self.resource_manager
+            .reset_transient(self.buffered_resource_number());
+        self.staging_pool
+            .borrow_mut()
+            .recycle(&self.resource_manager, self.buffered_resource_number());
 
         unsafe {
             self.vk_device
@@ -625,29 +1013,71 @@ impl GraphicsDevice {
                 &cmd_begin_info,
             )
         }?;
+        *self.recording_frame.borrow_mut() = true;
 
-        // Delete old image buffers
-        for buffer_to_delete in self.buffers_to_delete.borrow_mut().iter_mut() {
-            buffer_to_delete.1 -= 1;
+        self.bindless_manager.borrow_mut().release_retired_pools();
 
-            if buffer_to_delete.1 == 0 {
-                self.resource_manager.destroy_buffer(buffer_to_delete.0);
+        // Delete unloaded images once no frame in flight can still sample them
+        self.images_to_delete.borrow_mut().retain_mut(|image| {
+            image.frames_remaining -= 1;
+            if image.frames_remaining > 0 {
+                return true;
             }
-        }
-        self.buffers_to_delete.borrow_mut().clear();
+
+            self.resource_manager.destroy_image(image.image_handle);
+            if let Some(index) = image.bindless_index {
+                self.bindless_manager
+                    .borrow_mut()
+                    .release_bindless_index(index);
+            }
+            false
+        });
 
         // Upload images
-        // TODO: Remove buffers once upload has completed. Could use status enum so when fences are called, updates images that were submitted to being done.
-        // Can then clear done images from vec.
+        if !self.images_to_upload.borrow().is_empty() {
+            self.copy_image_uploads()?;
+        }
         for image in self.images_to_upload.borrow().iter() {
             profiling::scope!("Deferred Upload Image to GPU");
-            {
+
+            // Only the base level is uploaded, the rest are filtered by the renderer in compute
+            if image.mip_filter != MipFilter::Blit {
+                ImageBarrierBuilder::default()
+                    .add_image_barrier(ImageBarrier {
+                        image: AttachmentHandle::Image(image.image_handle),
+                        src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                        src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                        dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER
+                            | vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                        dst_access_mask: vk::AccessFlags2::SHADER_READ,
+                        old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        ..Default::default()
+                    })
+                    .build(
+                        self,
+                        &self.graphics_command_buffer[self.buffered_resource_number()],
+                    )?;
+                self.mip_requests.borrow_mut().push(MipRequest {
+                    image: image.image_handle,
+                    width: image.width,
+                    height: image.height,
+                    mip_levels: image.mip_levels,
+                    filter: image.mip_filter,
+                });
+            }
+            // Every level was supplied, so there is nothing to generate
+            else if image.supplied_levels == image.mip_levels {
                 ImageBarrierBuilder::default()
                     .add_image_barrier(ImageBarrier {
                         image: AttachmentHandle::Image(image.image_handle),
-                        dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
-                        dst_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
-                        new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                        src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                        dst_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                        dst_access_mask: vk::AccessFlags2::SHADER_READ,
+                        old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        base_mip_level: 0,
                         level_count: image.mip_levels,
                         image_layers: image.img_layers,
                         ..Default::default()
@@ -656,46 +1086,9 @@ impl GraphicsDevice {
                         self,
                         &self.graphics_command_buffer[self.buffered_resource_number()],
                     )?;
-
-                let copy_region = vk::BufferImageCopy::builder()
-                    .buffer_offset(0u64)
-                    .buffer_row_length(0u32)
-                    .buffer_image_height(0u32)
-                    .image_subresource(vk::ImageSubresourceLayers {
-                        aspect_mask: vk::ImageAspectFlags::COLOR,
-                        mip_level: 0u32,
-                        base_array_layer: 0u32,
-                        layer_count: image.img_layers,
-                    })
-                    .image_extent(vk::Extent3D {
-                        width: image.width,
-                        height: image.height,
-                        depth: 1,
-                    });
-
-                unsafe {
-                    self.vk_device.cmd_copy_buffer_to_image(
-                        self.graphics_command_buffer[self.buffered_resource_number()],
-                        self.resource_manager
-                            .get_buffer(image.buffer_handle)
-                            .unwrap()
-                            .buffer(),
-                        self.resource_manager
-                            .get_image(image.image_handle)
-                            .unwrap()
-                            .image(),
-                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                        &[*copy_region],
-                    );
-                }
-
-                self.buffers_to_delete
-                    .borrow_mut()
-                    .push((image.buffer_handle, 2));
             }
-
             // Generate mipmaps
-            {
+            else {
                 let mut mip_width = image.width;
                 let mut mip_height = image.height;
 
@@ -712,6 +1105,7 @@ impl GraphicsDevice {
                             base_mip_level: i - 1,
                             level_count: 1,
                             image_layers: image.img_layers,
+                            ..Default::default()
                         })
                         .build(
                             self,
@@ -762,7 +1156,7 @@ impl GraphicsDevice {
                             image_vk_handle,
                             ImageLayout::TRANSFER_DST_OPTIMAL,
                             &regions,
-                            vk::Filter::LINEAR,
+                            image.blit_filter,
                         )
                     }
 
@@ -778,6 +1172,7 @@ impl GraphicsDevice {
                             base_mip_level: i - 1,
                             level_count: 1,
                             image_layers: image.img_layers,
+                            ..Default::default()
                         })
                         .build(
                             self,
@@ -804,17 +1199,125 @@ impl GraphicsDevice {
                         base_mip_level: image.mip_levels - 1,
                         level_count: 1,
                         image_layers: image.img_layers,
+                        ..Default::default()
                     })
                     .build(
                         self,
                         &self.graphics_command_buffer[self.buffered_resource_number()],
                     )?;
             }
-            self.buffers_to_delete
-                .borrow_mut()
-                .push((image.buffer_handle, 2));
         }
         self.images_to_upload.borrow_mut().clear();
+        self.staging_pool
+            .borrow_mut()
+            .submit(self.buffered_resource_number());
+
+        Ok(true)
+    }
+
+    /// Records the staging buffer copies of every queued upload, leaving the images in
+    /// TRANSFER_DST_OPTIMAL on the graphics queue family. With a transfer queue the copies are
+    /// submitted there and ownership is handed to graphics, which waits on them in
+    /// [GraphicsDevice::end_frame]. Mips are always generated on graphics, as blits need a
+    /// graphics queue.
+    fn copy_image_uploads(&self) -> Result<()> {
+        profiling::scope!("Copy Image Uploads");
+
+        let frame = self.buffered_resource_number();
+        let cmd = match &self.transfer_context {
+            Some(context) => {
+                let cmd = context.command_buffer[frame];
+                unsafe {
+                    self.vk_device.reset_command_buffer(
+                        cmd,
+                        vk::CommandBufferResetFlags::RELEASE_RESOURCES,
+                    )?;
+
+                    let cmd_begin_info = vk::CommandBufferBeginInfo::builder()
+                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+                    self.vk_device.begin_command_buffer(cmd, &cmd_begin_info)?;
+                }
+                cmd
+            }
+            None => self.graphics_command_buffer[frame],
+        };
+
+        for image in self.images_to_upload.borrow().iter() {
+            ImageBarrierBuilder::default()
+                .add_image_barrier(ImageBarrier {
+                    image: AttachmentHandle::Image(image.image_handle),
+                    dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                    dst_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    level_count: image.mip_levels,
+                    image_layers: image.img_layers,
+                    ..Default::default()
+                })
+                .build(self, &cmd)?;
+
+            let vk_image = self
+                .resource_manager
+                .get_image(image.image_handle)
+                .unwrap()
+                .image();
+            for (buffer, copy_region) in image.copies.iter() {
+                unsafe {
+                    self.vk_device.cmd_copy_buffer_to_image(
+                        cmd,
+                        self.resource_manager.get_buffer(*buffer).unwrap().buffer(),
+                        vk_image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        std::slice::from_ref(copy_region),
+                    );
+                }
+            }
+        }
+
+        let context = match &self.transfer_context {
+            Some(context) => context,
+            None => return Ok(()),
+        };
+
+        // Release on the transfer queue, then acquire on graphics once the copies are done
+        let mut release_barriers = ImageBarrierBuilder::default();
+        let mut acquire_barriers = ImageBarrierBuilder::default();
+        for image in self.images_to_upload.borrow().iter() {
+            let ownership_barrier = ImageBarrier {
+                image: AttachmentHandle::Image(image.image_handle),
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                level_count: image.mip_levels,
+                image_layers: image.img_layers,
+                src_queue_family_index: context.queue_family_index,
+                dst_queue_family_index: self.graphics_queue_family_index,
+                ..Default::default()
+            };
+            release_barriers = release_barriers.add_image_barrier(ImageBarrier {
+                src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                ..ownership_barrier.clone()
+            });
+            acquire_barriers = acquire_barriers.add_image_barrier(ImageBarrier {
+                dst_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+                dst_access_mask: vk::AccessFlags2::TRANSFER_READ | vk::AccessFlags2::TRANSFER_WRITE,
+                ..ownership_barrier
+            });
+        }
+        release_barriers.build(self, &cmd)?;
+        unsafe { self.vk_device.end_command_buffer(cmd) }?;
+
+        let command_buffers = [cmd];
+        let signal_semaphores = [context.upload_complete_semaphore[frame]];
+        let submit_info = vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+        unsafe {
+            self.vk_device
+                .queue_submit(context.queue, &[*submit_info], vk::Fence::null())
+        }?;
+        *self.transfer_submitted.borrow_mut() = true;
+
+        acquire_barriers.build(self, &self.graphics_command_buffer[frame])?;
 
         Ok(())
     }
@@ -826,16 +1329,32 @@ impl GraphicsDevice {
             self.vk_device
                 .end_command_buffer(self.graphics_command_buffer())
         }?;
-
-        let wait_semaphores = [self.present_complete_semaphore()];
-        let wait_dst_stage_mask = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        *self.recording_frame.borrow_mut() = false;
+
+        let headless = self.is_headless();
+        let mut wait_semaphores = Vec::new();
+        let mut wait_dst_stage_mask = Vec::new();
+        // Nothing is acquired or presented without a swapchain
+        if !headless {
+            wait_semaphores.push(self.present_complete_semaphore());
+            wait_dst_stage_mask.push(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+        }
+        if self.transfer_submitted.replace(false) {
+            if let Some(context) = &self.transfer_context {
+                wait_semaphores
+                    .push(context.upload_complete_semaphore[self.buffered_resource_number()]);
+                wait_dst_stage_mask.push(vk::PipelineStageFlags::TRANSFER);
+            }
+        }
         let command_buffers = [self.graphics_command_buffer()];
         let signal_semaphores = [self.rendering_complete_semaphore()];
-        let submit_info = vk::SubmitInfo::builder()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&wait_dst_stage_mask)
+        let mut submit_info = vk::SubmitInfo::builder()
             .command_buffers(&command_buffers)
-            .signal_semaphores(&signal_semaphores);
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_dst_stage_mask);
+        if !headless {
+            submit_info = submit_info.signal_semaphores(&signal_semaphores);
+        }
 
         let submits = [*submit_info];
         let result = unsafe {
@@ -855,7 +1374,7 @@ impl GraphicsDevice {
                 self.vk_device.get_query_pool_results(
                     self.query_pool,
                     0,
-                    *self.timestamp_frame_count.borrow() as u32,
+                    (*self.timestamp_frame_count.borrow() as u32).min(QUERY_COUNT),
                     &mut query_pool_results,
                     vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
                 )
@@ -873,27 +1392,39 @@ impl GraphicsDevice {
             Some(timestamps) => *self.timestamps.borrow_mut() = timestamps,
         }
 
-        let wait_semaphores = [self.rendering_complete_semaphore[self.buffered_resource_number()]];
-        let swapchains = [self.swapchain.borrow().swapchain];
-        let image_indices = [self.present_index() as u32];
-        let present_info = vk::PresentInfoKHR::builder()
-            .wait_semaphores(&wait_semaphores)
-            .swapchains(&swapchains)
-            .image_indices(&image_indices);
-
-        unsafe {
-            self.swapchain
-                .borrow()
-                .swapchain_loader
-                .queue_present(self.graphics_queue, &present_info)
-        }?;
+        if !headless {
+            let wait_semaphores = [self.acquire_swapchain_image_on_present_queue()?];
+            if let PresentTarget::Window { swapchain, .. } = &*self.present_target.borrow() {
+                let swapchains = [swapchain.swapchain];
+                let image_indices = [self.present_index() as u32];
+                let present_info = vk::PresentInfoKHR::builder()
+                    .wait_semaphores(&wait_semaphores)
+                    .swapchains(&swapchains)
+                    .image_indices(&image_indices);
+
+                let result = unsafe {
+                    swapchain
+                        .swapchain_loader
+                        .queue_present(self.present_queue, &present_info)
+                };
+                match result {
+                    Ok(false) => {}
+                    Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                        *self.swapchain_out_of_date.borrow_mut() = true;
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+        }
 
         *self.frame_number.borrow_mut() += 1usize;
         Ok(())
     }
 
     pub fn resize(&self, new_size: winit::dpi::PhysicalSize<u32>) -> Result<bool> {
-        if new_size.width == 0u32 || new_size.height == 0u32 || new_size == self.size() {
+        let minimized = new_size.width == 0u32 || new_size.height == 0u32;
+        *self.minimized.borrow_mut() = minimized;
+        if minimized || new_size == self.size() {
             return Ok(false);
         }
 
@@ -902,65 +1433,148 @@ impl GraphicsDevice {
         unsafe { self.vk_device.device_wait_idle() }?;
         *self.size.borrow_mut() = new_size;
 
-        // Destroy old swapchain
+        self.recreate_swapchain()?;
 
-        unsafe {
-            self.swapchain
-                .borrow()
-                .swapchain_loader
-                .destroy_swapchain(self.swapchain.borrow().swapchain, None);
+        info!("Recreating swapchain.");
+        Ok(true)
+    }
 
-            for &image_view in self.swapchain.borrow().present_image_views.iter() {
-                self.vk_device.destroy_image_view(image_view, None);
+    /// True when the swapchain no longer matches the window surface and has to be recreated with
+    /// [GraphicsDevice::recreate_out_of_date_swapchain].
+    pub fn is_swapchain_out_of_date(&self) -> bool {
+        *self.swapchain_out_of_date.borrow()
+    }
+
+    /// Recreates the swapchain at the surface's current size. Returns false if it couldn't be,
+    /// because the surface has a zero size while minimized.
+    pub fn recreate_out_of_date_swapchain(&self) -> Result<bool> {
+        let extent = match &*self.present_target.borrow() {
+            PresentTarget::Window { surface, .. } => {
+                unsafe {
+                    surface
+                        .surface_loader
+                        .get_physical_device_surface_capabilities(self.pdevice, surface.surface)
+                }?
+                .current_extent
             }
+            PresentTarget::Offscreen(_) => return Ok(false),
+        };
+        // The surface takes the size of the swapchain when it has no extent of its own
+        let new_size = match extent.width {
+            u32::MAX => self.size(),
+            _ => PhysicalSize::new(extent.width, extent.height),
+        };
+        if new_size.width == 0u32 || new_size.height == 0u32 {
+            return Ok(false);
         }
 
-        // Create swapchain
-        let surface_capabilities = unsafe {
-            self.surface
-                .borrow()
-                .surface_loader
-                .get_physical_device_surface_capabilities(
+        profiling::scope!("Recreate Out Of Date Swapchain");
+
+        unsafe { self.vk_device.device_wait_idle() }?;
+        *self.size.borrow_mut() = new_size;
+        self.recreate_swapchain()?;
+        *self.swapchain_out_of_date.borrow_mut() = false;
+
+        info!("Recreating out of date swapchain.");
+        Ok(true)
+    }
+
+    /// Sets how finished frames are shown, recreating the swapchain if it changes.
+    /// Falls back to the next best mode if the surface doesn't support the requested one.
+    pub fn set_present_mode(&self, present_mode: PresentMode) -> Result<()> {
+        if present_mode == self.present_mode() {
+            return Ok(());
+        }
+
+        unsafe { self.vk_device.device_wait_idle() }?;
+        *self.present_mode.borrow_mut() = present_mode;
+        self.recreate_swapchain()?;
+
+        info!("Recreating swapchain with present mode {:?}.", present_mode);
+        Ok(())
+    }
+
+    /// The requested present mode, which may differ from the one in use if it isn't supported.
+    pub fn present_mode(&self) -> PresentMode {
+        *self.present_mode.borrow()
+    }
+
+    /// Destroys the swapchain and creates it again at the current size. The device must be idle.
+    fn recreate_swapchain(&self) -> Result<()> {
+        let present_mode = self.present_mode();
+        let mut present_target = self.present_target.borrow_mut();
+        match &mut *present_target {
+            PresentTarget::Window { surface, swapchain } => {
+                // Destroy old swapchain
+
+                unsafe {
+                    swapchain
+                        .swapchain_loader
+                        .destroy_swapchain(swapchain.swapchain, None);
+
+                    for &image_view in swapchain.present_image_views.iter() {
+                        self.vk_device.destroy_image_view(image_view, None);
+                    }
+                }
+
+                // Create swapchain
+                let surface_capabilities = unsafe {
+                    surface
+                        .surface_loader
+                        .get_physical_device_surface_capabilities(self.pdevice, surface.surface)
+                }?;
+                let mut desired_image_count = surface_capabilities.min_image_count + 1;
+                if surface_capabilities.max_image_count > 0
+                    && desired_image_count > surface_capabilities.max_image_count
+                {
+                    desired_image_count = surface_capabilities.max_image_count;
+                }
+                surface.surface_resolution = match surface_capabilities.current_extent.width {
+                    u32::MAX => vk::Extent2D {
+                        width: self.size().width,
+                        height: self.size().height,
+                    },
+                    _ => surface_capabilities.current_extent,
+                };
+                let pre_transform = if surface_capabilities
+                    .supported_transforms
+                    .contains(vk::SurfaceTransformFlagsKHR::IDENTITY)
+                {
+                    vk::SurfaceTransformFlagsKHR::IDENTITY
+                } else {
+                    surface_capabilities.current_transform
+                };
+                let loader = swapchain.swapchain_loader.clone();
+                *swapchain = Swapchain::new(
+                    &self.vk_device,
+                    loader,
                     self.pdevice,
-                    self.surface.borrow().surface,
-                )
-        }?;
-        let mut desired_image_count = surface_capabilities.min_image_count + 1;
-        if surface_capabilities.max_image_count > 0
-            && desired_image_count > surface_capabilities.max_image_count
-        {
-            desired_image_count = surface_capabilities.max_image_count;
+                    surface,
+                    pre_transform,
+                    desired_image_count,
+                    present_mode,
+                )?;
+            }
+            PresentTarget::Offscreen(offscreen) => {
+                for &image in offscreen.images.iter() {
+                    self.resource_manager.destroy_image(image);
+                }
+                *offscreen = OffscreenTarget::new(
+                    &self.resource_manager,
+                    self.frames_in_flight,
+                    vk::Extent2D {
+                        width: self.size().width,
+                        height: self.size().height,
+                    },
+                );
+            }
         }
-        self.surface.borrow_mut().surface_resolution =
-            match surface_capabilities.current_extent.width {
-                u32::MAX => vk::Extent2D {
-                    width: self.size().width,
-                    height: self.size().height,
-                },
-                _ => surface_capabilities.current_extent,
-            };
-        let pre_transform = if surface_capabilities
-            .supported_transforms
-            .contains(vk::SurfaceTransformFlagsKHR::IDENTITY)
-        {
-            vk::SurfaceTransformFlagsKHR::IDENTITY
-        } else {
-            surface_capabilities.current_transform
-        };
-        let loader = self.swapchain.borrow().swapchain_loader.clone();
-        self.swapchain.replace(Swapchain::new(
-            &self.vk_device,
-            loader,
-            self.pdevice,
-            &self.surface.borrow(),
-            pre_transform,
-            desired_image_count,
-        )?);
 
-        info!("Recreating swapchain.");
-        Ok(true)
+        Ok(())
     }
 
+    /// Loads an image from its base level, generating the other `mip_levels` with `mip_filter`.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn load_image(
         &self,
         img_bytes: &[u8],
@@ -969,39 +1583,194 @@ impl GraphicsDevice {
         image_type: &ImageFormatType,
         mip_levels: u32,
         img_layers: u32,
+        mip_filter: MipFilter,
     ) -> Result<ImageHandle> {
         profiling::scope!("Load Image");
 
-        let img_size = (img_width * img_height * 4u32 * img_layers) as DeviceSize;
+        let format = image_type.format();
+        let img_size =
+            (img_width * img_height * image_type.bytes_per_pixel() * img_layers) as DeviceSize;
+        ensure!(
+            img_bytes.len() == img_size as usize,
+            "Image data is {} bytes, expected {} for {:?}",
+            img_bytes.len(),
+            img_size,
+            format
+        );
+
+        self.upload_image(
+            &[img_bytes],
+            img_width,
+            img_height,
+            format,
+            mip_levels,
+            img_layers,
+            mip_filter,
+        )
+    }
+
+    /// Loads an image whose mip chain is already supplied, such as block compressed data read
+    /// from a KTX2 file. No mips are generated on the GPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `levels` - Data for each mip level starting at the base level. Each level holds every layer.
+    pub(crate) fn load_image_with_mips(
+        &self,
+        levels: &[Vec<u8>],
+        img_width: u32,
+        img_height: u32,
+        format: vk::Format,
+        img_layers: u32,
+    ) -> Result<ImageHandle> {
+        profiling::scope!("Load Image With Mips");
+
+        ensure!(!levels.is_empty(), "Image has no mip levels");
+        ensure!(
+            !is_bc_format(format) || self.features.texture_compression_bc == vk::TRUE,
+            "{:?} needs textureCompressionBC, which this device doesn't support",
+            format
+        );
+
+        let levels: Vec<&[u8]> = levels.iter().map(Vec::as_slice).collect();
+        self.upload_image(
+            &levels,
+            img_width,
+            img_height,
+            format,
+            levels.len() as u32,
+            img_layers,
+            MipFilter::Blit,
+        )
+    }
 
-        let staging_buffer_create_info = BufferCreateInfo {
-            size: img_size as usize,
-            usage: vk::BufferUsageFlags::TRANSFER_SRC,
-            storage_type: BufferStorageType::HostLocal,
+    /// Creates an image and queues the staged data to be copied into it at the start of the next
+    /// frame. Levels past those in `levels` are generated with `mip_filter`, falling back to
+    /// blitting for images the compute filters can't write.
+    #[allow(clippy::too_many_arguments)]
+    fn upload_image(
+        &self,
+        levels: &[&[u8]],
+        img_width: u32,
+        img_height: u32,
+        format: vk::Format,
+        mip_levels: u32,
+        img_layers: u32,
+        mip_filter: MipFilter,
+    ) -> Result<ImageHandle> {
+        // Blitting mips with a linear filter is optional per format, so fall back to nearest
+        let format_properties = unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.pdevice, format)
+        };
+        ensure!(
+            format_properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE),
+            "{:?} can't be sampled on this device",
+            format
+        );
+        let blit_filter = if format_properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+        {
+            vk::Filter::LINEAR
+        } else {
+            vk::Filter::NEAREST
+        };
+        let storage_format_properties = unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.pdevice, storage_format(format))
+        };
+        let compute_mips = mip_filter != MipFilter::Blit
+            && levels.len() < mip_levels as usize
+            && img_layers == 1
+            && downsample_shader(format).is_some()
+            && storage_format_properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::STORAGE_IMAGE);
+        let mip_filter = if compute_mips {
+            mip_filter
+        } else {
+            MipFilter::Blit
         };
 
-        let staging_buffer = self
-            .resource_manager
-            .create_buffer(&staging_buffer_create_info);
+        // Each layer is copied separately, split into bands of rows if it doesn't fit in a
+        // staging buffer. Block compressed rows are 4 texels high.
+        let block_height = if is_bc_format(format) { 4 } else { 1 };
+        let mut staging_pool = self.staging_pool.borrow_mut();
+        let mut copies = Vec::new();
+        for (level, level_bytes) in levels.iter().enumerate() {
+            ensure!(
+                !level_bytes.is_empty() && level_bytes.len() % img_layers as usize == 0,
+                "Mip level {} has {} bytes, which can't be split into {} layers",
+                level,
+                level_bytes.len(),
+                img_layers
+            );
+            let level_width = (img_width >> level).max(1);
+            let level_height = (img_height >> level).max(1);
+            let layer_size = level_bytes.len() / img_layers as usize;
+            let block_rows = ((level_height + block_height - 1) / block_height) as usize;
+            let row_size = layer_size / block_rows;
+            ensure!(
+                row_size > 0 && row_size * block_rows == layer_size,
+                "Mip level {} has {} bytes per layer, which can't be split into {} rows",
+                level,
+                layer_size,
+                block_rows
+            );
+            ensure!(
+                row_size <= staging_pool.buffer_size,
+                "A row of mip level {} is {} bytes, larger than the {} byte staging buffers",
+                level,
+                row_size,
+                staging_pool.buffer_size
+            );
+            let rows_per_copy = (staging_pool.buffer_size / row_size).min(block_rows);
+
+            for (layer, layer_bytes) in level_bytes.chunks_exact(layer_size).enumerate() {
+                for first_row in (0..block_rows).step_by(rows_per_copy) {
+                    let rows = rows_per_copy.min(block_rows - first_row);
+                    let (buffer, offset) = staging_pool.stage(
+                        &self.resource_manager,
+                        &layer_bytes[first_row * row_size..(first_row + rows) * row_size],
+                    )?;
 
-        self.resource_manager
-            .get_buffer(staging_buffer)
-            .unwrap()
-            .view()
-            .mapped_slice()?
-            .copy_from_slice(img_bytes);
-
-        let format = {
-            match image_type {
-                ImageFormatType::Default => vk::Format::R8G8B8A8_SRGB,
-                ImageFormatType::Normal => vk::Format::R8G8B8A8_UNORM,
+                    let y = first_row as u32 * block_height;
+                    let copy_region = vk::BufferImageCopy::builder()
+                        .buffer_offset(offset)
+                        .buffer_row_length(0u32)
+                        .buffer_image_height(0u32)
+                        .image_subresource(vk::ImageSubresourceLayers {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            mip_level: level as u32,
+                            base_array_layer: layer as u32,
+                            layer_count: 1,
+                        })
+                        .image_offset(vk::Offset3D {
+                            x: 0,
+                            y: y as i32,
+                            z: 0,
+                        })
+                        .image_extent(vk::Extent3D {
+                            width: level_width,
+                            height: (rows as u32 * block_height).min(level_height - y),
+                            depth: 1,
+                        });
+                    copies.push((buffer, *copy_region));
+                }
             }
-        };
+        }
+        drop(staging_pool);
 
         // TODO : Refactor all this to work off cube type instead of assuming based on layers
         let flags = {
             if img_layers > 1 {
                 ImageCreateFlags::CUBE_COMPATIBLE
+            } else if compute_mips && storage_format(format) != format {
+                // Written through a view of another format, which is the one that supports storage
+                ImageCreateFlags::MUTABLE_FORMAT | ImageCreateFlags::EXTENDED_USAGE
             } else {
                 ImageCreateFlags::empty()
             }
@@ -1009,11 +1778,15 @@ impl GraphicsDevice {
 
         let image_create_info = vk::ImageCreateInfo::builder()
             .format(format)
-            .usage(
+            .usage(if compute_mips {
+                vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::STORAGE
+            } else {
                 vk::ImageUsageFlags::SAMPLED
                     | vk::ImageUsageFlags::TRANSFER_SRC
-                    | vk::ImageUsageFlags::TRANSFER_DST,
-            )
+                    | vk::ImageUsageFlags::TRANSFER_DST
+            })
             .extent(vk::Extent3D {
                 width: img_width,
                 height: img_height,
@@ -1027,20 +1800,27 @@ impl GraphicsDevice {
             .flags(flags);
 
         let image = self.resource_manager.create_image(&image_create_info);
+        if let Err(err) = self
+            .bindless_manager
+            .borrow_mut()
+            .add_image_to_bindless(&image)
+        {
+            self.resource_manager.destroy_image(image);
+            return Err(err);
+        }
 
         self.images_to_upload.borrow_mut().push(ImageToUpload {
-            buffer_handle: staging_buffer,
             image_handle: image,
             width: img_width,
             height: img_height,
             mip_levels,
             img_layers,
+            supplied_levels: levels.len() as u32,
+            copies,
+            blit_filter,
+            mip_filter,
         });
 
-        self.bindless_manager
-            .borrow_mut()
-            .add_image_to_bindless(&image);
-
         Ok(image)
     }
 
@@ -1093,6 +1873,93 @@ impl GraphicsDevice {
         self.graphics_queue
     }
 
+    pub fn graphics_queue_family_index(&self) -> u32 {
+        self.graphics_queue_family_index
+    }
+
+    /// Returns the queue family swapchain images are presented from.
+    /// This is the graphics queue family unless the device needed a separate present family.
+    pub fn present_queue_family_index(&self) -> u32 {
+        self.present_context
+            .as_ref()
+            .map(|context| context.queue_family_index)
+            .unwrap_or(self.graphics_queue_family_index)
+    }
+
+    pub fn present_queue(&self) -> vk::Queue {
+        self.present_queue
+    }
+
+    /// Completes the ownership transfer of the swapchain image from the graphics queue family
+    /// to the present queue family. The release half is recorded by the renderer in the
+    /// barrier that transitions the swapchain image from COLOR_ATTACHMENT_OPTIMAL to
+    /// PRESENT_SRC_KHR.
+    ///
+    /// Returns the semaphore that presentation should wait on.
+    fn acquire_swapchain_image_on_present_queue(&self) -> Result<vk::Semaphore> {
+        let context = match &self.present_context {
+            Some(context) => context,
+            None => return Ok(self.rendering_complete_semaphore()),
+        };
+
+        let frame = self.buffered_resource_number();
+        let cmd = context.command_buffer[frame];
+        unsafe {
+            self.vk_device
+                .wait_for_fences(&[context.fence[frame]], true, u64::MAX)?;
+            self.vk_device.reset_fences(&[context.fence[frame]])?;
+            self.vk_device
+                .reset_command_buffer(cmd, vk::CommandBufferResetFlags::RELEASE_RESOURCES)?;
+
+            let cmd_begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            self.vk_device.begin_command_buffer(cmd, &cmd_begin_info)?;
+        }
+
+        let acquire_barrier = vk::ImageMemoryBarrier2::builder()
+            .src_stage_mask(vk::PipelineStageFlags2::NONE)
+            .src_access_mask(vk::AccessFlags2::NONE)
+            .dst_stage_mask(vk::PipelineStageFlags2::NONE)
+            .dst_access_mask(vk::AccessFlags2::NONE)
+            .old_layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .new_layout(ImageLayout::PRESENT_SRC_KHR)
+            .src_queue_family_index(self.graphics_queue_family_index)
+            .dst_queue_family_index(context.queue_family_index)
+            .image(self.get_present_image())
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+        let image_memory_barriers = [*acquire_barrier];
+        let dependency_info =
+            vk::DependencyInfo::builder().image_memory_barriers(&image_memory_barriers);
+
+        unsafe {
+            self.vk_device.cmd_pipeline_barrier2(cmd, &dependency_info);
+            self.vk_device.end_command_buffer(cmd)?;
+        }
+
+        let wait_semaphores = [self.rendering_complete_semaphore()];
+        let wait_dst_stage_mask = [vk::PipelineStageFlags::ALL_COMMANDS];
+        let command_buffers = [cmd];
+        let signal_semaphores = [context.ownership_semaphore[frame]];
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_dst_stage_mask)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+
+        unsafe {
+            self.vk_device
+                .queue_submit(self.present_queue, &[*submit_info], context.fence[frame])
+        }?;
+
+        Ok(context.ownership_semaphore[frame])
+    }
+
     pub fn graphics_command_buffer(&self) -> vk::CommandBuffer {
         self.graphics_command_buffer[self.buffered_resource_number()]
     }
@@ -1109,21 +1976,25 @@ impl GraphicsDevice {
         self.present_complete_semaphore[self.buffered_resource_number()]
     }
 
+    /// Names a Vulkan object for debuggers and validation messages. Does nothing when validation is
+    /// disabled.
     pub fn set_vulkan_debug_name(
         &self,
         object_handle: u64,
         object_type: ObjectType,
         debug_name: &str,
     ) -> Result<()> {
-        let object_name = CString::new(debug_name).unwrap();
-        let pipeline_debug_info = DebugUtilsObjectNameInfoEXT::builder()
-            .object_type(object_type)
-            .object_handle(object_handle)
-            .object_name(object_name.as_ref());
-
-        unsafe {
-            self.debug_utils_loader
-                .set_debug_utils_object_name(self.vk_device.handle(), &pipeline_debug_info)?;
+        if let Some((debug_utils_loader, ..)) = &self.debug_utils {
+            let object_name = CString::new(debug_name).unwrap();
+            let pipeline_debug_info = DebugUtilsObjectNameInfoEXT::builder()
+                .object_type(object_type)
+                .object_handle(object_handle)
+                .object_name(object_name.as_ref());
+
+            unsafe {
+                debug_utils_loader
+                    .set_debug_utils_object_name(self.vk_device.handle(), &pipeline_debug_info)?;
+            }
         }
         Ok(())
     }
@@ -1134,21 +2005,21 @@ impl GraphicsDevice {
         colour: [f32; 4],
         label: &str,
     ) {
-        let object_name = CString::new(label).unwrap();
-        let label = vk::DebugUtilsLabelEXT::builder()
-            .label_name(&object_name)
-            .color(colour);
-
-        unsafe {
-            self.debug_utils_loader
-                .cmd_begin_debug_utils_label(command_buffer, &label);
+        if let Some((debug_utils_loader, ..)) = &self.debug_utils {
+            let object_name = CString::new(label).unwrap();
+            let label = vk::DebugUtilsLabelEXT::builder()
+                .label_name(&object_name)
+                .color(colour);
+
+            unsafe {
+                debug_utils_loader.cmd_begin_debug_utils_label(command_buffer, &label);
+            }
         }
     }
 
     pub fn cmd_end_label(&self, command_buffer: vk::CommandBuffer) {
-        unsafe {
-            self.debug_utils_loader
-                .cmd_end_debug_utils_label(command_buffer);
+        if let Some((debug_utils_loader, ..)) = &self.debug_utils {
+            unsafe { debug_utils_loader.cmd_end_debug_utils_label(command_buffer) };
         }
     }
 
@@ -1159,15 +2030,65 @@ impl GraphicsDevice {
     ) -> TimeStampIndex {
         let mut timestamp_count = self.timestamp_frame_count.borrow_mut();
         let count = *timestamp_count as u32;
-        unsafe {
-            self.vk_device
-                .cmd_write_timestamp2(cmd, stage, self.query_pool, count);
+        // Past the end of the pool the index is still handed out, but never has a result
+        if count < QUERY_COUNT {
+            unsafe {
+                self.vk_device
+                    .cmd_write_timestamp2(cmd, stage, self.query_pool, count);
+            }
         }
         let timestamp_index = TimeStampIndex(*timestamp_count);
         *timestamp_count += 1;
         timestamp_index
     }
 
+    /// The raw GPU tick value of a timestamp read back last frame.
+    pub fn get_raw_timestamp(&self, index: TimeStampIndex) -> Option<u64> {
+        self.timestamps.borrow().get(index.0).copied()
+    }
+
+    /// Reads the GPU's current timestamp, waiting for a single timestamp write to complete.
+    pub fn query_gpu_timestamp(&self) -> Result<u64> {
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(1);
+        let query_pool = unsafe { self.vk_device.create_query_pool(&create_info, None) }?;
+        unsafe {
+            self.vk_device.reset_query_pool(query_pool, 0, 1);
+        }
+
+        let result = self
+            .immediate_submit(|device, cmd| {
+                unsafe {
+                    device.vk_device.cmd_write_timestamp2(
+                        *cmd,
+                        vk::PipelineStageFlags2::TOP_OF_PIPE,
+                        query_pool,
+                        0,
+                    );
+                }
+                Ok(())
+            })
+            .and_then(|_| {
+                let mut timestamp = [0u64; 1];
+                unsafe {
+                    self.vk_device.get_query_pool_results(
+                        query_pool,
+                        0,
+                        1,
+                        &mut timestamp,
+                        vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+                    )
+                }?;
+                Ok(timestamp[0])
+            });
+
+        unsafe {
+            self.vk_device.destroy_query_pool(query_pool, None);
+        }
+        result
+    }
+
     pub fn timestamp_period(&self) -> f32 {
         self.timestamp_period
     }
@@ -1198,18 +2119,274 @@ impl GraphicsDevice {
         self.bindless_descriptor_set_layout
     }
 
+    /// This frame's bindless set. Adding an image can replace the sets when they're full, so this
+    /// has to be fetched again rather than kept across frames.
     pub fn bindless_descriptor_set(&self) -> vk::DescriptorSet {
-        self.bindless_descriptor_set[self.buffered_resource_number()]
+        self.bindless_manager.borrow().descriptor_set[self.buffered_resource_number()]
+    }
+
+    /// Textures the bindless sets currently have room for.
+    pub fn bindless_capacity(&self) -> u32 {
+        self.bindless_manager.borrow().capacity()
+    }
+
+    pub(crate) fn add_image_to_bindless(&self, image: &ImageHandle) -> Result<()> {
+        self.bindless_manager
+            .borrow_mut()
+            .add_image_to_bindless(image)
     }
 
     pub fn get_descriptor_index(&self, image: &ImageHandle) -> Option<usize> {
         self.bindless_manager.borrow().get_bindless_index(image)
     }
+
+    /// Takes the images uploaded at the start of this frame whose mips are left for
+    /// [crate::mipgen::MipGenerator].
+    pub(crate) fn take_mip_requests(&self) -> Vec<MipRequest> {
+        self.mip_requests.take()
+    }
+
+    /// Exchanges the images behind two handles along with their bindless slots and queued uploads,
+    /// so `a` refers to what was `b` and the other way around. Frames in flight keep sampling the
+    /// slot they were recorded with.
+    pub(crate) fn swap_images(&self, a: ImageHandle, b: ImageHandle) {
+        self.resource_manager.swap_images(a, b);
+        self.bindless_manager.borrow_mut().swap_images(&a, &b);
+        for upload in self.images_to_upload.borrow_mut().iter_mut() {
+            if upload.image_handle == a {
+                upload.image_handle = b;
+            } else if upload.image_handle == b {
+                upload.image_handle = a;
+            }
+        }
+    }
+
+    /// Queues an image for destruction once every frame in flight has finished with it. The image
+    /// is removed from the bindless set straight away, and its slot is reused after it is freed.
+    pub(crate) fn unload_image(&self, image: ImageHandle) -> Result<()> {
+        ensure!(
+            self.resource_manager.get_image(image).is_some(),
+            "Image has already been destroyed"
+        );
+        ensure!(
+            !self
+                .images_to_delete
+                .borrow()
+                .iter()
+                .any(|pending| pending.image_handle == image),
+            "Image is already queued for unloading"
+        );
+
+        // Never uploaded, its staged data is recycled along with the rest of the staging pool
+        self.images_to_upload
+            .borrow_mut()
+            .retain(|upload| upload.image_handle != image);
+        self.mip_requests
+            .borrow_mut()
+            .retain(|request| request.image != image);
+
+        let bindless_index = self
+            .bindless_manager
+            .borrow_mut()
+            .remove_image_from_bindless(&image);
+        self.images_to_delete.borrow_mut().push(ImageToDelete {
+            image_handle: image,
+            bindless_index,
+            frames_remaining: self.frames_in_flight,
+        });
+
+        Ok(())
+    }
+
+    /// Number of error messages reported by the validation layers so far, not counting suppressed
+    /// ones. Always zero when validation is disabled.
+    pub fn validation_error_count(&self) -> usize {
+        self.debug_utils
+            .as_ref()
+            .map_or(0, |(_, _, state)| state.error_count.load(Ordering::Relaxed))
+    }
+
+    /// Core features enabled on the logical device.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.resource_manager.memory_stats()
+    }
+
+    pub fn enabled_features(&self) -> vk::PhysicalDeviceFeatures {
+        self.features
+    }
+
+    /// Returns the highest sample count, up to `requested`, that the device supports for both colour
+    /// and depth attachments.
+    pub fn get_supported_sample_count(&self, requested: u32) -> vk::SampleCountFlags {
+        let limits = unsafe {
+            self.instance
+                .get_physical_device_properties(self.pdevice)
+                .limits
+        };
+        let supported =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+        [
+            vk::SampleCountFlags::TYPE_64,
+            vk::SampleCountFlags::TYPE_32,
+            vk::SampleCountFlags::TYPE_16,
+            vk::SampleCountFlags::TYPE_8,
+            vk::SampleCountFlags::TYPE_4,
+            vk::SampleCountFlags::TYPE_2,
+        ]
+        .into_iter()
+        .find(|&count| count.as_raw() <= requested && supported.contains(count))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
+
+    /// Returns true if the physical device supports creating an optimally tiled 2D image
+    /// with the format and usage.
+    pub fn is_image_format_supported(
+        &self,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+    ) -> bool {
+        unsafe {
+            self.instance.get_physical_device_image_format_properties(
+                self.pdevice,
+                format,
+                vk::ImageType::TYPE_2D,
+                vk::ImageTiling::OPTIMAL,
+                usage,
+                ImageCreateFlags::empty(),
+            )
+        }
+        .is_ok()
+    }
+
+    /// Checks that a render target can be created with the format and usage, so unsupported
+    /// formats are reported up front rather than failing during image creation.
+    ///
+    /// # Arguments
+    ///
+    /// * `format`: Format of the render target.
+    /// * `usage`: Usage flags the render target will be created with.
+    pub fn validate_render_target_format(
+        &self,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+    ) -> Result<()> {
+        if self.is_image_format_supported(format, usage) {
+            return Ok(());
+        }
+
+        let candidates: &[vk::Format] =
+            if usage.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+                &[
+                    vk::Format::D32_SFLOAT,
+                    vk::Format::D32_SFLOAT_S8_UINT,
+                    vk::Format::D24_UNORM_S8_UINT,
+                    vk::Format::D16_UNORM,
+                ]
+            } else {
+                &[
+                    vk::Format::R32G32B32A32_SFLOAT,
+                    vk::Format::R16G16B16A16_SFLOAT,
+                    vk::Format::R8G8B8A8_UNORM,
+                    vk::Format::R8G8B8A8_SRGB,
+                    vk::Format::B8G8R8A8_UNORM,
+                    vk::Format::B8G8R8A8_SRGB,
+                ]
+            };
+
+        // Candidates are ordered by precision, so prefer the closest format below the requested one
+        let requested_position = candidates.iter().position(|&candidate| candidate == format);
+        let suggestion = candidates
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| requested_position.map_or(true, |position| *index > position))
+            .chain(candidates.iter().enumerate())
+            .map(|(_, &candidate)| candidate)
+            .find(|&candidate| {
+                candidate != format && self.is_image_format_supported(candidate, usage)
+            });
+
+        match suggestion {
+            Some(suggestion) => bail!(
+                "GPU does not support {:?} with usage {:?} as a render target. Nearest supported format is {:?}",
+                format,
+                usage,
+                suggestion
+            ),
+            None => bail!(
+                "GPU does not support {:?} with usage {:?} as a render target",
+                format,
+                usage
+            ),
+        }
+    }
 }
 
 impl GraphicsDevice {
     pub fn default_sampler(&self) -> vk::Sampler {
-        self.default_sampler
+        self.material_sampler(SamplerKind::Nearest)
+    }
+
+    pub fn material_sampler(&self, kind: SamplerKind) -> vk::Sampler {
+        self.material_samplers.borrow()[kind as usize]
+    }
+
+    /// Index into the bindless sampler array for a material. Materials capping their anisotropy
+    /// use the lower of their cap and the device's level.
+    pub(crate) fn material_sampler_index(
+        &self,
+        kind: SamplerKind,
+        max_anisotropy: Option<AnisotropyLevel>,
+    ) -> i32 {
+        match max_anisotropy {
+            Some(max_anisotropy) => {
+                let level = max_anisotropy.min(self.anisotropy());
+                (FIXED_SAMPLER_COUNT + level as usize * SamplerKind::COUNT + kind as usize) as i32
+            }
+            None => kind.bindless_index(),
+        }
+    }
+
+    /// Sets the anisotropic filtering used by the material texture samplers, recreating them and
+    /// pointing the bindless sampler slots at the new ones. The level is clamped to what the
+    /// device supports.
+    pub fn set_anisotropy(&self, anisotropy: AnisotropyLevel) -> Result<()> {
+        if anisotropy == self.anisotropy() {
+            return Ok(());
+        }
+
+        unsafe { self.vk_device.device_wait_idle() }?;
+        let samplers = create_material_samplers(
+            &self.vk_device,
+            anisotropy.clamped_samples(self.max_sampler_anisotropy),
+        )?;
+        for kind in SamplerKind::ALL {
+            self.bindless_manager
+                .borrow_mut()
+                .set_sampler(kind.bindless_index() as usize, samplers[kind as usize]);
+        }
+        let old_samplers = self.material_samplers.replace(samplers);
+        for sampler in old_samplers {
+            unsafe { self.vk_device.destroy_sampler(sampler, None) };
+        }
+        *self.anisotropy.borrow_mut() = anisotropy;
+
+        info!(
+            "Material sampler anisotropy set to {:?} ({}x).",
+            anisotropy,
+            anisotropy.clamped_samples(self.max_sampler_anisotropy)
+        );
+        Ok(())
+    }
+
+    /// The requested anisotropy level, which may be above what the device supports.
+    pub fn anisotropy(&self) -> AnisotropyLevel {
+        *self.anisotropy.borrow()
+    }
+
+    /// The highest anisotropy the device's samplers support. 1.0 when anisotropy is unsupported.
+    pub fn max_sampler_anisotropy(&self) -> f32 {
+        self.max_sampler_anisotropy
     }
     pub fn shadow_sampler(&self) -> vk::Sampler {
         self.shadow_sampler
@@ -1217,11 +2394,37 @@ impl GraphicsDevice {
     pub fn ui_sampler(&self) -> vk::Sampler {
         self.ui_sampler
     }
+    pub fn linear_clamp_sampler(&self) -> vk::Sampler {
+        self.linear_clamp_sampler
+    }
     pub fn skybox_sampler(&self) -> vk::Sampler {
         self.skybox_sampler
     }
 }
 
+/// Raw Vulkan handles for interop with external libraries.
+///
+/// These are an advanced escape hatch. The device still owns every handle returned here,
+/// so they must not be destroyed, and any work recorded or submitted with them has to be
+/// synchronised with the renderer by the caller.
+impl GraphicsDevice {
+    pub fn raw_instance(&self) -> &ash::Instance {
+        &self.instance
+    }
+    pub fn raw_device(&self) -> &ash::Device {
+        &self.vk_device
+    }
+    pub fn raw_physical_device(&self) -> vk::PhysicalDevice {
+        self.pdevice
+    }
+    pub fn raw_graphics_queue(&self) -> vk::Queue {
+        self.graphics_queue
+    }
+    pub fn raw_graphics_queue_family_index(&self) -> u32 {
+        self.graphics_queue_family_index
+    }
+}
+
 impl Drop for GraphicsDevice {
     fn drop(&mut self) {
         unsafe {
@@ -1229,43 +2432,71 @@ impl Drop for GraphicsDevice {
             self.vk_device.destroy_query_pool(self.query_pool, None);
             self.vk_device
                 .destroy_descriptor_set_layout(self.bindless_descriptor_set_layout, None);
-            self.vk_device
-                .destroy_descriptor_pool(self.bindless_descriptor_pool, None);
+            self.bindless_manager.borrow_mut().destroy();
             self.resource_manager.destroy_resources();
-            self.vk_device.destroy_sampler(self.default_sampler, None);
+            for sampler in self.material_samplers.borrow().iter() {
+                self.vk_device.destroy_sampler(*sampler, None);
+            }
+            for sampler in self.capped_material_samplers.iter().flatten() {
+                self.vk_device.destroy_sampler(*sampler, None);
+            }
             self.vk_device.destroy_sampler(self.shadow_sampler, None);
             self.vk_device.destroy_sampler(self.ui_sampler, None);
+            self.vk_device
+                .destroy_sampler(self.linear_clamp_sampler, None);
             self.vk_device.destroy_sampler(self.skybox_sampler, None);
-            for semaphore in self.present_complete_semaphore.into_iter() {
+            for &semaphore in self.present_complete_semaphore.iter() {
                 self.vk_device.destroy_semaphore(semaphore, None);
             }
-            for semaphore in self.rendering_complete_semaphore.into_iter() {
+            for &semaphore in self.rendering_complete_semaphore.iter() {
                 self.vk_device.destroy_semaphore(semaphore, None);
             }
             self.vk_device
                 .destroy_fence(self.upload_context.fence, None);
-            for fence in self.draw_commands_reuse_fence.into_iter() {
+            for &fence in self.draw_commands_reuse_fence.iter() {
                 self.vk_device.destroy_fence(fence, None);
             }
-            for &image_view in self.swapchain.borrow().present_image_views.iter() {
-                self.vk_device.destroy_image_view(image_view, None);
+            if let PresentTarget::Window { swapchain, .. } = &*self.present_target.borrow() {
+                for &image_view in swapchain.present_image_views.iter() {
+                    self.vk_device.destroy_image_view(image_view, None);
+                }
             }
             self.vk_device
                 .destroy_command_pool(self.upload_context.command_pool, None);
-            for pool in self.graphics_command_pool.into_iter() {
+            for &pool in self.graphics_command_pool.iter() {
                 self.vk_device.destroy_command_pool(pool, None);
             }
-            self.swapchain
-                .borrow()
-                .swapchain_loader
-                .destroy_swapchain(self.swapchain.borrow().swapchain, None);
+            if let Some(context) = &self.present_context {
+                for &fence in context.fence.iter() {
+                    self.vk_device.destroy_fence(fence, None);
+                }
+                for &semaphore in context.ownership_semaphore.iter() {
+                    self.vk_device.destroy_semaphore(semaphore, None);
+                }
+                self.vk_device
+                    .destroy_command_pool(context.command_pool, None);
+            }
+            if let Some(context) = &self.transfer_context {
+                for &semaphore in context.upload_complete_semaphore.iter() {
+                    self.vk_device.destroy_semaphore(semaphore, None);
+                }
+                self.vk_device
+                    .destroy_command_pool(context.command_pool, None);
+            }
+            if let PresentTarget::Window { swapchain, .. } = &*self.present_target.borrow() {
+                swapchain
+                    .swapchain_loader
+                    .destroy_swapchain(swapchain.swapchain, None);
+            }
             self.vk_device.destroy_device(None);
-            self.surface
-                .borrow()
-                .surface_loader
-                .destroy_surface(self.surface.borrow().surface, None);
-            self.debug_utils_loader
-                .destroy_debug_utils_messenger(self.debug_call_back, None);
+            if let PresentTarget::Window { surface, .. } = &*self.present_target.borrow() {
+                surface
+                    .surface_loader
+                    .destroy_surface(surface.surface, None);
+            }
+            if let Some((debug_utils_loader, debug_call_back, _)) = &self.debug_utils {
+                debug_utils_loader.destroy_debug_utils_messenger(*debug_call_back, None);
+            }
             self.instance.destroy_instance(None);
         }
     }
@@ -1278,13 +2509,151 @@ pub struct UploadContext {
     queue: vk::Queue,
 }
 
+/// Used when presentation happens on a different queue family to graphics.
+struct PresentContext {
+    queue_family_index: u32,
+    command_pool: vk::CommandPool,
+    command_buffer: Vec<vk::CommandBuffer>,
+    fence: Vec<vk::Fence>,
+    ownership_semaphore: Vec<vk::Semaphore>,
+}
+
+/// Used to copy image uploads on a dedicated transfer queue family.
+struct TransferContext {
+    queue_family_index: u32,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    command_buffer: Vec<vk::CommandBuffer>,
+    /// Signalled when each frame's copies finish, waited on by that frame's graphics submit.
+    upload_complete_semaphore: Vec<vk::Semaphore>,
+}
+
+/// Read by the debug messenger callback through its user data pointer.
+struct DebugMessengerState {
+    suppressed_message_ids: Vec<i32>,
+    error_count: AtomicUsize,
+}
+
+struct ImageToDelete {
+    image_handle: ImageHandle,
+    bindless_index: Option<usize>,
+    frames_remaining: usize,
+}
+
 struct ImageToUpload {
-    buffer_handle: BufferHandle,
     image_handle: ImageHandle,
     width: u32,
     height: u32,
     mip_levels: u32,
     img_layers: u32,
+    /// Mip levels that were supplied, the rest are generated.
+    supplied_levels: u32,
+    /// Staging buffer copies making up the supplied levels.
+    copies: Vec<(BufferHandle, vk::BufferImageCopy)>,
+    blit_filter: vk::Filter,
+    mip_filter: MipFilter,
+}
+
+/// An uploaded image whose levels past the base are generated by
+/// [crate::mipgen::MipGenerator].
+pub(crate) struct MipRequest {
+    pub image: ImageHandle,
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    pub filter: MipFilter,
+}
+
+/// Size of each staging buffer in the [StagingPool], unless the budget is smaller.
+const STAGING_BUFFER_SIZE: usize = 16 * 1024 * 1024;
+/// Copy offsets have to be a multiple of the texel block size, which is at most 16 bytes.
+const STAGING_ALIGNMENT: usize = 16;
+
+/// Host visible buffers that image data is staged in before being copied to the GPU, reused
+/// between uploads instead of creating a buffer per image.
+struct StagingPool {
+    buffer_size: usize,
+    /// Most free buffers kept for reuse, from [GraphicsDeviceConfig::staging_budget]. Any others
+    /// are destroyed once their uploads complete.
+    max_free: usize,
+    free: Vec<BufferHandle>,
+    /// Buffers holding data for uploads that haven't been recorded yet, with the bytes used.
+    filling: Vec<(BufferHandle, usize)>,
+    /// Buffers read by each frame's uploads, free again once that frame's fence is signalled.
+    in_flight: Vec<Vec<BufferHandle>>,
+}
+
+impl StagingPool {
+    fn new(budget: usize, frames_in_flight: usize) -> Self {
+        let buffer_size = STAGING_BUFFER_SIZE.min(budget).max(STAGING_ALIGNMENT);
+        Self {
+            buffer_size,
+            max_free: (budget / buffer_size).max(1),
+            free: Vec::new(),
+            filling: Vec::new(),
+            in_flight: vec![Vec::new(); frames_in_flight],
+        }
+    }
+
+    /// Copies `bytes` into a staging buffer, returning the buffer and the offset of the data.
+    fn stage(
+        &mut self,
+        resource_manager: &ResourceManager,
+        bytes: &[u8],
+    ) -> Result<(BufferHandle, DeviceSize)> {
+        ensure!(
+            bytes.len() <= self.buffer_size,
+            "{} bytes don't fit in a {} byte staging buffer",
+            bytes.len(),
+            self.buffer_size
+        );
+
+        let aligned = |used: usize| (used + STAGING_ALIGNMENT - 1) & !(STAGING_ALIGNMENT - 1);
+        let fits = match self.filling.last() {
+            Some(&(_, used)) => aligned(used) + bytes.len() <= self.buffer_size,
+            None => false,
+        };
+        if !fits {
+            let buffer = match self.free.pop() {
+                Some(buffer) => buffer,
+                None => resource_manager.create_buffer(&BufferCreateInfo {
+                    size: self.buffer_size,
+                    usage: vk::BufferUsageFlags::TRANSFER_SRC,
+                    storage_type: BufferStorageType::HostLocal,
+                }),
+            };
+            self.filling.push((buffer, 0));
+        }
+
+        let (buffer, used) = self.filling.last_mut().unwrap();
+        let offset = aligned(*used);
+        resource_manager
+            .get_buffer(*buffer)
+            .unwrap()
+            .view::<u8>()
+            .mapped_slice()?[offset..offset + bytes.len()]
+            .copy_from_slice(bytes);
+        *used = offset + bytes.len();
+
+        Ok((*buffer, offset as DeviceSize))
+    }
+
+    /// Hands every buffer staged into so far to the frame whose commands copy out of them.
+    fn submit(&mut self, frame: usize) {
+        let staged = self.filling.drain(..).map(|(buffer, _)| buffer);
+        self.in_flight[frame].extend(staged);
+    }
+
+    /// Returns the frame's buffers to the pool. The frame's fence must have been waited on.
+    fn recycle(&mut self, resource_manager: &ResourceManager, frame: usize) {
+        for buffer in self.in_flight[frame].drain(..) {
+            if self.free.len() < self.max_free {
+                self.free.push(buffer);
+            } else {
+                resource_manager.destroy_buffer(buffer);
+            }
+        }
+    }
 }
 
 pub(crate) fn cmd_copy_buffer(
@@ -1315,11 +2684,19 @@ unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _user_data: *mut std::os::raw::c_void,
+    p_user_data: *mut c_void,
 ) -> vk::Bool32 {
     let callback_data = *p_callback_data;
     let message_id_number = callback_data.message_id_number;
 
+    let state = &*(p_user_data as *const DebugMessengerState);
+    if state.suppressed_message_ids.contains(&message_id_number) {
+        return vk::FALSE;
+    }
+    if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        state.error_count.fetch_add(1, Ordering::Relaxed);
+    }
+
     let message_id_name = if callback_data.p_message_id_name.is_null() {
         Cow::from("")
     } else {
@@ -1332,21 +2709,442 @@ unsafe extern "system" fn vulkan_debug_callback(
         CStr::from_ptr(callback_data.p_message).to_string_lossy()
     };
 
-    println!(
-        "{:?}:\n{:?} [{} ({})] : {}\n",
-        message_severity,
-        message_type,
+    // Filter with e.g. RUST_LOG=vulkan::performance=off
+    let target = if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) {
+        "vulkan::validation"
+    } else if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE) {
+        "vulkan::performance"
+    } else {
+        "vulkan::general"
+    };
+    let level = if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        log::Level::Error
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        log::Level::Warn
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        log::Level::Info
+    } else {
+        log::Level::Debug
+    };
+
+    log::log!(
+        target: target,
+        level,
+        "[{} ({})] : {}",
         message_id_name,
-        &message_id_number.to_string(),
+        message_id_number,
         message,
     );
 
     vk::FALSE
 }
 
+/// How the mips of a loaded texture are generated from its base level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipFilter {
+    /// Halves each level with a linear blit. Cheap, but shimmers on high frequency textures. Blits
+    /// decode sRGB images before filtering, so these are filtered in linear space too.
+    Blit = 0,
+    /// Averages the texels under each texel of the next level, in a compute shader.
+    Box = 1,
+    /// A Kaiser windowed sinc in a compute shader. Keeps mips sharper than a box without aliasing.
+    Kaiser = 2,
+}
+
+impl Default for MipFilter {
+    fn default() -> Self {
+        Self::Blit
+    }
+}
+
+/// How the texel data of a loaded texture is interpreted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ImageFormatType {
+    /// sRGB colour, e.g. albedo and emissive.
     Default,
+    /// Tangent space normal map.
     Normal,
+    /// Linear data that isn't colour, e.g. metallic-roughness and occlusion.
+    Linear,
+    /// Single channel linear data such as masks. Expects one byte per pixel.
+    Mask,
+    /// High dynamic range colour stored as half floats, e.g. environment maps. Expects eight bytes
+    /// per pixel.
+    Hdr,
+}
+
+impl ImageFormatType {
+    pub fn format(&self) -> vk::Format {
+        match self {
+            ImageFormatType::Default => vk::Format::R8G8B8A8_SRGB,
+            ImageFormatType::Normal | ImageFormatType::Linear => vk::Format::R8G8B8A8_UNORM,
+            ImageFormatType::Mask => vk::Format::R8_UNORM,
+            ImageFormatType::Hdr => vk::Format::R16G16B16A16_SFLOAT,
+        }
+    }
+
+    pub fn bytes_per_pixel(&self) -> u32 {
+        match self {
+            ImageFormatType::Mask => 1,
+            ImageFormatType::Hdr => 8,
+            _ => 4,
+        }
+    }
+
+    /// Converts a decoded image into the texel layout expected for this format.
+    pub fn image_bytes(&self, image: &image::DynamicImage) -> Vec<u8> {
+        match self {
+            ImageFormatType::Mask => image.to_luma8().into_raw(),
+            ImageFormatType::Hdr => image
+                .to_rgba32f()
+                .into_raw()
+                .into_iter()
+                .flat_map(|value| f32_to_f16(value).to_le_bytes())
+                .collect(),
+            _ => image.to_rgba8().into_raw(),
+        }
+    }
+
+    /// A single texel shown while the real texture is loading. Neutral for how the format is
+    /// used, e.g. white albedo and a flat normal.
+    pub fn placeholder_texel(&self) -> Vec<u8> {
+        match self {
+            ImageFormatType::Normal => vec![128, 128, 255, 255],
+            ImageFormatType::Mask => vec![255],
+            ImageFormatType::Hdr => vec![0; 8],
+            _ => vec![255; 4],
+        }
+    }
+}
+
+/// Converts to an IEEE 754 half float, rounding to nearest. Values out of range become infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        // Too small for a normal half, so becomes denormal or flushes to zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        return sign | ((mantissa >> shift) + round) as u16;
+    }
+
+    // A carry from rounding moves into the exponent, which is still correct
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let round = (mantissa >> 12) & 1;
+    sign | (half + round) as u16
+}
+
+/// Options fixed when a [GraphicsDevice] is created.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphicsDeviceConfig {
+    /// Loads the debug utils extension, printing validation layer messages and naming Vulkan
+    /// objects for debuggers. Disable for release builds.
+    pub validation: bool,
+    /// Least severe validation message that is printed.
+    pub validation_severity: ValidationSeverity,
+    /// Validation message id numbers that are never logged, for drivers that spam benign warnings.
+    pub suppressed_message_ids: Vec<i32>,
+    /// Which GPU to create the device on, see [GraphicsDevice::enumerate_adapters].
+    pub adapter: AdapterPreference,
+    /// Frames recorded ahead of the GPU, from 1 to [MAX_FRAMES_IN_FLIGHT]. More frames avoid
+    /// stalling on the GPU at the cost of latency and per-frame buffer memory.
+    pub frames_in_flight: usize,
+    /// Bytes of staging buffers kept for uploading images, so loading many images reuses the same
+    /// buffers. Images with levels larger than a staging buffer are copied in several parts.
+    pub staging_budget: usize,
+    /// Textures the bindless sets start with room for. The sets are reallocated with double the
+    /// room whenever they fill up.
+    pub bindless_capacity: u32,
+    /// Most textures the bindless sets can grow to, checked against the device's sampled image
+    /// limits. None uses as many as the device allows, up to [DEFAULT_MAX_BINDLESS_CAPACITY].
+    pub max_bindless_capacity: Option<u32>,
+}
+
+impl Default for GraphicsDeviceConfig {
+    fn default() -> Self {
+        Self {
+            validation: cfg!(debug_assertions),
+            validation_severity: ValidationSeverity::Warning,
+            suppressed_message_ids: Vec::new(),
+            adapter: AdapterPreference::default(),
+            frames_in_flight: 2,
+            staging_budget: 64 * 1024 * 1024,
+            bindless_capacity: 256,
+            max_bindless_capacity: None,
+        }
+    }
+}
+
+/// How [GraphicsDevice::new] picks between multiple GPUs. If the preferred GPU can't be used, e.g.
+/// it can't present to the window, the next suitable one is picked instead.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AdapterPreference {
+    /// The first GPU that supports rendering and presenting.
+    FirstSuitable,
+    /// A discrete GPU over an integrated one, to avoid the integrated GPU on laptops.
+    PreferDiscrete,
+    /// The GPU at this index in [GraphicsDevice::enumerate_adapters].
+    Index(usize),
+}
+
+impl Default for AdapterPreference {
+    fn default() -> Self {
+        Self::PreferDiscrete
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AdapterType {
+    Discrete,
+    Integrated,
+    Virtual,
+    Cpu,
+    Other,
+}
+
+/// A GPU found by [GraphicsDevice::enumerate_adapters].
+#[derive(Clone, Debug)]
+pub struct AdapterInfo {
+    pub index: usize,
+    pub name: String,
+    pub adapter_type: AdapterType,
+    /// Device local memory in bytes.
+    pub vram: u64,
+}
+
+/// Severity of a validation message, from least to most severe.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValidationSeverity {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+}
+
+impl ValidationSeverity {
+    /// This severity and every more severe one.
+    fn severity_flags(&self) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+        let mut flags = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+        if *self <= ValidationSeverity::Warning {
+            flags |= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+        }
+        if *self <= ValidationSeverity::Info {
+            flags |= vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+        }
+        if *self <= ValidationSeverity::Verbose {
+            flags |= vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE;
+        }
+        flags
+    }
+}
+
+/// Anisotropic filtering applied by the material texture samplers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AnisotropyLevel {
+    /// Plain trilinear filtering, the same as 1x.
+    Off,
+    X2,
+    X4,
+    X8,
+    X16,
+}
+
+impl Default for AnisotropyLevel {
+    fn default() -> Self {
+        Self::X16
+    }
+}
+
+impl AnisotropyLevel {
+    const COUNT: usize = 5;
+    const ALL: [AnisotropyLevel; AnisotropyLevel::COUNT] = [
+        AnisotropyLevel::Off,
+        AnisotropyLevel::X2,
+        AnisotropyLevel::X4,
+        AnisotropyLevel::X8,
+        AnisotropyLevel::X16,
+    ];
+
+    pub fn samples(&self) -> f32 {
+        match self {
+            AnisotropyLevel::Off => 1.0,
+            AnisotropyLevel::X2 => 2.0,
+            AnisotropyLevel::X4 => 4.0,
+            AnisotropyLevel::X8 => 8.0,
+            AnisotropyLevel::X16 => 16.0,
+        }
+    }
+
+    fn clamped_samples(&self, max_anisotropy: f32) -> f32 {
+        self.samples().min(max_anisotropy).max(1.0)
+    }
+}
+
+/// Samplers given to [`BindlessManager::setup_samplers`] before the capped material samplers.
+const FIXED_SAMPLER_COUNT: usize = 6;
+/// Fixed samplers followed by each material sampler kind at every anisotropy level.
+pub(crate) const BINDLESS_SAMPLER_COUNT: usize =
+    FIXED_SAMPLER_COUNT + AnisotropyLevel::COUNT * SamplerKind::COUNT;
+
+/// Filtering used when a material samples its textures.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Nearest texel, blending between mip levels.
+    Nearest,
+    /// Bilinear filtering within the nearest mip level.
+    Linear,
+    /// Trilinear filtering, blending between mip levels.
+    LinearMipmap,
+}
+
+impl SamplerKind {
+    const COUNT: usize = 3;
+    const ALL: [SamplerKind; SamplerKind::COUNT] = [
+        SamplerKind::Nearest,
+        SamplerKind::Linear,
+        SamplerKind::LinearMipmap,
+    ];
+
+    /// Index into the bindless sampler array. Must match the order given to
+    /// [`BindlessManager::setup_samplers`].
+    pub(crate) fn bindless_index(&self) -> i32 {
+        match self {
+            SamplerKind::Nearest => 0,
+            SamplerKind::Linear => 4,
+            SamplerKind::LinearMipmap => 5,
+        }
+    }
+}
+
+impl Default for SamplerKind {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+
+fn create_material_samplers(
+    device: &ash::Device,
+    anisotropy: f32,
+) -> Result<[vk::Sampler; SamplerKind::COUNT]> {
+    let mut samplers = [vk::Sampler::null(); SamplerKind::COUNT];
+    for kind in SamplerKind::ALL {
+        let (filter, mipmap_mode) = match kind {
+            SamplerKind::Nearest => (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR),
+            SamplerKind::Linear => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST),
+            SamplerKind::LinearMipmap => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+        };
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .mipmap_mode(mipmap_mode)
+            .min_lod(0.0f32)
+            .max_lod(vk::LOD_CLAMP_NONE)
+            .anisotropy_enable(anisotropy > 1.0)
+            .max_anisotropy(anisotropy);
+
+        samplers[kind as usize] = unsafe { device.create_sampler(&sampler_info, None) }?;
+    }
+
+    Ok(samplers)
+}
+
+/// How finished frames are queued for display.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for vertical blank. Never tears and uses the least power.
+    Fifo,
+    /// Waits for vertical blank, replacing the queued frame with newer ones. Falls back to Fifo.
+    Mailbox,
+    /// Presents straight away without waiting, so can tear. Falls back to Mailbox, then Fifo.
+    Immediate,
+}
+
+impl PresentMode {
+    /// Modes to try in order. FIFO is always supported, so every chain ends with it.
+    fn fallback_chain(&self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            PresentMode::Fifo => &[vk::PresentModeKHR::FIFO],
+            PresentMode::Mailbox => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            PresentMode::Immediate => &[
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO,
+            ],
+        }
+    }
+}
+
+impl Default for PresentMode {
+    fn default() -> Self {
+        Self::Mailbox
+    }
+}
+
+/// Where finished frames are drawn to.
+enum PresentTarget {
+    Window {
+        surface: Surface,
+        swapchain: Swapchain,
+    },
+    /// Used by headless devices, with one image per frame in flight.
+    Offscreen(OffscreenTarget),
+}
+
+const OFFSCREEN_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
+
+struct OffscreenTarget {
+    images: Vec<ImageHandle>,
+    extent: vk::Extent2D,
+}
+
+impl OffscreenTarget {
+    fn new(resource_manager: &ResourceManager, image_count: usize, extent: vk::Extent2D) -> Self {
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(OFFSCREEN_FORMAT)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::TRANSFER_SRC,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(ImageLayout::UNDEFINED);
+
+        let images = (0..image_count)
+            .map(|_| resource_manager.create_image(&image_create_info))
+            .collect();
+
+        Self { images, extent }
+    }
 }
 
 struct Swapchain {
@@ -1364,16 +3162,18 @@ impl Swapchain {
         surface: &Surface,
         pre_transform: SurfaceTransformFlagsKHR,
         desired_image_count: u32,
+        requested_present_mode: PresentMode,
     ) -> Result<Self> {
         let present_modes = unsafe {
             surface
                 .surface_loader
                 .get_physical_device_surface_present_modes(pdevice, surface.surface)
         }?;
-        let present_mode = present_modes
+        let present_mode = requested_present_mode
+            .fallback_chain()
             .iter()
             .cloned()
-            .find(|&mode| mode == vk::PresentModeKHR::MAILBOX)
+            .find(|mode| present_modes.contains(mode))
             .unwrap_or(vk::PresentModeKHR::FIFO);
 
         let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
@@ -1382,7 +3182,11 @@ impl Swapchain {
             .image_color_space(surface.surface_format.color_space)
             .image_format(surface.surface_format.format)
             .image_extent(surface.surface_resolution)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST)
+            .image_usage(
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::TRANSFER_SRC,
+            )
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .pre_transform(pre_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
@@ -1435,3 +3239,10 @@ struct Surface {
 
 #[derive(Copy, Clone)]
 pub struct TimeStampIndex(usize);
+
+impl TimeStampIndex {
+    /// Whether the timestamp fit in the query pool, so a result will be read back for it.
+    pub fn has_result(self) -> bool {
+        self.0 < QUERY_COUNT as usize
+    }
+}
@@ -0,0 +1,350 @@
+use std::mem::size_of;
+
+use anyhow::Result;
+use ash::vk;
+use ash::vk::{AccessFlags2, PipelineStageFlags2};
+
+use crate::gpu_structs::ExposurePushConstants;
+use crate::pipeline::{
+    ComputePipelineCreateInfo, ComputePipelineHandle, PipelineLayoutCache, PipelineManager,
+};
+use crate::renderer::AutoExposureParams;
+use crate::resource::{BufferCreateInfo, BufferHandle, BufferStorageType, ImageHandle};
+use crate::util::descriptor::{
+    BufferDescriptorInfo, DescriptorAllocator, DescriptorLayoutBuilder, DescriptorLayoutCache,
+    ImageDescriptorInfo, JBDescriptorBuilder,
+};
+use crate::GraphicsDevice;
+
+/// Must match HISTOGRAM_BINS in exposure.glsl
+const HISTOGRAM_BINS: usize = 256;
+/// The scene is sampled on a grid this many texels across, whatever its resolution. Must match
+/// SAMPLE_GRID in luminance_histogram.comp
+const SAMPLE_GRID: u32 = 256;
+const WORKGROUP_SIZE: u32 = 16;
+/// Average luminance exposed at 1, which is what the combine pass uses while auto exposure is off.
+const NEUTRAL_LUMINANCE: f32 = 1.0 / 9.6;
+
+/// Luminance histogram of the scene and the exposure adapted from it, read by the combine pass.
+pub(crate) struct AutoExposure {
+    pso_layout: vk::PipelineLayout,
+    histogram_pso: ComputePipelineHandle,
+    average_pso: ComputePipelineHandle,
+    histogram_buffer: BufferHandle,
+    /// Adapted average luminance followed by the exposure it gives.
+    exposure_buffer: BufferHandle,
+    exposure_set_layout: vk::DescriptorSetLayout,
+    exposure_set: vk::DescriptorSet,
+    /// Whether the exposure buffer holds anything other than the neutral exposure.
+    active: bool,
+    last_time: f32,
+}
+
+impl AutoExposure {
+    pub fn new(
+        device: &GraphicsDevice,
+        pipeline_manager: &mut PipelineManager,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+        descriptor_allocator: &mut DescriptorAllocator,
+    ) -> Result<Self> {
+        let compute_set_layout = DescriptorLayoutBuilder::new(descriptor_layout_cache)
+            .bind_image(
+                0,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                vk::ShaderStageFlags::COMPUTE,
+            )
+            .bind_buffer(
+                1,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::ShaderStageFlags::COMPUTE,
+            )
+            .bind_buffer(
+                2,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::ShaderStageFlags::COMPUTE,
+            )
+            .build()?;
+
+        let push_constant_range = *vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .size(size_of::<ExposurePushConstants>() as u32);
+        let pso_layout = pipeline_layout_cache
+            .create_pipeline_layout(&[compute_set_layout], &[push_constant_range])?;
+
+        let histogram_pso =
+            pipeline_manager.create_compute_pipeline(&ComputePipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                compute_shader: "assets/shaders/luminance_histogram.comp".to_string(),
+            })?;
+        let average_pso = pipeline_manager.create_compute_pipeline(&ComputePipelineCreateInfo {
+            pipeline_layout: pso_layout,
+            compute_shader: "assets/shaders/exposure_average.comp".to_string(),
+        })?;
+
+        let histogram_buffer = device.resource_manager.create_buffer(&BufferCreateInfo {
+            size: size_of::<u32>() * HISTOGRAM_BINS,
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+            storage_type: BufferStorageType::HostLocal,
+        });
+        device
+            .resource_manager
+            .get_buffer(histogram_buffer)
+            .unwrap()
+            .view_custom::<u32>(0, HISTOGRAM_BINS)?
+            .mapped_slice()?
+            .fill(0);
+
+        let exposure_buffer = device.resource_manager.create_buffer(&BufferCreateInfo {
+            size: size_of::<[f32; 2]>(),
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            storage_type: BufferStorageType::HostLocal,
+        });
+        device
+            .resource_manager
+            .get_buffer(exposure_buffer)
+            .unwrap()
+            .view_custom::<f32>(0, 2)?
+            .mapped_slice()?
+            .copy_from_slice(&[NEUTRAL_LUMINANCE, 1.0]);
+
+        let (exposure_set, exposure_set_layout) = JBDescriptorBuilder::new(
+            &device.resource_manager,
+            descriptor_layout_cache,
+            descriptor_allocator,
+        )
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 0,
+            buffer: exposure_buffer,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .build()?;
+
+        Ok(Self {
+            pso_layout,
+            histogram_pso,
+            average_pso,
+            histogram_buffer,
+            exposure_buffer,
+            exposure_set_layout,
+            exposure_set,
+            active: false,
+            last_time: 0.0,
+        })
+    }
+
+    /// Layout of [AutoExposure::exposure_set], for pipelines that apply the exposure.
+    pub fn exposure_set_layout(&self) -> vk::DescriptorSetLayout {
+        self.exposure_set_layout
+    }
+
+    /// Set holding the current exposure, a storage buffer at binding 0.
+    pub fn exposure_set(&self) -> vk::DescriptorSet {
+        self.exposure_set
+    }
+
+    /// Adapts the exposure towards the average luminance of the HDR scene image, which must have
+    /// been transitioned for sampling. While disabled, the exposure is reset to 1.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &mut self,
+        device: &GraphicsDevice,
+        pipeline_manager: &PipelineManager,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+        descriptor_allocator: &mut DescriptorAllocator,
+        scene: ImageHandle,
+        params: &AutoExposureParams,
+        time: f32,
+    ) -> Result<()> {
+        let delta_time = (time - self.last_time).max(0.0);
+        self.last_time = time;
+
+        let cmd = device.graphics_command_buffer();
+        if !params.enabled {
+            if self.active {
+                self.reset(device, cmd);
+                self.active = false;
+            }
+            return Ok(());
+        }
+        self.active = true;
+
+        let (compute_set, _) = JBDescriptorBuilder::new(
+            &device.resource_manager,
+            descriptor_layout_cache,
+            descriptor_allocator,
+        )
+        .bind_image(ImageDescriptorInfo {
+            binding: 0,
+            image: scene,
+            sampler: device.linear_clamp_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 1,
+            buffer: self.histogram_buffer,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 2,
+            buffer: self.exposure_buffer,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+        })
+        .build()?;
+
+        let push_constants = ExposurePushConstants {
+            min_log_luminance: ev_to_log_luminance(params.min_ev),
+            log_luminance_range: (params.max_ev - params.min_ev).max(0.01),
+            adaptation: 1.0 - (-delta_time * params.speed.max(0.0)).exp(),
+            padding: 0.0,
+        };
+
+        device.cmd_begin_label(cmd, [0.9, 0.9, 0.6, 1.0], "auto_exposure");
+        // The scene was last written as a colour attachment, and the exposure last read by the
+        // combine pass
+        memory_barrier(
+            device,
+            cmd,
+            PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT | PipelineStageFlags2::FRAGMENT_SHADER,
+            AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            PipelineStageFlags2::COMPUTE_SHADER,
+            AccessFlags2::SHADER_SAMPLED_READ
+                | AccessFlags2::SHADER_STORAGE_READ
+                | AccessFlags2::SHADER_STORAGE_WRITE,
+        );
+        self.dispatch(
+            device,
+            cmd,
+            pipeline_manager.get_compute_pipeline(self.histogram_pso),
+            compute_set,
+            push_constants,
+            SAMPLE_GRID / WORKGROUP_SIZE,
+        );
+        memory_barrier(
+            device,
+            cmd,
+            PipelineStageFlags2::COMPUTE_SHADER,
+            AccessFlags2::SHADER_STORAGE_WRITE,
+            PipelineStageFlags2::COMPUTE_SHADER,
+            AccessFlags2::SHADER_STORAGE_READ | AccessFlags2::SHADER_STORAGE_WRITE,
+        );
+        self.dispatch(
+            device,
+            cmd,
+            pipeline_manager.get_compute_pipeline(self.average_pso),
+            compute_set,
+            push_constants,
+            1,
+        );
+        memory_barrier(
+            device,
+            cmd,
+            PipelineStageFlags2::COMPUTE_SHADER,
+            AccessFlags2::SHADER_STORAGE_WRITE,
+            PipelineStageFlags2::FRAGMENT_SHADER | PipelineStageFlags2::COMPUTE_SHADER,
+            AccessFlags2::SHADER_STORAGE_READ | AccessFlags2::SHADER_STORAGE_WRITE,
+        );
+        device.cmd_end_label(cmd);
+
+        Ok(())
+    }
+
+    fn dispatch(
+        &self,
+        device: &GraphicsDevice,
+        cmd: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+        compute_set: vk::DescriptorSet,
+        push_constants: ExposurePushConstants,
+        group_count: u32,
+    ) {
+        unsafe {
+            device
+                .vk_device
+                .cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, pipeline);
+            device.vk_device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pso_layout,
+                0u32,
+                &[compute_set],
+                &[],
+            );
+            device.vk_device.cmd_push_constants(
+                cmd,
+                self.pso_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0u32,
+                bytemuck::cast_slice(&[push_constants]),
+            );
+            device
+                .vk_device
+                .cmd_dispatch(cmd, group_count, group_count, 1);
+        }
+    }
+
+    /// Puts back the neutral exposure, once frames using the adapted one are done reading it.
+    fn reset(&self, device: &GraphicsDevice, cmd: vk::CommandBuffer) {
+        let buffer = device
+            .resource_manager
+            .get_buffer(self.exposure_buffer)
+            .unwrap()
+            .buffer();
+
+        memory_barrier(
+            device,
+            cmd,
+            PipelineStageFlags2::FRAGMENT_SHADER | PipelineStageFlags2::COMPUTE_SHADER,
+            AccessFlags2::SHADER_STORAGE_WRITE,
+            PipelineStageFlags2::TRANSFER,
+            AccessFlags2::TRANSFER_WRITE,
+        );
+        unsafe {
+            device.vk_device.cmd_update_buffer(
+                cmd,
+                buffer,
+                0,
+                bytemuck::cast_slice(&[NEUTRAL_LUMINANCE, 1.0f32]),
+            );
+        }
+        memory_barrier(
+            device,
+            cmd,
+            PipelineStageFlags2::TRANSFER,
+            AccessFlags2::TRANSFER_WRITE,
+            PipelineStageFlags2::FRAGMENT_SHADER | PipelineStageFlags2::COMPUTE_SHADER,
+            AccessFlags2::SHADER_STORAGE_READ | AccessFlags2::SHADER_STORAGE_WRITE,
+        );
+    }
+}
+
+/// Log2 of the average luminance that the exposure value EV100 exposes to middle grey.
+fn ev_to_log_luminance(ev: f32) -> f32 {
+    ev - 3.0
+}
+
+fn memory_barrier(
+    device: &GraphicsDevice,
+    cmd: vk::CommandBuffer,
+    src_stage_mask: PipelineStageFlags2,
+    src_access_mask: AccessFlags2,
+    dst_stage_mask: PipelineStageFlags2,
+    dst_access_mask: AccessFlags2,
+) {
+    let memory_barrier = vk::MemoryBarrier2::builder()
+        .src_stage_mask(src_stage_mask)
+        .src_access_mask(src_access_mask)
+        .dst_stage_mask(dst_stage_mask)
+        .dst_access_mask(dst_access_mask);
+    let memory_barriers = [*memory_barrier];
+    let dependency_info = vk::DependencyInfo::builder().memory_barriers(&memory_barriers);
+    unsafe {
+        device
+            .vk_device
+            .cmd_pipeline_barrier2(cmd, &dependency_info)
+    };
+}
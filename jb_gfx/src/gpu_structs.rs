@@ -1,6 +1,10 @@
-use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4, Zero};
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector2, Vector3, Vector4, Zero,
+};
 
-use crate::light::Light;
+use crate::camera::Projection;
+use crate::light::{AreaLight, Light, LightType};
+use crate::renderer::{FogMode, FogParams};
 use crate::{CameraTrait, DirectionalLight};
 
 #[repr(C)]
@@ -8,14 +12,22 @@ use crate::{CameraTrait, DirectionalLight};
 pub(crate) struct TransformSSBO {
     pub model: [[f32; 4]; 4],
     pub normal: [[f32; 4]; 4],
+    /// Model matrix the object was drawn with last frame.
+    pub prev_model: [[f32; 4]; 4],
+    /// Set with [crate::renderer::Renderer::set_render_model_params].
+    pub params: [f32; 4],
 }
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub(crate) struct MaterialParamSSBO {
     pub diffuse: [f32; 4],
+    /// Colour in rgb, strength multiplying it in w.
     pub emissive: [f32; 4],
     pub textures: [i32; 8],
+    /// Texture coordinate offset from scrolling in xy, alpha cutoff in z. The cutoff is negative
+    /// when disabled.
+    pub uv_scroll: [f32; 4],
 }
 
 #[repr(C)]
@@ -39,8 +51,30 @@ pub(crate) struct CameraUniform {
     pub directional_light_direction: [f32; 4],
     pub directional_light_proj: [[f32; 4]; 4],
     pub directional_light_view: [[f32; 4]; 4],
+    /// Left, right, bottom, top, near and far planes of the camera frustum in world space, normals pointing inwards.
+    pub frustum_planes: [[f32; 4]; 6],
     pub point_light_count: i32,
-    pub padding: [i32; 3],
+    pub time: f32,
+    /// Non-zero when the camera uses an orthographic projection.
+    pub orthographic: i32,
+    pub area_light_count: i32,
+    pub fog_colour: [f32; 4],
+    pub fog_density: f32,
+    pub fog_start: f32,
+    pub fog_end: f32,
+    /// Must match the FOG_ constants in camera.glsl
+    pub fog_mode: i32,
+    /// Unjittered projection and view of the previous frame.
+    pub prev_proj_view: [[f32; 4]; 4],
+    /// Offset added to the projection this frame, in normalized device coordinates, in xy.
+    pub jitter: [f32; 4],
+    /// Projection the light clusters are laid out in, left alone by viewports and jitter.
+    pub cluster_proj: [[f32; 4]; 4],
+    /// View space distances to the near and far planes in xy.
+    pub cluster_depth: [f32; 4],
+    /// Distance the point light cube shadow maps are rendered out to.
+    pub point_shadow_far_plane: f32,
+    pub padding: [f32; 3],
 }
 
 impl CameraUniform {
@@ -55,8 +89,22 @@ impl CameraUniform {
             directional_light_direction: Vector4::zero().into(),
             directional_light_proj: Matrix4::identity().into(),
             directional_light_view: Matrix4::identity().into(),
+            frustum_planes: [[0.0; 4]; 6],
             point_light_count: 0,
-            padding: [0, 0, 0],
+            time: 0.0,
+            orthographic: 0,
+            area_light_count: 0,
+            fog_colour: Vector4::zero().into(),
+            fog_density: 0.0,
+            fog_start: 0.0,
+            fog_end: 0.0,
+            fog_mode: 0,
+            prev_proj_view: Matrix4::identity().into(),
+            jitter: [0.0; 4],
+            cluster_proj: Matrix4::identity().into(),
+            cluster_depth: [0.1, 1.0, 0.0, 0.0],
+            point_shadow_far_plane: 1.0,
+            padding: [0.0; 3],
         }
     }
 
@@ -68,6 +116,43 @@ impl CameraUniform {
         self.view = view.into();
         self.inv_proj_view = (proj * view).invert().unwrap().into();
         self.position = camera.position().to_vec().extend(0f32).into();
+        self.frustum_planes = extract_frustum_planes(proj * view);
+        self.orthographic = match camera.projection() {
+            Projection::Perspective => 0,
+            Projection::Orthographic => 1,
+        };
+
+        self.cluster_proj = proj.into();
+        let inv_proj = proj.invert().unwrap();
+        let view_depth = |ndc_depth: f32| {
+            let point = inv_proj * Vector4::new(0.0, 0.0, ndc_depth, 1.0);
+            -point.z / point.w
+        };
+        self.cluster_depth = [view_depth(0.0), view_depth(1.0), 0.0, 0.0];
+    }
+
+    pub fn proj_view(&self) -> Matrix4<f32> {
+        Matrix4::from(self.proj) * Matrix4::from(self.view)
+    }
+
+    /// Squeezes the projection into the part of the screen covered by a viewport, given as the
+    /// centre and size of the viewport in normalized device coordinates. Frustum planes are left
+    /// fitted to the camera.
+    pub fn apply_viewport(&mut self, centre: Vector2<f32>, scale: Vector2<f32>) {
+        let proj = Matrix4::from_translation(centre.extend(0.0))
+            * Matrix4::from_nonuniform_scale(scale.x, scale.y, 1.0)
+            * Matrix4::from(self.proj);
+        self.proj = proj.into();
+        self.inv_proj_view = (proj * Matrix4::from(self.view)).invert().unwrap().into();
+    }
+
+    /// Offsets the projection by `jitter`, in normalized device coordinates, so every frame samples
+    /// a different point inside each pixel.
+    pub fn apply_jitter(&mut self, jitter: Vector2<f32>) {
+        let proj = Matrix4::from_translation(jitter.extend(0.0)) * Matrix4::from(self.proj);
+        self.proj = proj.into();
+        self.inv_proj_view = (proj * Matrix4::from(self.view)).invert().unwrap().into();
+        self.jitter = [jitter.x, jitter.y, 0.0, 0.0];
     }
 
     pub fn update_light(&mut self, light: &DirectionalLight) {
@@ -76,30 +161,139 @@ impl CameraUniform {
         self.directional_light_colour = light.colour.extend(light.intensity).into();
         self.directional_light_direction = light.direction.normalize().extend(0f32).into();
     }
+
+    pub fn update_fog(&mut self, fog: &FogParams) {
+        self.fog_colour = fog.colour.to_linear().into();
+        self.fog_density = fog.density;
+        self.fog_start = fog.start;
+        self.fog_end = fog.end;
+        self.fog_mode = match fog.mode {
+            FogMode::None => 0,
+            FogMode::Linear => 1,
+            FogMode::Exp => 2,
+            FogMode::Exp2 => 3,
+        };
+    }
+}
+
+/// Extracts the world space frustum planes from a view projection matrix (Gribb-Hartmann).
+/// The near plane is taken from the -w..w clip range, which is slightly conservative for Vulkan's 0..w.
+fn extract_frustum_planes(view_proj: Matrix4<f32>) -> [[f32; 4]; 6] {
+    let row = |i: usize| {
+        Vector4::new(
+            view_proj.x[i],
+            view_proj.y[i],
+            view_proj.z[i],
+            view_proj.w[i],
+        )
+    };
+    let planes = [
+        row(3) + row(0),
+        row(3) - row(0),
+        row(3) + row(1),
+        row(3) - row(1),
+        row(3) + row(2),
+        row(3) - row(2),
+    ];
+
+    planes.map(|plane| (plane / plane.truncate().magnitude()).into())
+}
+
+/// Bounds of a single instance for GPU culling.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CullObjectSSBO {
+    /// Object space bounding sphere, centre in xyz and radius in w.
+    pub bounding_sphere: [f32; 4],
+    /// Indirect draw the instance belongs to.
+    pub draw_index: u32,
+    pub padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CullPushConstants {
+    pub object_count: u32,
+    /// 0 marks every object as visible.
+    pub culling_enabled: u32,
 }
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub(crate) struct LightUniform {
-    pub pos: [f32; 4],
+    pub pos: [f32; 3],
+    /// Bindless index of the light's cube shadow map, 0 if it has none this frame.
+    pub shadow_index: i32,
     pub colour: [f32; 4],
+    pub direction: [f32; 3],
+    /// Distance past which the light contributes nothing. Bounds the light for clustered culling.
+    pub range: f32,
+    /// Cosines of the inner and outer cone half angles.
+    pub cone: [f32; 2],
+    /// 0 for point lights, 1 for spot lights.
+    pub light_type: i32,
+    pub padding: i32,
 }
 
 impl LightUniform {
-    pub fn new(position: Point3<f32>, colour: Vector3<f32>, intensity: f32) -> Self {
-        let position = position.to_vec().extend(0f32);
+    pub fn new(position: Point3<f32>, colour: Vector3<f32>, intensity: f32, range: f32) -> Self {
         let colour = colour.extend(intensity);
 
         Self {
             pos: position.into(),
+            shadow_index: 0,
             colour: colour.into(),
+            direction: [0.0; 3],
+            range: range.max(0.0),
+            cone: [0.0; 2],
+            light_type: 0,
+            padding: 0,
         }
     }
 }
 
 impl From<Light> for LightUniform {
     fn from(value: Light) -> Self {
-        LightUniform::new(value.position, value.colour, value.intensity)
+        let mut uniform =
+            LightUniform::new(value.position, value.colour, value.intensity, value.range);
+        if let LightType::Spot {
+            direction,
+            inner_cone,
+            outer_cone,
+        } = value.light_type
+        {
+            uniform.direction = direction.normalize().into();
+            uniform.cone = [inner_cone.0.cos(), outer_cone.0.cos()];
+            uniform.light_type = 1;
+        }
+        uniform
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct AreaLightUniform {
+    /// Corners of the rectangle in xyz, wound clockwise as seen from the lit side.
+    pub corners: [[f32; 4]; 4],
+    /// Intensity in w.
+    pub colour: [f32; 4],
+}
+
+impl From<AreaLight> for AreaLightUniform {
+    fn from(value: AreaLight) -> Self {
+        let centre = value.position.to_vec();
+        let (half_u, half_v) = (value.edge_u * 0.5, value.edge_v * 0.5);
+        let corners = [
+            centre - half_u - half_v,
+            centre - half_u + half_v,
+            centre + half_u + half_v,
+            centre + half_u - half_v,
+        ];
+
+        Self {
+            corners: corners.map(|corner| corner.extend(1.0).into()),
+            colour: value.colour.extend(value.intensity).into(),
+        }
     }
 }
 
@@ -127,13 +321,179 @@ pub struct WorldDebugUIDrawData {
     pub size: f32,
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugLineVertex {
+    pub position: [f32; 4],
+    pub colour: [f32; 4],
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ParticleDrawData {
     pub model: [[f32; 4]; 4],
     pub colour: [f32; 4],
     pub texture_index: i32,
-    pub padding: f32,
+    /// Non-zero to turn the quad to face the camera.
+    pub billboard: i32,
     pub padding_one: f32,
     pub padding_two: f32,
 }
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct GpuParticle {
+    /// Remaining life in w.
+    pub position: [f32; 4],
+    /// Life the particle spawned with in w.
+    pub velocity: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct GpuParticleParams {
+    pub emitter: [[f32; 4]; 4],
+    pub velocity_min: [f32; 4],
+    pub velocity_max: [f32; 4],
+    /// Time step in w.
+    pub gravity: [f32; 4],
+    pub start_colour: [f32; 4],
+    pub end_colour: [f32; 4],
+    pub start_size: f32,
+    pub end_size: f32,
+    pub lifetime: f32,
+    pub texture_index: i32,
+    /// First slot to spawn into, the ones after it are spawned into in turn.
+    pub spawn_start: u32,
+    pub spawn_count: u32,
+    pub capacity: u32,
+    pub seed: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct PointShadowPushConstants {
+    pub view_proj: [[f32; 4]; 4],
+    /// Light position, with the far plane in w.
+    pub light_position: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct SsaoPushConstants {
+    pub radius: f32,
+    pub bias: f32,
+    pub padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct BlurPushConstants {
+    pub horizontal: i32,
+    /// Distance between taps in texels, spreading the same kernel over a wider area.
+    pub texel_step: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct BloomPushConstants {
+    /// Luminance the source is thresholded at, or negative to downsample it as it is.
+    pub threshold: f32,
+    /// Range below the threshold over which colours ease into the bloom.
+    pub knee: f32,
+    pub padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct TaaPushConstants {
+    /// Zero when the history holds nothing to blend with, such as the first frame.
+    pub history_valid: i32,
+    pub padding: [i32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct OutlinePushConstants {
+    pub colour: [f32; 4],
+    /// Outline width in pixels.
+    pub width: i32,
+    pub padding: [i32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct DofPushConstants {
+    pub focus_distance: f32,
+    pub focus_range: f32,
+    pub padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ExposurePushConstants {
+    pub min_log_luminance: f32,
+    pub log_luminance_range: f32,
+    /// Fraction of the way to move from the current luminance to the measured one.
+    pub adaptation: f32,
+    pub padding: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct FxaaPushConstants {
+    pub edge_threshold: f32,
+    pub edge_threshold_min: f32,
+    pub subpixel_quality: f32,
+    pub bloom_intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CombinePushConstants {
+    pub bloom_intensity: f32,
+    pub padding: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct BackgroundPushConstants {
+    pub colour: [f32; 4],
+    pub background_type: i32,
+    pub skybox_index: i32,
+    pub shadow_filter_radius: i32,
+    pub shadow_bias: f32,
+    pub irradiance_index: i32,
+    pub prefiltered_index: i32,
+    pub brdf_lut_index: i32,
+    pub ibl_intensity: f32,
+    pub ltc_matrix_index: i32,
+    pub ltc_amplitude_index: i32,
+    pub skybox_intensity: f32,
+    pub padding: i32,
+    /// Inverse of the skybox rotation as a quaternion, taking world directions into the skybox.
+    pub skybox_rotation: [f32; 4],
+    /// Ground albedo of the procedural sky in rgb, turbidity in a.
+    pub sky_params: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct MipPushConstants {
+    pub source_index: i32,
+    pub source_level: i32,
+    pub source_width: i32,
+    pub source_height: i32,
+    pub width: i32,
+    pub height: i32,
+    pub filter: i32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct IblPushConstants {
+    pub source_index: i32,
+    pub roughness: f32,
+    pub size: u32,
+    pub source_lod: f32,
+}
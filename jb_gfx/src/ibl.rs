@@ -0,0 +1,557 @@
+use std::mem::size_of;
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::gpu_structs::IblPushConstants;
+use crate::pipeline::{
+    ComputePipelineCreateInfo, ComputePipelineHandle, PipelineLayoutCache, PipelineManager,
+};
+use crate::renderpass::barrier::{ImageBarrier, ImageBarrierBuilder};
+use crate::resource::ImageHandle;
+use crate::util::descriptor::{
+    DescriptorAllocator, DescriptorBuilder, DescriptorLayoutBuilder, DescriptorLayoutCache,
+};
+use crate::{AttachmentHandle, GraphicsDevice};
+
+const IBL_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+/// Roughness 0 to 1 is spread over the mips. Must match PREFILTERED_MIP_LEVELS in deferred_lighting.frag
+const PREFILTERED_MIP_LEVELS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 256;
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Largest face size of a skybox projected from an equirectangular image.
+const MAX_EQUIRECT_FACE_SIZE: u32 = 2048;
+
+/// Compute pipelines that build the image based lighting maps, and skyboxes from equirectangular
+/// images.
+pub(crate) struct IblGenerator {
+    pso_layout: vk::PipelineLayout,
+    irradiance_pso: ComputePipelineHandle,
+    prefilter_pso: ComputePipelineHandle,
+    brdf_lut_pso: ComputePipelineHandle,
+    equirect_pso: ComputePipelineHandle,
+}
+
+/// Diffuse irradiance and prefiltered specular cubemaps generated from a skybox.
+pub(crate) struct IblMaps {
+    pub irradiance: ImageHandle,
+    pub prefiltered: ImageHandle,
+    /// Set once the maps have been recorded into a frame.
+    pub generated: bool,
+    /// Irradiance first, then one per prefiltered mip.
+    storage_views: Vec<vk::ImageView>,
+    storage_sets: Vec<vk::DescriptorSet>,
+}
+
+/// A skybox cubemap projected from an equirectangular image.
+pub(crate) struct EquirectSkybox {
+    pub cubemap: ImageHandle,
+    /// The source image, freed once it has been projected.
+    equirect: Option<ImageHandle>,
+    equirect_width: u32,
+    face_size: u32,
+    mip_levels: u32,
+    /// One per cubemap mip.
+    storage_views: Vec<vk::ImageView>,
+    storage_sets: Vec<vk::DescriptorSet>,
+}
+
+impl IblGenerator {
+    pub fn new(
+        device: &GraphicsDevice,
+        pipeline_manager: &mut PipelineManager,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+    ) -> Result<Self> {
+        let storage_set_layout = DescriptorLayoutBuilder::new(descriptor_layout_cache)
+            .bind_image(
+                0,
+                vk::DescriptorType::STORAGE_IMAGE,
+                vk::ShaderStageFlags::COMPUTE,
+            )
+            .build()?;
+
+        let push_constant_range = *vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .size(size_of::<IblPushConstants>() as u32);
+        let pso_layout = pipeline_layout_cache.create_pipeline_layout(
+            &[device.bindless_descriptor_set_layout(), storage_set_layout],
+            &[push_constant_range],
+        )?;
+
+        let mut create_pipeline = |shader: &str| {
+            pipeline_manager.create_compute_pipeline(&ComputePipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                compute_shader: shader.to_string(),
+            })
+        };
+        let irradiance_pso = create_pipeline("assets/shaders/ibl_irradiance.comp")?;
+        let prefilter_pso = create_pipeline("assets/shaders/ibl_prefilter.comp")?;
+        let brdf_lut_pso = create_pipeline("assets/shaders/brdf_lut.comp")?;
+        let equirect_pso = create_pipeline("assets/shaders/equirect_to_cube.comp")?;
+
+        Ok(Self {
+            pso_layout,
+            irradiance_pso,
+            prefilter_pso,
+            brdf_lut_pso,
+            equirect_pso,
+        })
+    }
+
+    /// Builds the lookup table of the specular BRDF's response to roughness and view angle. It
+    /// doesn't depend on the skybox, so is only generated once.
+    pub fn generate_brdf_lut(
+        &self,
+        device: &GraphicsDevice,
+        pipeline_manager: &PipelineManager,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+        descriptor_allocator: &mut DescriptorAllocator,
+    ) -> Result<ImageHandle> {
+        let image = create_storage_image(device, BRDF_LUT_SIZE, 1, 1)?;
+        let image_view = device
+            .resource_manager
+            .get_image(image)
+            .unwrap()
+            .image_view();
+        let storage_set =
+            create_storage_set(image_view, descriptor_layout_cache, descriptor_allocator)?;
+
+        let pipeline = pipeline_manager.get_compute_pipeline(self.brdf_lut_pso);
+        device.immediate_submit(|device, cmd| {
+            ImageBarrierBuilder::default()
+                .add_image_barrier(ImageBarrier {
+                    image: AttachmentHandle::Image(image),
+                    dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    dst_access_mask: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                    new_layout: vk::ImageLayout::GENERAL,
+                    ..Default::default()
+                })
+                .build(device, cmd)?;
+
+            self.dispatch(
+                device,
+                *cmd,
+                pipeline,
+                storage_set,
+                IblPushConstants {
+                    source_index: 0,
+                    roughness: 0.0,
+                    size: BRDF_LUT_SIZE,
+                    source_lod: 0.0,
+                },
+                1,
+            );
+
+            ImageBarrierBuilder::default()
+                .add_image_barrier(ImageBarrier {
+                    image: AttachmentHandle::Image(image),
+                    src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    src_access_mask: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                    dst_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                    dst_access_mask: vk::AccessFlags2::SHADER_READ,
+                    old_layout: vk::ImageLayout::GENERAL,
+                    new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    ..Default::default()
+                })
+                .build(device, cmd)
+        })?;
+
+        device.add_image_to_bindless(&image)?;
+        Ok(image)
+    }
+
+    /// Creates the images for a skybox's lighting maps. They are filled in by [IblGenerator::record].
+    pub fn create_maps(
+        &self,
+        device: &GraphicsDevice,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+        descriptor_allocator: &mut DescriptorAllocator,
+    ) -> Result<IblMaps> {
+        let irradiance = create_storage_image(device, IRRADIANCE_SIZE, 1, 6)?;
+        let prefiltered =
+            create_storage_image(device, PREFILTERED_SIZE, PREFILTERED_MIP_LEVELS, 6)?;
+
+        let (mut storage_views, mut storage_sets) = create_face_storage(
+            device,
+            irradiance,
+            1,
+            descriptor_layout_cache,
+            descriptor_allocator,
+        )?;
+        let (prefiltered_views, prefiltered_sets) = create_face_storage(
+            device,
+            prefiltered,
+            PREFILTERED_MIP_LEVELS,
+            descriptor_layout_cache,
+            descriptor_allocator,
+        )?;
+        storage_views.extend(prefiltered_views);
+        storage_sets.extend(prefiltered_sets);
+
+        device.add_image_to_bindless(&irradiance)?;
+        device.add_image_to_bindless(&prefiltered)?;
+
+        Ok(IblMaps {
+            irradiance,
+            prefiltered,
+            generated: false,
+            storage_views,
+            storage_sets,
+        })
+    }
+
+    /// Records the convolution of the skybox into the maps. The skybox must already be recorded for
+    /// upload, so this is run at the start of a frame.
+    pub fn record(
+        &self,
+        device: &GraphicsDevice,
+        pipeline_manager: &PipelineManager,
+        maps: &IblMaps,
+        skybox_index: i32,
+    ) -> Result<()> {
+        let cmd = device.graphics_command_buffer();
+        device.cmd_begin_label(cmd, [0.9, 0.8, 0.4, 1.0], "ibl_precompute");
+
+        wait_for_uploads(device, cmd);
+        ImageBarrierBuilder::default()
+            .add_image_barrier(to_general(maps.irradiance, 1))
+            .add_image_barrier(to_general(maps.prefiltered, PREFILTERED_MIP_LEVELS))
+            .build(device, &cmd)?;
+
+        self.dispatch(
+            device,
+            cmd,
+            pipeline_manager.get_compute_pipeline(self.irradiance_pso),
+            maps.storage_sets[0],
+            IblPushConstants {
+                source_index: skybox_index,
+                roughness: 0.0,
+                size: IRRADIANCE_SIZE,
+                source_lod: 0.0,
+            },
+            6,
+        );
+
+        let prefilter_pipeline = pipeline_manager.get_compute_pipeline(self.prefilter_pso);
+        for mip in 0..PREFILTERED_MIP_LEVELS {
+            let roughness = mip as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32;
+            self.dispatch(
+                device,
+                cmd,
+                prefilter_pipeline,
+                maps.storage_sets[mip as usize + 1],
+                IblPushConstants {
+                    source_index: skybox_index,
+                    roughness,
+                    size: (PREFILTERED_SIZE >> mip).max(1),
+                    source_lod: 0.0,
+                },
+                6,
+            );
+        }
+
+        ImageBarrierBuilder::default()
+            .add_image_barrier(to_shader_read(maps.irradiance, 1))
+            .add_image_barrier(to_shader_read(maps.prefiltered, PREFILTERED_MIP_LEVELS))
+            .build(device, &cmd)?;
+
+        device.cmd_end_label(cmd);
+        Ok(())
+    }
+
+    /// Creates a cubemap to project an equirectangular image into. The image is projected by
+    /// [IblGenerator::record_equirect_skybox].
+    pub fn create_equirect_skybox(
+        &self,
+        device: &GraphicsDevice,
+        equirect: ImageHandle,
+        equirect_width: u32,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+        descriptor_allocator: &mut DescriptorAllocator,
+    ) -> Result<EquirectSkybox> {
+        // A panorama wraps around four faces horizontally
+        let face_size = (equirect_width / 4).clamp(1, MAX_EQUIRECT_FACE_SIZE);
+        let mip_levels = (face_size as f32).log2().floor() as u32 + 1u32;
+
+        let cubemap = create_storage_image(device, face_size, mip_levels, 6)?;
+        let (storage_views, storage_sets) = create_face_storage(
+            device,
+            cubemap,
+            mip_levels,
+            descriptor_layout_cache,
+            descriptor_allocator,
+        )?;
+        device.add_image_to_bindless(&cubemap)?;
+
+        Ok(EquirectSkybox {
+            cubemap,
+            equirect: Some(equirect),
+            equirect_width,
+            face_size,
+            mip_levels,
+            storage_views,
+            storage_sets,
+        })
+    }
+
+    /// Records the projection of each cubemap face and mip, then frees the equirectangular image.
+    /// Does nothing if the skybox has already been projected.
+    pub fn record_equirect_skybox(
+        &self,
+        device: &GraphicsDevice,
+        pipeline_manager: &PipelineManager,
+        skybox: &mut EquirectSkybox,
+    ) -> Result<()> {
+        let equirect = match skybox.equirect.take() {
+            Some(equirect) => equirect,
+            None => return Ok(()),
+        };
+        let equirect_index = device.get_descriptor_index(&equirect).unwrap_or(0) as i32;
+
+        let cmd = device.graphics_command_buffer();
+        device.cmd_begin_label(cmd, [0.9, 0.8, 0.4, 1.0], "equirect_to_cube");
+
+        wait_for_uploads(device, cmd);
+        ImageBarrierBuilder::default()
+            .add_image_barrier(to_general(skybox.cubemap, skybox.mip_levels))
+            .build(device, &cmd)?;
+
+        let pipeline = pipeline_manager.get_compute_pipeline(self.equirect_pso);
+        for mip in 0..skybox.mip_levels {
+            let size = (skybox.face_size >> mip).max(1);
+            // Reads from the equirect mip closest to one texel per cubemap texel, to avoid aliasing
+            let source_lod = ((skybox.equirect_width as f32) / (4 * size) as f32)
+                .log2()
+                .max(0.0);
+            self.dispatch(
+                device,
+                cmd,
+                pipeline,
+                skybox.storage_sets[mip as usize],
+                IblPushConstants {
+                    source_index: equirect_index,
+                    roughness: 0.0,
+                    size,
+                    source_lod,
+                },
+                6,
+            );
+        }
+
+        ImageBarrierBuilder::default()
+            .add_image_barrier(to_shader_read(skybox.cubemap, skybox.mip_levels))
+            .build(device, &cmd)?;
+        device.cmd_end_label(cmd);
+
+        // Kept alive until the frames in flight are done with it
+        device.unload_image(equirect)
+    }
+
+    fn dispatch(
+        &self,
+        device: &GraphicsDevice,
+        cmd: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+        storage_set: vk::DescriptorSet,
+        push_constants: IblPushConstants,
+        layers: u32,
+    ) {
+        let group_count = (push_constants.size + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+
+        unsafe {
+            device
+                .vk_device
+                .cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, pipeline);
+            device.vk_device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pso_layout,
+                0u32,
+                &[device.bindless_descriptor_set(), storage_set],
+                &[],
+            );
+            device.vk_device.cmd_push_constants(
+                cmd,
+                self.pso_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0u32,
+                bytemuck::cast_slice(&[push_constants]),
+            );
+            device
+                .vk_device
+                .cmd_dispatch(cmd, group_count, group_count, layers);
+        }
+    }
+}
+
+impl IblMaps {
+    /// Waits for the GPU to finish with the maps, then frees them.
+    pub fn destroy(self, device: &GraphicsDevice) -> Result<()> {
+        destroy_storage_views(device, self.storage_views)?;
+        device.unload_image(self.irradiance)?;
+        device.unload_image(self.prefiltered)?;
+        Ok(())
+    }
+}
+
+impl EquirectSkybox {
+    /// Waits for the GPU to finish with the projection, then frees the views used to write the
+    /// cubemap. The cubemap itself is left loaded.
+    pub fn destroy(self, device: &GraphicsDevice) -> Result<()> {
+        destroy_storage_views(device, self.storage_views)?;
+        if let Some(equirect) = self.equirect {
+            device.unload_image(equirect)?;
+        }
+        Ok(())
+    }
+}
+
+fn destroy_storage_views(device: &GraphicsDevice, views: Vec<vk::ImageView>) -> Result<()> {
+    unsafe { device.vk_device.device_wait_idle() }?;
+    for view in views {
+        unsafe { device.vk_device.destroy_image_view(view, None) };
+    }
+    Ok(())
+}
+
+/// Makes images uploaded at the start of the frame visible to the compute shaders.
+fn wait_for_uploads(device: &GraphicsDevice, cmd: vk::CommandBuffer) {
+    let memory_barrier = vk::MemoryBarrier2::builder()
+        .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+        .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ);
+    let memory_barriers = [*memory_barrier];
+    let dependency_info = vk::DependencyInfo::builder().memory_barriers(&memory_barriers);
+    unsafe {
+        device
+            .vk_device
+            .cmd_pipeline_barrier2(cmd, &dependency_info);
+    }
+}
+
+fn to_general(image: ImageHandle, level_count: u32) -> ImageBarrier {
+    ImageBarrier {
+        image: AttachmentHandle::Image(image),
+        dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+        dst_access_mask: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        new_layout: vk::ImageLayout::GENERAL,
+        level_count,
+        image_layers: 6,
+        ..Default::default()
+    }
+}
+
+fn to_shader_read(image: ImageHandle, level_count: u32) -> ImageBarrier {
+    ImageBarrier {
+        image: AttachmentHandle::Image(image),
+        src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+        src_access_mask: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER
+            | vk::PipelineStageFlags2::COMPUTE_SHADER,
+        dst_access_mask: vk::AccessFlags2::SHADER_READ,
+        old_layout: vk::ImageLayout::GENERAL,
+        new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        level_count,
+        image_layers: 6,
+        ..Default::default()
+    }
+}
+
+/// The default views of cubemaps can't be written to, so each mip is written through a view of its
+/// faces as an array.
+fn create_face_storage(
+    device: &GraphicsDevice,
+    image: ImageHandle,
+    mip_levels: u32,
+    descriptor_layout_cache: &mut DescriptorLayoutCache,
+    descriptor_allocator: &mut DescriptorAllocator,
+) -> Result<(Vec<vk::ImageView>, Vec<vk::DescriptorSet>)> {
+    let storage_views = (0..mip_levels)
+        .map(|mip| create_face_array_view(device, image, mip))
+        .collect::<Result<Vec<_>>>()?;
+    let storage_sets = storage_views
+        .iter()
+        .map(|view| create_storage_set(*view, descriptor_layout_cache, descriptor_allocator))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((storage_views, storage_sets))
+}
+
+fn create_storage_image(
+    device: &GraphicsDevice,
+    size: u32,
+    mip_levels: u32,
+    layers: u32,
+) -> Result<ImageHandle> {
+    let flags = if layers == 6 {
+        vk::ImageCreateFlags::CUBE_COMPATIBLE
+    } else {
+        vk::ImageCreateFlags::empty()
+    };
+
+    let image_create_info = vk::ImageCreateInfo::builder()
+        .format(IBL_FORMAT)
+        .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE)
+        .extent(vk::Extent3D {
+            width: size,
+            height: size,
+            depth: 1,
+        })
+        .image_type(vk::ImageType::TYPE_2D)
+        .array_layers(layers)
+        .mip_levels(mip_levels)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .flags(flags);
+
+    Ok(device.resource_manager.create_image(&image_create_info))
+}
+
+fn create_face_array_view(
+    device: &GraphicsDevice,
+    image: ImageHandle,
+    mip_level: u32,
+) -> Result<vk::ImageView> {
+    let image_view_create_info = vk::ImageViewCreateInfo::builder()
+        .format(IBL_FORMAT)
+        .image(device.resource_manager.get_image(image).unwrap().image())
+        .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: mip_level,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 6,
+        });
+
+    Ok(unsafe {
+        device
+            .vk_device
+            .create_image_view(&image_view_create_info, None)
+    }?)
+}
+
+fn create_storage_set(
+    image_view: vk::ImageView,
+    descriptor_layout_cache: &mut DescriptorLayoutCache,
+    descriptor_allocator: &mut DescriptorAllocator,
+) -> Result<vk::DescriptorSet> {
+    let image_info = [*vk::DescriptorImageInfo::builder()
+        .image_view(image_view)
+        .image_layout(vk::ImageLayout::GENERAL)];
+
+    let (set, _) = DescriptorBuilder::new(descriptor_layout_cache, descriptor_allocator)
+        .bind_image(
+            0,
+            &image_info,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::ShaderStageFlags::COMPUTE,
+        )
+        .build()?;
+
+    Ok(set)
+}
@@ -3,9 +3,13 @@ pub use crate::prelude::*;
 pub mod camera;
 pub mod colour;
 pub mod core;
+pub mod exposure;
 pub mod gpu_structs;
+pub mod ibl;
 pub mod light;
+pub mod ltc;
 pub mod mesh;
+pub mod mipgen;
 pub mod particle;
 pub mod pipeline;
 pub mod prelude;
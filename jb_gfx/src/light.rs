@@ -1,12 +1,68 @@
 use std::ops::Neg;
 
-use cgmath::{abs_diff_eq, EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+use cgmath::{abs_diff_eq, EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, Vector3};
+
+use crate::SHADOWMAP_SIZE;
 
 #[derive(Copy, Clone)]
 pub struct Light {
     pub position: Point3<f32>,
     pub colour: Vector3<f32>,
+    /// Multiplies `colour`, so brightness is set separately from hue. This is the brightness one
+    /// unit away from the light, falling off with the inverse square of distance.
     pub intensity: f32,
+    /// Distance past which the light contributes nothing. Light falls off with the inverse square
+    /// of distance, smoothly faded to zero at the range.
+    pub range: f32,
+    /// Renders a cube shadow map for the light when it is one of the closest shadow casters to the camera.
+    pub casts_shadows: bool,
+    pub light_type: LightType,
+}
+
+impl Light {
+    /// Creates a spot light at `position` shining along `direction`.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner_cone` - Half angle of the fully lit part of the cone.
+    /// * `outer_cone` - Half angle at which the light has faded out completely.
+    /// * `range` - Distance past which the light contributes nothing, see [Light::range].
+    pub fn spot(
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        inner_cone: Rad<f32>,
+        outer_cone: Rad<f32>,
+        range: f32,
+    ) -> Self {
+        Self {
+            position,
+            light_type: LightType::Spot {
+                direction: direction.normalize(),
+                inner_cone,
+                outer_cone,
+            },
+            range,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightType {
+    /// Shines in all directions, falling off with distance.
+    Point,
+    /// Shines along `direction`, fading out between the inner and outer cone half angles.
+    Spot {
+        direction: Vector3<f32>,
+        inner_cone: Rad<f32>,
+        outer_cone: Rad<f32>,
+    },
+}
+
+impl Default for LightType {
+    fn default() -> Self {
+        Self::Point
+    }
 }
 
 impl Default for Light {
@@ -15,6 +71,36 @@ impl Default for Light {
             position: Point3::new(0f32, 0f32, 0f32),
             colour: Vector3::new(1f32, 1f32, 1f32),
             intensity: 1.0,
+            range: 20.0,
+            casts_shadows: false,
+            light_type: LightType::default(),
+        }
+    }
+}
+
+/// A rectangle giving off light from one side, for soft studio-style lighting. Shaded with
+/// linearly transformed cosines, without shadows.
+#[derive(Copy, Clone)]
+pub struct AreaLight {
+    /// Centre of the rectangle.
+    pub position: Point3<f32>,
+    /// Full length sides of the rectangle. Light is given off from the side `edge_u.cross(edge_v)`
+    /// points towards.
+    pub edge_u: Vector3<f32>,
+    pub edge_v: Vector3<f32>,
+    pub colour: Vector3<f32>,
+    /// Multiplies `colour`, so brightness is set separately from hue.
+    pub intensity: f32,
+}
+
+impl Default for AreaLight {
+    fn default() -> Self {
+        Self {
+            position: Point3::new(0f32, 0f32, 0f32),
+            edge_u: Vector3::new(1f32, 0f32, 0f32),
+            edge_v: Vector3::new(0f32, 0f32, 1f32),
+            colour: Vector3::new(1f32, 1f32, 1f32),
+            intensity: 1.0,
         }
     }
 }
@@ -23,6 +109,7 @@ impl Default for Light {
 pub struct DirectionalLight {
     pub direction: Vector3<f32>,
     pub colour: Vector3<f32>,
+    /// Multiplies `colour`, so brightness is set separately from hue.
     pub intensity: f32,
     znear: f32,
     zfar: f32,
@@ -31,6 +118,8 @@ pub struct DirectionalLight {
 }
 
 impl DirectionalLight {
+    /// Creates a light shining along `direction` at an intensity of 1.
+    /// `render_offset` is how far back from the origin the shadow map is rendered from.
     pub fn new(direction: Vector3<f32>, colour: Vector3<f32>, render_offset: f32) -> Self {
         Self {
             direction: direction.normalize(),
@@ -66,3 +155,61 @@ impl DirectionalLight {
         )
     }
 }
+
+/// How often a shadow casting light redraws its shadow map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShadowUpdateMode {
+    EveryFrame,
+    /// Redraws once every `n` frames, reusing the cached shadow map in between.
+    EveryNFrames(u32),
+    /// Draws the shadow map once and reuses it until the shadow is invalidated.
+    Once,
+}
+
+impl Default for ShadowUpdateMode {
+    fn default() -> Self {
+        Self::EveryFrame
+    }
+}
+
+/// Size of the percentage-closer filtering kernel used when sampling the sun's shadow map.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShadowFilter {
+    /// A single sample, giving hard edges.
+    Hard,
+    Pcf3x3,
+    Pcf5x5,
+}
+
+impl ShadowFilter {
+    /// Number of texels sampled either side of the centre texel.
+    pub(crate) fn radius(&self) -> i32 {
+        match self {
+            ShadowFilter::Hard => 0,
+            ShadowFilter::Pcf3x3 => 1,
+            ShadowFilter::Pcf5x5 => 2,
+        }
+    }
+}
+
+impl Default for ShadowFilter {
+    fn default() -> Self {
+        Self::Pcf3x3
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ShadowSettings {
+    /// Width and height of the shadow map in texels.
+    pub resolution: u32,
+    pub update_mode: ShadowUpdateMode,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: SHADOWMAP_SIZE,
+            update_mode: ShadowUpdateMode::default(),
+        }
+    }
+}
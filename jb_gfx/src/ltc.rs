@@ -0,0 +1,56 @@
+use anyhow::Result;
+use image::{DynamicImage, Rgba, Rgba32FImage};
+use log::warn;
+
+use crate::core::device::{ImageFormatType, MipFilter};
+use crate::resource::ImageHandle;
+use crate::GraphicsDevice;
+
+/// Fitted GGX tables from "Real-Time Polygonal-Light Shading with Linearly Transformed Cosines"
+/// (Heitz et al. 2016), indexed by roughness and the square root of 1 - N.V. Regenerate them with
+/// the `fit_ltc` example.
+const LTC_MATRIX_PATH: &str = "assets/textures/ltc/ltc_1.exr";
+const LTC_AMPLITUDE_PATH: &str = "assets/textures/ltc/ltc_2.exr";
+
+/// Lookup textures for shading area lights with linearly transformed cosines.
+pub(crate) struct LtcTables {
+    /// The four non-trivial entries of the inverse transform in rgba.
+    pub matrix: ImageHandle,
+    /// Magnitude and fresnel of the transformed lobe in rg.
+    pub amplitude: ImageHandle,
+}
+
+impl LtcTables {
+    /// Loads both tables. A missing table is replaced by a single texel of the identity
+    /// transform, which shades highlights with the diffuse lobe instead of GGX.
+    pub fn load(device: &GraphicsDevice) -> Result<Self> {
+        Ok(Self {
+            matrix: load_table(device, LTC_MATRIX_PATH, [1.0, 0.0, 0.0, 1.0])?,
+            amplitude: load_table(device, LTC_AMPLITUDE_PATH, [1.0, 0.0, 0.0, 0.0])?,
+        })
+    }
+}
+
+fn load_table(device: &GraphicsDevice, path: &str, fallback: [f32; 4]) -> Result<ImageHandle> {
+    let table = match image::open(path) {
+        Ok(table) => table,
+        Err(error) => {
+            warn!(
+                "Couldn't load LTC table [{}], area light highlights won't follow GGX: {}",
+                path, error
+            );
+            DynamicImage::ImageRgba32F(Rgba32FImage::from_pixel(1, 1, Rgba(fallback)))
+        }
+    };
+
+    let image_type = ImageFormatType::Hdr;
+    device.load_image(
+        &image_type.image_bytes(&table),
+        table.width(),
+        table.height(),
+        &image_type,
+        1,
+        1,
+        MipFilter::Blit,
+    )
+}
@@ -1,3 +1,7 @@
+use std::f32::consts::PI;
+
+use cgmath::{Array, EuclideanSpace, InnerSpace, Point3, Vector3, Zero};
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
@@ -8,6 +12,7 @@ pub struct Vertex {
     pub tangent: [f32; 4],
 }
 
+#[derive(Clone)]
 pub struct MeshData {
     pub vertices: Vec<Vertex>,
     pub indices: Option<Vec<Index>>,
@@ -113,12 +118,354 @@ impl MeshData {
             faces: vec![],
         }
     }
+
+    /// A UV sphere with a radius of 1. Segments go around the equator and rings go from pole to
+    /// pole.
+    pub fn sphere(segments: u32, rings: u32) -> MeshData {
+        let segments = segments.max(3);
+        let rings = rings.max(2);
+
+        let mut vertices = Vec::with_capacity(((segments + 1) * (rings + 1)) as usize);
+        for ring in 0..=rings {
+            let v = ring as f32 / rings as f32;
+            let phi = v * PI;
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let theta = u * 2.0f32 * PI;
+                let normal =
+                    Vector3::new(phi.sin() * theta.sin(), phi.cos(), phi.sin() * theta.cos());
+                let tangent = Vector3::new(theta.cos(), 0.0f32, -theta.sin());
+                let bitangent =
+                    Vector3::new(phi.cos() * theta.sin(), -phi.sin(), phi.cos() * theta.cos());
+                vertices.push(primitive_vertex(normal, normal, [u, v], tangent, bitangent));
+            }
+        }
+
+        let mut indices = Vec::new();
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let current = ring * (segments + 1) + segment;
+                let below = current + segments + 1;
+                // The triangles touching the poles would be degenerate
+                if ring != 0 {
+                    indices.extend_from_slice(&[current, below, current + 1]);
+                }
+                if ring != rings - 1 {
+                    indices.extend_from_slice(&[current + 1, below, below + 1]);
+                }
+            }
+        }
+
+        MeshData {
+            vertices,
+            indices: Some(indices),
+            faces: vec![],
+        }
+    }
+
+    /// A flat 2x2 square on the XZ plane facing up, split into a grid with the given number of
+    /// cuts along each side.
+    pub fn plane(subdivisions: u32) -> MeshData {
+        let cells = subdivisions + 1;
+
+        let mut vertices = Vec::with_capacity(((cells + 1) * (cells + 1)) as usize);
+        for row in 0..=cells {
+            let v = row as f32 / cells as f32;
+            for column in 0..=cells {
+                let u = column as f32 / cells as f32;
+                vertices.push(primitive_vertex(
+                    Vector3::new(u * 2.0f32 - 1.0f32, 0.0f32, v * 2.0f32 - 1.0f32),
+                    Vector3::unit_y(),
+                    [u, v],
+                    Vector3::unit_x(),
+                    Vector3::unit_z(),
+                ));
+            }
+        }
+
+        let mut indices = Vec::with_capacity((cells * cells * 6) as usize);
+        for row in 0..cells {
+            for column in 0..cells {
+                let current = row * (cells + 1) + column;
+                let below = current + cells + 1;
+                indices.extend_from_slice(&[current, below, current + 1]);
+                indices.extend_from_slice(&[current + 1, below, below + 1]);
+            }
+        }
+
+        MeshData {
+            vertices,
+            indices: Some(indices),
+            faces: vec![],
+        }
+    }
+
+    /// A capped cylinder with a radius of 1, running from -1 to 1 along the Y axis.
+    pub fn cylinder(segments: u32) -> MeshData {
+        let segments = segments.max(3);
+
+        let mut vertices = Vec::new();
+        for (y, v) in [(1.0f32, 0.0f32), (-1.0f32, 1.0f32)] {
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let theta = u * 2.0f32 * PI;
+                let normal = Vector3::new(theta.sin(), 0.0f32, theta.cos());
+                vertices.push(primitive_vertex(
+                    Vector3::new(normal.x, y, normal.z),
+                    normal,
+                    [u, v],
+                    Vector3::new(theta.cos(), 0.0f32, -theta.sin()),
+                    -Vector3::unit_y(),
+                ));
+            }
+        }
+
+        let mut indices = Vec::new();
+        for segment in 0..segments {
+            let below = segment + segments + 1;
+            indices.extend_from_slice(&[segment, below, segment + 1]);
+            indices.extend_from_slice(&[segment + 1, below, below + 1]);
+        }
+
+        add_cap(&mut vertices, &mut indices, segments, 1.0f32);
+        add_cap(&mut vertices, &mut indices, segments, -1.0f32);
+
+        MeshData {
+            vertices,
+            indices: Some(indices),
+            faces: vec![],
+        }
+    }
+
+    /// A capped cone with a base radius of 1 at -1 on the Y axis and its tip at 1.
+    pub fn cone(segments: u32) -> MeshData {
+        let segments = segments.max(3);
+
+        // The sides rise 2 units over a radius of 1
+        let side_normal = |theta: f32| {
+            Vector3::new(2.0f32 * theta.sin(), 1.0f32, 2.0f32 * theta.cos()).normalize()
+        };
+        let side_vertex = |position: Vector3<f32>, u: f32, v: f32, theta: f32| {
+            primitive_vertex(
+                position,
+                side_normal(theta),
+                [u, v],
+                Vector3::new(theta.cos(), 0.0f32, -theta.sin()),
+                Vector3::new(theta.sin(), -2.0f32, theta.cos()),
+            )
+        };
+
+        let mut vertices = Vec::new();
+        // Each segment gets its own tip, facing the middle of the segment, so the shading doesn't
+        // pinch at the top
+        for segment in 0..segments {
+            let u = (segment as f32 + 0.5f32) / segments as f32;
+            vertices.push(side_vertex(Vector3::unit_y(), u, 0.0f32, u * 2.0f32 * PI));
+        }
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let theta = u * 2.0f32 * PI;
+            let position = Vector3::new(theta.sin(), -1.0f32, theta.cos());
+            vertices.push(side_vertex(position, u, 1.0f32, theta));
+        }
+
+        let mut indices = Vec::new();
+        for segment in 0..segments {
+            let base = segments + segment;
+            indices.extend_from_slice(&[segment, base, base + 1]);
+        }
+
+        add_cap(&mut vertices, &mut indices, segments, -1.0f32);
+
+        MeshData {
+            vertices,
+            indices: Some(indices),
+            faces: vec![],
+        }
+    }
+}
+
+/// Builds a white vertex, with the tangent handedness picked so the bitangent follows the
+/// direction V increases in.
+fn primitive_vertex(
+    position: Vector3<f32>,
+    normal: Vector3<f32>,
+    tex_coords: [f32; 2],
+    tangent: Vector3<f32>,
+    bitangent: Vector3<f32>,
+) -> Vertex {
+    let handedness = if normal.cross(tangent).dot(bitangent) < 0.0f32 {
+        -1.0f32
+    } else {
+        1.0f32
+    };
+
+    Vertex {
+        position: position.into(),
+        tex_coords,
+        normal: normal.into(),
+        color: [1.0f32; 3],
+        tangent: tangent.extend(handedness).into(),
+    }
+}
+
+/// Adds a flat disc with a radius of 1 at the given height. It faces up when above the origin,
+/// otherwise down.
+fn add_cap(vertices: &mut Vec<Vertex>, indices: &mut Vec<Index>, segments: u32, y: f32) {
+    let facing = y.signum();
+    let normal = Vector3::new(0.0f32, facing, 0.0f32);
+    // Seen from below, the texture is mirrored on Z so it isn't flipped
+    let bitangent = Vector3::new(0.0f32, 0.0f32, facing);
+
+    let centre = vertices.len() as Index;
+    vertices.push(primitive_vertex(
+        Vector3::new(0.0f32, y, 0.0f32),
+        normal,
+        [0.5f32, 0.5f32],
+        Vector3::unit_x(),
+        bitangent,
+    ));
+    for segment in 0..=segments {
+        let theta = segment as f32 / segments as f32 * 2.0f32 * PI;
+        let position = Vector3::new(theta.sin(), y, theta.cos());
+        let tex_coords = [
+            0.5f32 + 0.5f32 * position.x,
+            0.5f32 + 0.5f32 * position.z * facing,
+        ];
+        vertices.push(primitive_vertex(
+            position,
+            normal,
+            tex_coords,
+            Vector3::unit_x(),
+            bitangent,
+        ));
+    }
+
+    for segment in 0..segments {
+        let current = centre + 1 + segment;
+        if facing > 0.0f32 {
+            indices.extend_from_slice(&[centre, current, current + 1]);
+        } else {
+            indices.extend_from_slice(&[centre, current + 1, current]);
+        }
+    }
 }
 
 impl MeshData {
     pub fn generate_tangents(&mut self) -> bool {
         mikktspace::generate_tangents(self)
     }
+
+    /// Replaces the vertex normals with the area weighted average of the normals of the
+    /// triangles that use each vertex. Triangles are counter-clockwise when viewed from the front.
+    ///
+    /// Triangles come from the faces if there are any, otherwise the indices,
+    /// otherwise every three vertices.
+    pub fn recompute_normals(&mut self) {
+        let triangles: Vec<[usize; 3]> = if !self.faces.is_empty() {
+            self.faces
+                .iter()
+                .map(|face| [face[0] as usize, face[1] as usize, face[2] as usize])
+                .collect()
+        } else if let Some(indices) = &self.indices {
+            indices
+                .chunks_exact(3)
+                .map(|tri| [tri[0] as usize, tri[1] as usize, tri[2] as usize])
+                .collect()
+        } else {
+            (0..self.vertices.len() / 3)
+                .map(|tri| [tri * 3, tri * 3 + 1, tri * 3 + 2])
+                .collect()
+        };
+
+        let mut normals = vec![Vector3::zero(); self.vertices.len()];
+        for triangle in triangles.iter() {
+            let a = Vector3::from(self.vertices[triangle[0]].position);
+            let b = Vector3::from(self.vertices[triangle[1]].position);
+            let c = Vector3::from(self.vertices[triangle[2]].position);
+
+            // Not normalised, so larger triangles contribute more
+            let face_normal = (b - a).cross(c - a);
+            for &index in triangle.iter() {
+                normals[index] += face_normal;
+            }
+        }
+
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            if normal.magnitude2() > 0.0f32 {
+                vertex.normal = normal.normalize().into();
+            }
+        }
+    }
+
+    /// Computes the object space bounding box and a bounding sphere around its centre.
+    /// An empty mesh has zero sized bounds at the origin.
+    pub fn bounds(&self) -> MeshBounds {
+        if self.vertices.is_empty() {
+            return MeshBounds::default();
+        }
+
+        let positions = self
+            .vertices
+            .iter()
+            .map(|vertex| Point3::from(vertex.position));
+        let (min, max) = positions.clone().fold(
+            (Point3::from_value(f32::MAX), Point3::from_value(f32::MIN)),
+            |(min, max), position| {
+                (
+                    Point3::new(
+                        min.x.min(position.x),
+                        min.y.min(position.y),
+                        min.z.min(position.z),
+                    ),
+                    Point3::new(
+                        max.x.max(position.x),
+                        max.y.max(position.y),
+                        max.z.max(position.z),
+                    ),
+                )
+            },
+        );
+        let centre = min.midpoint(max);
+        let radius = positions
+            .map(|position| (position - centre).magnitude())
+            .fold(0.0f32, f32::max);
+
+        MeshBounds {
+            min,
+            max,
+            centre,
+            radius,
+        }
+    }
+
+    /// Returns true if no vertex has a normal set.
+    pub fn has_zero_normals(&self) -> bool {
+        self.vertices
+            .iter()
+            .all(|vertex| vertex.normal == [0.0f32; 3])
+    }
+}
+
+/// Axis aligned bounding box and bounding sphere of a mesh, in object space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshBounds {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+    /// Centre of the bounding sphere, which is also the centre of the box.
+    pub centre: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Default for MeshBounds {
+    fn default() -> Self {
+        Self {
+            min: Point3::origin(),
+            max: Point3::origin(),
+            centre: Point3::origin(),
+            radius: 0.0,
+        }
+    }
 }
 
 pub type Face = [u32; 3];
@@ -157,3 +504,191 @@ impl mikktspace::Geometry for MeshData {
         vert.tangent = tangent;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex_at(position: [f32; 3]) -> Vertex {
+        Vertex {
+            position,
+            ..Default::default()
+        }
+    }
+
+    fn assert_normal(actual: [f32; 3], expected: [f32; 3]) {
+        let difference = Vector3::from(actual) - Vector3::from(expected);
+        assert!(
+            difference.magnitude() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    /// Checks the primitive has the expected number of vertices and indices, that every index
+    /// points at a vertex, that normals are unit length and that triangles wind counter-clockwise
+    /// when seen from the side their normals face.
+    fn assert_primitive(mesh: &MeshData, vertex_count: usize, index_count: usize) {
+        assert_eq!(mesh.vertices.len(), vertex_count);
+        let indices = mesh.indices.as_ref().unwrap();
+        assert_eq!(indices.len(), index_count);
+        assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < mesh.vertices.len()));
+
+        for vertex in mesh.vertices.iter() {
+            let length = Vector3::from(vertex.normal).magnitude();
+            assert!((length - 1.0).abs() < 1e-5, "{:?}", vertex.normal);
+        }
+
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize]);
+            let face_normal = (Vector3::from(b.position) - Vector3::from(a.position))
+                .cross(Vector3::from(c.position) - Vector3::from(a.position));
+            let vertex_normal =
+                Vector3::from(a.normal) + Vector3::from(b.normal) + Vector3::from(c.normal);
+            assert!(face_normal.dot(vertex_normal) > 0.0, "{:?}", triangle);
+        }
+    }
+
+    #[test]
+    fn sphere_has_a_vertex_per_ring_and_segment_and_skips_pole_triangles() {
+        let mesh = MeshData::sphere(8, 4);
+        assert_primitive(&mesh, 9 * 5, 8 * 3 * 6);
+    }
+
+    #[test]
+    fn sphere_clamps_segments_and_rings() {
+        let mesh = MeshData::sphere(0, 0);
+        assert_primitive(&mesh, 4 * 3, 3 * 6);
+    }
+
+    #[test]
+    fn plane_has_a_quad_per_cell() {
+        assert_primitive(&MeshData::plane(0), 4, 6);
+        assert_primitive(&MeshData::plane(3), 5 * 5, 4 * 4 * 6);
+    }
+
+    #[test]
+    fn cylinder_has_sides_and_two_caps() {
+        // Two rings of side vertices, then a centre and ring for each cap
+        let mesh = MeshData::cylinder(6);
+        assert_primitive(&mesh, 2 * 7 + 2 * (1 + 7), 6 * 6 + 2 * 6 * 3);
+    }
+
+    #[test]
+    fn cone_has_a_tip_per_segment_and_a_base_cap() {
+        let mesh = MeshData::cone(6);
+        assert_primitive(&mesh, 6 + 7 + (1 + 7), 6 * 3 + 6 * 3);
+    }
+
+    #[test]
+    fn recompute_normals_faces_counter_clockwise_triangles_towards_the_viewer() {
+        let mut mesh = MeshData {
+            vertices: vec![
+                vertex_at([0.0, 0.0, 0.0]),
+                vertex_at([1.0, 0.0, 0.0]),
+                vertex_at([1.0, 1.0, 0.0]),
+                vertex_at([0.0, 1.0, 0.0]),
+            ],
+            indices: Some(vec![0, 1, 2, 0, 2, 3]),
+            faces: vec![],
+        };
+        mesh.recompute_normals();
+
+        for vertex in mesh.vertices.iter() {
+            assert_normal(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn recompute_normals_prefers_faces_over_indices() {
+        let mut mesh = MeshData {
+            vertices: vec![
+                vertex_at([0.0, 0.0, 0.0]),
+                vertex_at([1.0, 0.0, 0.0]),
+                vertex_at([0.0, 1.0, 0.0]),
+            ],
+            // Wound the other way to the faces
+            indices: Some(vec![0, 2, 1]),
+            faces: vec![[0, 1, 2]],
+        };
+        mesh.recompute_normals();
+
+        for vertex in mesh.vertices.iter() {
+            assert_normal(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn recompute_normals_reads_unindexed_meshes_as_triangle_lists() {
+        let mut mesh = MeshData {
+            vertices: vec![
+                vertex_at([0.0, 0.0, 0.0]),
+                vertex_at([0.0, 0.0, 1.0]),
+                vertex_at([1.0, 0.0, 0.0]),
+            ],
+            indices: None,
+            faces: vec![],
+        };
+        mesh.recompute_normals();
+
+        for vertex in mesh.vertices.iter() {
+            assert_normal(vertex.normal, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn recompute_normals_points_a_generated_plane_up() {
+        let mut mesh = MeshData::plane(3);
+        for vertex in mesh.vertices.iter_mut() {
+            vertex.normal = [0.0; 3];
+        }
+        mesh.recompute_normals();
+
+        for vertex in mesh.vertices.iter() {
+            assert_normal(vertex.normal, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn recompute_normals_weights_triangles_by_area() {
+        // Vertex 0 is shared by a large triangle facing +Z and a small one facing +X
+        let mut mesh = MeshData {
+            vertices: vec![
+                vertex_at([0.0, 0.0, 0.0]),
+                vertex_at([4.0, 0.0, 0.0]),
+                vertex_at([0.0, 4.0, 0.0]),
+                vertex_at([0.0, 1.0, 0.0]),
+                vertex_at([0.0, 0.0, 1.0]),
+            ],
+            indices: Some(vec![0, 1, 2, 0, 3, 4]),
+            faces: vec![],
+        };
+        mesh.recompute_normals();
+
+        // Twice the areas of the triangles are 16 and 1
+        let expected = Vector3::new(1.0f32, 0.0, 16.0).normalize();
+        assert_normal(mesh.vertices[0].normal, expected.into());
+    }
+
+    #[test]
+    fn recompute_normals_keeps_the_normals_of_unused_vertices() {
+        let mut unused = vertex_at([5.0, 5.0, 5.0]);
+        unused.normal = [1.0, 0.0, 0.0];
+        let mut mesh = MeshData {
+            vertices: vec![
+                vertex_at([0.0, 0.0, 0.0]),
+                vertex_at([1.0, 0.0, 0.0]),
+                vertex_at([0.0, 1.0, 0.0]),
+                unused,
+            ],
+            indices: Some(vec![0, 1, 2]),
+            faces: vec![],
+        };
+        mesh.recompute_normals();
+
+        assert_normal(mesh.vertices[3].normal, [1.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,247 @@
+use std::mem::size_of;
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::core::device::MipRequest;
+use crate::gpu_structs::MipPushConstants;
+use crate::pipeline::{
+    ComputePipelineCreateInfo, ComputePipelineHandle, PipelineLayoutCache, PipelineManager,
+};
+use crate::renderpass::barrier::{ImageBarrier, ImageBarrierBuilder};
+use crate::util::descriptor::{
+    DescriptorAllocator, DescriptorBuilder, DescriptorLayoutBuilder, DescriptorLayoutCache,
+};
+use crate::{AttachmentHandle, GraphicsDevice};
+
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Shader writing mips of each format that can be filtered in compute.
+pub(crate) fn downsample_shader(format: vk::Format) -> Option<&'static str> {
+    match format {
+        vk::Format::R8G8B8A8_SRGB => Some("assets/shaders/mip_downsample_srgb.comp"),
+        vk::Format::R8G8B8A8_UNORM => Some("assets/shaders/mip_downsample_rgba8.comp"),
+        vk::Format::R8_UNORM => Some("assets/shaders/mip_downsample_r8.comp"),
+        vk::Format::R16G16B16A16_SFLOAT => Some("assets/shaders/mip_downsample_rgba16f.comp"),
+        _ => None,
+    }
+}
+
+/// Format of the views mips are written through. sRGB formats can't be storage images, so are
+/// written through a UNORM view with the shader encoding to sRGB. Reads through the image's own
+/// view decode to linear, so the filtering happens in linear space.
+pub(crate) fn storage_format(format: vk::Format) -> vk::Format {
+    match format {
+        vk::Format::R8G8B8A8_SRGB => vk::Format::R8G8B8A8_UNORM,
+        _ => format,
+    }
+}
+
+/// Generates the mips of uploaded textures with a compute downsample, for textures loaded with a
+/// [crate::MipFilter] other than blitting.
+pub(crate) struct MipGenerator {
+    pso_layout: vk::PipelineLayout,
+    pipelines: Vec<(vk::Format, ComputePipelineHandle)>,
+    /// Views written by each frame in flight, destroyed once the frame comes around again.
+    storage_views: Vec<Vec<vk::ImageView>>,
+}
+
+impl MipGenerator {
+    pub fn new(
+        device: &GraphicsDevice,
+        pipeline_manager: &mut PipelineManager,
+        pipeline_layout_cache: &mut PipelineLayoutCache,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+    ) -> Result<Self> {
+        let storage_set_layout = DescriptorLayoutBuilder::new(descriptor_layout_cache)
+            .bind_image(
+                0,
+                vk::DescriptorType::STORAGE_IMAGE,
+                vk::ShaderStageFlags::COMPUTE,
+            )
+            .build()?;
+
+        let push_constant_range = *vk::PushConstantRange::builder()
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .size(size_of::<MipPushConstants>() as u32);
+        let pso_layout = pipeline_layout_cache.create_pipeline_layout(
+            &[device.bindless_descriptor_set_layout(), storage_set_layout],
+            &[push_constant_range],
+        )?;
+
+        let pipelines = [
+            vk::Format::R8G8B8A8_SRGB,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::Format::R8_UNORM,
+            vk::Format::R16G16B16A16_SFLOAT,
+        ]
+        .into_iter()
+        .map(|format| {
+            let pipeline =
+                pipeline_manager.create_compute_pipeline(&ComputePipelineCreateInfo {
+                    pipeline_layout: pso_layout,
+                    compute_shader: downsample_shader(format).unwrap().to_string(),
+                })?;
+            Ok((format, pipeline))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            pso_layout,
+            pipelines,
+            storage_views: vec![Vec::new(); device.frames_in_flight()],
+        })
+    }
+
+    /// Records the mips of images uploaded at the start of this frame. Their base level is
+    /// already readable, and every level is readable once this is done.
+    pub fn record(
+        &mut self,
+        device: &GraphicsDevice,
+        pipeline_manager: &PipelineManager,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+        descriptor_allocator: &mut DescriptorAllocator,
+        requests: &[MipRequest],
+    ) -> Result<()> {
+        // This frame's fence has been waited on, so nothing is still writing through these
+        let frame = device.buffered_resource_number();
+        for view in self.storage_views[frame].drain(..) {
+            unsafe { device.vk_device.destroy_image_view(view, None) };
+        }
+        if requests.is_empty() {
+            return Ok(());
+        }
+
+        let cmd = device.graphics_command_buffer();
+        device.cmd_begin_label(cmd, [0.6, 0.8, 0.9, 1.0], "mip_generation");
+
+        for request in requests.iter() {
+            let image = device.resource_manager.get_image(request.image).unwrap();
+            let pipeline = self
+                .pipelines
+                .iter()
+                .find(|(format, _)| *format == image.format())
+                .map(|(_, pipeline)| pipeline_manager.get_compute_pipeline(*pipeline))
+                .unwrap();
+            let source_index = device.get_descriptor_index(&request.image).unwrap_or(0) as i32;
+
+            ImageBarrierBuilder::default()
+                .add_image_barrier(ImageBarrier {
+                    image: AttachmentHandle::Image(request.image),
+                    dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                    dst_access_mask: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                    new_layout: vk::ImageLayout::GENERAL,
+                    base_mip_level: 1,
+                    level_count: request.mip_levels - 1,
+                    ..Default::default()
+                })
+                .build(device, &cmd)?;
+
+            for level in 1..request.mip_levels {
+                let view_create_info = vk::ImageViewCreateInfo::builder()
+                    .format(storage_format(image.format()))
+                    .image(image.image())
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: level,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    });
+                let view = unsafe { device.vk_device.create_image_view(&view_create_info, None) }?;
+                self.storage_views[frame].push(view);
+
+                let image_info = [*vk::DescriptorImageInfo::builder()
+                    .image_view(view)
+                    .image_layout(vk::ImageLayout::GENERAL)];
+                let (storage_set, _) =
+                    DescriptorBuilder::new(descriptor_layout_cache, descriptor_allocator)
+                        .bind_image(
+                            0,
+                            &image_info,
+                            vk::DescriptorType::STORAGE_IMAGE,
+                            vk::ShaderStageFlags::COMPUTE,
+                        )
+                        .build()?;
+
+                let push_constants = MipPushConstants {
+                    source_index,
+                    source_level: level as i32 - 1,
+                    source_width: (request.width >> (level - 1)).max(1) as i32,
+                    source_height: (request.height >> (level - 1)).max(1) as i32,
+                    width: (request.width >> level).max(1) as i32,
+                    height: (request.height >> level).max(1) as i32,
+                    filter: request.filter as i32,
+                };
+                self.dispatch(device, cmd, pipeline, storage_set, push_constants);
+
+                // The next level reads this one
+                ImageBarrierBuilder::default()
+                    .add_image_barrier(ImageBarrier {
+                        image: AttachmentHandle::Image(request.image),
+                        src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                        src_access_mask: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                        dst_stage_mask: vk::PipelineStageFlags2::FRAGMENT_SHADER
+                            | vk::PipelineStageFlags2::COMPUTE_SHADER,
+                        dst_access_mask: vk::AccessFlags2::SHADER_READ,
+                        old_layout: vk::ImageLayout::GENERAL,
+                        new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        base_mip_level: level,
+                        ..Default::default()
+                    })
+                    .build(device, &cmd)?;
+            }
+        }
+
+        device.cmd_end_label(cmd);
+        Ok(())
+    }
+
+    fn dispatch(
+        &self,
+        device: &GraphicsDevice,
+        cmd: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+        storage_set: vk::DescriptorSet,
+        push_constants: MipPushConstants,
+    ) {
+        let group_count_x = (push_constants.width as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+        let group_count_y = (push_constants.height as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+
+        unsafe {
+            device
+                .vk_device
+                .cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, pipeline);
+            device.vk_device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pso_layout,
+                0u32,
+                &[device.bindless_descriptor_set(), storage_set],
+                &[],
+            );
+            device.vk_device.cmd_push_constants(
+                cmd,
+                self.pso_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0u32,
+                bytemuck::cast_slice(&[push_constants]),
+            );
+            device
+                .vk_device
+                .cmd_dispatch(cmd, group_count_x, group_count_y, 1);
+        }
+    }
+
+    /// Frees the views of every frame. The GPU must be idle.
+    pub fn destroy(&mut self, device: &GraphicsDevice) {
+        for view in self
+            .storage_views
+            .iter_mut()
+            .flat_map(|views| views.drain(..))
+        {
+            unsafe { device.vk_device.destroy_image_view(view, None) };
+        }
+    }
+}
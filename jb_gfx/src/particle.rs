@@ -1,19 +1,33 @@
 use crate::{ImageHandle, MeshHandle};
-use cgmath::{Array, Vector3, Vector4, Zero};
+use cgmath::{
+    Array, EuclideanSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3, Vector4, VectorSpace,
+    Zero,
+};
 use profiling::scope;
 use rand::{thread_rng, Rng};
 use std::hash::{Hash, Hasher};
 
 pub struct ParticleSystem {
     particles: Vec<Particle>,
+    particle_limit: usize,
+    simulation: ParticleSimulation,
     state: ParticleSystemState,
     time_since_last_spawn: f32,
+    gpu_step: GpuParticleStep,
+    transform: Matrix4<f32>,
     pub spawn_rate: f32,
+    /// Relative to the system's transform.
     pub spawn_position: Vector3<f32>,
     pub velocity: VectorParameter,
+    /// Acceleration applied to every live particle, in world space.
+    pub gravity: Vector3<f32>,
     pub initial_colour: Vector4<f32>,
+    /// Colour particles fade to by the end of their life.
+    pub final_colour: Vector4<f32>,
     pub texture: Option<ImageHandle>,
     pub scale: f32,
+    /// Scale particles grow or shrink to by the end of their life.
+    pub final_scale: f32,
     pub rotation: VectorParameter,
     pub rotation_velocity: VectorParameter,
     pub life: f32,
@@ -26,22 +40,55 @@ impl ParticleSystem {
         particles.resize(particle_limit, Particle::default());
         Self {
             particles,
+            particle_limit,
             ..Default::default()
         }
     }
 
+    pub fn particle_limit(&self) -> usize {
+        self.particle_limit
+    }
+
+    pub fn simulation(&self) -> ParticleSimulation {
+        self.simulation
+    }
+
+    pub fn transform(&self) -> Matrix4<f32> {
+        self.transform
+    }
+
     pub fn set_state(&mut self, state: ParticleSystemState) {
         self.state = state
     }
 
+    /// Places the emitter. Spawn positions and velocities are transformed by it.
+    pub fn set_transform(&mut self, transform: Matrix4<f32>) {
+        self.transform = transform
+    }
+
+    /// Simulates CPU particles. GPU particles only have the time and spawns queued up for the
+    /// renderer's next simulation dispatch.
     pub fn tick(&mut self, delta_time: f32) {
-        {
-            scope!("Particle:Spawn");
+        let mut spawn_count = 0;
+        if let ParticleSystemState::Running = self.state {
             self.time_since_last_spawn += delta_time;
             while self.time_since_last_spawn >= (1.0 / self.spawn_rate) {
+                spawn_count += 1;
+                self.time_since_last_spawn -= (1.0 / self.spawn_rate);
+            }
+        }
+
+        if self.simulation == ParticleSimulation::Gpu {
+            self.gpu_step.delta_time += delta_time;
+            self.gpu_step.spawn_count += spawn_count;
+            return;
+        }
+
+        {
+            scope!("Particle:Spawn");
+            for _ in 0..spawn_count {
                 let unused_particle_index = self.first_unused_particle();
                 self.spawn_particle(unused_particle_index);
-                self.time_since_last_spawn -= (1.0 / self.spawn_rate);
             }
         }
 
@@ -50,14 +97,27 @@ impl ParticleSystem {
             for particle in self.particles.iter_mut() {
                 particle.life -= delta_time;
                 if particle.life >= 0.0 {
+                    particle.velocity += self.gravity * delta_time;
                     particle.position += particle.velocity * delta_time;
                     particle.rotation += particle.rotation_velocity * delta_time;
-                    particle.colour.w -= delta_time * 0.5;
+
+                    let age = 1.0 - particle.life / particle.lifetime;
+                    particle.colour = self.initial_colour.lerp(self.final_colour, age);
+                    particle.size = self.scale + (self.final_scale - self.scale) * age;
                 }
             }
         }
     }
 
+    /// Takes the time and spawns queued since the last GPU simulation dispatch.
+    pub(crate) fn take_gpu_step(&mut self) -> GpuParticleStep {
+        let mut step = std::mem::take(&mut self.gpu_step);
+        step.spawn_count = step.spawn_count.min(self.particle_limit as u32);
+        step
+    }
+
+    /// Live CPU simulated particles. Always empty for [ParticleSimulation::Gpu], whose particles
+    /// never leave the GPU.
     pub fn particles(&self) -> Vec<&Particle> {
         self.particles
             .iter()
@@ -76,9 +136,13 @@ impl ParticleSystem {
 
     fn spawn_particle(&mut self, particle_index: usize) {
         let mut particle = &mut self.particles[particle_index];
-        particle.position = self.spawn_position;
-        particle.velocity = self.velocity.into();
+        particle.position = self
+            .transform
+            .transform_point(Point3::from_vec(self.spawn_position))
+            .to_vec();
+        particle.velocity = self.transform.transform_vector(self.velocity.into());
         particle.life = self.life;
+        particle.lifetime = self.life;
         particle.colour = self.initial_colour;
         particle.texture_index = self.texture;
         particle.size = self.scale;
@@ -94,14 +158,21 @@ impl Default for ParticleSystem {
         particles.resize(particle_limit, Particle::default());
         Self {
             particles,
+            particle_limit,
+            simulation: ParticleSimulation::Cpu,
             time_since_last_spawn: 0.0,
+            gpu_step: GpuParticleStep::default(),
+            transform: Matrix4::identity(),
             spawn_rate: 1.0,
             spawn_position: Vector3::zero(),
             velocity: VectorParameter::default(),
+            gravity: Vector3::zero(),
             state: ParticleSystemState::Stopped,
             initial_colour: Vector4::from_value(1.0),
+            final_colour: Vector4::new(1.0, 1.0, 1.0, 0.0),
             texture: None,
             scale: 1.0,
+            final_scale: 1.0,
             rotation: VectorParameter::default(),
             rotation_velocity: VectorParameter::default(),
             life: 5.0,
@@ -110,6 +181,88 @@ impl Default for ParticleSystem {
     }
 }
 
+impl From<ParticleSystemDesc> for ParticleSystem {
+    fn from(desc: ParticleSystemDesc) -> Self {
+        // GPU particles are stored in a buffer made by the renderer instead
+        let particles = match desc.simulation {
+            ParticleSimulation::Cpu => vec![Particle::default(); desc.particle_limit],
+            ParticleSimulation::Gpu => Vec::new(),
+        };
+
+        Self {
+            particles,
+            particle_limit: desc.particle_limit,
+            simulation: desc.simulation,
+            spawn_rate: desc.emission_rate,
+            velocity: desc.velocity,
+            gravity: desc.gravity,
+            initial_colour: desc.start_colour,
+            final_colour: desc.end_colour,
+            texture: desc.texture,
+            scale: desc.start_size,
+            final_scale: desc.end_size,
+            life: desc.lifetime,
+            ..Default::default()
+        }
+    }
+}
+
+/// Describes a particle system for [crate::renderer::Renderer::create_particle_system].
+#[derive(Copy, Clone)]
+pub struct ParticleSystemDesc {
+    /// Most particles alive at once.
+    pub particle_limit: usize,
+    /// Particles spawned per second.
+    pub emission_rate: f32,
+    /// Seconds each particle lives for.
+    pub lifetime: f32,
+    /// Velocity at spawn, relative to the system's transform.
+    pub velocity: VectorParameter,
+    /// Acceleration in world space.
+    pub gravity: Vector3<f32>,
+    pub start_colour: Vector4<f32>,
+    pub end_colour: Vector4<f32>,
+    pub start_size: f32,
+    pub end_size: f32,
+    pub texture: Option<ImageHandle>,
+    pub simulation: ParticleSimulation,
+}
+
+impl Default for ParticleSystemDesc {
+    fn default() -> Self {
+        Self {
+            particle_limit: 64,
+            emission_rate: 1.0,
+            lifetime: 5.0,
+            velocity: VectorParameter::default(),
+            gravity: Vector3::zero(),
+            start_colour: Vector4::from_value(1.0),
+            end_colour: Vector4::new(1.0, 1.0, 1.0, 0.0),
+            start_size: 1.0,
+            end_size: 1.0,
+            texture: None,
+            simulation: ParticleSimulation::Cpu,
+        }
+    }
+}
+
+/// Where a particle system's particles are spawned and moved.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParticleSimulation {
+    /// Simulated by [ParticleSystem::tick] and uploaded every frame. Supports meshes and rotation.
+    Cpu,
+    /// Simulated by a compute shader over a buffer that stays on the GPU, so tens of thousands of
+    /// particles cost no uploads. Always drawn as unrotated billboards.
+    Gpu,
+}
+
+/// Time and spawns queued for the next GPU simulation dispatch.
+#[derive(Copy, Clone, Default)]
+pub(crate) struct GpuParticleStep {
+    pub delta_time: f32,
+    pub spawn_count: u32,
+}
+
 pub enum ParticleSystemState {
     Stopped,
     Running,
@@ -118,6 +271,8 @@ pub enum ParticleSystemState {
 #[derive(Copy, Clone)]
 pub struct Particle {
     pub life: f32,
+    /// Life the particle spawned with.
+    pub lifetime: f32,
     pub position: Vector3<f32>,
     pub rotation: Vector3<f32>,
     pub size: f32,
@@ -131,6 +286,7 @@ impl Default for Particle {
     fn default() -> Self {
         Self {
             life: 0.0f32,
+            lifetime: 0.0f32,
             position: Vector3::zero(),
             rotation: Vector3::zero(),
             velocity: Vector3::zero(),
@@ -1,76 +1,135 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CStr;
 use std::fs;
 use std::hash::{Hash, Hasher};
 use std::ops::BitOr;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{mpsc, Arc};
+use std::thread;
 
-use anyhow::Result;
+use anyhow::{anyhow, ensure, Result};
 use ash::vk;
 use ash::vk::{DescriptorSetLayout, Handle, ObjectType, PushConstantRange};
-use log::{error, info, trace};
+use log::{error, info, trace, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use slotmap::{new_key_type, SlotMap};
 
+use crate::util::descriptor::LayoutCacheStats;
 use crate::GraphicsDevice;
 
 pub(crate) struct PipelineManager {
     device: Arc<GraphicsDevice>,
     shader_compiler: shaderc::Compiler,
     pipelines: SlotMap<PipelineHandle, Pipeline>,
-    old_pipelines: Vec<vk::Pipeline>,
+    /// Pipelines replaced by reloads, with the frames left until no frame in flight can be using
+    /// them.
+    old_pipelines: Vec<(vk::Pipeline, usize)>,
+    pipeline_cache: vk::PipelineCache,
+    pipeline_cache_path: Option<String>,
+    pending_reloads: Vec<Receiver<Vec<CompiledPipeline>>>,
+    /// Pipelines using each shader file, keyed by canonical path so they can be matched to watcher events.
+    shader_users: HashMap<PathBuf, Vec<PipelineHandle>>,
+    shader_watcher: Option<ShaderWatcher>,
+    compute_pipelines: SlotMap<ComputePipelineHandle, ComputePipeline>,
+}
+
+struct ShaderWatcher {
+    // Kept alive to keep receiving events
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
 }
 
 impl PipelineManager {
-    pub fn new(device: Arc<GraphicsDevice>) -> Self {
+    /// Creates the manager, seeding its pipeline cache from `pipeline_cache_path` if the file exists and
+    /// was written by the same device and driver. The cache is saved back to the path on [PipelineManager::deinit].
+    pub fn new(device: Arc<GraphicsDevice>, pipeline_cache_path: Option<String>) -> Result<Self> {
         let shader_compiler = shaderc::Compiler::new().unwrap();
-        Self {
+
+        let initial_data = pipeline_cache_path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .filter(|data| is_pipeline_cache_compatible(&device, data))
+            .unwrap_or_default();
+        if !initial_data.is_empty() {
+            info!(
+                "Loaded pipeline cache. [Size: {} bytes]",
+                initial_data.len()
+            );
+        }
+        let cache_create_info = vk::PipelineCacheCreateInfo::builder().initial_data(&initial_data);
+        let pipeline_cache = unsafe {
+            device
+                .vk_device
+                .create_pipeline_cache(&cache_create_info, None)
+        }?;
+
+        Ok(Self {
             device,
             shader_compiler,
             pipelines: SlotMap::default(),
             old_pipelines: Vec::default(),
-        }
+            pipeline_cache,
+            pipeline_cache_path,
+            pending_reloads: Vec::new(),
+            shader_users: HashMap::new(),
+            shader_watcher: None,
+            compute_pipelines: SlotMap::default(),
+        })
     }
 
     pub fn create_pipeline(&mut self, build_info: &PipelineCreateInfo) -> Result<PipelineHandle> {
         let pso = PipelineManager::create_pipeline_internal(
             &mut self.shader_compiler,
             &self.device,
+            self.pipeline_cache,
             build_info,
         )?;
-        Ok(self.pipelines.insert(Pipeline {
+        let handle = self.pipelines.insert(Pipeline {
             pso,
             create_info: build_info.clone(),
-        }))
+        });
+        for shader in [&build_info.vertex_shader, &build_info.fragment_shader] {
+            self.shader_users
+                .entry(canonical_shader_path(shader))
+                .or_default()
+                .push(handle);
+        }
+        Ok(handle)
     }
 
     fn create_pipeline_internal(
         shader_compiler: &mut shaderc::Compiler,
         device: &GraphicsDevice,
+        pipeline_cache: vk::PipelineCache,
         build_info: &PipelineCreateInfo,
     ) -> Result<vk::Pipeline> {
-        let vertex_file = fs::read_to_string(&build_info.vertex_shader)?;
-        let frag_file = fs::read_to_string(&build_info.fragment_shader)?;
-
-        let mut options = shaderc::CompileOptions::new().unwrap();
-        options.set_include_callback(include_resolve_callback);
+        let features = device.enabled_features();
+        ensure!(
+            build_info.polygon_mode == vk::PolygonMode::FILL
+                || features.fill_mode_non_solid == vk::TRUE,
+            "Non-fill polygon modes require the fillModeNonSolid device feature"
+        );
+        ensure!(
+            build_info.line_width == 1.0f32 || features.wide_lines == vk::TRUE,
+            "Line widths other than 1.0 require the wideLines device feature"
+        );
 
-        let vert_binary = shader_compiler.compile_into_spirv(
-            &vertex_file,
-            shaderc::ShaderKind::Vertex,
+        let shaders = compile_shaders(
+            shader_compiler,
             &build_info.vertex_shader,
-            "main",
-            Some(&options),
-        )?;
-
-        let frag_binary = shader_compiler.compile_into_spirv(
-            &frag_file,
-            shaderc::ShaderKind::Fragment,
             &build_info.fragment_shader,
-            "main",
-            Some(&options),
         )?;
+        PipelineManager::create_pipeline_from_spirv(device, pipeline_cache, build_info, &shaders)
+    }
 
-        let vertex_shader = load_shader_module(&device.vk_device, vert_binary.as_binary())?;
+    fn create_pipeline_from_spirv(
+        device: &GraphicsDevice,
+        pipeline_cache: vk::PipelineCache,
+        build_info: &PipelineCreateInfo,
+        shaders: &CompiledShaders,
+    ) -> Result<vk::Pipeline> {
+        let vertex_shader = load_shader_module(&device.vk_device, &shaders.vertex)?;
 
         let vertex_stage_info = vk::PipelineShaderStageCreateInfo::builder()
             .name(unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") })
@@ -78,7 +137,7 @@ impl PipelineManager {
             .module(vertex_shader)
             .build();
 
-        let fragment_shader = load_shader_module(&device.vk_device, frag_binary.as_binary())?;
+        let fragment_shader = load_shader_module(&device.vk_device, &shaders.fragment)?;
 
         let fragment_stage_info = vk::PipelineShaderStageCreateInfo::builder()
             .name(unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") })
@@ -94,9 +153,13 @@ impl PipelineManager {
             depth_stencil_state: build_info.depth_stencil_state,
             pipeline_layout: build_info.pipeline_layout,
             cull_mode: build_info.cull_mode,
+            sample_count: build_info.sample_count,
+            polygon_mode: build_info.polygon_mode,
+            line_width: build_info.line_width,
+            topology: build_info.topology,
         };
 
-        let pipeline = build_pipeline(&device.vk_device, info);
+        let pipeline = build_pipeline(&device.vk_device, pipeline_cache, info);
 
         {
             let object_name_string = String::from("Shader:")
@@ -124,52 +187,337 @@ impl PipelineManager {
         self.pipelines.get(handle).unwrap().pso
     }
 
-    pub fn reload_shaders(&mut self, device: &GraphicsDevice) {
-        let mut new_pipelines = Vec::new();
-        for (_, pipeline) in self.pipelines.iter() {
-            new_pipelines.push(PipelineManager::create_pipeline_internal(
-                &mut self.shader_compiler,
-                device,
-                &pipeline.create_info,
-            ));
+    /// Compiles and creates a compute pipeline. Compute pipelines aren't hot reloaded.
+    pub fn create_compute_pipeline(
+        &mut self,
+        create_info: &ComputePipelineCreateInfo,
+    ) -> Result<ComputePipelineHandle> {
+        let code = compile_shader(
+            &mut self.shader_compiler,
+            &create_info.compute_shader,
+            shaderc::ShaderKind::Compute,
+        )?;
+        let shader = load_shader_module(&self.device.vk_device, &code)?;
+
+        let stage_info = vk::PipelineShaderStageCreateInfo::builder()
+            .name(unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") })
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(shader);
+        let pso_create_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(*stage_info)
+            .layout(create_info.pipeline_layout);
+
+        let result = unsafe {
+            self.device.vk_device.create_compute_pipelines(
+                self.pipeline_cache,
+                &[*pso_create_info],
+                None,
+            )
+        };
+        unsafe { self.device.vk_device.destroy_shader_module(shader, None) };
+        let pso = result.map_err(|(_, error)| error)?[0];
+
+        let object_name_string = String::from("Shader:")
+            + create_info
+                .compute_shader
+                .rsplit_once('/')
+                .map_or(create_info.compute_shader.as_str(), |(_, name)| name);
+        self.device.set_vulkan_debug_name(
+            pso.as_raw(),
+            ObjectType::PIPELINE,
+            &object_name_string,
+        )?;
+
+        Ok(self.compute_pipelines.insert(ComputePipeline { pso }))
+    }
+
+    pub fn get_compute_pipeline(&self, handle: ComputePipelineHandle) -> vk::Pipeline {
+        self.compute_pipelines.get(handle).unwrap().pso
+    }
+
+    /// Rebuilds a pipeline to draw with a different number of samples per pixel.
+    /// The old pipeline is kept alive until the manager is deinitialised, like with shader reloads.
+    pub fn set_sample_count(
+        &mut self,
+        handle: PipelineHandle,
+        sample_count: vk::SampleCountFlags,
+    ) -> Result<()> {
+        let pipeline = self.pipelines.get_mut(handle).unwrap();
+        let mut create_info = pipeline.create_info.clone();
+        create_info.sample_count = sample_count;
+
+        let pso = PipelineManager::create_pipeline_internal(
+            &mut self.shader_compiler,
+            &self.device,
+            self.pipeline_cache,
+            &create_info,
+        )?;
+        self.old_pipelines
+            .push((pipeline.pso, self.device.frames_in_flight()));
+        pipeline.pso = pso;
+        pipeline.create_info = create_info;
+
+        Ok(())
+    }
+
+    /// Starts recompiling the shaders of every pipeline on a background thread.
+    /// The new pipelines replace the old ones in [PipelineManager::poll_reloads] once compiled.
+    pub fn reload_shaders(&mut self) {
+        let handles = self.pipelines.keys().collect();
+        self.reload_pipelines(handles);
+    }
+
+    /// Starts recompiling the shaders of `handles` on a background thread.
+    pub fn reload_pipelines(&mut self, handles: Vec<PipelineHandle>) {
+        let sources: Vec<(PipelineHandle, String, String)> = handles
+            .into_iter()
+            .filter_map(|handle| {
+                let create_info = &self.pipelines.get(handle)?.create_info;
+                Some((
+                    handle,
+                    create_info.vertex_shader.clone(),
+                    create_info.fragment_shader.clone(),
+                ))
+            })
+            .collect();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            profiling::scope!("Compile shaders");
+            let mut shader_compiler = shaderc::Compiler::new().unwrap();
+            let compiled = sources
+                .into_iter()
+                .map(
+                    |(handle, vertex_shader, fragment_shader)| CompiledPipeline {
+                        handle,
+                        shaders: compile_shaders(
+                            &mut shader_compiler,
+                            &vertex_shader,
+                            &fragment_shader,
+                        ),
+                    },
+                )
+                .collect();
+            // The manager may have been dropped while compiling, in which case nobody needs the result
+            let _ = sender.send(compiled);
+        });
+        self.pending_reloads.push(receiver);
+    }
+
+    /// Starts or stops watching `directory` for shader changes, see [PipelineManager::reload_changed_shaders].
+    pub fn set_shader_watch(&mut self, enabled: bool, directory: &str) -> Result<()> {
+        if !enabled {
+            self.shader_watcher = None;
+            return Ok(());
         }
+        if self.shader_watcher.is_some() {
+            return Ok(());
+        }
+
+        let (sender, events) = mpsc::channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let _ = sender.send(event);
+            })?;
+        watcher.watch(Path::new(directory), RecursiveMode::Recursive)?;
+        info!("Watching {} for shader changes", directory);
+
+        self.shader_watcher = Some(ShaderWatcher {
+            _watcher: watcher,
+            events,
+        });
+        Ok(())
+    }
 
-        // Set ones that reloaded successfully
-        for (i, (_, pipeline)) in self.pipelines.iter_mut().enumerate() {
-            if let Ok(new_pipeline) = new_pipelines.get(i).unwrap() {
-                self.old_pipelines.push(pipeline.pso);
-                pipeline.pso = *new_pipeline;
-            } else {
-                error!(
-                    "Unable to reload shader: [VERT:{}][FRAG:{}]",
-                    pipeline.create_info.vertex_shader, pipeline.create_info.fragment_shader
-                );
+    /// Starts a background reload of the pipelines using any shader files changed on disk since the last call.
+    /// A change to an included library file reloads every pipeline, as includes aren't tracked.
+    pub fn reload_changed_shaders(&mut self) {
+        let watcher = match &self.shader_watcher {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        let mut handles = HashSet::new();
+        let mut reload_all = false;
+        for event in watcher.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    warn!("Shader watcher error: {}", error);
+                    continue;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in event.paths.iter() {
+                match path.extension().and_then(|extension| extension.to_str()) {
+                    Some("vert") | Some("frag") => {
+                        if let Some(users) = self.shader_users.get(&canonical_shader_path(path)) {
+                            handles.extend(users.iter().copied());
+                        }
+                    }
+                    Some("glsl") => reload_all = true,
+                    _ => {}
+                }
             }
         }
 
-        let successful_reloads = new_pipelines
-            .into_iter()
-            .filter_map(|result| result.ok())
-            .count();
-        info!(
-            "Reloaded {}/{} shaders!",
-            successful_reloads,
-            self.pipelines.len()
-        );
+        if reload_all {
+            self.reload_shaders();
+        } else if !handles.is_empty() {
+            self.reload_pipelines(handles.into_iter().collect());
+        }
+    }
+
+    /// Swaps in the pipelines from any background reloads that have finished compiling.
+    /// Pipelines that failed to compile keep their last working version.
+    ///
+    /// Returns [None] while no reload has finished, otherwise the combined result of the finished reloads.
+    pub fn poll_reloads(&mut self) -> Option<Result<()>> {
+        let mut finished = Vec::new();
+        self.pending_reloads
+            .retain(|receiver| match receiver.try_recv() {
+                Ok(compiled) => {
+                    finished.extend(compiled);
+                    false
+                }
+                Err(TryRecvError::Empty) => true,
+                Err(TryRecvError::Disconnected) => false,
+            });
+        if finished.is_empty() {
+            return None;
+        }
+
+        let total = finished.len();
+        let mut errors = Vec::new();
+        for compiled in finished.into_iter() {
+            // The pipeline may have been removed while its shaders were compiling
+            let pipeline = match self.pipelines.get_mut(compiled.handle) {
+                Some(pipeline) => pipeline,
+                None => continue,
+            };
+            let result = compiled.shaders.and_then(|shaders| {
+                PipelineManager::create_pipeline_from_spirv(
+                    &self.device,
+                    self.pipeline_cache,
+                    &pipeline.create_info,
+                    &shaders,
+                )
+            });
+            match result {
+                Ok(pso) => {
+                    self.old_pipelines
+                        .push((pipeline.pso, self.device.frames_in_flight()));
+                    pipeline.pso = pso;
+                }
+                Err(error) => {
+                    error!(
+                        "Unable to reload shader: [VERT:{}][FRAG:{}]\n{}",
+                        pipeline.create_info.vertex_shader,
+                        pipeline.create_info.fragment_shader,
+                        error
+                    );
+                    errors.push(error.to_string());
+                }
+            }
+        }
+        info!("Reloaded {}/{} shaders!", total - errors.len(), total);
+
+        if errors.is_empty() {
+            Some(Ok(()))
+        } else {
+            Some(Err(anyhow!(
+                "{} shaders failed to reload:\n{}",
+                errors.len(),
+                errors.join("\n")
+            )))
+        }
+    }
+
+    /// Called at the start of each frame, destroys replaced pipelines no frame in flight can be
+    /// using.
+    pub fn release_retired_pipelines(&mut self) {
+        let device = &self.device;
+        self.old_pipelines
+            .retain_mut(|(pipeline, frames_remaining)| {
+                *frames_remaining -= 1;
+                if *frames_remaining > 0 {
+                    return true;
+                }
+
+                unsafe { device.vk_device.destroy_pipeline(*pipeline, None) };
+                false
+            });
     }
 
     pub fn deinit(&mut self) {
-        for pipeline in self.old_pipelines.iter() {
+        for (pipeline, _) in self.old_pipelines.iter() {
             unsafe { self.device.vk_device.destroy_pipeline(*pipeline, None) };
         }
         for (_, pipeline) in self.pipelines.iter() {
             unsafe { self.device.vk_device.destroy_pipeline(pipeline.pso, None) };
         }
+        for (_, pipeline) in self.compute_pipelines.iter() {
+            unsafe { self.device.vk_device.destroy_pipeline(pipeline.pso, None) };
+        }
+
+        if let Some(path) = &self.pipeline_cache_path {
+            let result = unsafe {
+                self.device
+                    .vk_device
+                    .get_pipeline_cache_data(self.pipeline_cache)
+            }
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(fs::write(path, data)?));
+            if let Err(error) = result {
+                warn!("Unable to save pipeline cache to {}: {}", path, error);
+            }
+        }
+        unsafe {
+            self.device
+                .vk_device
+                .destroy_pipeline_cache(self.pipeline_cache, None)
+        };
+    }
+}
+
+/// Checks the cache header written by the driver, so a cache saved on another device or driver version
+/// is thrown away rather than handed to the driver.
+fn is_pipeline_cache_compatible(device: &GraphicsDevice, data: &[u8]) -> bool {
+    // VkPipelineCacheHeaderVersionOne: length, version, vendor ID, device ID, then the cache UUID
+    const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+    let read_u32 = |offset: usize| {
+        u32::from_ne_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    };
+
+    let properties = unsafe {
+        device
+            .raw_instance()
+            .get_physical_device_properties(device.raw_physical_device())
+    };
+    let compatible = read_u32(0) as usize >= HEADER_SIZE
+        && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..HEADER_SIZE] == properties.pipeline_cache_uuid;
+    if !compatible {
+        info!("Discarding pipeline cache created by a different device or driver");
     }
+    compatible
 }
 
 new_key_type! {
     pub(crate) struct PipelineHandle;
+    pub(crate) struct ComputePipelineHandle;
 }
 
 struct Pipeline {
@@ -177,6 +525,16 @@ struct Pipeline {
     create_info: PipelineCreateInfo,
 }
 
+struct ComputePipeline {
+    pso: vk::Pipeline,
+}
+
+#[derive(Clone)]
+pub struct ComputePipelineCreateInfo {
+    pub pipeline_layout: vk::PipelineLayout,
+    pub compute_shader: String,
+}
+
 #[derive(Clone)]
 pub struct PipelineCreateInfo {
     pub pipeline_layout: vk::PipelineLayout,
@@ -186,7 +544,20 @@ pub struct PipelineCreateInfo {
     pub color_attachment_formats: Vec<PipelineColorAttachment>,
     pub depth_attachment_format: Option<vk::Format>,
     pub depth_stencil_state: vk::PipelineDepthStencilStateCreateInfo,
+    /// Meshes use counter-clockwise front faces, so [vk::CullModeFlags::BACK] culls back faces as
+    /// usual when drawing into offscreen targets.
+    ///
+    /// Projections follow the OpenGL convention of Y pointing up, while Vulkan's framebuffer Y
+    /// points down. Offscreen targets are therefore rendered upside down, which mirrors the
+    /// winding, and are only turned the right way up by the flipped viewport of the pass that
+    /// writes the backbuffer. Pipelines are built with clockwise front faces to account for this,
+    /// which means culling is reversed for anything drawn directly into the backbuffer.
     pub cull_mode: vk::CullModeFlags,
+    pub sample_count: vk::SampleCountFlags,
+    pub polygon_mode: vk::PolygonMode,
+    /// Width of rasterized lines. Must be 1.0 unless the device supports wide lines.
+    pub line_width: f32,
+    pub topology: vk::PrimitiveTopology,
 }
 
 pub struct PipelineBuildInfo {
@@ -197,6 +568,11 @@ pub struct PipelineBuildInfo {
     pub depth_stencil_state: vk::PipelineDepthStencilStateCreateInfo,
     pub pipeline_layout: vk::PipelineLayout,
     pub cull_mode: vk::CullModeFlags,
+    pub sample_count: vk::SampleCountFlags,
+    pub polygon_mode: vk::PolygonMode,
+    /// Width of rasterized lines. Must be 1.0 unless the device supports wide lines.
+    pub line_width: f32,
+    pub topology: vk::PrimitiveTopology,
 }
 
 #[derive(Clone)]
@@ -226,7 +602,11 @@ impl Default for PipelineColorAttachment {
     }
 }
 
-pub fn build_pipeline(device: &ash::Device, build_info: PipelineBuildInfo) -> vk::Pipeline {
+pub fn build_pipeline(
+    device: &ash::Device,
+    pipeline_cache: vk::PipelineCache,
+    build_info: PipelineBuildInfo,
+) -> vk::Pipeline {
     // Defaults
 
     let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
@@ -260,23 +640,25 @@ pub fn build_pipeline(device: &ash::Device, build_info: PipelineBuildInfo) -> vk
         .vertex_attribute_descriptions(&build_info.vertex_input_state.attributes);
 
     let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .topology(build_info.topology)
         .primitive_restart_enable(false);
 
     let tess_state = vk::PipelineTessellationStateCreateInfo::builder();
 
     let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        .rasterization_samples(build_info.sample_count);
 
     let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
-        .polygon_mode(vk::PolygonMode::FILL)
+        .polygon_mode(build_info.polygon_mode)
         .cull_mode(build_info.cull_mode)
-        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        // Counter-clockwise meshes end up clockwise in the upside down offscreen targets, see
+        // PipelineCreateInfo::cull_mode
+        .front_face(vk::FrontFace::CLOCKWISE)
         .depth_bias_enable(false)
         .depth_bias_constant_factor(0.0f32)
         .depth_bias_clamp(0.0f32)
         .depth_bias_slope_factor(0.0f32)
-        .line_width(1.0f32);
+        .line_width(build_info.line_width);
 
     let color_attachment_formats: Vec<vk::Format> = build_info
         .color_attachment_formats
@@ -309,13 +691,56 @@ pub fn build_pipeline(device: &ash::Device, build_info: PipelineBuildInfo) -> vk
         .layout(build_info.pipeline_layout);
 
     let create_info = [*pso_create_info];
-    let pso =
-        unsafe { device.create_graphics_pipelines(vk::PipelineCache::null(), &create_info, None) };
+    let pso = unsafe { device.create_graphics_pipelines(pipeline_cache, &create_info, None) };
 
     let pipeline_object = *pso.unwrap().get(0usize).unwrap();
     pipeline_object
 }
 
+fn canonical_shader_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    fs::canonicalize(&path).unwrap_or_else(|_| path.as_ref().to_path_buf())
+}
+
+struct CompiledShaders {
+    vertex: Vec<u32>,
+    fragment: Vec<u32>,
+}
+
+struct CompiledPipeline {
+    handle: PipelineHandle,
+    shaders: Result<CompiledShaders>,
+}
+
+fn compile_shaders(
+    shader_compiler: &mut shaderc::Compiler,
+    vertex_shader: &str,
+    fragment_shader: &str,
+) -> Result<CompiledShaders> {
+    Ok(CompiledShaders {
+        vertex: compile_shader(shader_compiler, vertex_shader, shaderc::ShaderKind::Vertex)?,
+        fragment: compile_shader(
+            shader_compiler,
+            fragment_shader,
+            shaderc::ShaderKind::Fragment,
+        )?,
+    })
+}
+
+fn compile_shader(
+    shader_compiler: &mut shaderc::Compiler,
+    shader: &str,
+    kind: shaderc::ShaderKind,
+) -> Result<Vec<u32>> {
+    let file = fs::read_to_string(shader)?;
+
+    let mut options = shaderc::CompileOptions::new().unwrap();
+    options.set_include_callback(include_resolve_callback);
+
+    let binary = shader_compiler.compile_into_spirv(&file, kind, shader, "main", Some(&options))?;
+
+    Ok(binary.as_binary().to_vec())
+}
+
 pub fn load_shader_module(device: &ash::Device, code: &[u32]) -> Result<vk::ShaderModule> {
     let create_info = vk::ShaderModuleCreateInfo::builder().code(code);
 
@@ -350,6 +775,8 @@ pub struct VertexInputDescription {
 pub struct PipelineLayoutCache {
     device: Arc<ash::Device>,
     layout_cache: HashMap<PipelineLayoutInfo, vk::PipelineLayout>,
+    hits: usize,
+    misses: usize,
 }
 
 impl PipelineLayoutCache {
@@ -357,6 +784,8 @@ impl PipelineLayoutCache {
         Self {
             device,
             layout_cache: HashMap::default(),
+            hits: 0,
+            misses: 0,
         }
     }
 
@@ -366,6 +795,14 @@ impl PipelineLayoutCache {
         }
     }
 
+    pub fn cache_stats(&self) -> LayoutCacheStats {
+        LayoutCacheStats {
+            cached_layouts: self.layout_cache.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+
     pub fn create_pipeline_layout(
         &mut self,
         descriptor_sets: &[DescriptorSetLayout],
@@ -381,8 +818,10 @@ impl PipelineLayoutCache {
             .push_constant_ranges(&layout_info.push_constant_range);
 
         return if let Some(layout) = self.layout_cache.get(&layout_info) {
+            self.hits += 1;
             Ok(*layout)
         } else {
+            self.misses += 1;
             let layout = unsafe {
                 self.device
                     .create_pipeline_layout(&pipeline_layout_create_info, None)
@@ -1,14 +1,23 @@
-pub use crate::camera::CameraTrait;
-pub use crate::colour::Colour;
-pub use crate::core::device::{GraphicsDevice, ImageFormatType, FRAMES_IN_FLIGHT, SHADOWMAP_SIZE};
+pub use crate::camera::{CameraTrait, OrthographicCamera, Projection};
+pub use crate::colour::{Colour, Gradient};
+pub use crate::core::device::{
+    AdapterInfo, AdapterPreference, AdapterType, AnisotropyLevel, GraphicsDevice,
+    GraphicsDeviceConfig, ImageFormatType, MipFilter, PresentMode, SamplerKind, ValidationSeverity,
+    DEFAULT_MAX_BINDLESS_CAPACITY, MAX_FRAMES_IN_FLIGHT, SHADOWMAP_SIZE,
+};
 pub use crate::light::DirectionalLight;
-pub use crate::light::Light;
-pub use crate::mesh::{Face, MeshData, Vertex};
-pub use crate::renderer::{LightHandle, MaterialInstance, Renderer, UIMesh, UIVertex};
+pub use crate::light::{AreaLight, Light, LightType};
+pub use crate::light::{ShadowFilter, ShadowSettings, ShadowUpdateMode};
+pub use crate::mesh::{Face, MeshBounds, MeshData, Vertex};
+pub use crate::renderer::{
+    AreaLightHandle, AutoExposureParams, Background, BloomParams, DofParams, Filter, FogMode,
+    FogParams, FxaaSettings, LightHandle, MaterialInstance, Rect, Renderer, RendererSettings,
+    SkyParams, TransparencyMode, UIMesh, UIVertex,
+};
 pub use crate::renderpass::attachment::{AttachmentHandle, AttachmentInfo};
 pub use crate::renderpass::builder::RenderPassBuilder;
 pub use crate::renderpass::resource::ImageUsageTracker;
 pub use crate::renderpass::RenderPass;
-pub use crate::resource::{BufferHandle, ImageHandle};
+pub use crate::resource::{BufferHandle, ImageHandle, MemoryStats};
 pub use crate::util::bindless::BindlessManager;
 pub use crate::util::meshpool::MeshHandle;
@@ -1,60 +1,140 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::mem::size_of;
-use std::sync::Arc;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Instant;
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use ash::vk;
 use ash::vk::{
     AccessFlags2, ClearDepthStencilValue, Handle, ImageLayout, ObjectType, PipelineStageFlags2,
 };
 use bytemuck::{offset_of, Zeroable};
 use cgmath::{
-    Array, Deg, Euler, Matrix, Matrix4, Quaternion, Rotation3, SquareMatrix, Vector3, Vector4, Zero,
+    Array, Deg, EuclideanSpace, Euler, InnerSpace, Matrix, Matrix4, MetricSpace, Point3,
+    Quaternion, Rotation3, SquareMatrix, Transform, Vector2, Vector3, Vector4, Zero,
 };
-use image::EncodableLayout;
 use log::{info, trace, warn};
 use slotmap::{new_key_type, SlotMap};
 use winit::{dpi::PhysicalSize, window::Window};
 
 use crate::camera::DefaultCamera;
+use crate::exposure::AutoExposure;
 use crate::gpu_structs::{
-    CameraUniform, InstanceSSBO, LightUniform, MaterialParamSSBO, ParticleDrawData, TransformSSBO,
-    UIUniformData, UIVertexData, WorldDebugUIDrawData,
+    AreaLightUniform, BackgroundPushConstants, BloomPushConstants, BlurPushConstants,
+    CameraUniform, CombinePushConstants, CullObjectSSBO, CullPushConstants, DebugLineVertex,
+    DofPushConstants, FxaaPushConstants, GpuParticle, GpuParticleParams, InstanceSSBO,
+    LightUniform, MaterialParamSSBO, OutlinePushConstants, ParticleDrawData,
+    PointShadowPushConstants, SsaoPushConstants, TaaPushConstants, TransformSSBO, UIUniformData,
+    UIVertexData, WorldDebugUIDrawData,
 };
+use crate::ibl::{EquirectSkybox, IblGenerator, IblMaps};
+use crate::ltc::LtcTables;
 use crate::mesh::Index;
-use crate::particle::{ParticleSystem, ParticleSystemState};
+use crate::mipgen::MipGenerator;
+use crate::particle::{
+    ParticleSimulation, ParticleSystem, ParticleSystemDesc, ParticleSystemState, VectorParameter,
+};
 use crate::pipeline::{
-    PipelineColorAttachment, PipelineCreateInfo, PipelineHandle, PipelineLayoutCache,
-    PipelineManager, VertexInputDescription,
+    ComputePipelineCreateInfo, ComputePipelineHandle, PipelineColorAttachment, PipelineCreateInfo,
+    PipelineHandle, PipelineLayoutCache, PipelineManager, VertexInputDescription,
 };
+use crate::rendergraph::attachment::SizeClass;
 use crate::rendergraph::virtual_resource::VirtualRenderPassHandle;
-use crate::rendergraph::{RenderList, RenderPassLayout};
-use crate::renderpass::barrier::{ImageBarrier, ImageBarrierBuilder};
+use crate::rendergraph::{ClearColour, RenderList, RenderPassLayout};
+use crate::renderpass::barrier::{
+    is_supported_transition_usage, ImageBarrier, ImageBarrierBuilder,
+};
 use crate::renderpass::builder::RenderPassBuilder;
 use crate::renderpass::resource::ImageUsageTracker;
-use crate::resource::{BufferCreateInfo, BufferHandle, BufferStorageType, ImageHandle};
+use crate::resource::{
+    BufferCreateInfo, BufferHandle, BufferStorageType, ImageHandle, MemoryStats, ResourceManager,
+};
+use crate::util::debug_text::{build_font_atlas, layout_text};
 use crate::util::descriptor::{
-    BufferDescriptorInfo, DescriptorAllocator, DescriptorLayoutBuilder, DescriptorLayoutCache,
-    ImageDescriptorInfo, JBDescriptorBuilder,
+    BufferDescriptorInfo, DescriptorAllocator, DescriptorBuilder, DescriptorLayoutBuilder,
+    DescriptorLayoutCache, ImageDescriptorInfo, JBDescriptorBuilder, LayoutCacheStats,
 };
+use crate::util::ktx2::Ktx2Texture;
 use crate::util::meshpool::MeshPool;
 use crate::util::targets::{RenderImageType, RenderTargetHandle, RenderTargetSize, RenderTargets};
 use crate::{
-    AttachmentHandle, AttachmentInfo, CameraTrait, Colour, DirectionalLight, GraphicsDevice,
-    ImageFormatType, Light, MeshData, MeshHandle, Vertex, FRAMES_IN_FLIGHT, SHADOWMAP_SIZE,
+    AnisotropyLevel, AreaLight, AttachmentHandle, AttachmentInfo, CameraTrait, Colour,
+    DirectionalLight, GraphicsDevice, GraphicsDeviceConfig, ImageFormatType, Light, LightType,
+    MeshBounds, MeshData, MeshHandle, MipFilter, PresentMode, SamplerKind, ShadowFilter,
+    ShadowSettings, ShadowUpdateMode, Vertex, SHADOWMAP_SIZE,
 };
 
-const MAX_OBJECTS: u64 = 10000u64;
-const MAX_QUADS: u64 = 100000u64;
 const MAX_DEBUG_UI: u64 = 100u64;
-
-const MAX_MATERIAL_INSTANCES: usize = 128;
-const MAX_LIGHTS: usize = 64;
+const MAX_DEBUG_LINE_VERTICES: u64 = 20000u64;
+
+/// Render textures drawn each frame, further requests wait for the following frames.
+const MAX_RENDER_TEXTURES_PER_FRAME: usize = 4;
+/// Must match the MATERIAL_FLAG_ constants in object.glsl
+const MATERIAL_FLAG_NORMAL_MAP: i32 = 1;
+const MATERIAL_FLAG_VERTEX_COLOR: i32 = 2;
+const MATERIAL_FLAG_DOUBLE_SIDED: i32 = 4;
+const SHADER_DIRECTORY: &str = "assets/shaders";
 const MAX_PARTICLES: usize = 10000;
+/// Tiles across, tiles down and depth slices the view frustum is split into for light culling.
+/// Must match CLUSTER_GRID in cluster.glsl
+const CLUSTER_GRID: [usize; 3] = [16, 9, 24];
+const CLUSTER_COUNT: usize = CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2];
+/// Lights past this many in one cluster are left out of it. Must match cluster.glsl
+const MAX_LIGHTS_PER_CLUSTER: usize = 64;
+/// A light count for every cluster, followed by each cluster's light indices
+const LIGHT_CLUSTER_BUFFER_SIZE: usize =
+    size_of::<u32>() * CLUSTER_COUNT * (1 + MAX_LIGHTS_PER_CLUSTER);
+const MAX_SHADOWED_POINT_LIGHTS: usize = 4;
+const POINT_SHADOW_SIZE: u32 = 1024;
+const DEFAULT_SHADOW_BIAS: f32 = 0.001;
+const DEFAULT_IBL_INTENSITY: f32 = 1.0;
+/// Range of turbidity the procedural sky model was fitted over.
+const MIN_SKY_TURBIDITY: f32 = 1.7;
+const MAX_SKY_TURBIDITY: f32 = 10.0;
+/// Levels of the bloom mip chain, each half the size of the one before, starting at half the
+/// render size.
+const MIN_BLOOM_MIPS: usize = 2;
+const MAX_BLOOM_MIPS: usize = 8;
+/// Distance point light cube shadow maps are rendered out to, passed to the shaders in the camera
+/// uniform.
+const POINT_SHADOW_FAR_PLANE: f32 = 100.0;
 
 const DEFERRED_POSITION_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
 const DEFERRED_NORMAL_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
 const DEFERRED_COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+const VELOCITY_FORMAT: vk::Format = vk::Format::R16G16_SFLOAT;
+const SSAO_FORMAT: vk::Format = vk::Format::R8_UNORM;
+const OUTLINE_MASK_FORMAT: vk::Format = vk::Format::R8_UNORM;
+const OIT_ACCUMULATION_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const OIT_COVERAGE_FORMAT: vk::Format = vk::Format::R16_SFLOAT;
+const DEFAULT_SSAO_RADIUS: f32 = 0.5;
+const DEFAULT_SSAO_BIAS: f32 = 0.025;
+/// Length of the repeating jitter sequence used by temporal anti-aliasing.
+const TAA_JITTER_SAMPLES: usize = 8;
+
+/// One of the cube shadow maps handed out to the shadow casting point lights closest to the camera.
+#[derive(Copy, Clone)]
+struct PointShadowMap {
+    target: RenderTargetHandle,
+    /// Width and height of each face.
+    resolution: u32,
+    /// Light the shadow map was last drawn for, kept while it stays one of the closest casters.
+    light: Option<LightHandle>,
+    /// Position the light was at when the shadow map was last drawn.
+    light_position: Point3<f32>,
+    frames_since_update: u32,
+}
+
+/// A texture from [Renderer::load_texture_async] being decoded on a background thread.
+struct StreamedTexture {
+    /// Shows the placeholder until the decoded image is uploaded.
+    image: ImageHandle,
+    file_location: String,
+    image_type: ImageFormatType,
+    receiver: Receiver<Result<(Vec<u8>, u32, u32)>>,
+}
 
 /// The renderer for the GameEngine.
 /// Used to draw objects using the GPU.
@@ -62,94 +142,263 @@ pub struct Renderer {
     device: Arc<GraphicsDevice>,
     descriptor_layout_cache: DescriptorLayoutCache,
     descriptor_allocator: DescriptorAllocator,
-    frame_descriptor_allocator: [DescriptorAllocator; FRAMES_IN_FLIGHT],
+    frame_descriptor_allocator: Vec<DescriptorAllocator>,
     pipeline_layout_cache: PipelineLayoutCache,
     pipeline_manager: PipelineManager,
     mesh_pool: MeshPool,
     timestamps: TimeStamp,
+    start_time: Instant,
 
     stored_particle_systems: SlotMap<ParticleSystemHandle, ParticleSystem>,
     quad_mesh: MeshHandle,
 
     shadow_pso: PipelineHandle,
+    double_sided_shadow_pso: PipelineHandle,
+    point_shadow_pso: PipelineHandle,
+    point_shadow_pso_layout: vk::PipelineLayout,
+    render_targets: RenderTargets,
+    point_shadow_maps: Vec<PointShadowMap>,
+    /// Lights without an entry use [default_point_shadow_settings].
+    point_shadow_settings: HashMap<LightHandle, ShadowSettings>,
+    invalidated_point_shadows: HashSet<LightHandle>,
 
     forward_pass: ForwardPass,
     deferred_fill: DeferredPass,
+    cull_pso: ComputePipelineHandle,
+    cull_pso_layout: vk::PipelineLayout,
+    light_cull_pso: ComputePipelineHandle,
+    light_cull_pso_layout: vk::PipelineLayout,
+    /// Lights reaching each cluster of the main camera, filled in by the light culling pass.
+    light_cluster_buffer: Vec<BufferHandle>,
     deferred_lighting_combine: DeferredLightingCombinePass,
+    ssao_pass: SsaoPass,
+    taa_pass: TaaPass,
+    dof_pass: DofPass,
+    outline_pass: OutlinePass,
     particle_pipeline: (PipelineHandle, vk::PipelineLayout),
+    gpu_particles: GpuParticlePass,
 
     bloom_pass: BloomPass,
     combine_pso: PipelineHandle,
     combine_pso_layout: vk::PipelineLayout,
+    fxaa_pso: PipelineHandle,
+    fxaa_pso_layout: vk::PipelineLayout,
+    oit_composite_pso: PipelineHandle,
+    oit_composite_pso_layout: vk::PipelineLayout,
+    shadow_debug_pso: PipelineHandle,
+    shadow_debug_pso_layout: vk::PipelineLayout,
     world_debug_pso: PipelineHandle,
     world_debug_pso_layout: vk::PipelineLayout,
-    world_debug_desc_set: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
-    world_debug_draw_data: [BufferHandle; FRAMES_IN_FLIGHT],
+    world_debug_desc_set: Vec<vk::DescriptorSet>,
+    world_debug_draw_data: Vec<BufferHandle>,
+    debug_line_pso: PipelineHandle,
+    debug_line_pso_layout: vk::PipelineLayout,
+    /// Points at this frame's debug line vertices, which are uploaded into transient memory.
+    debug_line_desc_set: vk::DescriptorSet,
+    lines_to_draw: Vec<DebugLineVertex>,
+    /// Vertices in this frame's debug line buffer.
+    debug_line_vertex_count: usize,
 
     render_models: SlotMap<RenderModelHandle, RenderModel>,
-    descriptor_set: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
-    camera_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
+    descriptor_set: Vec<vk::DescriptorSet>,
+    camera_buffer: Vec<BufferHandle>,
     camera_uniform: CameraUniform,
-    light_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
+    light_buffer: Vec<BufferHandle>,
     stored_lights: SlotMap<LightHandle, Light>,
-    transform_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
-    material_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
-    instance_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
+    area_light_buffer: Vec<BufferHandle>,
+    stored_area_lights: SlotMap<AreaLightHandle, AreaLight>,
+    transform_buffer: Vec<BufferHandle>,
+    material_buffer: Vec<BufferHandle>,
+    material_buffer_capacity: Vec<usize>,
+    /// Most materials, including the ones appended for lights, that fit in one storage buffer
+    /// binding.
+    max_material_buffer_capacity: usize,
+    /// Render models the object buffers have room for.
+    max_objects: usize,
+    max_lights: usize,
+    max_area_lights: usize,
+    instance_buffer: Vec<BufferHandle>,
+    cull_object_buffer: Vec<BufferHandle>,
+    draw_indirect_buffer: Vec<BufferHandle>,
     material_instances: SlotMap<MaterialInstanceHandle, MaterialInstance>,
 
     ui_pass: UiPass,
     ui_to_draw: Vec<UIMesh>,
+    debug_font: ImageHandle,
 
     skybox: Option<ImageHandle>,
-    skybox_pso: PipelineHandle,
-    skybox_pso_layout: vk::PipelineLayout,
-    cube_mesh: MeshHandle,
+    equirect_skybox: Option<EquirectSkybox>,
+    ibl_generator: IblGenerator,
+    mip_generator: MipGenerator,
+    ibl_maps: Option<IblMaps>,
+    brdf_lut: ImageHandle,
+    ltc_tables: LtcTables,
+    ibl_intensity: f32,
+    skybox_rotation: Quaternion<f32>,
+    skybox_intensity: f32,
+    sky: SkyParams,
+    background: Background,
+    upscale_filter: Filter,
+    fxaa_enabled: bool,
+    fxaa_settings: FxaaSettings,
+    taa_enabled: bool,
+    /// Set once the TAA history holds a resolved frame, cleared whenever it is discarded.
+    taa_history_valid: bool,
+    fog: FogParams,
+    dof: DofParams,
+    bloom: BloomParams,
+    auto_exposure: AutoExposure,
+    auto_exposure_params: AutoExposureParams,
+    transparency_mode: TransparencyMode,
+    outline_models: HashSet<RenderModelHandle>,
+    viewports: SlotMap<ViewportHandle, SceneViewport>,
+    render_textures: HashMap<ImageHandle, ViewportHandle>,
+    /// Render textures waiting to be drawn, at most [MAX_RENDER_TEXTURES_PER_FRAME] a frame.
+    pending_render_textures: Vec<ViewportHandle>,
+    streamed_textures: Vec<StreamedTexture>,
+    outline_colour: Colour,
+    outline_width: u32,
 
     pub sun: DirectionalLight,
+    sun_shadow_settings: ShadowSettings,
+    shadow_filter: ShadowFilter,
+    shadow_bias: f32,
+    msaa_samples: vk::SampleCountFlags,
+    wireframe: bool,
+    light_gizmos: bool,
+    sun_shadow_dirty: bool,
+    sun_shadow_direction: Vector3<f32>,
+    frames_since_sun_shadow: u32,
     pub draw_debug_ui: bool,
+    /// Draws the directional light's shadow map in the bottom left corner of the screen.
+    pub draw_shadow_map_debug: bool,
     pub debug_ui_size: f32,
+    /// Skips drawing objects whose bounding sphere is outside the camera frustum, tested on the GPU.
+    /// Shadow passes always draw every object.
+    pub enable_gpu_culling: bool,
+    /// Adds a glow around bright areas. When disabled the bloom passes only clear their images.
     pub enable_bloom_pass: bool,
+    ssao_enabled: bool,
+    ssao_radius: f32,
+    ssao_bias: f32,
     pub light_texture: Option<ImageHandle>,
+    /// Colour the deferred and combine passes clear to. The deferred pass draws the [Background]
+    /// over every pixel of the scene, so this only shows where the scene isn't drawn at all, such
+    /// as outside of [Renderer::set_scene_scissor]. Use [Background::Colour] to give the scene a
+    /// flat colour behind its geometry.
     pub clear_colour: Colour,
 
     list: RenderList,
 
     shadow: VirtualRenderPassHandle,
     gbuffer: VirtualRenderPassHandle,
+    ssao: VirtualRenderPassHandle,
+    ssao_blur_horizontal: VirtualRenderPassHandle,
+    ssao_blur_vertical: VirtualRenderPassHandle,
     deferred_lighting: VirtualRenderPassHandle,
-    forward: VirtualRenderPassHandle,
-    bloom_initial: VirtualRenderPassHandle,
-    bloom_horizontal: VirtualRenderPassHandle,
-    bloom_vertical: VirtualRenderPassHandle,
+    transparent: VirtualRenderPassHandle,
+    transparent_oit: VirtualRenderPassHandle,
+    oit_composite: VirtualRenderPassHandle,
+    particles: VirtualRenderPassHandle,
+    taa_resolve: VirtualRenderPassHandle,
+    taa_copy: VirtualRenderPassHandle,
+    dof_prepare: VirtualRenderPassHandle,
+    dof_far_blur_horizontal: VirtualRenderPassHandle,
+    dof_far_blur_vertical: VirtualRenderPassHandle,
+    dof_near_blur_horizontal: VirtualRenderPassHandle,
+    dof_near_blur_vertical: VirtualRenderPassHandle,
+    dof_composite: VirtualRenderPassHandle,
+    debug_lines: VirtualRenderPassHandle,
     combine: VirtualRenderPassHandle,
+    outline_mask: VirtualRenderPassHandle,
+    outline: VirtualRenderPassHandle,
     ui: VirtualRenderPassHandle,
-    bloom_final: VirtualRenderPassHandle,
-
-    particle_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
-    particle_set: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
+    builtin_passes: Vec<VirtualRenderPassHandle>,
+    custom_passes: Vec<CustomPass>,
+    scene_scissor: Option<vk::Rect2D>,
+    custom_pass_commands: HashMap<VirtualRenderPassHandle, CustomPassCommands>,
+    capture_request: Option<String>,
+    shader_reload_result: Option<Result<()>>,
+    pending_captures: Vec<PendingCapture>,
+    /// Threads encoding finished captures to PNG.
+    capture_writers: Vec<thread::JoinHandle<()>>,
+
+    particle_buffer: Vec<BufferHandle>,
+    particle_set: Vec<vk::DescriptorSet>,
+    /// Live particles packed by [Renderer::tick_particle_systems], copied to `particle_buffer`
+    /// when rendering.
+    particle_draw_data: Vec<ParticleDrawData>,
+    particle_draw_commands: Vec<DrawCommand>,
+    gpu_particle_buffers: HashMap<ParticleSystemHandle, GpuParticleBuffers>,
 }
 
 impl Renderer {
     pub fn new(window: &Window) -> Result<Self> {
+        Self::with_settings(window, RendererSettings::default())
+    }
+
+    pub fn with_settings(window: &Window, settings: RendererSettings) -> Result<Self> {
+        Self::with_device(
+            GraphicsDevice::new(window, settings.device.clone())?,
+            settings,
+        )
+    }
+
+    /// Creates a renderer without a window, drawing into an offscreen image of the given size.
+    /// Use [Renderer::capture_frame] and [Renderer::flush_captures] to read frames back.
+    pub fn new_headless(width: u32, height: u32) -> Result<Self> {
+        Self::headless_with_settings(width, height, RendererSettings::default())
+    }
+
+    pub fn headless_with_settings(
+        width: u32,
+        height: u32,
+        settings: RendererSettings,
+    ) -> Result<Self> {
+        Self::with_device(
+            GraphicsDevice::new_headless(width, height, settings.device.clone())?,
+            settings,
+        )
+    }
+
+    fn with_device(device: GraphicsDevice, settings: RendererSettings) -> Result<Self> {
         profiling::scope!("Renderer::new");
+        ensure!(
+            settings.max_objects > 0
+                && settings.max_lights > 0
+                && settings.max_area_lights > 0
+                && settings.max_quads > 0
+                && settings.initial_material_capacity > 0,
+            "Renderer limits must be greater than zero"
+        );
+        let max_objects = settings.max_objects;
+        let max_lights = settings.max_lights;
+        let max_area_lights = settings.max_area_lights;
 
-        let device = Arc::new(GraphicsDevice::new(window)?);
-        let mut pipeline_manager = PipelineManager::new(device.clone());
+        device.set_anisotropy(settings.anisotropy)?;
+        let device = Arc::new(device);
+        let frames_in_flight = device.frames_in_flight();
+        let mut pipeline_manager =
+            PipelineManager::new(device.clone(), settings.pipeline_cache_path)?;
 
         let render_image_format = vk::Format::R8G8B8A8_SRGB;
 
         let mut descriptor_layout_cache = DescriptorLayoutCache::new(device.vk_device.clone());
         let mut descriptor_allocator = DescriptorAllocator::new(device.vk_device.clone());
-        let frame_descriptor_allocator = [
-            DescriptorAllocator::new(device.vk_device.clone()),
-            DescriptorAllocator::new(device.vk_device.clone()),
-        ];
+        let frame_descriptor_allocator = (0..frames_in_flight)
+            .map(|_| DescriptorAllocator::new(device.vk_device.clone()))
+            .collect();
         let mut pipeline_layout_cache = PipelineLayoutCache::new(device.vk_device.clone());
         let mut mesh_pool = MeshPool::new(device.clone());
 
         let mut list = RenderList::new(device.clone(), (device.size().width, device.size().height));
 
+        let sun_shadow_settings = ShadowSettings::default();
         let scene_shadow = crate::rendergraph::attachment::AttachmentInfo {
+            size: SizeClass::Custom(
+                sun_shadow_settings.resolution,
+                sun_shadow_settings.resolution,
+            ),
             format: vk::Format::D32_SFLOAT,
             ..Default::default()
         };
@@ -172,6 +421,11 @@ impl Renderer {
             format: DEFERRED_COLOR_FORMAT,
             ..Default::default()
         };
+        // Screen space motion since last frame, zero where nothing moved
+        let velocity = crate::rendergraph::attachment::AttachmentInfo {
+            format: VELOCITY_FORMAT,
+            ..Default::default()
+        };
         let depth = crate::rendergraph::attachment::AttachmentInfo {
             format: vk::Format::D32_SFLOAT,
             ..Default::default()
@@ -182,11 +436,40 @@ impl Renderer {
                 .add_color_attachment("emissive", &emissive)
                 .add_color_attachment("normal", &normal)
                 .add_color_attachment("color", &color)
+                .add_color_attachment("velocity", &velocity)
                 .set_depth_stencil_attachment("depth", &depth)
                 .set_clear_colour([0.0, 0.0, 0.0, 1.0])
                 .set_depth_stencil_clear(1.0, 0),
         );
 
+        // Cleared to white, so the lighting pass sees no occlusion while SSAO is disabled
+        let ssao_attachment = crate::rendergraph::attachment::AttachmentInfo {
+            format: SSAO_FORMAT,
+            ..Default::default()
+        };
+        let ssao = list.add_pass(
+            "ssao",
+            RenderPassLayout::default()
+                .add_texture_input("normal")
+                .add_texture_input("depth")
+                .add_color_attachment("ssao_raw", &ssao_attachment)
+                .set_clear_colour([1.0, 1.0, 1.0, 1.0]),
+        );
+        let ssao_blur_horizontal = list.add_pass(
+            "ssao_blur_horizontal_pass",
+            RenderPassLayout::default()
+                .add_texture_input("ssao_raw")
+                .add_color_attachment("ssao_blur", &ssao_attachment)
+                .set_clear_colour([1.0, 1.0, 1.0, 1.0]),
+        );
+        let ssao_blur_vertical = list.add_pass(
+            "ssao_blur_vertical_pass",
+            RenderPassLayout::default()
+                .add_texture_input("ssao_blur")
+                .add_color_attachment("ssao", &ssao_attachment)
+                .set_clear_colour([1.0, 1.0, 1.0, 1.0]),
+        );
+
         let default_attachment = crate::rendergraph::attachment::AttachmentInfo {
             format: render_image_format,
             ..Default::default()
@@ -206,61 +489,220 @@ impl Renderer {
                 .add_texture_input("normal")
                 .add_texture_input("color")
                 .add_texture_input("depth")
-                .add_texture_input("scene_shadow"),
+                .add_texture_input("scene_shadow")
+                .add_texture_input("ssao"),
         );
 
-        let forward = list.add_pass(
-            "forward",
+        // Blended geometry is lit separately, on top of the lit opaque scene
+        let transparent = list.add_pass(
+            "transparent",
             RenderPassLayout::default()
                 .add_color_attachment("forward", &default_attachment)
                 .add_color_attachment("bright", &bright)
                 .set_clear_colour([0.0, 0.0, 0.0, 1.0])
                 .set_depth_stencil_attachment("depth", &depth)
-                .set_depth_stencil_clear(1.0, 0),
+                .set_depth_stencil_clear(1.0, 0)
+                .add_texture_input("scene_shadow"),
         );
 
-        let bloom_attachment = crate::rendergraph::attachment::AttachmentInfo {
-            format: render_image_format,
+        // Both targets start at zero, see forward_oit.frag
+        let oit_accumulation = crate::rendergraph::attachment::AttachmentInfo {
+            format: OIT_ACCUMULATION_FORMAT,
             ..Default::default()
         };
+        let oit_coverage = crate::rendergraph::attachment::AttachmentInfo {
+            format: OIT_COVERAGE_FORMAT,
+            ..Default::default()
+        };
+        let transparent_oit = list.add_pass(
+            "transparent_oit",
+            RenderPassLayout::default()
+                .add_color_attachment("oit_accumulation", &oit_accumulation)
+                .add_color_attachment("oit_coverage", &oit_coverage)
+                .set_clear_colour([0.0, 0.0, 0.0, 0.0])
+                .set_depth_stencil_attachment("depth", &depth)
+                .set_depth_stencil_clear(1.0, 0)
+                .add_texture_input("scene_shadow"),
+        );
+        let oit_composite = list.add_pass(
+            "oit_composite",
+            RenderPassLayout::default()
+                .add_color_attachment("forward", &default_attachment)
+                .add_color_attachment("bright", &bright)
+                .set_clear_colour([0.0, 0.0, 0.0, 1.0])
+                .add_texture_input("oit_accumulation")
+                .add_texture_input("oit_coverage"),
+        );
 
-        let bloom_initial = list.add_pass(
-            "bloom_initial_pass",
+        let particles = list.add_pass(
+            "particles",
             RenderPassLayout::default()
-                .add_texture_input("bright")
-                .add_texture_input("bloom_vertical")
-                .add_color_attachment("bloom_horizontal", &bloom_attachment)
-                .set_clear_colour([0.0, 0.0, 0.0, 1.0]),
+                .add_color_attachment("forward", &default_attachment)
+                .add_color_attachment("bright", &bright)
+                .set_clear_colour([0.0, 0.0, 0.0, 1.0])
+                .set_depth_stencil_attachment("depth", &depth)
+                .set_depth_stencil_clear(1.0, 0),
         );
-        let bloom_vertical = list.add_pass(
-            "bloom_vertical_pass",
+
+        // Blends the jittered scene with last frame's result, reprojected with the motion vectors
+        let taa_resolve = list.add_pass(
+            "taa_resolve",
             RenderPassLayout::default()
-                .add_texture_input("bloom_horizontal")
-                .add_color_attachment("bloom_vertical", &bloom_attachment)
+                .add_texture_input("forward")
+                .add_texture_input("velocity")
+                .add_texture_input("depth")
+                .add_history_input("taa_history")
+                .add_color_attachment("taa_resolved", &default_attachment)
                 .set_clear_colour([0.0, 0.0, 0.0, 1.0]),
         );
-        let bloom_horizontal = list.add_pass(
-            "bloom_horizontal_pass",
+        // Copies the result back for the rest of the frame and keeps it for the next one
+        let taa_copy = list.add_pass(
+            "taa_copy",
             RenderPassLayout::default()
-                .add_texture_input("bloom_vertical")
-                .add_color_attachment("bloom_horizontal", &bloom_attachment)
+                .add_texture_input("taa_resolved")
+                .add_color_attachment("forward", &default_attachment)
+                .add_color_attachment("taa_history", &default_attachment)
                 .set_clear_colour([0.0, 0.0, 0.0, 1.0]),
         );
-        // TODO : This is here as otherwise assumes bloom_horizontal is run before. Find better solution.
-        let bloom_final = list.add_pass(
-            "bloom_final_pass",
+
+        // Splits the scene into premultiplied layers in front of and behind the focus distance,
+        // each blurred on its own so that sharp pixels never leak into the other layer
+        let dof_attachment = crate::rendergraph::attachment::AttachmentInfo {
+            format: render_image_format,
+            ..Default::default()
+        };
+        let dof_prepare = list.add_pass(
+            "dof_prepare",
+            RenderPassLayout::default()
+                .add_texture_input("forward")
+                .add_texture_input("depth")
+                .add_color_attachment("dof_far", &dof_attachment)
+                .add_color_attachment("dof_near", &dof_attachment)
+                .set_clear_colour([0.0, 0.0, 0.0, 0.0]),
+        );
+        let dof_far_blur_horizontal = list.add_pass(
+            "dof_far_blur_horizontal",
+            RenderPassLayout::default()
+                .add_texture_input("dof_far")
+                .add_color_attachment("dof_far_blur", &dof_attachment)
+                .set_clear_colour([0.0, 0.0, 0.0, 0.0]),
+        );
+        let dof_far_blur_vertical = list.add_pass(
+            "dof_far_blur_vertical",
+            RenderPassLayout::default()
+                .add_texture_input("dof_far_blur")
+                .add_color_attachment("dof_far_blurred", &dof_attachment)
+                .set_clear_colour([0.0, 0.0, 0.0, 0.0]),
+        );
+        let dof_near_blur_horizontal = list.add_pass(
+            "dof_near_blur_horizontal",
+            RenderPassLayout::default()
+                .add_texture_input("dof_near")
+                .add_color_attachment("dof_near_blur", &dof_attachment)
+                .set_clear_colour([0.0, 0.0, 0.0, 0.0]),
+        );
+        let dof_near_blur_vertical = list.add_pass(
+            "dof_near_blur_vertical",
+            RenderPassLayout::default()
+                .add_texture_input("dof_near_blur")
+                .add_color_attachment("dof_near_blurred", &dof_attachment)
+                .set_clear_colour([0.0, 0.0, 0.0, 0.0]),
+        );
+        // Blends the blurred layers over the sharp scene
+        let dof_composite = list.add_pass(
+            "dof_composite",
             RenderPassLayout::default()
-                .add_texture_input("bloom_horizontal")
-                .add_color_attachment("bloom_vertical", &bloom_attachment)
+                .add_texture_input("depth")
+                .add_texture_input("dof_far_blurred")
+                .add_texture_input("dof_near_blurred")
+                .add_color_attachment("forward", &default_attachment)
                 .set_clear_colour([0.0, 0.0, 0.0, 1.0]),
         );
 
+        // Debug lines are drawn into the scene, so they are depth tested with the same orientation
+        let debug_lines = list.add_pass(
+            "debug_lines",
+            RenderPassLayout::default()
+                .add_color_attachment("forward", &default_attachment)
+                .set_depth_stencil_attachment("depth", &depth)
+                .set_clear_colour([0.0, 0.0, 0.0, 1.0])
+                .set_depth_stencil_clear(1.0, 0),
+        );
+
+        let bloom_attachment = crate::rendergraph::attachment::AttachmentInfo {
+            format: render_image_format,
+            ..Default::default()
+        };
+
+        // Every level of the chain is declared, but only the passes of the levels in use are run
+        let bloom_downsample_passes: Vec<VirtualRenderPassHandle> = (0..MAX_BLOOM_MIPS)
+            .map(|level| {
+                let source = if level == 0 {
+                    "bright".to_string()
+                } else {
+                    bloom_mip_name(level - 1)
+                };
+                let attachment = crate::rendergraph::attachment::AttachmentInfo {
+                    size: SizeClass::SwapchainDivided(2 << level),
+                    ..bloom_attachment.clone()
+                };
+                list.add_pass(
+                    &format!("bloom_downsample_{}", level),
+                    RenderPassLayout::default()
+                        .add_texture_input(&source)
+                        .add_color_attachment(&bloom_mip_name(level), &attachment)
+                        .set_clear_colour([0.0, 0.0, 0.0, 1.0]),
+                )
+            })
+            .collect();
+        // The smallest level in use is upsampled straight from its mip, the others from the
+        // upsampled level below them
+        let bloom_upsample_passes: Vec<VirtualRenderPassHandle> = (0..MAX_BLOOM_MIPS - 1)
+            .map(|level| {
+                let attachment = crate::rendergraph::attachment::AttachmentInfo {
+                    size: SizeClass::SwapchainDivided(2 << level),
+                    ..bloom_attachment.clone()
+                };
+                let mut layout = RenderPassLayout::default()
+                    .add_texture_input(&bloom_mip_name(level))
+                    .add_texture_input(&bloom_mip_name(level + 1));
+                if level + 1 < MAX_BLOOM_MIPS - 1 {
+                    layout = layout.add_texture_input(&bloom_upsampled_name(level + 1));
+                }
+                list.add_pass(
+                    &format!("bloom_upsample_{}", level),
+                    layout
+                        .add_color_attachment(&bloom_upsampled_name(level), &attachment)
+                        .set_clear_colour([0.0, 0.0, 0.0, 1.0]),
+                )
+            })
+            .collect();
+
         let combine = list.add_pass(
             "combine",
             RenderPassLayout::default()
                 .add_color_attachment("output", &default_attachment)
                 .add_texture_input("forward")
-                .add_texture_input("bloom_vertical")
+                .add_texture_input(&bloom_upsampled_name(0))
+                .set_clear_colour([0.0, 0.0, 0.0, 1.0]),
+        );
+
+        // Selected models are drawn into a mask, then outlined on the final image under the UI
+        let outline_mask_attachment = crate::rendergraph::attachment::AttachmentInfo {
+            format: OUTLINE_MASK_FORMAT,
+            ..Default::default()
+        };
+        let outline_mask = list.add_pass(
+            "outline_mask",
+            RenderPassLayout::default()
+                .add_color_attachment("outline_mask", &outline_mask_attachment)
+                .set_clear_colour([0.0, 0.0, 0.0, 0.0]),
+        );
+        let outline = list.add_pass(
+            "outline",
+            RenderPassLayout::default()
+                .add_texture_input("outline_mask")
+                .add_color_attachment("output", &default_attachment)
                 .set_clear_colour([0.0, 0.0, 0.0, 1.0]),
         );
 
@@ -274,20 +716,43 @@ impl Renderer {
         );
 
         list.set_backbuffer("output");
-        list.set_pass_order(&[
+        let mut builtin_passes = vec![
             shadow,
             gbuffer,
+            ssao,
+            ssao_blur_horizontal,
+            ssao_blur_vertical,
             deferred_lighting,
-            forward,
-            bloom_initial,
-            bloom_vertical,
-            bloom_horizontal,
-            bloom_final,
-            combine,
-            ui,
-        ]);
+            transparent,
+            transparent_oit,
+            oit_composite,
+            particles,
+            taa_resolve,
+            taa_copy,
+            dof_prepare,
+            dof_far_blur_horizontal,
+            dof_far_blur_vertical,
+            dof_near_blur_horizontal,
+            dof_near_blur_vertical,
+            dof_composite,
+            debug_lines,
+        ];
+        builtin_passes.extend(bloom_downsample_passes.iter().copied());
+        builtin_passes.extend(bloom_upsample_passes.iter().rev().copied());
+        builtin_passes.extend([combine, outline_mask, outline, ui]);
+        let unused_bloom_passes = unused_bloom_passes(
+            &bloom_downsample_passes,
+            &bloom_upsample_passes,
+            BloomParams::default().mip_count,
+        );
+        let order: Vec<VirtualRenderPassHandle> = builtin_passes
+            .iter()
+            .copied()
+            .filter(|pass| !unused_bloom_passes.contains(pass))
+            .collect();
+        list.set_pass_order(&order);
 
-        list.bake();
+        list.bake()?;
 
         let swapchain_image_format = vk::Format::B8G8R8A8_SRGB;
         let depth_image_format = vk::Format::D32_SFLOAT;
@@ -302,48 +767,94 @@ impl Renderer {
                 .build()
                 .unwrap();
 
-            let (bloom_pso, bloom_pso_layout) = {
-                let pso_layout = pipeline_layout_cache.create_pipeline_layout(
-                    &[bloom_set_layout],
-                    &[*vk::PushConstantRange::builder()
-                        .size(size_of::<i32>() as u32)
-                        .stage_flags(vk::ShaderStageFlags::FRAGMENT)],
-                )?;
-
-                let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
-                    .depth_test_enable(false)
-                    .depth_write_enable(false)
-                    .depth_compare_op(vk::CompareOp::ALWAYS)
-                    .depth_bounds_test_enable(false)
-                    .stencil_test_enable(false)
-                    .min_depth_bounds(0.0f32)
-                    .max_depth_bounds(1.0f32);
+            let bloom_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[bloom_set_layout],
+                &[*vk::PushConstantRange::builder()
+                    .size(size_of::<BlurPushConstants>() as u32)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)],
+            )?;
 
-                let pso_build_info = PipelineCreateInfo {
-                    pipeline_layout: pso_layout,
-                    vertex_shader: "assets/shaders/quad.vert".to_string(),
-                    fragment_shader: "assets/shaders/blur.frag".to_string(),
-                    vertex_input_state: Vertex::get_empty_vertex_input_desc(),
-                    color_attachment_formats: vec![PipelineColorAttachment {
-                        format: render_image_format,
-                        blend: false,
-                        ..Default::default()
-                    }],
-                    depth_attachment_format: None,
-                    depth_stencil_state: *depth_stencil_state,
-                    cull_mode: vk::CullModeFlags::NONE,
-                };
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::ALWAYS)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
 
-                let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
-                (pso, pso_layout)
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: bloom_pso_layout,
+                vertex_shader: "assets/shaders/quad.vert".to_string(),
+                fragment_shader: "assets/shaders/blur.frag".to_string(),
+                vertex_input_state: Vertex::get_empty_vertex_input_desc(),
+                color_attachment_formats: vec![PipelineColorAttachment {
+                    format: render_image_format,
+                    blend: false,
+                    ..Default::default()
+                }],
+                depth_attachment_format: None,
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             };
+            let bloom_pso = pipeline_manager.create_pipeline(&pso_build_info)?;
+
+            let downsample_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[bloom_set_layout],
+                &[*vk::PushConstantRange::builder()
+                    .size(size_of::<BloomPushConstants>() as u32)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)],
+            )?;
+            let downsample_pso = pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                pipeline_layout: downsample_pso_layout,
+                fragment_shader: "assets/shaders/bloom_downsample.frag".to_string(),
+                ..pso_build_info.clone()
+            })?;
+
+            let upsample_set_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                .bind_image(
+                    0,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .bind_image(
+                    1,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .build()?;
+            let upsample_pso_layout =
+                pipeline_layout_cache.create_pipeline_layout(&[upsample_set_layout], &[])?;
+            let upsample_pso = pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                pipeline_layout: upsample_pso_layout,
+                fragment_shader: "assets/shaders/bloom_upsample.frag".to_string(),
+                ..pso_build_info
+            })?;
 
             BloomPass {
                 bloom_pso,
                 bloom_pso_layout,
+                downsample_pso,
+                downsample_pso_layout,
+                upsample_pso,
+                upsample_pso_layout,
+                downsample_passes: bloom_downsample_passes,
+                upsample_passes: bloom_upsample_passes,
             }
         };
 
+        let auto_exposure = AutoExposure::new(
+            &device,
+            &mut pipeline_manager,
+            &mut pipeline_layout_cache,
+            &mut descriptor_layout_cache,
+            &mut descriptor_allocator,
+        )?;
+
         let combine_set_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
             .bind_image(
                 0,
@@ -358,7 +869,63 @@ impl Renderer {
             .build()
             .unwrap();
 
-        let (combine_pso, combine_pso_layout) = {
+        let (combine_pso, combine_pso_layout, fxaa_pso, fxaa_pso_layout) = {
+            let set_layouts = [combine_set_layout, auto_exposure.exposure_set_layout()];
+            let pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &set_layouts,
+                &[*vk::PushConstantRange::builder()
+                    .size(size_of::<CombinePushConstants>() as u32)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)],
+            )?;
+
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::ALWAYS)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
+
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                vertex_shader: "assets/shaders/quad.vert".to_string(),
+                fragment_shader: "assets/shaders/combine.frag".to_string(),
+                vertex_input_state: Vertex::get_empty_vertex_input_desc(),
+                color_attachment_formats: vec![PipelineColorAttachment {
+                    format: swapchain_image_format,
+                    blend: false,
+                    ..Default::default()
+                }],
+                depth_attachment_format: None,
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            };
+
+            let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
+
+            // Variant that also anti-aliases the combined image
+            let fxaa_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &set_layouts,
+                &[*vk::PushConstantRange::builder()
+                    .size(size_of::<FxaaPushConstants>() as u32)
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)],
+            )?;
+            let fxaa_pso = pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                pipeline_layout: fxaa_pso_layout,
+                fragment_shader: "assets/shaders/combine_fxaa.frag".to_string(),
+                ..pso_build_info
+            })?;
+
+            (pso, pso_layout, fxaa_pso, fxaa_pso_layout)
+        };
+
+        // Reads the accumulation and coverage targets, so has the same layout as the combine pass
+        let (oit_composite_pso, oit_composite_pso_layout) = {
             let pso_layout =
                 pipeline_layout_cache.create_pipeline_layout(&[combine_set_layout], &[])?;
 
@@ -371,10 +938,63 @@ impl Renderer {
                 .min_depth_bounds(0.0f32)
                 .max_depth_bounds(1.0f32);
 
+            let blended_attachment = PipelineColorAttachment {
+                format: render_image_format,
+                blend: true,
+                src_blend_factor_color: vk::BlendFactor::SRC_ALPHA,
+                dst_blend_factor_color: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                src_blend_factor_alpha: vk::BlendFactor::ONE,
+                dst_blend_factor_alpha: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                ..Default::default()
+            };
             let pso_build_info = PipelineCreateInfo {
                 pipeline_layout: pso_layout,
                 vertex_shader: "assets/shaders/quad.vert".to_string(),
-                fragment_shader: "assets/shaders/combine.frag".to_string(),
+                fragment_shader: "assets/shaders/oit_composite.frag".to_string(),
+                vertex_input_state: Vertex::get_empty_vertex_input_desc(),
+                color_attachment_formats: vec![blended_attachment.clone(), blended_attachment],
+                depth_attachment_format: None,
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            };
+
+            (
+                pipeline_manager.create_pipeline(&pso_build_info)?,
+                pso_layout,
+            )
+        };
+
+        let (shadow_debug_pso, shadow_debug_pso_layout) = {
+            let shadow_debug_set_layout =
+                DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                    .bind_image(
+                        0,
+                        vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        vk::ShaderStageFlags::FRAGMENT,
+                    )
+                    .build()
+                    .unwrap();
+
+            let pso_layout =
+                pipeline_layout_cache.create_pipeline_layout(&[shadow_debug_set_layout], &[])?;
+
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::ALWAYS)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
+
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                vertex_shader: "assets/shaders/quad.vert".to_string(),
+                fragment_shader: "assets/shaders/debug_shadow.frag".to_string(),
                 vertex_input_state: Vertex::get_empty_vertex_input_desc(),
                 color_attachment_formats: vec![PipelineColorAttachment {
                     format: swapchain_image_format,
@@ -384,6 +1004,10 @@ impl Renderer {
                 depth_attachment_format: None,
                 depth_stencil_state: *depth_stencil_state,
                 cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             };
 
             let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
@@ -416,112 +1040,153 @@ impl Renderer {
                 storage_type: BufferStorageType::HostLocal,
             };
 
-            [
-                device.resource_manager.create_buffer(&buffer_create_info),
-                device.resource_manager.create_buffer(&buffer_create_info),
-            ]
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
         };
 
         let transform_buffer = {
             let buffer_create_info = BufferCreateInfo {
-                size: size_of::<TransformSSBO>() * MAX_OBJECTS as usize,
+                size: size_of::<TransformSSBO>() * max_objects,
                 usage: vk::BufferUsageFlags::STORAGE_BUFFER,
                 storage_type: BufferStorageType::HostLocal,
             };
 
-            [
-                device.resource_manager.create_buffer(&buffer_create_info),
-                device.resource_manager.create_buffer(&buffer_create_info),
-            ]
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
         };
 
+        let max_material_buffer_capacity = {
+            let max_range = unsafe {
+                device
+                    .raw_instance()
+                    .get_physical_device_properties(device.raw_physical_device())
+                    .limits
+                    .max_storage_buffer_range
+            };
+            max_range as usize / size_of::<MaterialParamSSBO>()
+        };
         let material_buffer = {
             let buffer_create_info = BufferCreateInfo {
-                size: size_of::<MaterialParamSSBO>() * MAX_MATERIAL_INSTANCES as usize,
+                size: size_of::<MaterialParamSSBO>() * settings.initial_material_capacity,
                 usage: vk::BufferUsageFlags::STORAGE_BUFFER,
                 storage_type: BufferStorageType::HostLocal,
             };
 
-            [
-                device.resource_manager.create_buffer(&buffer_create_info),
-                device.resource_manager.create_buffer(&buffer_create_info),
-            ]
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
         };
 
         let light_buffer = {
             let buffer_create_info = BufferCreateInfo {
-                size: size_of::<LightUniform>() * MAX_LIGHTS,
-                usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
+                size: size_of::<LightUniform>() * max_lights,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER,
                 storage_type: BufferStorageType::HostLocal,
             };
 
-            [
-                device.resource_manager.create_buffer(&buffer_create_info),
-                device.resource_manager.create_buffer(&buffer_create_info),
-            ]
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
         };
 
-        let instance_buffer = {
+        let area_light_buffer = {
             let buffer_create_info = BufferCreateInfo {
-                size: size_of::<InstanceSSBO>() * MAX_OBJECTS as usize,
+                size: size_of::<AreaLightUniform>() * max_area_lights,
                 usage: vk::BufferUsageFlags::STORAGE_BUFFER,
                 storage_type: BufferStorageType::HostLocal,
             };
 
-            [
-                device.resource_manager.create_buffer(&buffer_create_info),
-                device.resource_manager.create_buffer(&buffer_create_info),
-            ]
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
         };
 
-        let (descriptor_set, descriptor_set_layout) = {
-            let mut sets = [vk::DescriptorSet::null(); FRAMES_IN_FLIGHT];
-            let mut layout = None;
-            for i in 0..FRAMES_IN_FLIGHT {
-                let (set, set_layout) = JBDescriptorBuilder::new(
+        let instance_buffer = {
+            let buffer_create_info = BufferCreateInfo {
+                size: size_of::<InstanceSSBO>() * max_objects,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+                storage_type: BufferStorageType::HostLocal,
+            };
+
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
+        };
+
+        let cull_object_buffer = {
+            let buffer_create_info = BufferCreateInfo {
+                size: size_of::<CullObjectSSBO>() * max_objects,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+                storage_type: BufferStorageType::HostLocal,
+            };
+
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
+        };
+
+        // Instance counts are reset from the CPU each frame, then filled in by the culling pass
+        let draw_indirect_buffer = {
+            let buffer_create_info = BufferCreateInfo {
+                size: size_of::<vk::DrawIndexedIndirectCommand>() * max_objects,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+                storage_type: BufferStorageType::HostLocal,
+            };
+
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
+        };
+
+        let visible_instance_buffer = {
+            let buffer_create_info = BufferCreateInfo {
+                size: size_of::<u32>() * max_objects,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+                storage_type: BufferStorageType::Device,
+            };
+
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
+        };
+
+        let light_cluster_buffer = {
+            let buffer_create_info = BufferCreateInfo {
+                size: LIGHT_CLUSTER_BUFFER_SIZE,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+                storage_type: BufferStorageType::Device,
+            };
+
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
+        };
+
+        let (descriptor_set, descriptor_set_layout) = {
+            let mut sets = vec![vk::DescriptorSet::null(); frames_in_flight];
+            let mut layout = None;
+            for i in 0..frames_in_flight {
+                let (set, set_layout) = build_global_descriptor_set(
                     &device.resource_manager,
                     &mut descriptor_layout_cache,
                     &mut descriptor_allocator,
-                )
-                .bind_buffer(BufferDescriptorInfo {
-                    binding: 0,
-                    buffer: camera_buffer[i],
-                    desc_type: vk::DescriptorType::UNIFORM_BUFFER,
-                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                })
-                .bind_buffer(BufferDescriptorInfo {
-                    binding: 1,
-                    buffer: light_buffer[i],
-                    desc_type: vk::DescriptorType::UNIFORM_BUFFER,
-                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                })
-                .bind_buffer(BufferDescriptorInfo {
-                    binding: 2,
-                    buffer: transform_buffer[i],
-                    desc_type: vk::DescriptorType::STORAGE_BUFFER,
-                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                })
-                .bind_buffer(BufferDescriptorInfo {
-                    binding: 3,
-                    buffer: material_buffer[i],
-                    desc_type: vk::DescriptorType::STORAGE_BUFFER,
-                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                })
-                .bind_image(ImageDescriptorInfo {
-                    binding: 4,
-                    image: list.get_physical_resource("scene_shadow"), // TODO : Put this in own descriptor set and make every frame
-                    sampler: device.shadow_sampler(),
-                    desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                })
-                .bind_buffer(BufferDescriptorInfo {
-                    binding: 5,
-                    buffer: instance_buffer[i],
-                    desc_type: vk::DescriptorType::STORAGE_BUFFER,
-                    stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                })
-                .build()
-                .unwrap();
+                    &GlobalSetBuffers {
+                        camera: camera_buffer[i],
+                        light: light_buffer[i],
+                        transform: transform_buffer[i],
+                        material: material_buffer[i],
+                        instance: instance_buffer[i],
+                        cull_object: cull_object_buffer[i],
+                        draw_indirect: draw_indirect_buffer[i],
+                        visible_instance: visible_instance_buffer[i],
+                        light_clusters: light_cluster_buffer[i],
+                        area_light: area_light_buffer[i],
+                    },
+                    list.get_physical_resource("scene_shadow"), // TODO : Put this in own descriptor set and make every frame
+                    device.shadow_sampler(),
+                )?;
 
                 sets[i] = set;
                 layout = Some(set_layout);
@@ -547,7 +1212,7 @@ impl Renderer {
                 .copy_from_slice(&[camera_uniform]);
         }
 
-        let (forward_pass, shadow_pso) = {
+        let (forward_pass, (shadow_pso, double_sided_shadow_pso)) = {
             let pso_layout = pipeline_layout_cache.create_pipeline_layout(
                 &[
                     device.bindless_descriptor_set_layout(),
@@ -556,39 +1221,76 @@ impl Renderer {
                 &[],
             )?;
 
-            let pso = {
+            let (pso, double_sided_pso, oit_pso, double_sided_oit_pso) = {
+                // Tested against the opaque scene, but doesn't write depth so transparent
+                // surfaces behind each other all blend
                 let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
                     .depth_test_enable(true)
-                    .depth_write_enable(true)
+                    .depth_write_enable(false)
                     .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
                     .depth_bounds_test_enable(false)
                     .stencil_test_enable(false)
                     .min_depth_bounds(0.0f32)
                     .max_depth_bounds(1.0f32);
 
+                let blended_attachment = PipelineColorAttachment {
+                    format: render_image_format,
+                    blend: true,
+                    src_blend_factor_color: vk::BlendFactor::SRC_ALPHA,
+                    dst_blend_factor_color: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    src_blend_factor_alpha: vk::BlendFactor::ONE,
+                    dst_blend_factor_alpha: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    ..Default::default()
+                };
                 let pso_build_info = PipelineCreateInfo {
                     pipeline_layout: pso_layout,
                     vertex_shader: "assets/shaders/forward.vert".to_string(),
                     fragment_shader: "assets/shaders/forward.frag".to_string(),
                     vertex_input_state: Vertex::get_vertex_input_desc(),
+                    color_attachment_formats: vec![blended_attachment.clone(), blended_attachment],
+                    depth_attachment_format: Some(depth_image_format),
+                    depth_stencil_state: *depth_stencil_state,
+                    cull_mode: vk::CullModeFlags::BACK,
+                    sample_count: vk::SampleCountFlags::TYPE_1,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    line_width: 1.0f32,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                };
+
+                // Accumulation is summed, coverage blends like alpha
+                let oit_build_info = PipelineCreateInfo {
+                    fragment_shader: "assets/shaders/forward_oit.frag".to_string(),
                     color_attachment_formats: vec![
                         PipelineColorAttachment {
-                            format: render_image_format,
-                            blend: false,
+                            format: OIT_ACCUMULATION_FORMAT,
+                            blend: true,
                             ..Default::default()
                         },
                         PipelineColorAttachment {
-                            format: render_image_format,
-                            blend: false,
+                            format: OIT_COVERAGE_FORMAT,
+                            blend: true,
+                            src_blend_factor_color: vk::BlendFactor::ONE,
+                            dst_blend_factor_color: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                            src_blend_factor_alpha: vk::BlendFactor::ONE,
+                            dst_blend_factor_alpha: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
                             ..Default::default()
                         },
                     ],
-                    depth_attachment_format: Some(depth_image_format),
-                    depth_stencil_state: *depth_stencil_state,
-                    cull_mode: vk::CullModeFlags::FRONT,
+                    ..pso_build_info.clone()
                 };
 
-                pipeline_manager.create_pipeline(&pso_build_info)?
+                (
+                    pipeline_manager.create_pipeline(&pso_build_info)?,
+                    pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                        cull_mode: vk::CullModeFlags::NONE,
+                        ..pso_build_info
+                    })?,
+                    pipeline_manager.create_pipeline(&oit_build_info)?,
+                    pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                        cull_mode: vk::CullModeFlags::NONE,
+                        ..oit_build_info
+                    })?,
+                )
             };
 
             let shadow_pso = {
@@ -609,40 +1311,119 @@ impl Renderer {
                     color_attachment_formats: vec![],
                     depth_attachment_format: Some(depth_image_format),
                     depth_stencil_state: *depth_stencil_state,
-                    cull_mode: vk::CullModeFlags::FRONT,
+                    cull_mode: vk::CullModeFlags::BACK,
+                    sample_count: vk::SampleCountFlags::TYPE_1,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    line_width: 1.0f32,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
                 };
 
-                pipeline_manager.create_pipeline(&pso_build_info)?
+                (
+                    pipeline_manager.create_pipeline(&pso_build_info)?,
+                    pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                        cull_mode: vk::CullModeFlags::NONE,
+                        ..pso_build_info
+                    })?,
+                )
+            };
+
+            (
+                ForwardPass {
+                    pso_layout,
+                    pso,
+                    double_sided_pso,
+                    oit_pso,
+                    double_sided_oit_pso,
+                },
+                shadow_pso,
+            )
+        };
+
+        let (point_shadow_pso, point_shadow_pso_layout) = {
+            let push_constant_range = *vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+                .size(size_of::<PointShadowPushConstants>() as u32)
+                .offset(0u32);
+
+            let pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[
+                    device.bindless_descriptor_set_layout(),
+                    descriptor_set_layout,
+                ],
+                &[push_constant_range],
+            )?;
+
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(true)
+                .depth_write_enable(true)
+                .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
+
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                vertex_shader: "assets/shaders/shadow_point.vert".to_string(),
+                fragment_shader: "assets/shaders/shadow_point.frag".to_string(),
+                vertex_input_state: Vertex::get_vertex_input_desc(),
+                color_attachment_formats: vec![],
+                depth_attachment_format: Some(depth_image_format),
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             };
 
-            (ForwardPass { pso_layout, pso }, shadow_pso)
+            (
+                pipeline_manager.create_pipeline(&pso_build_info)?,
+                pso_layout,
+            )
         };
 
+        let mut render_targets = RenderTargets::new(device.clone());
+        let mut point_shadow_maps = Vec::with_capacity(MAX_SHADOWED_POINT_LIGHTS);
+        for _ in 0..MAX_SHADOWED_POINT_LIGHTS {
+            let target = render_targets.create_render_target(
+                depth_image_format,
+                RenderTargetSize::StaticCube(POINT_SHADOW_SIZE),
+                RenderImageType::Depth,
+            )?;
+            device.add_image_to_bindless(&render_targets.get(target).unwrap())?;
+            point_shadow_maps.push(PointShadowMap {
+                target,
+                resolution: POINT_SHADOW_SIZE,
+                light: None,
+                light_position: Point3::origin(),
+                frames_since_update: 0,
+            });
+        }
+
         let ui_pass = {
             let vertex_data_buffer = {
                 let buffer_create_info = BufferCreateInfo {
-                    size: size_of::<UIVertexData>() * MAX_QUADS as usize,
+                    size: size_of::<UIVertexData>() * settings.max_quads,
                     usage: vk::BufferUsageFlags::STORAGE_BUFFER,
                     storage_type: BufferStorageType::HostLocal,
                 };
 
-                [
-                    device.resource_manager.create_buffer(&buffer_create_info),
-                    device.resource_manager.create_buffer(&buffer_create_info),
-                ]
+                (0..frames_in_flight)
+                    .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                    .collect::<Vec<_>>()
             };
 
             let index_buffer = {
                 let buffer_create_info = BufferCreateInfo {
-                    size: size_of::<Index>() * MAX_QUADS as usize * 3,
+                    size: size_of::<Index>() * settings.max_quads * 3,
                     usage: vk::BufferUsageFlags::INDEX_BUFFER,
                     storage_type: BufferStorageType::HostLocal,
                 };
 
-                [
-                    device.resource_manager.create_buffer(&buffer_create_info),
-                    device.resource_manager.create_buffer(&buffer_create_info),
-                ]
+                (0..frames_in_flight)
+                    .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                    .collect::<Vec<_>>()
             };
 
             let uniform_buffer = {
@@ -652,16 +1433,15 @@ impl Renderer {
                     storage_type: BufferStorageType::HostLocal,
                 };
 
-                [
-                    device.resource_manager.create_buffer(&buffer_create_info),
-                    device.resource_manager.create_buffer(&buffer_create_info),
-                ]
+                (0..frames_in_flight)
+                    .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                    .collect::<Vec<_>>()
             };
 
             let (desc_set, ui_descriptor_set_layout) = {
-                let mut sets = [vk::DescriptorSet::null(); FRAMES_IN_FLIGHT];
+                let mut sets = vec![vk::DescriptorSet::null(); frames_in_flight];
                 let mut layout = None;
-                for i in 0..FRAMES_IN_FLIGHT {
+                for i in 0..frames_in_flight {
                     let (set, set_layout) = JBDescriptorBuilder::new(
                         &device.resource_manager,
                         &mut descriptor_layout_cache,
@@ -721,6 +1501,10 @@ impl Renderer {
                     depth_attachment_format: Some(depth_image_format),
                     depth_stencil_state: *depth_stencil_state,
                     cull_mode: vk::CullModeFlags::NONE,
+                    sample_count: vk::SampleCountFlags::TYPE_1,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    line_width: 1.0f32,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
                 };
 
                 let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
@@ -744,16 +1528,15 @@ impl Renderer {
                 storage_type: BufferStorageType::HostLocal,
             };
 
-            [
-                device.resource_manager.create_buffer(&buffer_create_info),
-                device.resource_manager.create_buffer(&buffer_create_info),
-            ]
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
         };
 
         let (world_debug_desc_set, world_debug_desc_layout) = {
-            let mut sets = [vk::DescriptorSet::null(); FRAMES_IN_FLIGHT];
+            let mut sets = vec![vk::DescriptorSet::null(); frames_in_flight];
             let mut layout = None;
-            for i in 0..FRAMES_IN_FLIGHT {
+            for i in 0..frames_in_flight {
                 let (set, set_layout) = JBDescriptorBuilder::new(
                     &device.resource_manager,
                     &mut descriptor_layout_cache,
@@ -813,12 +1596,136 @@ impl Renderer {
                 depth_attachment_format: Some(depth_image_format),
                 depth_stencil_state: *depth_stencil_state,
                 cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            };
+
+            let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
+            (pso, pso_layout)
+        };
+
+        let debug_line_desc_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+            .bind_buffer(
+                0,
+                vk::DescriptorType::STORAGE_BUFFER,
+                vk::ShaderStageFlags::VERTEX,
+            )
+            .build()?;
+
+        let (debug_line_pso, debug_line_pso_layout) = {
+            let pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[
+                    device.bindless_descriptor_set_layout(),
+                    descriptor_set_layout,
+                    debug_line_desc_layout,
+                ],
+                &[],
+            )?;
+
+            // Tested against the scene so lines behind geometry are hidden, but never written
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(true)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
+
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                vertex_shader: "assets/shaders/debug_line.vert".to_string(),
+                fragment_shader: "assets/shaders/debug_line.frag".to_string(),
+                vertex_input_state: Vertex::get_empty_vertex_input_desc(),
+                color_attachment_formats: vec![PipelineColorAttachment {
+                    format: render_image_format,
+                    ..Default::default()
+                }],
+                depth_attachment_format: Some(depth_image_format),
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::LINE_LIST,
             };
 
             let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
             (pso, pso_layout)
         };
 
+        let debug_font = {
+            let (texels, width, height) = build_font_atlas();
+            device.load_image(
+                &texels,
+                width,
+                height,
+                &ImageFormatType::Default,
+                1,
+                1,
+                MipFilter::Blit,
+            )?
+        };
+
+        let (cull_pso, cull_pso_layout) = {
+            let push_constant_range = *vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .size(size_of::<CullPushConstants>() as u32);
+            let pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[
+                    device.bindless_descriptor_set_layout(),
+                    descriptor_set_layout,
+                ],
+                &[push_constant_range],
+            )?;
+
+            let pso = pipeline_manager.create_compute_pipeline(&ComputePipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                compute_shader: "assets/shaders/cull.comp".to_string(),
+            })?;
+
+            (pso, pso_layout)
+        };
+
+        let (light_cull_pso, light_cull_pso_layout) = {
+            let pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[
+                    device.bindless_descriptor_set_layout(),
+                    descriptor_set_layout,
+                ],
+                &[],
+            )?;
+
+            let pso = pipeline_manager.create_compute_pipeline(&ComputePipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                compute_shader: "assets/shaders/light_cull.comp".to_string(),
+            })?;
+
+            (pso, pso_layout)
+        };
+
+        let ibl_generator = IblGenerator::new(
+            &device,
+            &mut pipeline_manager,
+            &mut pipeline_layout_cache,
+            &mut descriptor_layout_cache,
+        )?;
+        let mip_generator = MipGenerator::new(
+            &device,
+            &mut pipeline_manager,
+            &mut pipeline_layout_cache,
+            &mut descriptor_layout_cache,
+        )?;
+        let brdf_lut = ibl_generator.generate_brdf_lut(
+            &device,
+            &pipeline_manager,
+            &mut descriptor_layout_cache,
+            &mut descriptor_allocator,
+        )?;
+        let ltc_tables = LtcTables::load(&device)?;
+
         let deferred_fill = {
             let pso_layout = pipeline_layout_cache.create_pipeline_layout(
                 &[
@@ -828,7 +1735,7 @@ impl Renderer {
                 &[],
             )?;
 
-            let pso = {
+            let (pso, double_sided_pso, wireframe_psos) = {
                 let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
                     .depth_test_enable(true)
                     .depth_write_enable(true)
@@ -859,16 +1766,53 @@ impl Renderer {
                             blend: false,
                             ..Default::default()
                         },
+                        PipelineColorAttachment {
+                            format: VELOCITY_FORMAT,
+                            blend: false,
+                            ..Default::default()
+                        },
                     ],
                     depth_attachment_format: Some(depth_image_format),
                     depth_stencil_state: *depth_stencil_state,
-                    cull_mode: vk::CullModeFlags::FRONT,
+                    cull_mode: vk::CullModeFlags::BACK,
+                    sample_count: vk::SampleCountFlags::TYPE_1,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    line_width: 1.0f32,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+                };
+
+                let double_sided_build_info = PipelineCreateInfo {
+                    cull_mode: vk::CullModeFlags::NONE,
+                    ..pso_build_info.clone()
+                };
+                let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
+                let double_sided_pso =
+                    pipeline_manager.create_pipeline(&double_sided_build_info)?;
+                let wireframe_psos = if device.enabled_features().fill_mode_non_solid == vk::TRUE {
+                    Some((
+                        pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                            polygon_mode: vk::PolygonMode::LINE,
+                            ..pso_build_info
+                        })?,
+                        pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                            polygon_mode: vk::PolygonMode::LINE,
+                            ..double_sided_build_info
+                        })?,
+                    ))
+                } else {
+                    None
                 };
 
-                pipeline_manager.create_pipeline(&pso_build_info)?
+                (pso, double_sided_pso, wireframe_psos)
             };
 
-            DeferredPass { pso, pso_layout }
+            DeferredPass {
+                pso,
+                double_sided_pso,
+                wireframe_pso: wireframe_psos.map(|(pso, _)| pso),
+                double_sided_wireframe_pso: wireframe_psos.map(|(_, pso)| pso),
+                pso_layout,
+            }
         };
 
         let deferred_lighting_combine = {
@@ -894,16 +1838,26 @@ impl Renderer {
                         vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
                         vk::ShaderStageFlags::FRAGMENT,
                     )
+                    .bind_image(
+                        4,
+                        vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        vk::ShaderStageFlags::FRAGMENT,
+                    )
                     .build()
                     .unwrap();
 
+            let push_constant_range = *vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .size(size_of::<BackgroundPushConstants>() as u32)
+                .offset(0u32);
+
             let pso_layout = pipeline_layout_cache.create_pipeline_layout(
                 &[
                     device.bindless_descriptor_set_layout(),
                     descriptor_set_layout,
                     deferred_lighting_desc_layout,
                 ],
-                &[],
+                &[push_constant_range],
             )?;
 
             let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
@@ -935,6 +1889,10 @@ impl Renderer {
                 depth_attachment_format: None,
                 depth_stencil_state: *depth_stencil_state,
                 cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             };
 
             let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
@@ -942,63 +1900,356 @@ impl Renderer {
             DeferredLightingCombinePass { pso, pso_layout }
         };
 
-        let cube_mesh = mesh_pool.add_mesh(&MeshData::cube()).unwrap();
+        let ssao_pass = {
+            let ssao_desc_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                .bind_image(
+                    0,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .bind_image(
+                    1,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .build()
+                .unwrap();
 
-        let (skybox_pso, skybox_pso_layout) = {
             let push_constant_range = *vk::PushConstantRange::builder()
-                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
-                .size(size_of::<i32>() as u32)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .size(size_of::<SsaoPushConstants>() as u32)
                 .offset(0u32);
 
             let pso_layout = pipeline_layout_cache.create_pipeline_layout(
                 &[
                     device.bindless_descriptor_set_layout(),
                     descriptor_set_layout,
+                    ssao_desc_layout,
                 ],
                 &[push_constant_range],
             )?;
 
-            let pso = {
-                let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
-                    .depth_test_enable(true)
-                    .depth_write_enable(false)
-                    .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
-                    .depth_bounds_test_enable(false)
-                    .stencil_test_enable(false)
-                    .min_depth_bounds(0.0f32)
-                    .max_depth_bounds(1.0f32);
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::ALWAYS)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
 
-                let pso_build_info = PipelineCreateInfo {
-                    pipeline_layout: pso_layout,
-                    vertex_shader: "assets/shaders/skybox.vert".to_string(),
-                    fragment_shader: "assets/shaders/skybox.frag".to_string(),
-                    vertex_input_state: Vertex::get_vertex_input_desc(),
-                    color_attachment_formats: vec![
-                        PipelineColorAttachment {
-                            format: DEFERRED_POSITION_FORMAT,
-                            blend: false,
-                            ..Default::default()
-                        },
-                        PipelineColorAttachment {
-                            format: DEFERRED_NORMAL_FORMAT,
-                            blend: false,
-                            ..Default::default()
-                        },
-                        PipelineColorAttachment {
-                            format: DEFERRED_COLOR_FORMAT,
-                            blend: false,
-                            ..Default::default()
-                        },
-                    ],
-                    depth_attachment_format: Some(depth_image_format),
-                    depth_stencil_state: *depth_stencil_state,
-                    cull_mode: vk::CullModeFlags::NONE,
-                };
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: pso_layout,
+                vertex_shader: "assets/shaders/deferred_lighting.vert".to_string(),
+                fragment_shader: "assets/shaders/ssao.frag".to_string(),
+                vertex_input_state: Vertex::get_empty_vertex_input_desc(),
+                color_attachment_formats: vec![PipelineColorAttachment {
+                    format: SSAO_FORMAT,
+                    blend: false,
+                    ..Default::default()
+                }],
+                depth_attachment_format: None,
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            };
+
+            let pso = pipeline_manager.create_pipeline(&pso_build_info)?;
+
+            // Same blur as bloom, only writing to the single channel occlusion images
+            let blur_pso = pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                pipeline_layout: bloom_pass.bloom_pso_layout,
+                vertex_shader: "assets/shaders/quad.vert".to_string(),
+                fragment_shader: "assets/shaders/blur.frag".to_string(),
+                ..pso_build_info
+            })?;
+
+            SsaoPass {
+                pso,
+                pso_layout,
+                blur_pso,
+            }
+        };
+
+        let taa_pass = {
+            let resolve_set_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                .bind_image(
+                    0,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .bind_image(
+                    1,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .bind_image(
+                    2,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .bind_image(
+                    3,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .build()
+                .unwrap();
+            let copy_set_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                .bind_image(
+                    0,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .build()
+                .unwrap();
+
+            let resolve_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[
+                    device.bindless_descriptor_set_layout(),
+                    descriptor_set_layout,
+                    resolve_set_layout,
+                ],
+                &[*vk::PushConstantRange::builder()
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                    .size(size_of::<TaaPushConstants>() as u32)
+                    .offset(0u32)],
+            )?;
+            let copy_pso_layout =
+                pipeline_layout_cache.create_pipeline_layout(&[copy_set_layout], &[])?;
+
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::ALWAYS)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
 
-                pipeline_manager.create_pipeline(&pso_build_info)?
+            let attachment = PipelineColorAttachment {
+                format: render_image_format,
+                blend: false,
+                ..Default::default()
+            };
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: resolve_pso_layout,
+                vertex_shader: "assets/shaders/quad.vert".to_string(),
+                fragment_shader: "assets/shaders/taa_resolve.frag".to_string(),
+                vertex_input_state: Vertex::get_empty_vertex_input_desc(),
+                color_attachment_formats: vec![attachment.clone()],
+                depth_attachment_format: None,
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
             };
+            let resolve_pso = pipeline_manager.create_pipeline(&pso_build_info)?;
+            let copy_pso = pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                pipeline_layout: copy_pso_layout,
+                fragment_shader: "assets/shaders/taa_copy.frag".to_string(),
+                color_attachment_formats: vec![attachment.clone(), attachment],
+                ..pso_build_info
+            })?;
+
+            TaaPass {
+                resolve_pso,
+                resolve_pso_layout,
+                copy_pso,
+                copy_pso_layout,
+            }
+        };
 
-            (pso, pso_layout)
+        let dof_pass = {
+            let prepare_set_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                .bind_image(
+                    0,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .bind_image(
+                    1,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .build()
+                .unwrap();
+            let composite_set_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                .bind_image(
+                    0,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .bind_image(
+                    1,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .bind_image(
+                    2,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .build()
+                .unwrap();
+
+            let push_constant_range = *vk::PushConstantRange::builder()
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .size(size_of::<DofPushConstants>() as u32)
+                .offset(0u32);
+
+            let prepare_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[
+                    device.bindless_descriptor_set_layout(),
+                    descriptor_set_layout,
+                    prepare_set_layout,
+                ],
+                &[push_constant_range],
+            )?;
+            let composite_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[
+                    device.bindless_descriptor_set_layout(),
+                    descriptor_set_layout,
+                    composite_set_layout,
+                ],
+                &[push_constant_range],
+            )?;
+
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::ALWAYS)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
+
+            let layer_attachment = PipelineColorAttachment {
+                format: render_image_format,
+                blend: false,
+                ..Default::default()
+            };
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: prepare_pso_layout,
+                vertex_shader: "assets/shaders/quad.vert".to_string(),
+                fragment_shader: "assets/shaders/dof_prepare.frag".to_string(),
+                vertex_input_state: Vertex::get_empty_vertex_input_desc(),
+                color_attachment_formats: vec![layer_attachment.clone(), layer_attachment],
+                depth_attachment_format: None,
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            };
+            let prepare_pso = pipeline_manager.create_pipeline(&pso_build_info)?;
+
+            let composite_pso = pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                pipeline_layout: composite_pso_layout,
+                fragment_shader: "assets/shaders/dof_composite.frag".to_string(),
+                color_attachment_formats: vec![PipelineColorAttachment {
+                    format: render_image_format,
+                    blend: true,
+                    src_blend_factor_color: vk::BlendFactor::SRC_ALPHA,
+                    dst_blend_factor_color: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    src_blend_factor_alpha: vk::BlendFactor::ZERO,
+                    dst_blend_factor_alpha: vk::BlendFactor::ONE,
+                    ..Default::default()
+                }],
+                ..pso_build_info
+            })?;
+
+            DofPass {
+                prepare_pso,
+                prepare_pso_layout,
+                composite_pso,
+                composite_pso_layout,
+            }
+        };
+
+        let outline_pass = {
+            let mask_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[
+                    device.bindless_descriptor_set_layout(),
+                    descriptor_set_layout,
+                ],
+                &[],
+            )?;
+            let composite_set_layout = DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                .bind_image(
+                    0,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT,
+                )
+                .build()
+                .unwrap();
+            let composite_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+                &[composite_set_layout],
+                &[*vk::PushConstantRange::builder()
+                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                    .size(size_of::<OutlinePushConstants>() as u32)
+                    .offset(0u32)],
+            )?;
+
+            let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(false)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::ALWAYS)
+                .depth_bounds_test_enable(false)
+                .stencil_test_enable(false)
+                .min_depth_bounds(0.0f32)
+                .max_depth_bounds(1.0f32);
+
+            // Whole silhouettes are masked, including parts hidden behind other geometry
+            let pso_build_info = PipelineCreateInfo {
+                pipeline_layout: mask_pso_layout,
+                vertex_shader: "assets/shaders/outline_mask.vert".to_string(),
+                fragment_shader: "assets/shaders/outline_mask.frag".to_string(),
+                vertex_input_state: Vertex::get_vertex_input_desc(),
+                color_attachment_formats: vec![PipelineColorAttachment {
+                    format: OUTLINE_MASK_FORMAT,
+                    blend: false,
+                    ..Default::default()
+                }],
+                depth_attachment_format: None,
+                depth_stencil_state: *depth_stencil_state,
+                cull_mode: vk::CullModeFlags::NONE,
+                sample_count: vk::SampleCountFlags::TYPE_1,
+                polygon_mode: vk::PolygonMode::FILL,
+                line_width: 1.0f32,
+                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            };
+            let mask_pso = pipeline_manager.create_pipeline(&pso_build_info)?;
+
+            let composite_pso = pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                pipeline_layout: composite_pso_layout,
+                vertex_shader: "assets/shaders/quad.vert".to_string(),
+                fragment_shader: "assets/shaders/outline.frag".to_string(),
+                vertex_input_state: Vertex::get_empty_vertex_input_desc(),
+                color_attachment_formats: vec![PipelineColorAttachment {
+                    format: render_image_format,
+                    blend: true,
+                    src_blend_factor_color: vk::BlendFactor::SRC_ALPHA,
+                    dst_blend_factor_color: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                    src_blend_factor_alpha: vk::BlendFactor::ZERO,
+                    dst_blend_factor_alpha: vk::BlendFactor::ONE,
+                    ..Default::default()
+                }],
+                ..pso_build_info
+            })?;
+
+            OutlinePass {
+                mask_pso,
+                mask_pso_layout,
+                composite_pso,
+                composite_pso_layout,
+            }
         };
 
         let particle_buffer = {
@@ -1008,16 +2259,15 @@ impl Renderer {
                 storage_type: BufferStorageType::HostLocal,
             };
 
-            [
-                device.resource_manager.create_buffer(&buffer_create_info),
-                device.resource_manager.create_buffer(&buffer_create_info),
-            ]
+            (0..frames_in_flight)
+                .map(|_| device.resource_manager.create_buffer(&buffer_create_info))
+                .collect::<Vec<_>>()
         };
 
         let (particle_set, particle_descriptor_set_layout) = {
-            let mut sets = [vk::DescriptorSet::null(); FRAMES_IN_FLIGHT];
+            let mut sets = vec![vk::DescriptorSet::null(); frames_in_flight];
             let mut layout = None;
-            for i in 0..FRAMES_IN_FLIGHT {
+            for i in 0..frames_in_flight {
                 let (set, set_layout) = JBDescriptorBuilder::new(
                     &device.resource_manager,
                     &mut descriptor_layout_cache,
@@ -1038,7 +2288,34 @@ impl Renderer {
             (sets, layout.unwrap())
         };
 
-        let particle_pipeline = {
+        // Simulated and drawn with the same layout, the particle buffers being set 2 in both
+        let gpu_particle_set_layout = {
+            let stage_flags = vk::ShaderStageFlags::COMPUTE
+                | vk::ShaderStageFlags::VERTEX
+                | vk::ShaderStageFlags::FRAGMENT;
+            DescriptorLayoutBuilder::new(&mut descriptor_layout_cache)
+                .bind_buffer(0, vk::DescriptorType::STORAGE_BUFFER, stage_flags)
+                .bind_buffer(1, vk::DescriptorType::STORAGE_BUFFER, stage_flags)
+                .bind_buffer(2, vk::DescriptorType::STORAGE_BUFFER, stage_flags)
+                .bind_buffer(3, vk::DescriptorType::STORAGE_BUFFER, stage_flags)
+                .build()
+                .unwrap()
+        };
+        let gpu_particle_pso_layout = pipeline_layout_cache.create_pipeline_layout(
+            &[
+                device.bindless_descriptor_set_layout(),
+                descriptor_set_layout,
+                gpu_particle_set_layout,
+            ],
+            &[],
+        )?;
+        let gpu_particle_simulate_pso =
+            pipeline_manager.create_compute_pipeline(&ComputePipelineCreateInfo {
+                pipeline_layout: gpu_particle_pso_layout,
+                compute_shader: "assets/shaders/particle_simulate.comp".to_string(),
+            })?;
+
+        let (particle_pipeline, gpu_particle_draw_pso) = {
             let pso_layout = pipeline_layout_cache.create_pipeline_layout(
                 &[
                     device.bindless_descriptor_set_layout(),
@@ -1048,7 +2325,7 @@ impl Renderer {
                 &[],
             )?;
 
-            let pso = {
+            let (pso, gpu_pso) = {
                 let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
                     .depth_test_enable(true)
                     .depth_write_enable(false)
@@ -1086,12 +2363,29 @@ impl Renderer {
                     depth_attachment_format: Some(depth_image_format),
                     depth_stencil_state: *depth_stencil_state,
                     cull_mode: vk::CullModeFlags::NONE,
+                    sample_count: vk::SampleCountFlags::TYPE_1,
+                    polygon_mode: vk::PolygonMode::FILL,
+                    line_width: 1.0f32,
+                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
                 };
 
-                pipeline_manager.create_pipeline(&pso_build_info)?
+                (
+                    pipeline_manager.create_pipeline(&pso_build_info)?,
+                    pipeline_manager.create_pipeline(&PipelineCreateInfo {
+                        pipeline_layout: gpu_particle_pso_layout,
+                        vertex_shader: "assets/shaders/particle_gpu.vert".to_string(),
+                        fragment_shader: "assets/shaders/particle_gpu.frag".to_string(),
+                        ..pso_build_info
+                    })?,
+                )
             };
 
-            (pso, pso_layout)
+            ((pso, pso_layout), gpu_pso)
+        };
+        let gpu_particles = GpuParticlePass {
+            pso_layout: gpu_particle_pso_layout,
+            simulate_pso: gpu_particle_simulate_pso,
+            draw_pso: gpu_particle_draw_pso,
         };
 
         let quad_mesh = mesh_pool.add_mesh(&MeshData::quad()).unwrap();
@@ -1108,139 +2402,865 @@ impl Renderer {
             light_buffer,
             transform_buffer,
             material_buffer,
+            material_buffer_capacity: vec![settings.initial_material_capacity; frames_in_flight],
+            max_material_buffer_capacity,
+            max_objects,
+            max_lights,
+            max_area_lights,
             light_texture: None,
             stored_lights: SlotMap::default(),
+            area_light_buffer,
+            stored_area_lights: SlotMap::default(),
             shadow_pso,
+            double_sided_shadow_pso,
+            point_shadow_pso,
+            point_shadow_pso_layout,
+            render_targets,
+            point_shadow_maps,
+            point_shadow_settings: HashMap::default(),
+            invalidated_point_shadows: HashSet::default(),
             sun,
+            sun_shadow_settings,
+            shadow_filter: ShadowFilter::default(),
+            shadow_bias: DEFAULT_SHADOW_BIAS,
+            msaa_samples: vk::SampleCountFlags::TYPE_1,
+            wireframe: false,
+            light_gizmos: false,
+            sun_shadow_dirty: true,
+            sun_shadow_direction: sun.direction,
+            frames_since_sun_shadow: 0,
             ui_pass,
             ui_to_draw: Vec::new(),
+            debug_font,
             descriptor_layout_cache,
             descriptor_allocator,
             timestamps: TimeStamp::default(),
+            start_time: Instant::now(),
             pipeline_layout_cache,
             bloom_pass,
             frame_descriptor_allocator,
             combine_pso,
             combine_pso_layout,
+            fxaa_pso,
+            fxaa_pso_layout,
+            oit_composite_pso,
+            oit_composite_pso_layout,
+            shadow_debug_pso,
+            shadow_debug_pso_layout,
+            draw_shadow_map_debug: false,
             enable_bloom_pass: true,
+            ssao_enabled: false,
+            ssao_radius: DEFAULT_SSAO_RADIUS,
+            ssao_bias: DEFAULT_SSAO_BIAS,
+            enable_gpu_culling: true,
             world_debug_pso,
             world_debug_pso_layout,
             draw_debug_ui: true,
             world_debug_desc_set,
             world_debug_draw_data,
+            debug_line_pso,
+            debug_line_pso_layout,
+            debug_line_desc_set: vk::DescriptorSet::null(),
+            lines_to_draw: Vec::new(),
+            debug_line_vertex_count: 0,
             debug_ui_size: 2.5f32,
             mesh_pool,
             forward_pass,
             deferred_fill,
+            cull_pso,
+            cull_pso_layout,
+            light_cull_pso,
+            light_cull_pso_layout,
+            light_cluster_buffer,
             deferred_lighting_combine,
+            ssao_pass,
+            taa_pass,
+            dof_pass,
+            outline_pass,
             material_instances: SlotMap::default(),
             skybox: None,
-            skybox_pso,
-            skybox_pso_layout,
-            cube_mesh,
+            equirect_skybox: None,
+            ibl_generator,
+            mip_generator,
+            ibl_maps: None,
+            brdf_lut,
+            ltc_tables,
+            ibl_intensity: DEFAULT_IBL_INTENSITY,
+            skybox_rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            skybox_intensity: 1.0,
+            sky: SkyParams::default(),
+            background: Background::Skybox,
+            upscale_filter: Filter::default(),
+            fxaa_enabled: false,
+            taa_enabled: false,
+            taa_history_valid: false,
+            fxaa_settings: FxaaSettings::default(),
+            fog: FogParams::default(),
+            dof: DofParams::default(),
+            bloom: BloomParams::default(),
+            auto_exposure,
+            auto_exposure_params: AutoExposureParams::default(),
+            transparency_mode: TransparencyMode::default(),
+            outline_models: HashSet::new(),
+            viewports: SlotMap::default(),
+            render_textures: HashMap::default(),
+            pending_render_textures: Vec::new(),
+            streamed_textures: Vec::new(),
+            outline_colour: Colour::new(1.0, 0.6, 0.0),
+            outline_width: 2,
             list,
             shadow,
             gbuffer,
+            ssao,
+            ssao_blur_horizontal,
+            ssao_blur_vertical,
             deferred_lighting,
-            forward,
-            bloom_initial,
-            bloom_horizontal,
-            bloom_vertical,
-            bloom_final,
+            transparent,
+            transparent_oit,
+            oit_composite,
+            particles,
+            taa_resolve,
+            taa_copy,
+            dof_prepare,
+            dof_far_blur_horizontal,
+            dof_far_blur_vertical,
+            dof_near_blur_horizontal,
+            dof_near_blur_vertical,
+            dof_composite,
+            debug_lines,
             combine,
+            outline_mask,
+            outline,
             ui,
+            builtin_passes,
+            custom_passes: Vec::new(),
+            scene_scissor: None,
+            custom_pass_commands: HashMap::new(),
+            capture_request: None,
+            shader_reload_result: None,
+            pending_captures: Vec::new(),
+            capture_writers: Vec::new(),
             particle_buffer,
             particle_pipeline,
+            gpu_particles,
             particle_set,
+            particle_draw_data: Vec::new(),
+            particle_draw_commands: Vec::new(),
+            gpu_particle_buffers: HashMap::default(),
             stored_particle_systems: SlotMap::default(),
             quad_mesh,
             instance_buffer,
+            cull_object_buffer,
+            draw_indirect_buffer,
         });
         result
     }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) -> Result<()> {
         if self.device.resize(new_size)? {
-            self.list.reset();
-            self.list.swapchain_size = (self.device.size().width, self.device.size().height);
-            self.list.bake();
-
-            let shadow = self.list.get_physical_resource("scene_shadow");
-
-            JBDescriptorBuilder::new(
-                &self.device.resource_manager,
-                &mut self.descriptor_layout_cache,
-                &mut self.descriptor_allocator,
-            )
-            .bind_image(ImageDescriptorInfo {
-                binding: 4,
-                image: shadow,
-                sampler: self.device.shadow_sampler(),
-                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-            })
-            .update(&self.descriptor_set)
-            .unwrap();
+            self.resize_render_images()?;
         }
 
         Ok(())
     }
 
-    pub fn reload_shaders(&mut self) -> Result<()> {
-        profiling::scope!("Reload shaders");
-        self.pipeline_manager.reload_shaders(&self.device);
+    /// Resizes the swapchain sized images to match the device. The device must be idle.
+    fn resize_render_images(&mut self) -> Result<()> {
+        let size = self.device.size();
+        self.list.resize((size.width, size.height))?;
+        self.render_targets.recreate_render_targets()?;
+        self.update_graph_descriptors()?;
+        self.taa_history_valid = false;
+
         Ok(())
     }
 
-    pub fn render(&mut self) -> Result<()> {
-        profiling::scope!("Render Frame");
+    /// Starts the device's frame, recreating the swapchain and retrying if it is out of date.
+    /// Returns false if the frame can't be drawn, e.g. while the window is minimized.
+    fn start_frame(&mut self) -> Result<bool> {
+        for _ in 0..2 {
+            if self.device.is_swapchain_out_of_date() {
+                if !self.device.recreate_out_of_date_swapchain()? {
+                    return Ok(false);
+                }
+                self.resize_render_images()?;
+            }
+            if self.device.start_frame()? {
+                return Ok(true);
+            }
+        }
 
-        self.device.start_frame()?;
+        Ok(false)
+    }
 
-        let resource_index = self.device.buffered_resource_number();
+    /// Sets how frames are shown on screen, for example [PresentMode::Fifo] to enable vsync.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<()> {
+        self.device.set_present_mode(present_mode)
+    }
 
-        // Reset desc allocator
-        self.frame_descriptor_allocator[resource_index].reset_pools()?;
+    pub fn present_mode(&self) -> PresentMode {
+        self.device.present_mode()
+    }
 
-        // Copy gpu data
-        {
-            self.camera_uniform.update_light(&self.sun);
-            self.camera_uniform.point_light_count = self.stored_lights.len() as i32;
+    /// Sets the anisotropic filtering of the material texture samplers. Waits for the GPU to be
+    /// idle.
+    pub fn set_anisotropy(&mut self, anisotropy: AnisotropyLevel) -> Result<()> {
+        self.device.set_anisotropy(anisotropy)
+    }
 
-            self.device
-                .resource_manager
-                .get_buffer(self.camera_buffer[resource_index])
-                .unwrap()
-                .view()
-                .mapped_slice()?
-                .copy_from_slice(&[self.camera_uniform]);
+    pub fn anisotropy(&self) -> AnisotropyLevel {
+        self.device.anisotropy()
+    }
 
-            let test = self.stored_lights.values();
-            let uniforms: Vec<LightUniform> =
-                test.map(|&light| LightUniform::from(light)).collect();
+    /// Recreates every render graph image and rebinds the ones used outside of the graph.
+    /// The device must be idle.
+    fn rebuild_render_list(&mut self) -> Result<()> {
+        self.list.bake()?;
+        self.update_graph_descriptors()?;
 
-            self.device
-                .resource_manager
-                .get_buffer(self.light_buffer[resource_index])
-                .unwrap()
-                .view_custom::<LightUniform>(0, uniforms.len())?
-                .mapped_slice()?
-                .copy_from_slice(&uniforms);
+        // The cached shadow map was destroyed with the rest of the graph images
+        self.invalidate_sun_shadow();
+        self.taa_history_valid = false;
 
-            // Copy materials
-            let mut materials = Vec::new();
-            for material_instance in self.material_instances.values() {
-                let material_params = self.get_material_ssbo_from_instance(&material_instance);
-                materials.push(material_params);
-            }
-            // Push light materials
-            for light in self.stored_lights.values() {
-                materials.push(self.get_material_ssbo_from_instance(&MaterialInstance {
-                    diffuse: Vector4::zero(),
-                    emissive: light.colour,
-                    ..Default::default()
-                }));
-            }
+        Ok(())
+    }
+
+    /// Points the persistent descriptor sets at the current render graph images. Sets built every
+    /// frame look the images up when they are drawn, so don't need updating.
+    fn update_graph_descriptors(&mut self) -> Result<()> {
+        let shadow = self.list.get_physical_resource("scene_shadow");
+
+        JBDescriptorBuilder::new(
+            &self.device.resource_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.descriptor_allocator,
+        )
+        .bind_image(ImageDescriptorInfo {
+            binding: 4,
+            image: shadow,
+            sampler: self.device.shadow_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        })
+        .update(&self.descriptor_set)?;
+
+        Ok(())
+    }
+
+    /// Sets the samples per pixel used to draw the gbuffer, resolving it before lighting. 1 disables
+    /// MSAA.
+    ///
+    /// Only the gbuffer is multisampled. Transparent geometry and particles are drawn over the
+    /// resolved, single sampled scene, so their edges stay aliased.
+    ///
+    /// Falls back to the highest sample count below `samples` that the device supports.
+    /// Returns the sample count used.
+    pub fn set_msaa(&mut self, samples: u32) -> Result<u32> {
+        ensure!(samples > 0, "MSAA sample count must be at least 1");
+
+        let sample_count = self.device.get_supported_sample_count(samples);
+        if sample_count.as_raw() != samples {
+            warn!(
+                "{}x MSAA is not supported, using {}x instead",
+                samples,
+                sample_count.as_raw()
+            );
+        }
+        if sample_count == self.msaa_samples {
+            return Ok(sample_count.as_raw());
+        }
+
+        unsafe { self.device.vk_device.device_wait_idle() }?;
+        for resource in ["emissive", "normal", "color", "velocity", "depth"] {
+            self.list.set_resource_samples(resource, sample_count);
+        }
+        self.rebuild_render_list()?;
+        let fill_psos = [
+            Some(self.deferred_fill.pso),
+            Some(self.deferred_fill.double_sided_pso),
+            self.deferred_fill.wireframe_pso,
+            self.deferred_fill.double_sided_wireframe_pso,
+        ];
+        for pso in fill_psos.into_iter().flatten() {
+            self.pipeline_manager.set_sample_count(pso, sample_count)?;
+        }
+        self.msaa_samples = sample_count;
+
+        Ok(sample_count.as_raw())
+    }
+
+    /// Draws scene geometry as wireframe. Requires the fillModeNonSolid device feature.
+    pub fn set_wireframe(&mut self, enabled: bool) -> Result<()> {
+        ensure!(
+            !enabled || self.deferred_fill.wireframe_pso.is_some(),
+            "Wireframe rendering is not supported by this device"
+        );
+        self.wireframe = enabled;
+        Ok(())
+    }
+
+    pub fn wireframe(&self) -> bool {
+        self.wireframe
+    }
+
+    /// Draws wire gizmos for every light with the debug lines: point lights as spheres at their
+    /// range, spot lights as cones, area lights as their rectangle and the sun as an arrow from
+    /// the world origin.
+    pub fn set_light_gizmos(&mut self, enabled: bool) {
+        self.light_gizmos = enabled;
+    }
+
+    pub fn light_gizmos(&self) -> bool {
+        self.light_gizmos
+    }
+
+    /// Darkens ambient light in creases and corners using screen space ambient occlusion.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled`: Whether the SSAO passes draw anything. Disabled by default.
+    /// * `radius`: World space distance around each surface that is searched for occluders.
+    /// * `bias`: Distance samples are pushed away from the surface, to stop flat surfaces occluding themselves.
+    pub fn set_ssao(&mut self, enabled: bool, radius: f32, bias: f32) {
+        self.ssao_enabled = enabled;
+        self.ssao_radius = radius.max(0.0f32);
+        self.ssao_bias = bias.max(0.0f32);
+    }
+
+    pub fn ssao_enabled(&self) -> bool {
+        self.ssao_enabled
+    }
+
+    /// Adds a render pass to the frame graph, to run straight after another pass.
+    ///
+    /// Custom passes can follow the shadow, gbuffer, ssao, deferred_lighting, forward, combine or ui
+    /// passes, or another custom pass. Bloom passes come and go with the bloom mip count so can't be
+    /// followed.
+    /// A custom pass runs after the pass it follows and after any custom passes inserted after that
+    /// pass before it, including the passes that follow those. Every custom pass runs each frame,
+    /// with the commands from [Renderer::run_custom_pass] if any were given, so attachments it
+    /// writes first are always cleared. Passes that write "forward" or "output" are restricted by
+    /// [Renderer::set_scene_scissor].
+    ///
+    /// The frame graph is baked again, so images from [Renderer::debug_shadow_map] and similar must
+    /// be fetched again.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: Unique name of the pass.
+    /// * `layout`: Attachments and inputs of the pass. Use "output" to draw to the swapchain image.
+    /// * `after`: Name of the pass to run after.
+    pub fn insert_custom_pass(
+        &mut self,
+        name: &str,
+        layout: RenderPassLayout,
+        after: &str,
+    ) -> Result<VirtualRenderPassHandle> {
+        ensure!(
+            self.list.find_pass(name).is_none(),
+            "Render pass {} already exists",
+            name
+        );
+        let after_pass = self
+            .list
+            .find_pass(after)
+            .ok_or_else(|| anyhow!("Render pass {} does not exist", after))?;
+        ensure!(
+            !self
+                .bloom_pass
+                .downsample_passes
+                .iter()
+                .chain(self.bloom_pass.upsample_passes.iter())
+                .any(|&pass| pass == after_pass),
+            "Custom passes can't be inserted after bloom pass {}",
+            after
+        );
+
+        unsafe { self.device.vk_device.device_wait_idle() }?;
+
+        let writes_scene = layout
+            .color_attachments
+            .iter()
+            .any(|(name, _)| name == "forward" || name == "output");
+        let handle = self.list.add_pass(name, layout);
+        self.custom_passes.push(CustomPass {
+            handle,
+            after: after_pass,
+            writes_scene,
+        });
+        if writes_scene {
+            self.list.set_scissor_override(handle, self.scene_scissor);
+        }
+
+        self.update_pass_order();
+        self.rebuild_render_list()?;
+
+        Ok(handle)
+    }
+
+    /// Records commands for a custom pass for the next frame. Called once per frame, the commands
+    /// are dropped after the frame is rendered.
+    ///
+    /// # Arguments
+    ///
+    /// * `pass`: Handle returned by [Renderer::insert_custom_pass].
+    /// * `commands`: Records draws into the command buffer, inside the pass's rendering scope.
+    pub fn run_custom_pass<F>(&mut self, pass: VirtualRenderPassHandle, commands: F) -> Result<()>
+    where
+        F: FnOnce(&mut RenderList, vk::CommandBuffer) + 'static,
+    {
+        ensure!(
+            self.custom_passes
+                .iter()
+                .any(|custom| custom.handle == pass),
+            "Render pass is not a custom pass"
+        );
+        self.custom_pass_commands.insert(pass, Box::new(commands));
+
+        Ok(())
+    }
+
+    /// Orders the built in passes, without the bloom levels past the mip count, each followed by
+    /// its custom passes. Takes effect once the render list is baked again.
+    fn update_pass_order(&mut self) {
+        let unused_bloom_passes = self.bloom_pass.unused_passes(self.bloom.mip_count);
+        let mut order = Vec::new();
+        for &pass in self
+            .builtin_passes
+            .iter()
+            .filter(|pass| !unused_bloom_passes.contains(pass))
+        {
+            order.push(pass);
+            self.push_custom_passes_after(pass, &mut order);
+        }
+        self.list.set_pass_order(&order);
+    }
+
+    fn push_custom_passes_after(
+        &self,
+        pass: VirtualRenderPassHandle,
+        order: &mut Vec<VirtualRenderPassHandle>,
+    ) {
+        for custom in self
+            .custom_passes
+            .iter()
+            .filter(|custom| custom.after == pass)
+        {
+            order.push(custom.handle);
+            self.push_custom_passes_after(custom.handle, order);
+        }
+    }
+
+    fn run_custom_passes_after(&mut self, pass: VirtualRenderPassHandle) {
+        let following: Vec<VirtualRenderPassHandle> = self
+            .custom_passes
+            .iter()
+            .filter(|custom| custom.after == pass)
+            .map(|custom| custom.handle)
+            .collect();
+
+        for custom in following {
+            let commands = self.custom_pass_commands.remove(&custom);
+            self.list.run_pass(custom, |list, cmd| {
+                if let Some(commands) = commands {
+                    commands(list, cmd);
+                }
+            });
+            self.run_custom_passes_after(custom);
+        }
+    }
+
+    /// Sets the shadow map resolution and update frequency of the sun.
+    ///
+    /// Changing the resolution recreates the render graph images, so avoid doing it every frame.
+    pub fn set_sun_shadow_settings(&mut self, settings: ShadowSettings) -> Result<()> {
+        let max_dimension = unsafe {
+            self.device
+                .raw_instance()
+                .get_physical_device_properties(self.device.raw_physical_device())
+                .limits
+                .max_image_dimension2_d
+        };
+        validate_shadow_settings(&settings, max_dimension)?;
+
+        if settings.resolution != self.sun_shadow_settings.resolution {
+            unsafe { self.device.vk_device.device_wait_idle() }?;
+            self.list.set_resource_size(
+                "scene_shadow",
+                SizeClass::Custom(settings.resolution, settings.resolution),
+            );
+            self.rebuild_render_list()?;
+        }
+
+        self.sun_shadow_settings = settings;
+        self.invalidate_sun_shadow();
+
+        Ok(())
+    }
+
+    pub fn sun_shadow_settings(&self) -> ShadowSettings {
+        self.sun_shadow_settings
+    }
+
+    /// Sets the filter kernel used to soften the edges of the sun's shadow.
+    pub fn set_shadow_filter(&mut self, kernel: ShadowFilter) {
+        self.shadow_filter = kernel;
+    }
+
+    pub fn shadow_filter(&self) -> ShadowFilter {
+        self.shadow_filter
+    }
+
+    /// Sets the depth bias applied when sampling the sun's shadow map.
+    /// Raise it if surfaces show shadow acne, lower it if shadows detach from their casters.
+    pub fn set_shadow_bias(&mut self, bias: f32) {
+        self.shadow_bias = bias.max(0.0f32);
+    }
+
+    pub fn shadow_bias(&self) -> f32 {
+        self.shadow_bias
+    }
+
+    /// Forces the sun's shadow map to be redrawn next frame, whatever its update mode.
+    /// Use after moving static geometry when the shadow is cached.
+    pub fn invalidate_sun_shadow(&mut self) {
+        self.sun_shadow_dirty = true;
+    }
+
+    /// Sets the cube shadow map resolution and update frequency of a shadow casting point light.
+    ///
+    /// Only the casters closest to the camera get a shadow map, and a light's shadow is always
+    /// drawn in the frame it gets one, so a cached shadow is only kept while the light stays close.
+    pub fn set_light_shadow_settings(
+        &mut self,
+        light: LightHandle,
+        settings: ShadowSettings,
+    ) -> Result<()> {
+        ensure!(self.stored_lights.contains_key(light), "No light exists");
+        let max_dimension = unsafe {
+            self.device
+                .raw_instance()
+                .get_physical_device_properties(self.device.raw_physical_device())
+                .limits
+                .max_image_dimension_cube
+        };
+        validate_shadow_settings(&settings, max_dimension)?;
+
+        self.point_shadow_settings.insert(light, settings);
+        self.invalidate_shadow(light);
+
+        Ok(())
+    }
+
+    pub fn light_shadow_settings(&self, light: LightHandle) -> Option<ShadowSettings> {
+        self.stored_lights.contains_key(light).then(|| {
+            self.point_shadow_settings
+                .get(&light)
+                .copied()
+                .unwrap_or_else(default_point_shadow_settings)
+        })
+    }
+
+    /// Forces a point light's shadow map to be redrawn next frame, whatever its update mode.
+    /// Use after moving static geometry near the light when the shadow is cached.
+    pub fn invalidate_shadow(&mut self, light: LightHandle) {
+        self.invalidated_point_shadows.insert(light);
+    }
+
+    /// Works out if the sun's shadow map needs drawing this frame.
+    fn should_draw_sun_shadow(&mut self) -> bool {
+        // A cached shadow map is useless once the light has moved
+        if self.sun.direction != self.sun_shadow_direction {
+            self.sun_shadow_direction = self.sun.direction;
+            self.invalidate_sun_shadow();
+        }
+
+        let redraw = shadow_needs_redraw(
+            self.sun_shadow_settings.update_mode,
+            &mut self.frames_since_sun_shadow,
+            self.sun_shadow_dirty,
+        );
+        self.sun_shadow_dirty = false;
+        redraw
+    }
+
+    /// Hands the cube shadow maps out to the shadow casting point lights closest to the camera, each
+    /// light keeping its map while it stays one of them. Returns the indices of the maps that need
+    /// drawing this frame.
+    fn update_point_shadow_maps(&mut self) -> Result<Vec<usize>> {
+        let casters = self.get_point_shadow_casters();
+
+        for shadow_map in self.point_shadow_maps.iter_mut() {
+            if shadow_map
+                .light
+                .map_or(false, |light| !casters.contains(&light))
+            {
+                shadow_map.light = None;
+            }
+        }
+
+        let mut to_draw = Vec::new();
+        for &light in casters.iter() {
+            let settings = self
+                .point_shadow_settings
+                .get(&light)
+                .copied()
+                .unwrap_or_else(default_point_shadow_settings);
+            let position = self.stored_lights[light].position;
+            let mut invalidated = self.invalidated_point_shadows.contains(&light);
+
+            let index = match self
+                .point_shadow_maps
+                .iter()
+                .position(|shadow_map| shadow_map.light == Some(light))
+            {
+                Some(index) => index,
+                None => {
+                    // There are never more casters than shadow maps, so one is always free
+                    let index = self
+                        .point_shadow_maps
+                        .iter()
+                        .position(|shadow_map| shadow_map.light.is_none())
+                        .unwrap();
+                    self.point_shadow_maps[index].light = Some(light);
+                    invalidated = true;
+                    index
+                }
+            };
+
+            let shadow_map = &mut self.point_shadow_maps[index];
+            if shadow_map.resolution != settings.resolution {
+                self.render_targets.resize_render_target(
+                    shadow_map.target,
+                    RenderTargetSize::StaticCube(settings.resolution),
+                )?;
+                self.device
+                    .add_image_to_bindless(&self.render_targets.get(shadow_map.target).unwrap())?;
+                shadow_map.resolution = settings.resolution;
+                invalidated = true;
+            }
+            // A cached shadow map is useless once the light has moved
+            if shadow_map.light_position != position {
+                invalidated = true;
+            }
+
+            if shadow_needs_redraw(
+                settings.update_mode,
+                &mut shadow_map.frames_since_update,
+                invalidated,
+            ) {
+                shadow_map.light_position = position;
+                to_draw.push(index);
+            }
+        }
+        // Lights without a shadow map are drawn as soon as they get one anyway
+        self.invalidated_point_shadows.clear();
+
+        Ok(to_draw)
+    }
+
+    /// Replaces the material buffer for a frame with one holding `new_capacity` materials.
+    /// Only call once the frame's fence has been waited on, as the old buffer is destroyed immediately.
+    fn grow_material_buffer(&mut self, resource_index: usize, new_capacity: usize) -> Result<()> {
+        let buffer_create_info = BufferCreateInfo {
+            size: size_of::<MaterialParamSSBO>() * new_capacity,
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+            storage_type: BufferStorageType::HostLocal,
+        };
+        let new_buffer = self
+            .device
+            .resource_manager
+            .create_buffer(&buffer_create_info);
+
+        JBDescriptorBuilder::new(
+            &self.device.resource_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.descriptor_allocator,
+        )
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 3,
+            buffer: new_buffer,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        })
+        .update(&[self.descriptor_set[resource_index]])?;
+
+        self.device
+            .resource_manager
+            .destroy_buffer(self.material_buffer[resource_index]);
+        self.material_buffer[resource_index] = new_buffer;
+        self.material_buffer_capacity[resource_index] = new_capacity;
+
+        info!(
+            "Material buffer grown. [Frame: {}, Capacity: {}]",
+            resource_index, new_capacity
+        );
+        Ok(())
+    }
+
+    /// Starts recompiling every shader in the background. The new pipelines are swapped in at the start of
+    /// the first frame after they finish compiling, see [Renderer::take_shader_reload_result].
+    pub fn reload_shaders(&mut self) -> Result<()> {
+        profiling::scope!("Reload shaders");
+        self.pipeline_manager.reload_shaders();
+        Ok(())
+    }
+
+    /// Watches the shader directory and reloads pipelines in the background when their shaders change on disk.
+    /// Shaders that fail to compile log the error and keep using their last working pipeline.
+    pub fn enable_shader_watch(&mut self, enabled: bool) -> Result<()> {
+        self.pipeline_manager
+            .set_shader_watch(enabled, SHADER_DIRECTORY)
+    }
+
+    /// Returns the result of the last finished shader reload, if it hasn't been taken yet.
+    /// Shaders that failed to compile keep using their last working pipeline.
+    pub fn take_shader_reload_result(&mut self) -> Option<Result<()>> {
+        self.shader_reload_result.take()
+    }
+
+    pub fn render(&mut self) -> Result<()> {
+        profiling::scope!("Render Frame");
+
+        // The object buffers are sized for RendererSettings::max_objects, so fail before anything
+        // is recorded
+        ensure!(
+            self.render_models.len() <= self.max_objects,
+            "{} render models exceed the max limit of [{}]",
+            self.render_models.len(),
+            self.max_objects
+        );
+
+        // Before the frame starts, so their copies are recorded ahead of any draw sampling them
+        self.poll_streamed_textures()?;
+
+        if !self.start_frame()? {
+            // Nothing is drawn, so drop this frame's queued debug lines and UI
+            self.lines_to_draw.clear();
+            self.ui_to_draw.clear();
+            return Ok(());
+        }
+
+        self.pipeline_manager.release_retired_pipelines();
+        self.pipeline_manager.reload_changed_shaders();
+        if let Some(result) = self.pipeline_manager.poll_reloads() {
+            self.shader_reload_result = Some(result);
+        }
+
+        let resource_index = self.device.buffered_resource_number();
+
+        // Reset desc allocator
+        self.frame_descriptor_allocator[resource_index].reset_pools()?;
+
+        let mip_requests = self.device.take_mip_requests();
+        self.mip_generator.record(
+            &self.device,
+            &self.pipeline_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.frame_descriptor_allocator[resource_index],
+            &mip_requests,
+        )?;
+
+        if let Some(skybox) = &mut self.equirect_skybox {
+            self.ibl_generator.record_equirect_skybox(
+                &self.device,
+                &self.pipeline_manager,
+                skybox,
+            )?;
+        }
+        self.generate_pending_ibl_maps()?;
+
+        // This frame's fence has been waited on, so captures recorded with the same resources are done
+        self.write_finished_captures(resource_index);
+
+        self.render_targets.release_retired_views();
+        let point_shadows_to_draw = self.update_point_shadow_maps()?;
+
+        // Copy gpu data
+        {
+            self.camera_uniform.update_light(&self.sun);
+            self.camera_uniform.update_fog(&self.fog);
+            self.camera_uniform.point_light_count = self.stored_lights.len() as i32;
+            self.camera_uniform.area_light_count = self.stored_area_lights.len() as i32;
+            self.camera_uniform.time = self.start_time.elapsed().as_secs_f32();
+            self.camera_uniform.point_shadow_far_plane = POINT_SHADOW_FAR_PLANE;
+
+            // Only the uploaded copy is jittered, culling and motion vectors use the real camera
+            let mut camera_uniform = self.camera_uniform;
+            if self.taa_enabled {
+                camera_uniform.apply_jitter(self.taa_jitter());
+            }
+            self.device
+                .resource_manager
+                .get_buffer(self.camera_buffer[resource_index])
+                .unwrap()
+                .view()
+                .mapped_slice()?
+                .copy_from_slice(&[camera_uniform]);
+            self.camera_uniform.prev_proj_view = self.camera_uniform.proj_view().into();
+
+            let uniforms: Vec<LightUniform> = self
+                .stored_lights
+                .iter()
+                .map(|(handle, &light)| {
+                    let mut uniform = LightUniform::from(light);
+                    if let Some(shadow_map) = self
+                        .point_shadow_maps
+                        .iter()
+                        .find(|shadow_map| shadow_map.light == Some(handle))
+                    {
+                        let image = self.render_targets.get(shadow_map.target).unwrap();
+                        uniform.shadow_index =
+                            self.device.get_descriptor_index(&image).unwrap_or(0usize) as i32;
+                    }
+                    uniform
+                })
+                .collect();
+
+            self.device
+                .resource_manager
+                .get_buffer(self.light_buffer[resource_index])
+                .unwrap()
+                .view_custom::<LightUniform>(0, uniforms.len())?
+                .mapped_slice()?
+                .copy_from_slice(&uniforms);
+
+            let area_uniforms: Vec<AreaLightUniform> = self
+                .stored_area_lights
+                .values()
+                .map(|&light| AreaLightUniform::from(light))
+                .collect();
+            self.device
+                .resource_manager
+                .get_buffer(self.area_light_buffer[resource_index])
+                .unwrap()
+                .view_custom::<AreaLightUniform>(0, area_uniforms.len())?
+                .mapped_slice()?
+                .copy_from_slice(&area_uniforms);
+
+            // Copy materials
+            let mut materials = Vec::new();
+            for material_instance in self.material_instances.values() {
+                let material_params = self.get_material_ssbo_from_instance(&material_instance);
+                materials.push(material_params);
+            }
+            // Push light materials
+            for light in self.stored_lights.values() {
+                materials.push(self.get_material_ssbo_from_instance(&MaterialInstance {
+                    diffuse: Vector4::zero(),
+                    emissive: light.colour,
+                    ..Default::default()
+                }));
+            }
+
+            // Light materials are appended after regular materials, so both count towards the capacity
+            if let Some(new_capacity) = grown_material_capacity(
+                self.material_buffer_capacity[resource_index],
+                materials.len(),
+                self.max_material_buffer_capacity,
+            ) {
+                self.grow_material_buffer(resource_index, new_capacity)?;
+            }
 
             self.device
                 .resource_manager
@@ -1251,25 +3271,112 @@ impl Renderer {
                 .copy_from_slice(&materials);
         }
 
-        // Sort draws by mesh, materials all use the same shader at the moment so not needed to sort by material
-        let mut sorted_draws: HashMap<MeshHandle, Vec<RenderModelHandle>> = HashMap::default();
+        // Sort draws by mesh and cull mode, materials all use the same shader at the moment so not needed to sort by material
+        let mut sorted_draws: HashMap<DrawKey, Vec<RenderModelHandle>> = HashMap::default();
+        let mut transparent_models = Vec::new();
         for model_handle in self.render_models.keys() {
             let model = self.render_models.get(model_handle).unwrap();
+            let material = self.material_instances.get(model.material_instance);
+            let key = DrawKey {
+                mesh: model.mesh_handle,
+                double_sided: material.map_or(false, |material| material.double_sided),
+                transparent: material.map_or(false, |material| material.transparent),
+            };
 
-            if let Some(models) = sorted_draws.get_mut(&model.mesh_handle) {
+            if key.transparent && self.transparency_mode == TransparencyMode::Sorted {
+                transparent_models.push((key, model_handle));
+            } else if let Some(models) = sorted_draws.get_mut(&key) {
                 models.push(model_handle);
             } else {
                 let draws = vec![model_handle];
-                sorted_draws.insert(model.mesh_handle, draws);
+                sorted_draws.insert(key, draws);
             }
         }
+        let camera_position = Point3::new(
+            self.camera_uniform.position[0],
+            self.camera_uniform.position[1],
+            self.camera_uniform.position[2],
+        );
+        // Squared distance from the camera to the centre of a model's mesh bounds
+        let model_distance = |key: &DrawKey, model_handle: RenderModelHandle| {
+            let model = self.render_models.get(model_handle).unwrap();
+            let centre = self
+                .mesh_pool
+                .get(key.mesh)
+                .map_or(Point3::origin(), |mesh| mesh.bounds.centre);
+            model
+                .transform
+                .transform_point(centre)
+                .distance2(camera_position)
+        };
+
+        // Opaque draws come first, then single-sided draws before double-sided, so each pipeline is
+        // bound once. Within a pipeline, draws go front to back by their nearest instance so early
+        // depth testing rejects more of the gbuffer pass. A draw's instances are grouped by
+        // material, so neighbouring instances read the same material parameters, then front to
+        // back. The culling pass only roughly keeps their order.
+        let mut sorted_draws: Vec<(f32, DrawKey, Vec<RenderModelHandle>)> = sorted_draws
+            .into_iter()
+            .map(|(key, models)| {
+                let mut models: Vec<(MaterialInstanceHandle, f32, RenderModelHandle)> = models
+                    .into_iter()
+                    .map(|model_handle| {
+                        let material = self.render_models[model_handle].material_instance;
+                        (material, model_distance(&key, model_handle), model_handle)
+                    })
+                    .collect();
+                models.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+                let nearest = models
+                    .iter()
+                    .map(|(_, distance, _)| *distance)
+                    .min_by(|a, b| a.total_cmp(b))
+                    .unwrap_or(0.0f32);
+                (
+                    nearest,
+                    key,
+                    models.into_iter().map(|(_, _, model)| model).collect(),
+                )
+            })
+            .collect();
+        sorted_draws.sort_by(|a, b| {
+            (a.1.transparent, a.1.double_sided)
+                .cmp(&(b.1.transparent, b.1.double_sided))
+                .then(a.0.total_cmp(&b.0))
+        });
+        let mut sorted_draws: Vec<(DrawKey, Vec<RenderModelHandle>)> = sorted_draws
+            .into_iter()
+            .map(|(_, key, models)| (key, models))
+            .collect();
+
+        // With sorted transparency, transparent models go last and are drawn one at a time from
+        // back to front, so they blend in the right order
+        let mut transparent_models: Vec<(f32, DrawKey, RenderModelHandle)> = transparent_models
+            .into_iter()
+            .map(|(key, model_handle)| (model_distance(&key, model_handle), key, model_handle))
+            .collect();
+        transparent_models.sort_by(|a, b| b.0.total_cmp(&a.0));
+        sorted_draws.extend(
+            transparent_models
+                .into_iter()
+                .map(|(_, key, model_handle)| (key, vec![model_handle])),
+        );
+
+        // Materials are uploaded in storage order, so look up each instance's index once per frame
+        let material_indices: HashMap<MaterialInstanceHandle, usize> = self
+            .material_instances
+            .keys()
+            .enumerate()
+            .map(|(index, handle)| (handle, index))
+            .collect();
 
         let mut transform_matrices = Vec::new();
         let mut instance_data = Vec::new();
+        let mut cull_objects = Vec::new();
         let mut draw_commands = Vec::new();
+        let mut outline_draws = Vec::new();
 
-        for (&mesh, objects) in sorted_draws.iter() {
-            if let Some(mesh) = self.mesh_pool.get(mesh) {
+        for (key, objects) in sorted_draws.iter() {
+            if let Some(mesh) = self.mesh_pool.get(key.mesh) {
                 let index_count = {
                     if mesh.index_count == 0 {
                         mesh.vertex_count
@@ -1280,6 +3387,19 @@ impl Renderer {
 
                 let instance_offset = instance_data.len();
 
+                outline_draws.extend(
+                    objects
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, model)| self.outline_models.contains(model))
+                        .map(|(i, _)| OutlineDraw {
+                            vertex_offset: mesh.vertex_offset,
+                            index_offset: mesh.index_offset,
+                            index_count,
+                            instance: instance_offset + i,
+                        }),
+                );
+
                 // Copy transforms for draw
                 let mut transforms: Vec<TransformSSBO> = objects
                     .iter()
@@ -1288,6 +3408,8 @@ impl Renderer {
                         TransformSSBO {
                             model: model.transform.into(),
                             normal: model.transform.invert().unwrap().transpose().into(),
+                            prev_model: model.prev_transform.into(),
+                            params: model.params,
                         }
                     })
                     .collect();
@@ -1300,11 +3422,7 @@ impl Renderer {
                     .map(|(i, &object)| {
                         let model = self.render_models.get(object).unwrap();
 
-                        let material_index = self
-                            .material_instances
-                            .keys()
-                            .position(|handle| handle == model.material_instance)
-                            .unwrap();
+                        let material_index = material_indices[&model.material_instance];
 
                         InstanceSSBO {
                             transform_index: (instance_offset + i) as i32,
@@ -1314,13 +3432,28 @@ impl Renderer {
                     })
                     .collect();
 
-                // Create DrawCommand
+                cull_objects.extend(objects.iter().map(|_| {
+                    CullObjectSSBO {
+                        bounding_sphere: mesh
+                            .bounds
+                            .centre
+                            .to_vec()
+                            .extend(mesh.bounds.radius)
+                            .into(),
+                        draw_index: draw_commands.len() as u32,
+                        ..Default::default()
+                    }
+                }));
+
+                // Create DrawCommand
                 draw_commands.push(DrawCommand {
                     vertex_offset: mesh.vertex_offset,
                     index_offset: mesh.index_offset,
                     index_count,
                     instance_count: objects.len(),
                     instance_offset,
+                    double_sided: key.double_sided,
+                    transparent: key.transparent,
                 });
 
                 instance_data.append(&mut objects_instance_data);
@@ -1335,6 +3468,9 @@ impl Renderer {
             .view_custom(0, transform_matrices.len())?
             .mapped_slice()?
             .copy_from_slice(&transform_matrices);
+        for model in self.render_models.values_mut() {
+            model.prev_transform = model.transform;
+        }
         self.device
             .resource_manager
             .get_buffer(self.instance_buffer[resource_index])
@@ -1342,103 +3478,43 @@ impl Renderer {
             .view_custom(0, instance_data.len())?
             .mapped_slice()?
             .copy_from_slice(&instance_data);
+        self.device
+            .resource_manager
+            .get_buffer(self.cull_object_buffer[resource_index])
+            .unwrap()
+            .view_custom(0, cull_objects.len())?
+            .mapped_slice()?
+            .copy_from_slice(&cull_objects);
+
+        // Instance counts start at zero, the culling pass adds each visible instance
+        let indirect_draws: Vec<vk::DrawIndexedIndirectCommand> = draw_commands
+            .iter()
+            .map(|draw| vk::DrawIndexedIndirectCommand {
+                index_count: draw.index_count as u32,
+                instance_count: 0,
+                first_index: draw.index_offset as u32,
+                vertex_offset: draw.vertex_offset as i32,
+                first_instance: draw.instance_offset as u32,
+            })
+            .collect();
+        self.device
+            .resource_manager
+            .get_buffer(self.draw_indirect_buffer[resource_index])
+            .unwrap()
+            .view_custom(0, indirect_draws.len())?
+            .mapped_slice()?
+            .copy_from_slice(&indirect_draws);
 
-        // Copy particles
-        let particle_draw_commands = {
-            let mut draw_commands = Vec::new();
-
-            let mut all_particle_data = Vec::default();
-            for (_, system) in self.stored_particle_systems.iter() {
-                let mut particle_data: Vec<ParticleDrawData> = system
-                    .particles()
-                    .iter()
-                    .map(|particle| {
-                        let mut model = Matrix4::from_translation(particle.position);
-                        // Camera facing quad used when no mesh
-                        if system.mesh.is_none() {
-                            model[0][0] = self.camera_uniform.view[0][0];
-                            model[0][1] = self.camera_uniform.view[1][0];
-                            model[0][2] = self.camera_uniform.view[2][0];
-                            model[1][0] = self.camera_uniform.view[0][1];
-                            model[1][1] = self.camera_uniform.view[1][1];
-                            model[1][2] = self.camera_uniform.view[2][1];
-                            model[2][0] = self.camera_uniform.view[0][2];
-                            model[2][1] = self.camera_uniform.view[1][2];
-                            model[2][2] = self.camera_uniform.view[2][2];
-                        }
-
-                        let rotation_euler = {
-                            if system.mesh.is_none() {
-                                Euler {
-                                    x: Deg(0.0),
-                                    y: Deg(0.0),
-                                    z: Deg(particle.rotation.z),
-                                }
-                            } else {
-                                Euler {
-                                    x: Deg(particle.rotation.x),
-                                    y: Deg(particle.rotation.y),
-                                    z: Deg(particle.rotation.z),
-                                }
-                            }
-                        };
-                        let rotation = Matrix4::from(Quaternion::from(rotation_euler));
-                        let scale = Matrix4::from_nonuniform_scale(
-                            particle.size,
-                            particle.size,
-                            particle.size,
-                        );
-                        model = model * rotation * scale;
-
-                        ParticleDrawData {
-                            model: model.into(),
-                            colour: particle.colour.into(),
-                            texture_index: {
-                                if let Some(tex) = particle.texture_index {
-                                    self.device.get_descriptor_index(&tex).unwrap() as i32
-                                } else {
-                                    0
-                                }
-                            },
-                            padding: 0.0,
-                            padding_two: 0.0,
-                            padding_one: 0.0,
-                        }
-                    })
-                    .collect();
-
-                let mesh = system.mesh.unwrap_or(self.quad_mesh);
-                if let Some(mesh) = self.mesh_pool.get(mesh) {
-                    let index_count = {
-                        if mesh.index_count == 0 {
-                            mesh.vertex_count
-                        } else {
-                            mesh.index_count
-                        }
-                    };
-
-                    draw_commands.push(DrawCommand {
-                        vertex_offset: mesh.vertex_offset,
-                        index_offset: mesh.index_offset,
-                        index_count,
-                        instance_offset: all_particle_data.len(),
-                        instance_count: particle_data.len(),
-                    });
-                }
-
-                all_particle_data.append(&mut particle_data);
-            }
-
+        // Copy particles packed by the last tick
+        if !self.particle_draw_data.is_empty() {
             self.device
                 .resource_manager
                 .get_buffer(self.particle_buffer[resource_index])
                 .unwrap()
-                .view_custom(0, all_particle_data.len())?
+                .view_custom(0, self.particle_draw_data.len())?
                 .mapped_slice()?
-                .copy_from_slice(&all_particle_data);
-
-            draw_commands
-        };
+                .copy_from_slice(&self.particle_draw_data);
+        }
 
         // Copy debug UI
         let debug_ui_draw_amount = {
@@ -1448,7 +3524,7 @@ impl Renderer {
                     for light in self.stored_lights.values() {
                         let draw = WorldDebugUIDrawData {
                             position: light.position.into(),
-                            texture_index: self.device.get_descriptor_index(&texture).unwrap()
+                            texture_index: self.device.get_descriptor_index(&texture).unwrap_or(0)
                                 as i32,
                             colour: light.colour.into(),
                             size: self.debug_ui_size,
@@ -1471,6 +3547,56 @@ impl Renderer {
             }
         };
 
+        // Copy debug lines
+        if self.light_gizmos {
+            self.draw_light_gizmos();
+        }
+        self.debug_line_vertex_count = {
+            if self.lines_to_draw.len() > MAX_DEBUG_LINE_VERTICES as usize {
+                warn!(
+                    "Too many debug lines this frame, only drawing the first {}",
+                    MAX_DEBUG_LINE_VERTICES / 2
+                );
+                self.lines_to_draw
+                    .truncate(MAX_DEBUG_LINE_VERTICES as usize);
+            }
+
+            if !self.lines_to_draw.is_empty() {
+                let vertices: &[u8] = bytemuck::cast_slice(&self.lines_to_draw);
+                let (buffer, offset) = self
+                    .device
+                    .resource_manager
+                    .allocate_transient(vertices.len())?;
+                let mut buffer = self.device.resource_manager.get_buffer(buffer).unwrap();
+                buffer
+                    .view_custom::<u8>(offset, vertices.len())?
+                    .mapped_slice()?
+                    .copy_from_slice(vertices);
+
+                // The shader indexes from the start of the range, so point it at this frame's lines
+                let buffer_info = *vk::DescriptorBufferInfo::builder()
+                    .buffer(buffer.buffer())
+                    .offset(offset as vk::DeviceSize)
+                    .range(vertices.len() as vk::DeviceSize);
+                let (set, _) = DescriptorBuilder::new(
+                    &mut self.descriptor_layout_cache,
+                    &mut self.frame_descriptor_allocator[resource_index],
+                )
+                .bind_buffer(
+                    0,
+                    &[buffer_info],
+                    vk::DescriptorType::STORAGE_BUFFER,
+                    vk::ShaderStageFlags::VERTEX,
+                )
+                .build()?;
+                self.debug_line_desc_set = set;
+            }
+
+            let count = self.lines_to_draw.len();
+            self.lines_to_draw.clear();
+            count
+        };
+
         // Copy UI
         {
             let ui_uniform = UIUniformData {
@@ -1549,22 +3675,30 @@ impl Renderer {
             self.mesh_pool.bind(self.device.graphics_command_buffer());
         }
 
+        // Only visible where nothing is drawn over the clear, such as outside the scene scissor
+        let clear_colour = ClearColour::Colour(self.clear_colour);
+        self.list
+            .set_pass_clear_colour(self.deferred_lighting, clear_colour);
+        self.list.set_pass_clear_colour(self.combine, clear_colour);
         self.list
             .setup_attachments(self.device.get_present_image_view());
 
+        self.cull_objects(cull_objects.len(), self.descriptor_set[resource_index]);
+        self.cull_lights(self.descriptor_set[resource_index]);
+        self.simulate_gpu_particles(resource_index)?;
+        let opaque_end = draw_commands.partition_point(|draw| !draw.transparent);
+        let double_sided_start =
+            draw_commands[..opaque_end].partition_point(|draw| !draw.double_sided);
+
         // Shadow pass
-        let shadow_pass_start = self.device.write_timestamp(
+        let draw_sun_shadow = self.should_draw_sun_shadow();
+        self.list.set_pass_skipped(self.shadow, !draw_sun_shadow);
+        let frame_start = self.device.write_timestamp(
             self.device.graphics_command_buffer(),
             vk::PipelineStageFlags2::TOP_OF_PIPE,
         );
         self.list.run_pass(self.shadow, |list, cmd| {
-            let pipeline = self.pipeline_manager.get_pipeline(self.shadow_pso);
             unsafe {
-                self.device.vk_device.cmd_bind_pipeline(
-                    cmd,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    pipeline,
-                );
                 self.device.vk_device.cmd_bind_descriptor_sets(
                     cmd,
                     vk::PipelineBindPoint::GRAPHICS,
@@ -1578,122 +3712,114 @@ impl Renderer {
                 );
             };
 
-            // Draw commands
-            Self::draw_objects_free(&draw_commands, &self.device.vk_device, &cmd).unwrap();
+            // Draw commands, each cull mode with its own pipeline
+            let (single_sided, double_sided) =
+                draw_commands[..opaque_end].split_at(double_sided_start);
+            for (pso, draws) in [
+                (self.shadow_pso, single_sided),
+                (self.double_sided_shadow_pso, double_sided),
+            ] {
+                if draws.is_empty() {
+                    continue;
+                }
+                let pipeline = self.pipeline_manager.get_pipeline(pso);
+                unsafe {
+                    self.device.vk_device.cmd_bind_pipeline(
+                        cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                }
+                Self::draw_objects_free(draws, &self.device.vk_device, &cmd).unwrap();
+            }
         });
-        let shadow_pass_end = self.device.write_timestamp(
-            self.device.graphics_command_buffer(),
-            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-        );
-
-        self.list.run_pass(self.gbuffer, |list, cmd| {
-            let pipeline = self.pipeline_manager.get_pipeline(self.deferred_fill.pso);
-
-            unsafe {
-                self.device.vk_device.cmd_bind_pipeline(
-                    self.device.graphics_command_buffer(),
-                    vk::PipelineBindPoint::GRAPHICS,
-                    pipeline,
-                );
-                self.device.vk_device.cmd_bind_descriptor_sets(
-                    self.device.graphics_command_buffer(),
-                    vk::PipelineBindPoint::GRAPHICS,
-                    self.deferred_fill.pso_layout,
-                    0u32,
-                    &[
-                        self.device.bindless_descriptor_set(),
-                        self.descriptor_set[resource_index],
-                    ],
-                    &[],
-                );
-            };
+        self.run_custom_passes_after(self.shadow);
+        self.draw_point_light_shadows(
+            &point_shadows_to_draw,
+            &draw_commands[..opaque_end],
+            resource_index,
+        )?;
 
-            // Draw commands
+        let render_textures: Vec<ViewportHandle> = {
+            let count = self
+                .pending_render_textures
+                .len()
+                .min(MAX_RENDER_TEXTURES_PER_FRAME);
+            self.pending_render_textures.drain(..count).collect()
+        };
+        self.draw_viewports(
+            &render_textures,
+            &draw_commands,
+            &indirect_draws,
+            cull_objects.len(),
+            resource_index,
+        )?;
 
-            Self::draw_objects_free(&draw_commands, &self.device.vk_device, &cmd).unwrap();
+        self.draw_scene(
+            &draw_commands,
+            self.descriptor_set[resource_index],
+            self.draw_indirect_buffer[resource_index],
+            resource_index,
+        );
+        self.draw_outline(&outline_draws, resource_index);
+        // Measured from the main camera, and used by every viewport from the next frame
+        self.auto_exposure.record(
+            &self.device,
+            &self.pipeline_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.frame_descriptor_allocator[resource_index],
+            self.list.get_physical_resource("forward"),
+            &self.auto_exposure_params,
+            self.camera_uniform.time,
+        )?;
+        let viewports: Vec<ViewportHandle> = self
+            .viewports
+            .iter()
+            .filter(|(_, viewport)| viewport.target.is_none())
+            .map(|(handle, _)| handle)
+            .collect();
+        self.draw_viewports(
+            &viewports,
+            &draw_commands,
+            &indirect_draws,
+            cull_objects.len(),
+            resource_index,
+        )?;
+        self.list.run_pass(self.ui, |list, cmd| {
+            if self.draw_debug_ui {
+                let pipeline = self.pipeline_manager.get_pipeline(self.world_debug_pso);
 
-            if self.skybox.is_some() {
-                let pso = self.pipeline_manager.get_pipeline(self.skybox_pso);
                 unsafe {
                     self.device.vk_device.cmd_bind_pipeline(
                         self.device.graphics_command_buffer(),
                         vk::PipelineBindPoint::GRAPHICS,
-                        pso,
+                        pipeline,
                     );
                     self.device.vk_device.cmd_bind_descriptor_sets(
                         self.device.graphics_command_buffer(),
                         vk::PipelineBindPoint::GRAPHICS,
-                        self.skybox_pso_layout,
+                        self.world_debug_pso_layout,
                         0u32,
                         &[
                             self.device.bindless_descriptor_set(),
-                            self.descriptor_set[resource_index],
+                            self.world_debug_desc_set[resource_index],
                         ],
                         &[],
                     );
                 };
 
-                Self::draw_skybox_free(
-                    &self.device,
-                    &self.mesh_pool,
-                    self.cube_mesh,
-                    self.skybox.unwrap(),
-                    &cmd,
-                    &self.skybox_pso_layout,
-                )
-                .unwrap();
+                unsafe {
+                    self.device.vk_device.cmd_draw(
+                        self.device.graphics_command_buffer(),
+                        6u32 * debug_ui_draw_amount as u32,
+                        1u32,
+                        0u32,
+                        0u32,
+                    );
+                };
             }
-        });
-        let gbuffer = self.device.write_timestamp(
-            self.device.graphics_command_buffer(),
-            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-        );
-
-        self.list.run_pass(self.deferred_lighting, |list, cmd| {
-            let emissive = list.get_physical_resource("emissive");
-            let normal = list.get_physical_resource("normal");
-            let color = list.get_physical_resource("color");
-            let depth = list.get_physical_resource("depth");
-
-            let (render_target_set, _) = JBDescriptorBuilder::new(
-                &self.device.resource_manager,
-                &mut self.descriptor_layout_cache,
-                &mut self.frame_descriptor_allocator[resource_index],
-            )
-            .bind_image(ImageDescriptorInfo {
-                binding: 0,
-                image: emissive,
-                sampler: self.device.ui_sampler(),
-                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                stage_flags: vk::ShaderStageFlags::FRAGMENT,
-            })
-            .bind_image(ImageDescriptorInfo {
-                binding: 1,
-                image: normal,
-                sampler: self.device.ui_sampler(),
-                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                stage_flags: vk::ShaderStageFlags::FRAGMENT,
-            })
-            .bind_image(ImageDescriptorInfo {
-                binding: 2,
-                image: color,
-                sampler: self.device.ui_sampler(),
-                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                stage_flags: vk::ShaderStageFlags::FRAGMENT,
-            })
-            .bind_image(ImageDescriptorInfo {
-                binding: 3,
-                image: depth,
-                sampler: self.device.ui_sampler(),
-                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                stage_flags: vk::ShaderStageFlags::FRAGMENT,
-            })
-            .build()
-            .unwrap();
 
-            let pipeline = self
-                .pipeline_manager
-                .get_pipeline(self.deferred_lighting_combine.pso);
+            let pipeline = self.pipeline_manager.get_pipeline(self.ui_pass.pso);
 
             unsafe {
                 self.device.vk_device.cmd_bind_pipeline(
@@ -1704,433 +3830,2056 @@ impl Renderer {
                 self.device.vk_device.cmd_bind_descriptor_sets(
                     self.device.graphics_command_buffer(),
                     vk::PipelineBindPoint::GRAPHICS,
-                    self.deferred_lighting_combine.pso_layout,
+                    self.ui_pass.pso_layout,
                     0u32,
                     &[
                         self.device.bindless_descriptor_set(),
-                        self.descriptor_set[resource_index],
-                        render_target_set,
+                        self.ui_pass.desc_set[resource_index],
                     ],
                     &[],
                 );
             };
 
-            //// Draw commands
+            let index_buffer = self
+                .device
+                .resource_manager
+                .get_buffer(self.ui_pass.index_buffer[resource_index])
+                .unwrap();
 
             unsafe {
-                self.device.vk_device.cmd_draw(
+                self.device.vk_device.cmd_bind_index_buffer(
                     self.device.graphics_command_buffer(),
-                    6u32,
-                    1u32,
-                    0u32,
+                    index_buffer.buffer(),
+                    0u64,
+                    vk::IndexType::UINT32,
+                );
+            }
+
+            for draw in ui_draw_calls.iter() {
+                let y = self.device.size().height as i32;
+                let height = self.device.size().height as i32;
+
+                let max = [
+                    draw.scissor.1[0] - draw.scissor.0[0],
+                    draw.scissor.1[1] - draw.scissor.0[1],
+                ];
+
+                let scissor = vk::Rect2D::builder()
+                    .offset(vk::Offset2D {
+                        x: draw.scissor.0[0] as i32,
+                        y: draw.scissor.0[1] as i32, //y: height - (draw.scissor.0[1] as i32 + max[1] as i32),
+                    })
+                    .extent(vk::Extent2D {
+                        width: max[0] as u32,
+                        height: max[1] as u32,
+                    });
+
+                unsafe {
+                    self.device
+                        .vk_device
+                        .cmd_set_scissor(cmd, 0u32, &[*scissor]);
+                };
+
+                //render_pass.set_scissor(draw.scissor.0, max);
+                // Draw commands
+                unsafe {
+                    self.device.vk_device.cmd_draw_indexed(
+                        self.device.graphics_command_buffer(),
+                        draw.amount as u32,
+                        1u32,
+                        draw.index_offset as u32,
+                        draw.vertex_offset as i32,
+                        0u32,
+                    );
+                };
+            }
+        });
+
+        self.run_custom_passes_after(self.ui);
+        let frame_end = self.device.write_timestamp(
+            self.device.graphics_command_buffer(),
+            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+        );
+
+        let captured = if let Some(path) = self.capture_request.take() {
+            self.record_capture(path, resource_index)?;
+            true
+        } else {
+            false
+        };
+
+        ImageBarrierBuilder::default()
+            .add_image_barrier(ImageBarrier {
+                image: AttachmentHandle::SwapchainImage,
+                src_stage_mask: if captured {
+                    PipelineStageFlags2::TRANSFER
+                } else {
+                    PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+                },
+                src_access_mask: if captured {
+                    AccessFlags2::NONE
+                } else {
+                    AccessFlags2::COLOR_ATTACHMENT_WRITE
+                },
+                old_layout: if captured {
+                    ImageLayout::TRANSFER_SRC_OPTIMAL
+                } else {
+                    ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                },
+                // Headless frames stay ready to be copied out, as they are never presented
+                new_layout: if self.device.is_headless() {
+                    ImageLayout::TRANSFER_SRC_OPTIMAL
+                } else {
+                    ImageLayout::PRESENT_SRC_KHR
+                },
+                src_queue_family_index: self.device.graphics_queue_family_index(),
+                dst_queue_family_index: self.device.present_queue_family_index(),
+                ..Default::default()
+            })
+            .build(&self.device, &self.device.graphics_command_buffer())?;
+
+        self.device.end_frame()?;
+
+        self.list.resolve_timings();
+        let timings = self.list.timings();
+        let pass_time =
+            |names: &[&str]| -> f64 { names.iter().filter_map(|name| timings.get(*name)).sum() };
+        self.timestamps.shadow_pass = pass_time(&["shadow"]);
+        self.timestamps.deferred_fill_pass = pass_time(&["gbuffer"]);
+        self.timestamps.deferred_lighting_pass = pass_time(&[
+            "ssao",
+            "ssao_blur_horizontal_pass",
+            "ssao_blur_vertical_pass",
+            "deferred",
+        ]);
+        self.timestamps.forward_pass = pass_time(&[
+            "transparent",
+            "transparent_oit",
+            "oit_composite",
+            "particles",
+        ]);
+        // However many levels of the mip chain ran
+        self.timestamps.bloom_pass = timings
+            .iter()
+            .filter(|(name, _)| name.starts_with("bloom_"))
+            .map(|(_, time)| time)
+            .sum();
+        self.timestamps.combine_pass = pass_time(&["combine"]);
+        self.timestamps.ui_pass = pass_time(&["ui"]);
+        if let Some(time) = self.device.get_timestamp_result(frame_start, frame_end) {
+            self.timestamps.total = time;
+        }
+
+        Ok(())
+    }
+
+    /// Saves the next rendered frame to a PNG file at `path`.
+    ///
+    /// The swapchain image is copied during the next [Renderer::render] and written to disk once that
+    /// frame has finished on the GPU, so the file appears a couple of frames after this is called.
+    pub fn capture_frame(&mut self, path: &str) -> Result<()> {
+        ensure!(
+            self.capture_request.is_none(),
+            "A frame capture is already queued for the next frame"
+        );
+        self.capture_request = Some(path.to_string());
+        Ok(())
+    }
+
+    /// Waits for the GPU to finish every submitted frame, then writes out all queued captures and
+    /// waits for the files to be written. Useful with [Renderer::new_headless], where the program
+    /// may exit straight after rendering.
+    pub fn flush_captures(&mut self) -> Result<()> {
+        unsafe { self.device.vk_device.device_wait_idle() }?;
+        for resource_index in 0..self.device.frames_in_flight() {
+            self.write_finished_captures(resource_index);
+        }
+        for writer in self.capture_writers.drain(..) {
+            writer
+                .join()
+                .map_err(|_| anyhow!("Frame capture writer panicked"))?;
+        }
+        Ok(())
+    }
+
+    /// Copies the swapchain image, which must be in COLOR_ATTACHMENT_OPTIMAL, into a readback buffer.
+    /// Leaves the image in TRANSFER_SRC_OPTIMAL.
+    fn record_capture(&mut self, path: String, resource_index: usize) -> Result<()> {
+        let extent = self.device.swapchain_extent();
+        let format = self.device.surface_format().format;
+        let buffer = self
+            .device
+            .resource_manager
+            .create_buffer(&BufferCreateInfo {
+                size: (extent.width * extent.height * 4) as usize,
+                usage: vk::BufferUsageFlags::TRANSFER_DST,
+                storage_type: BufferStorageType::Readback,
+            });
+        let cmd = self.device.graphics_command_buffer();
+
+        ImageBarrierBuilder::default()
+            .add_image_barrier(ImageBarrier {
+                image: AttachmentHandle::SwapchainImage,
+                src_stage_mask: PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: AccessFlags2::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: PipelineStageFlags2::TRANSFER,
+                dst_access_mask: AccessFlags2::TRANSFER_READ,
+                old_layout: ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                new_layout: ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ..Default::default()
+            })
+            .build(&self.device, &cmd)?;
+
+        let copy_region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0u32,
+                base_array_layer: 0u32,
+                layer_count: 1u32,
+            })
+            .image_extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            });
+        let host_read_barrier = vk::MemoryBarrier2::builder()
+            .src_stage_mask(PipelineStageFlags2::TRANSFER)
+            .src_access_mask(AccessFlags2::TRANSFER_WRITE)
+            .dst_stage_mask(PipelineStageFlags2::HOST)
+            .dst_access_mask(AccessFlags2::HOST_READ);
+        let memory_barriers = [*host_read_barrier];
+        let dependency_info = vk::DependencyInfo::builder().memory_barriers(&memory_barriers);
+        unsafe {
+            self.device.vk_device.cmd_copy_image_to_buffer(
+                cmd,
+                self.device.get_present_image(),
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.device
+                    .resource_manager
+                    .get_buffer(buffer)
+                    .unwrap()
+                    .buffer(),
+                &[*copy_region],
+            );
+            self.device
+                .vk_device
+                .cmd_pipeline_barrier2(cmd, &dependency_info);
+        }
+
+        self.pending_captures.push(PendingCapture {
+            path,
+            buffer,
+            extent,
+            format,
+            resource_index,
+        });
+        Ok(())
+    }
+
+    /// Copies out any captures recorded with `resource_index`, whose frame has now finished, and
+    /// writes them to disk on another thread so encoding doesn't hold up the frame.
+    fn write_finished_captures(&mut self, resource_index: usize) {
+        let (finished, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_captures)
+            .into_iter()
+            .partition(|capture| capture.resource_index == resource_index);
+        self.pending_captures = pending;
+
+        for capture in finished {
+            let pixels = self.read_capture(&capture);
+            self.device.resource_manager.destroy_buffer(capture.buffer);
+            match pixels {
+                Ok(pixels) => {
+                    self.capture_writers.push(thread::spawn(move || {
+                        profiling::scope!("Write Frame Capture");
+                        if let Err(error) = save_capture(&capture, pixels) {
+                            warn!(
+                                "Failed to save frame capture to {}: {}",
+                                capture.path, error
+                            );
+                        } else {
+                            info!("Saved frame capture to {}", capture.path);
+                        }
+                    }));
+                }
+                Err(error) => warn!(
+                    "Failed to save frame capture to {}: {}",
+                    capture.path, error
+                ),
+            }
+        }
+        self.capture_writers.retain(|writer| !writer.is_finished());
+    }
+
+    fn read_capture(&self, capture: &PendingCapture) -> Result<Vec<u8>> {
+        Ok(self
+            .device
+            .resource_manager
+            .get_buffer(capture.buffer)
+            .ok_or_else(|| anyhow!("Capture buffer no longer exists"))?
+            .view::<u8>()
+            .mapped_slice()?
+            .to_vec())
+    }
+
+    /// Draws everything from the gbuffer to the combine pass, from the camera the global descriptor set
+    /// was built with. Shadows are drawn once beforehand and shared by every camera.
+    fn draw_scene(
+        &mut self,
+        draw_commands: &[DrawCommand],
+        global_set: vk::DescriptorSet,
+        draw_indirect_buffer: BufferHandle,
+        resource_index: usize,
+    ) {
+        let opaque_end = draw_commands.partition_point(|draw| !draw.transparent);
+        let double_sided_start =
+            draw_commands[..opaque_end].partition_point(|draw| !draw.double_sided);
+
+        self.list.run_pass(self.gbuffer, |list, cmd| {
+            let (pso, double_sided_pso) = match (
+                self.deferred_fill.wireframe_pso,
+                self.deferred_fill.double_sided_wireframe_pso,
+            ) {
+                (Some(wireframe_pso), Some(double_sided_wireframe_pso)) if self.wireframe => {
+                    (wireframe_pso, double_sided_wireframe_pso)
+                }
+                _ => (self.deferred_fill.pso, self.deferred_fill.double_sided_pso),
+            };
+
+            unsafe {
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    self.device.graphics_command_buffer(),
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.deferred_fill.pso_layout,
+                    0u32,
+                    &[self.device.bindless_descriptor_set(), global_set],
+                    &[],
+                );
+            };
+
+            // Draw commands, with instance counts filled in by the culling pass
+            let indirect_buffer = self
+                .device
+                .resource_manager
+                .get_buffer(draw_indirect_buffer)
+                .unwrap()
+                .buffer();
+            let multi_draw = self.device.enabled_features().multi_draw_indirect == vk::TRUE;
+            for (pso, draws) in [
+                (pso, 0..double_sided_start),
+                (double_sided_pso, double_sided_start..opaque_end),
+            ] {
+                if draws.is_empty() {
+                    continue;
+                }
+                let pipeline = self.pipeline_manager.get_pipeline(pso);
+                unsafe {
+                    self.device.vk_device.cmd_bind_pipeline(
+                        cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                }
+                // Each pipeline's draws are next to each other in the buffer
+                if multi_draw {
+                    unsafe {
+                        self.device.vk_device.cmd_draw_indexed_indirect(
+                            cmd,
+                            indirect_buffer,
+                            (draws.start * size_of::<vk::DrawIndexedIndirectCommand>())
+                                as vk::DeviceSize,
+                            draws.len() as u32,
+                            size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                        );
+                    }
+                    continue;
+                }
+                for i in draws {
+                    unsafe {
+                        self.device.vk_device.cmd_draw_indexed_indirect(
+                            cmd,
+                            indirect_buffer,
+                            (i * size_of::<vk::DrawIndexedIndirectCommand>()) as vk::DeviceSize,
+                            1u32,
+                            size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                        );
+                    }
+                }
+            }
+        });
+        self.run_custom_passes_after(self.gbuffer);
+
+        self.draw_ssao(global_set, resource_index);
+
+        let background = self.get_background_push_constants();
+        self.list.run_pass(self.deferred_lighting, |list, cmd| {
+            let emissive = list.get_physical_resource("emissive");
+            let normal = list.get_physical_resource("normal");
+            let color = list.get_physical_resource("color");
+            let depth = list.get_physical_resource("depth");
+            let ssao = list.get_physical_resource("ssao");
+
+            let (render_target_set, _) = JBDescriptorBuilder::new(
+                &self.device.resource_manager,
+                &mut self.descriptor_layout_cache,
+                &mut self.frame_descriptor_allocator[resource_index],
+            )
+            .bind_image(ImageDescriptorInfo {
+                binding: 0,
+                image: emissive,
+                sampler: self.device.ui_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            })
+            .bind_image(ImageDescriptorInfo {
+                binding: 1,
+                image: normal,
+                sampler: self.device.ui_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            })
+            .bind_image(ImageDescriptorInfo {
+                binding: 2,
+                image: color,
+                sampler: self.device.ui_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            })
+            .bind_image(ImageDescriptorInfo {
+                binding: 3,
+                image: depth,
+                sampler: self.device.ui_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            })
+            .bind_image(ImageDescriptorInfo {
+                binding: 4,
+                image: ssao,
+                sampler: self.device.ui_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            })
+            .build()
+            .unwrap();
+
+            let pipeline = self
+                .pipeline_manager
+                .get_pipeline(self.deferred_lighting_combine.pso);
+
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    self.device.graphics_command_buffer(),
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    self.device.graphics_command_buffer(),
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.deferred_lighting_combine.pso_layout,
+                    0u32,
+                    &[
+                        self.device.bindless_descriptor_set(),
+                        global_set,
+                        render_target_set,
+                    ],
+                    &[],
+                );
+                self.device.vk_device.cmd_push_constants(
+                    self.device.graphics_command_buffer(),
+                    self.deferred_lighting_combine.pso_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0u32,
+                    bytemuck::cast_slice(&[background]),
+                );
+            };
+
+            //// Draw commands
+
+            unsafe {
+                self.device.vk_device.cmd_draw(
+                    self.device.graphics_command_buffer(),
+                    6u32,
+                    1u32,
+                    0u32,
+                    0u32,
+                );
+            };
+        });
+        self.run_custom_passes_after(self.deferred_lighting);
+
+        // Both transparency modes' passes always run, the unused ones drawing nothing, so the
+        // barriers baked into the render list stay valid
+        let has_transparent_draws = opaque_end < draw_commands.len();
+        let transparent_descriptor_sets = [self.device.bindless_descriptor_set(), global_set];
+        let indirect_buffer = self
+            .device
+            .resource_manager
+            .get_buffer(draw_indirect_buffer)
+            .unwrap()
+            .buffer();
+        self.list.run_pass(self.transparent, |_, cmd| {
+            if !has_transparent_draws || self.transparency_mode != TransparencyMode::Sorted {
+                return;
+            }
+
+            Self::draw_transparent_objects(
+                &self.device.vk_device,
+                cmd,
+                self.forward_pass.pso_layout,
+                &transparent_descriptor_sets,
+                (
+                    self.pipeline_manager.get_pipeline(self.forward_pass.pso),
+                    self.pipeline_manager
+                        .get_pipeline(self.forward_pass.double_sided_pso),
+                ),
+                indirect_buffer,
+                draw_commands,
+            );
+        });
+        self.run_custom_passes_after(self.transparent);
+
+        self.list.run_pass(self.transparent_oit, |_, cmd| {
+            if !has_transparent_draws || self.transparency_mode != TransparencyMode::WeightedOIT {
+                return;
+            }
+
+            Self::draw_transparent_objects(
+                &self.device.vk_device,
+                cmd,
+                self.forward_pass.pso_layout,
+                &transparent_descriptor_sets,
+                (
+                    self.pipeline_manager
+                        .get_pipeline(self.forward_pass.oit_pso),
+                    self.pipeline_manager
+                        .get_pipeline(self.forward_pass.double_sided_oit_pso),
+                ),
+                indirect_buffer,
+                draw_commands,
+            );
+        });
+        self.run_custom_passes_after(self.transparent_oit);
+
+        self.list.run_pass(self.oit_composite, |list, cmd| {
+            if !has_transparent_draws || self.transparency_mode != TransparencyMode::WeightedOIT {
+                return;
+            }
+
+            let accumulation = list.get_physical_resource("oit_accumulation");
+            let coverage = list.get_physical_resource("oit_coverage");
+            let (composite_set, _) = JBDescriptorBuilder::new(
+                &self.device.resource_manager,
+                &mut self.descriptor_layout_cache,
+                &mut self.frame_descriptor_allocator[resource_index],
+            )
+            .bind_image(ImageDescriptorInfo {
+                binding: 0,
+                image: accumulation,
+                sampler: self.device.ui_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            })
+            .bind_image(ImageDescriptorInfo {
+                binding: 1,
+                image: coverage,
+                sampler: self.device.ui_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            })
+            .build()
+            .unwrap();
+
+            let pipeline = self.pipeline_manager.get_pipeline(self.oit_composite_pso);
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.oit_composite_pso_layout,
+                    0u32,
+                    &[composite_set],
+                    &[],
+                );
+                self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+            };
+        });
+        self.run_custom_passes_after(self.oit_composite);
+
+        self.list.run_pass(self.particles, |list, cmd| {
+            if !self.particle_draw_commands.is_empty() {
+                let pipeline = self.pipeline_manager.get_pipeline(self.particle_pipeline.0);
+
+                unsafe {
+                    self.device.vk_device.cmd_bind_pipeline(
+                        self.device.graphics_command_buffer(),
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    self.device.vk_device.cmd_bind_descriptor_sets(
+                        self.device.graphics_command_buffer(),
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.particle_pipeline.1,
+                        0u32,
+                        &[
+                            self.device.bindless_descriptor_set(),
+                            global_set,
+                            self.particle_set[resource_index],
+                        ],
+                        &[],
+                    );
+                };
+
+                for draw in self.particle_draw_commands.iter() {
+                    //// Draw commands
+                    unsafe {
+                        self.device.vk_device.cmd_draw_indexed(
+                            self.device.graphics_command_buffer(),
+                            draw.index_count as u32,
+                            draw.instance_count as u32,
+                            draw.index_offset as u32,
+                            draw.vertex_offset as i32,
+                            draw.instance_offset as u32,
+                        );
+                    };
+                }
+            }
+
+            // Drawn indirectly, as only the simulation knows how many particles are alive
+            if !self.gpu_particle_buffers.is_empty() {
+                let pipeline = self
+                    .pipeline_manager
+                    .get_pipeline(self.gpu_particles.draw_pso);
+                unsafe {
+                    self.device.vk_device.cmd_bind_pipeline(
+                        cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                }
+
+                for buffers in self.gpu_particle_buffers.values() {
+                    let draw_buffer = self
+                        .device
+                        .resource_manager
+                        .get_buffer(buffers.draw)
+                        .unwrap()
+                        .buffer();
+                    unsafe {
+                        self.device.vk_device.cmd_bind_descriptor_sets(
+                            cmd,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            self.gpu_particles.pso_layout,
+                            0u32,
+                            &[
+                                self.device.bindless_descriptor_set(),
+                                global_set,
+                                buffers.sets[resource_index],
+                            ],
+                            &[],
+                        );
+                        self.device.vk_device.cmd_draw_indexed_indirect(
+                            cmd,
+                            draw_buffer,
+                            0,
+                            1u32,
+                            size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                        );
+                    }
+                }
+            }
+        });
+        self.run_custom_passes_after(self.particles);
+
+        self.draw_taa(global_set, resource_index);
+        self.draw_depth_of_field(resource_index);
+        self.draw_debug_lines(global_set);
+
+        // Bloom, downsampled through a chain of mips then upsampled back up it
+        let mip_count = self.bloom.mip_count;
+        for level in 0..mip_count {
+            let source = if level == 0 {
+                self.list.get_physical_resource("bright")
+            } else {
+                self.list.get_physical_resource(&bloom_mip_name(level - 1))
+            };
+            let (source_set, _) = JBDescriptorBuilder::new(
+                &self.device.resource_manager,
+                &mut self.descriptor_layout_cache,
+                &mut self.frame_descriptor_allocator[resource_index],
+            )
+            .bind_image(ImageDescriptorInfo {
+                binding: 0,
+                image: source,
+                sampler: self.device.linear_clamp_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            })
+            .build()
+            .unwrap();
+            let push_constants = BloomPushConstants {
+                // The first level extracts the bright parts of the scene
+                threshold: if level == 0 {
+                    self.bloom.threshold
+                } else {
+                    -1.0
+                },
+                knee: self.bloom.knee,
+                padding: [0.0f32; 2],
+            };
+
+            // Passes still run with bloom disabled, clearing the bloom images to black, so the
+            // barriers baked into the render list stay valid
+            self.list
+                .run_pass(self.bloom_pass.downsample_passes[level], |_, cmd| {
+                    if !self.enable_bloom_pass {
+                        return;
+                    }
+
+                    let pipeline = self
+                        .pipeline_manager
+                        .get_pipeline(self.bloom_pass.downsample_pso);
+                    unsafe {
+                        self.device.vk_device.cmd_bind_pipeline(
+                            cmd,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            pipeline,
+                        );
+                        self.device.vk_device.cmd_bind_descriptor_sets(
+                            cmd,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            self.bloom_pass.downsample_pso_layout,
+                            0u32,
+                            &[source_set],
+                            &[],
+                        );
+                        self.device.vk_device.cmd_push_constants(
+                            cmd,
+                            self.bloom_pass.downsample_pso_layout,
+                            vk::ShaderStageFlags::FRAGMENT,
+                            0u32,
+                            bytemuck::cast_slice(&[push_constants]),
+                        );
+                        self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+                    }
+                });
+        }
+        for level in (0..mip_count - 1).rev() {
+            let level_image = self.list.get_physical_resource(&bloom_mip_name(level));
+            // The smallest level has nothing below it to add, so is upsampled as it is
+            let smaller_image = if level + 2 == mip_count {
+                self.list.get_physical_resource(&bloom_mip_name(level + 1))
+            } else {
+                self.list
+                    .get_physical_resource(&bloom_upsampled_name(level + 1))
+            };
+            let (upsample_set, _) = JBDescriptorBuilder::new(
+                &self.device.resource_manager,
+                &mut self.descriptor_layout_cache,
+                &mut self.frame_descriptor_allocator[resource_index],
+            )
+            .bind_image(ImageDescriptorInfo {
+                binding: 0,
+                image: level_image,
+                sampler: self.device.linear_clamp_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            })
+            .bind_image(ImageDescriptorInfo {
+                binding: 1,
+                image: smaller_image,
+                sampler: self.device.linear_clamp_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            })
+            .build()
+            .unwrap();
+
+            self.list
+                .run_pass(self.bloom_pass.upsample_passes[level], |_, cmd| {
+                    if !self.enable_bloom_pass {
+                        return;
+                    }
+
+                    let pipeline = self
+                        .pipeline_manager
+                        .get_pipeline(self.bloom_pass.upsample_pso);
+                    unsafe {
+                        self.device.vk_device.cmd_bind_pipeline(
+                            cmd,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            pipeline,
+                        );
+                        self.device.vk_device.cmd_bind_descriptor_sets(
+                            cmd,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            self.bloom_pass.upsample_pso_layout,
+                            0u32,
+                            &[upsample_set],
+                            &[],
+                        );
+                        self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+                    }
+                });
+        }
+        // Every level of the chain is added together, so the sum is averaged to keep the bloom as
+        // bright whatever the mip count
+        let bloom_intensity = self.bloom.intensity / mip_count as f32;
+        let upscale_sampler = self
+            .upscale_filter
+            .sampler(self.device.ui_sampler(), self.device.linear_clamp_sampler());
+        self.list.run_pass(self.combine, |list, cmd| {
+            let forward = list.get_physical_resource("forward");
+            let bloom_result = list.get_physical_resource(&bloom_upsampled_name(0));
+
+            let (combine_set, _) = JBDescriptorBuilder::new(
+                &self.device.resource_manager,
+                &mut self.descriptor_layout_cache,
+                &mut self.frame_descriptor_allocator[resource_index],
+            )
+            .bind_image(ImageDescriptorInfo {
+                binding: 0,
+                image: forward,
+                sampler: upscale_sampler,
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            })
+            .bind_image(ImageDescriptorInfo {
+                binding: 1,
+                image: bloom_result,
+                sampler: self.device.linear_clamp_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            })
+            .build()
+            .unwrap();
+
+            let (pso, pso_layout) = if self.fxaa_enabled {
+                (self.fxaa_pso, self.fxaa_pso_layout)
+            } else {
+                (self.combine_pso, self.combine_pso_layout)
+            };
+            let pipeline = self.pipeline_manager.get_pipeline(pso);
+
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    self.device.graphics_command_buffer(),
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    self.device.graphics_command_buffer(),
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pso_layout,
+                    0u32,
+                    &[combine_set, self.auto_exposure.exposure_set()],
+                    &[],
+                );
+                if self.fxaa_enabled {
+                    let push_constants = FxaaPushConstants {
+                        edge_threshold: self.fxaa_settings.edge_threshold,
+                        edge_threshold_min: self.fxaa_settings.edge_threshold_min,
+                        subpixel_quality: self.fxaa_settings.subpixel_quality,
+                        bloom_intensity,
+                    };
+                    self.device.vk_device.cmd_push_constants(
+                        self.device.graphics_command_buffer(),
+                        pso_layout,
+                        vk::ShaderStageFlags::FRAGMENT,
+                        0u32,
+                        bytemuck::cast_slice(&[push_constants]),
+                    );
+                } else {
+                    let push_constants = CombinePushConstants {
+                        bloom_intensity,
+                        padding: [0.0f32; 3],
+                    };
+                    self.device.vk_device.cmd_push_constants(
+                        self.device.graphics_command_buffer(),
+                        pso_layout,
+                        vk::ShaderStageFlags::FRAGMENT,
+                        0u32,
+                        bytemuck::cast_slice(&[push_constants]),
+                    );
+                }
+            };
+
+            // Draw commands
+
+            unsafe {
+                self.device.vk_device.cmd_draw(
+                    self.device.graphics_command_buffer(),
+                    6u32,
+                    1u32,
+                    0u32,
+                    0u32,
+                );
+            };
+
+            if self.draw_shadow_map_debug {
+                let shadow_map = list.get_physical_resource("scene_shadow");
+
+                let (shadow_debug_set, _) = JBDescriptorBuilder::new(
+                    &self.device.resource_manager,
+                    &mut self.descriptor_layout_cache,
+                    &mut self.frame_descriptor_allocator[resource_index],
+                )
+                .bind_image(ImageDescriptorInfo {
+                    binding: 0,
+                    image: shadow_map,
+                    sampler: self.device.ui_sampler(),
+                    desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                })
+                .build()
+                .unwrap();
+
+                // Square in the bottom left corner, flipped like the rest of the backbuffer
+                let size = self.device.size();
+                let debug_size = size.height as f32 * 0.3f32;
+                let viewport = vk::Viewport::builder()
+                    .x(0.0f32)
+                    .y(size.height as f32)
+                    .width(debug_size)
+                    .height(-debug_size)
+                    .min_depth(0.0f32)
+                    .max_depth(1.0f32)
+                    .build();
+
+                let pipeline = self.pipeline_manager.get_pipeline(self.shadow_debug_pso);
+
+                unsafe {
+                    self.device.vk_device.cmd_set_viewport(
+                        self.device.graphics_command_buffer(),
+                        0u32,
+                        &[viewport],
+                    );
+                    self.device.vk_device.cmd_bind_pipeline(
+                        self.device.graphics_command_buffer(),
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    self.device.vk_device.cmd_bind_descriptor_sets(
+                        self.device.graphics_command_buffer(),
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.shadow_debug_pso_layout,
+                        0u32,
+                        &[shadow_debug_set],
+                        &[],
+                    );
+                    self.device.vk_device.cmd_draw(
+                        self.device.graphics_command_buffer(),
+                        6u32,
+                        1u32,
+                        0u32,
+                        0u32,
+                    );
+                };
+            }
+        });
+        self.run_custom_passes_after(self.combine);
+    }
+
+    /// Draws the scene again from each viewport's camera, over the main camera inside the viewport's rectangle.
+    /// Render texture viewports are then copied out of the backbuffer, so must be drawn before the main camera.
+    fn draw_viewports(
+        &mut self,
+        handles: &[ViewportHandle],
+        draw_commands: &[DrawCommand],
+        indirect_draws: &[vk::DrawIndexedIndirectCommand],
+        object_count: usize,
+        resource_index: usize,
+    ) -> Result<()> {
+        if handles.is_empty() {
+            return Ok(());
+        }
+
+        let size = self.device.size();
+        let jitter = self.taa_jitter();
+        let shadow_map = self.list.get_physical_resource("scene_shadow");
+        for &handle in handles.iter() {
+            let viewport = self.viewports.get_mut(handle).unwrap();
+            if let Some(target) = viewport.target {
+                viewport.rect = target.backbuffer_rect(size.width, size.height);
+            }
+            let target = viewport.target;
+            viewport.camera_uniform.update_light(&self.sun);
+            viewport.camera_uniform.update_fog(&self.fog);
+            viewport.camera_uniform.point_light_count = self.camera_uniform.point_light_count;
+            viewport.camera_uniform.area_light_count = self.camera_uniform.area_light_count;
+            viewport.camera_uniform.time = self.camera_uniform.time;
+            viewport.camera_uniform.point_shadow_far_plane =
+                self.camera_uniform.point_shadow_far_plane;
+
+            // Squeezed into the viewport, so fullscreen passes reconstruct positions the same way
+            let rect = viewport.rect;
+            let mut camera_uniform = viewport.camera_uniform;
+            camera_uniform.apply_viewport(
+                Vector2::new(
+                    2.0 * (rect.x as f32 + rect.width as f32 * 0.5) / size.width as f32 - 1.0,
+                    1.0 - 2.0 * (rect.y as f32 + rect.height as f32 * 0.5) / size.height as f32,
+                ),
+                Vector2::new(
+                    rect.width as f32 / size.width as f32,
+                    rect.height as f32 / size.height as f32,
+                ),
+            );
+            viewport.camera_uniform.prev_proj_view = camera_uniform.proj_view().into();
+            // Render textures skip TAA, which would blend them with the main camera's history
+            if self.taa_enabled && target.is_none() {
+                camera_uniform.apply_jitter(jitter);
+            }
+
+            self.device
+                .resource_manager
+                .get_buffer(viewport.camera_buffer[resource_index])
+                .unwrap()
+                .view()
+                .mapped_slice()?
+                .copy_from_slice(&[camera_uniform]);
+            self.device
+                .resource_manager
+                .get_buffer(viewport.draw_indirect_buffer[resource_index])
+                .unwrap()
+                .view_custom(0, indirect_draws.len())?
+                .mapped_slice()?
+                .copy_from_slice(indirect_draws);
+
+            let buffers = GlobalSetBuffers {
+                camera: viewport.camera_buffer[resource_index],
+                light: self.light_buffer[resource_index],
+                transform: self.transform_buffer[resource_index],
+                material: self.material_buffer[resource_index],
+                instance: self.instance_buffer[resource_index],
+                cull_object: self.cull_object_buffer[resource_index],
+                draw_indirect: viewport.draw_indirect_buffer[resource_index],
+                visible_instance: viewport.visible_instance_buffer[resource_index],
+                light_clusters: viewport.light_cluster_buffer[resource_index],
+                area_light: self.area_light_buffer[resource_index],
+            };
+            let (global_set, _) = build_global_descriptor_set(
+                &self.device.resource_manager,
+                &mut self.descriptor_layout_cache,
+                &mut self.frame_descriptor_allocator[resource_index],
+                &buffers,
+                shadow_map,
+                self.device.shadow_sampler(),
+            )?;
+
+            self.list.set_view_rect(Some(rect.into()));
+            self.cull_objects(object_count, global_set);
+            self.cull_lights(global_set);
+            if let Some(target) = target {
+                self.list.set_pass_skipped(self.taa_resolve, true);
+                self.list.set_pass_skipped(self.taa_copy, true);
+                self.draw_scene(
+                    draw_commands,
+                    global_set,
+                    buffers.draw_indirect,
+                    resource_index,
+                );
+                self.list.set_pass_skipped(self.taa_resolve, false);
+                self.list.set_pass_skipped(self.taa_copy, false);
+                self.copy_to_render_texture(rect, target)?;
+            } else {
+                self.draw_scene(
+                    draw_commands,
+                    global_set,
+                    buffers.draw_indirect,
+                    resource_index,
+                );
+            }
+        }
+        self.list.set_view_rect(None);
+
+        Ok(())
+    }
+
+    /// Blits a render texture viewport from the backbuffer into its texture, leaving the texture ready to be sampled
+    /// and the backbuffer ready to be drawn over by the main camera.
+    fn copy_to_render_texture(&self, rect: Rect, target: RenderTextureTarget) -> Result<()> {
+        let cmd = self.device.graphics_command_buffer();
+        ImageBarrierBuilder::default()
+            .add_image_barrier(
+                ImageBarrier::new(AttachmentHandle::SwapchainImage)
+                    .old_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+                    .new_usage(vk::ImageUsageFlags::TRANSFER_SRC),
+            )
+            .add_image_barrier(
+                ImageBarrier::new(AttachmentHandle::Image(target.image))
+                    .old_usage(vk::ImageUsageFlags::SAMPLED)
+                    .new_usage(vk::ImageUsageFlags::TRANSFER_DST),
+            )
+            .build(&self.device, &cmd)?;
+
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0u32,
+            base_array_layer: 0u32,
+            layer_count: 1u32,
+        };
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(subresource)
+            .src_offsets([
+                vk::Offset3D {
+                    x: rect.x,
+                    y: rect.y,
+                    z: 0,
+                },
+                vk::Offset3D {
+                    x: rect.x + rect.width as i32,
+                    y: rect.y + rect.height as i32,
+                    z: 1,
+                },
+            ])
+            .dst_subresource(subresource)
+            .dst_offsets([
+                vk::Offset3D { x: 0, y: 0, z: 0 },
+                vk::Offset3D {
+                    x: target.width as i32,
+                    y: target.height as i32,
+                    z: 1,
+                },
+            ]);
+        let image = self
+            .device
+            .resource_manager
+            .get_image(target.image)
+            .unwrap()
+            .image();
+        unsafe {
+            self.device.vk_device.cmd_blit_image(
+                cmd,
+                self.device.get_present_image(),
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[*blit],
+                vk::Filter::LINEAR,
+            );
+        }
+
+        ImageBarrierBuilder::default()
+            .add_image_barrier(
+                ImageBarrier::new(AttachmentHandle::SwapchainImage)
+                    .old_usage(vk::ImageUsageFlags::TRANSFER_SRC)
+                    .new_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT),
+            )
+            .add_image_barrier(
+                ImageBarrier::new(AttachmentHandle::Image(target.image))
+                    .old_usage(vk::ImageUsageFlags::TRANSFER_DST)
+                    .new_usage(vk::ImageUsageFlags::SAMPLED),
+            )
+            .build(&self.device, &cmd)
+    }
+
+    /// Dispatches the culling compute shader, which fills in the indirect draws' instance counts and the
+    /// visible instance list used by the gbuffer pass.
+    fn cull_objects(&self, object_count: usize, global_set: vk::DescriptorSet) {
+        let cmd = self.device.graphics_command_buffer();
+        let push_constants = CullPushConstants {
+            object_count: object_count as u32,
+            culling_enabled: self.enable_gpu_culling as u32,
+        };
+
+        self.device
+            .cmd_begin_label(cmd, [0.6, 0.6, 0.6, 1.0], "frustum_culling");
+        unsafe {
+            self.device.vk_device.cmd_bind_pipeline(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_manager.get_compute_pipeline(self.cull_pso),
+            );
+            self.device.vk_device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.cull_pso_layout,
+                0u32,
+                &[self.device.bindless_descriptor_set(), global_set],
+                &[],
+            );
+            self.device.vk_device.cmd_push_constants(
+                cmd,
+                self.cull_pso_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                0u32,
+                bytemuck::cast_slice(&[push_constants]),
+            );
+            self.device
+                .vk_device
+                .cmd_dispatch(cmd, (object_count as u32 + 63) / 64, 1, 1);
+
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(PipelineStageFlags2::COMPUTE_SHADER)
+                .src_access_mask(AccessFlags2::SHADER_STORAGE_WRITE)
+                .dst_stage_mask(
+                    PipelineStageFlags2::DRAW_INDIRECT | PipelineStageFlags2::VERTEX_SHADER,
+                )
+                .dst_access_mask(
+                    AccessFlags2::INDIRECT_COMMAND_READ | AccessFlags2::SHADER_STORAGE_READ,
+                );
+            let memory_barriers = [*memory_barrier];
+            let dependency_info = vk::DependencyInfo::builder().memory_barriers(&memory_barriers);
+            self.device
+                .vk_device
+                .cmd_pipeline_barrier2(cmd, &dependency_info);
+        }
+        self.device.cmd_end_label(cmd);
+    }
+
+    /// Dispatches the light culling compute shader, which lists the lights reaching each cluster of
+    /// the camera's frustum for the lighting passes.
+    fn cull_lights(&self, global_set: vk::DescriptorSet) {
+        let cmd = self.device.graphics_command_buffer();
+
+        self.device
+            .cmd_begin_label(cmd, [0.6, 0.6, 0.6, 1.0], "light_culling");
+        unsafe {
+            self.device.vk_device.cmd_bind_pipeline(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_manager
+                    .get_compute_pipeline(self.light_cull_pso),
+            );
+            self.device.vk_device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.light_cull_pso_layout,
+                0u32,
+                &[self.device.bindless_descriptor_set(), global_set],
+                &[],
+            );
+            self.device
+                .vk_device
+                .cmd_dispatch(cmd, (CLUSTER_COUNT as u32 + 63) / 64, 1, 1);
+
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(PipelineStageFlags2::COMPUTE_SHADER)
+                .src_access_mask(AccessFlags2::SHADER_STORAGE_WRITE)
+                .dst_stage_mask(PipelineStageFlags2::FRAGMENT_SHADER)
+                .dst_access_mask(AccessFlags2::SHADER_STORAGE_READ);
+            let memory_barriers = [*memory_barrier];
+            let dependency_info = vk::DependencyInfo::builder().memory_barriers(&memory_barriers);
+            self.device
+                .vk_device
+                .cmd_pipeline_barrier2(cmd, &dependency_info);
+        }
+        self.device.cmd_end_label(cmd);
+    }
+
+    /// Draws ambient occlusion from the gbuffer normals and depth, then blurs it with the bloom blur.
+    /// The passes still run while SSAO is disabled, leaving the occlusion images cleared to white.
+    fn draw_ssao(&mut self, global_set: vk::DescriptorSet, resource_index: usize) {
+        let normal = self.list.get_physical_resource("normal");
+        let depth = self.list.get_physical_resource("depth");
+        let ssao_raw = self.list.get_physical_resource("ssao_raw");
+        let ssao_blur = self.list.get_physical_resource("ssao_blur");
+
+        let (ssao_set, _) = JBDescriptorBuilder::new(
+            &self.device.resource_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.frame_descriptor_allocator[resource_index],
+        )
+        .bind_image(ImageDescriptorInfo {
+            binding: 0,
+            image: normal,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_image(ImageDescriptorInfo {
+            binding: 1,
+            image: depth,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .build()
+        .unwrap();
+        let (horizontal_set, _) = JBDescriptorBuilder::new(
+            &self.device.resource_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.frame_descriptor_allocator[resource_index],
+        )
+        .bind_image(ImageDescriptorInfo {
+            binding: 0,
+            image: ssao_raw,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        })
+        .build()
+        .unwrap();
+        let (vertical_set, _) = JBDescriptorBuilder::new(
+            &self.device.resource_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.frame_descriptor_allocator[resource_index],
+        )
+        .bind_image(ImageDescriptorInfo {
+            binding: 0,
+            image: ssao_blur,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        })
+        .build()
+        .unwrap();
+
+        let push_constants = SsaoPushConstants {
+            radius: self.ssao_radius,
+            bias: self.ssao_bias,
+            padding: [0.0f32; 2],
+        };
+        self.list.run_pass(self.ssao, |_, cmd| {
+            if !self.ssao_enabled {
+                return;
+            }
+
+            let pipeline = self.pipeline_manager.get_pipeline(self.ssao_pass.pso);
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.ssao_pass.pso_layout,
+                    0u32,
+                    &[self.device.bindless_descriptor_set(), global_set, ssao_set],
+                    &[],
+                );
+                self.device.vk_device.cmd_push_constants(
+                    cmd,
+                    self.ssao_pass.pso_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0u32,
+                    bytemuck::cast_slice(&[push_constants]),
+                );
+                self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+            }
+        });
+        self.run_custom_passes_after(self.ssao);
+
+        for (pass, set, horizontal) in [
+            (self.ssao_blur_horizontal, horizontal_set, true),
+            (self.ssao_blur_vertical, vertical_set, false),
+        ] {
+            self.list.run_pass(pass, |_, cmd| {
+                if !self.ssao_enabled {
+                    return;
+                }
+
+                let pipeline = self.pipeline_manager.get_pipeline(self.ssao_pass.blur_pso);
+                unsafe {
+                    self.device.vk_device.cmd_bind_pipeline(
+                        cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    self.device.vk_device.cmd_bind_descriptor_sets(
+                        cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.bloom_pass.bloom_pso_layout,
+                        0u32,
+                        &[set],
+                        &[],
+                    );
+                    self.device.vk_device.cmd_push_constants(
+                        cmd,
+                        self.bloom_pass.bloom_pso_layout,
+                        vk::ShaderStageFlags::FRAGMENT,
+                        0u32,
+                        bytemuck::cast_slice(&[BlurPushConstants {
+                            horizontal: horizontal as i32,
+                            texel_step: 1.0,
+                        }]),
+                    );
+                    self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+                }
+            });
+            self.run_custom_passes_after(pass);
+        }
+    }
+
+    fn draw_taa(&mut self, global_set: vk::DescriptorSet, resource_index: usize) {
+        let forward = self.list.get_physical_resource("forward");
+        let velocity = self.list.get_physical_resource("velocity");
+        let depth = self.list.get_physical_resource("depth");
+        let history = self.list.get_physical_resource("taa_history");
+        let resolved = self.list.get_physical_resource("taa_resolved");
+
+        let (resolve_set, _) = JBDescriptorBuilder::new(
+            &self.device.resource_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.frame_descriptor_allocator[resource_index],
+        )
+        .bind_image(ImageDescriptorInfo {
+            binding: 0,
+            image: forward,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_image(ImageDescriptorInfo {
+            binding: 1,
+            image: velocity,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_image(ImageDescriptorInfo {
+            binding: 2,
+            image: depth,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_image(ImageDescriptorInfo {
+            binding: 3,
+            image: history,
+            sampler: self.device.linear_clamp_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .build()
+        .unwrap();
+        let (copy_set, _) = JBDescriptorBuilder::new(
+            &self.device.resource_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.frame_descriptor_allocator[resource_index],
+        )
+        .bind_image(ImageDescriptorInfo {
+            binding: 0,
+            image: resolved,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .build()
+        .unwrap();
+
+        let push_constants = TaaPushConstants {
+            history_valid: self.taa_history_valid as i32,
+            padding: [0; 3],
+        };
+        self.list.run_pass(self.taa_resolve, |_, cmd| {
+            if !self.taa_enabled {
+                return;
+            }
+
+            let pipeline = self
+                .pipeline_manager
+                .get_pipeline(self.taa_pass.resolve_pso);
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.taa_pass.resolve_pso_layout,
+                    0u32,
+                    &[
+                        self.device.bindless_descriptor_set(),
+                        global_set,
+                        resolve_set,
+                    ],
+                    &[],
+                );
+                self.device.vk_device.cmd_push_constants(
+                    cmd,
+                    self.taa_pass.resolve_pso_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0u32,
+                    bytemuck::cast_slice(&[push_constants]),
+                );
+                self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+            }
+        });
+        self.run_custom_passes_after(self.taa_resolve);
+
+        // Leaves the scene untouched while disabled. The history is cleared, and marked invalid.
+        self.list.run_pass(self.taa_copy, |_, cmd| {
+            if !self.taa_enabled {
+                return;
+            }
+
+            let pipeline = self.pipeline_manager.get_pipeline(self.taa_pass.copy_pso);
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.taa_pass.copy_pso_layout,
+                    0u32,
+                    &[copy_set],
+                    &[],
+                );
+                self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+            }
+        });
+        self.run_custom_passes_after(self.taa_copy);
+
+        self.taa_history_valid = self.taa_enabled;
+    }
+
+    fn draw_outline(&mut self, outline_draws: &[OutlineDraw], resource_index: usize) {
+        self.list.run_pass(self.outline_mask, |_, cmd| {
+            if outline_draws.is_empty() {
+                return;
+            }
+
+            let pipeline = self
+                .pipeline_manager
+                .get_pipeline(self.outline_pass.mask_pso);
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.outline_pass.mask_pso_layout,
                     0u32,
+                    &[
+                        self.device.bindless_descriptor_set(),
+                        self.descriptor_set[resource_index],
+                    ],
+                    &[],
                 );
-            };
-        });
-        let deferred_lighting_end = self.device.write_timestamp(
-            self.device.graphics_command_buffer(),
-            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-        );
-
-        self.list.run_pass(self.forward, |list, cmd| {
-            // Draw particles
-            {
-                let pipeline = self.pipeline_manager.get_pipeline(self.particle_pipeline.0);
-
+            }
+            // Passes since the gbuffer may have bound their own buffers
+            self.mesh_pool.bind(cmd);
+            for draw in outline_draws.iter() {
                 unsafe {
-                    self.device.vk_device.cmd_bind_pipeline(
-                        self.device.graphics_command_buffer(),
-                        vk::PipelineBindPoint::GRAPHICS,
-                        pipeline,
-                    );
-                    self.device.vk_device.cmd_bind_descriptor_sets(
-                        self.device.graphics_command_buffer(),
-                        vk::PipelineBindPoint::GRAPHICS,
-                        self.particle_pipeline.1,
-                        0u32,
-                        &[
-                            self.device.bindless_descriptor_set(),
-                            self.descriptor_set[resource_index],
-                            self.particle_set[resource_index],
-                        ],
-                        &[],
+                    self.device.vk_device.cmd_draw_indexed(
+                        cmd,
+                        draw.index_count as u32,
+                        1u32,
+                        draw.index_offset as u32,
+                        draw.vertex_offset as i32,
+                        draw.instance as u32,
                     );
-                };
-
-                for draw in particle_draw_commands.into_iter() {
-                    //// Draw commands
-                    unsafe {
-                        self.device.vk_device.cmd_draw_indexed(
-                            self.device.graphics_command_buffer(),
-                            draw.index_count as u32,
-                            draw.instance_count as u32,
-                            draw.index_offset as u32,
-                            draw.vertex_offset as i32,
-                            draw.instance_offset as u32,
-                        );
-                    };
                 }
             }
         });
-        let forward_pass_end = self.device.write_timestamp(
-            self.device.graphics_command_buffer(),
-            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-        );
-
-        let mut horizontal = true;
+        self.run_custom_passes_after(self.outline_mask);
 
-        let bright = self.list.get_physical_resource("bright");
-        let horizontal_image = self.list.get_physical_resource("bloom_horizontal");
-        let vertical_image = self.list.get_physical_resource("bloom_vertical");
-
-        let (first_bloom_set, _) = JBDescriptorBuilder::new(
+        let mask = self.list.get_physical_resource("outline_mask");
+        let (composite_set, _) = JBDescriptorBuilder::new(
             &self.device.resource_manager,
             &mut self.descriptor_layout_cache,
             &mut self.frame_descriptor_allocator[resource_index],
         )
         .bind_image(ImageDescriptorInfo {
             binding: 0,
-            image: bright,
+            image: mask,
             sampler: self.device.ui_sampler(),
             desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
         })
         .build()
         .unwrap();
-        let (bloom_set, _) = JBDescriptorBuilder::new(
+
+        let push_constants = OutlinePushConstants {
+            colour: self.outline_colour.to_linear().into(),
+            width: self.outline_width as i32,
+            padding: [0; 3],
+        };
+        self.list.run_pass(self.outline, |_, cmd| {
+            if outline_draws.is_empty() {
+                return;
+            }
+
+            let pipeline = self
+                .pipeline_manager
+                .get_pipeline(self.outline_pass.composite_pso);
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.outline_pass.composite_pso_layout,
+                    0u32,
+                    &[composite_set],
+                    &[],
+                );
+                self.device.vk_device.cmd_push_constants(
+                    cmd,
+                    self.outline_pass.composite_pso_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0u32,
+                    bytemuck::cast_slice(&[push_constants]),
+                );
+                self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+            }
+        });
+        self.run_custom_passes_after(self.outline);
+    }
+
+    fn draw_depth_of_field(&mut self, resource_index: usize) {
+        let forward = self.list.get_physical_resource("forward");
+        let depth = self.list.get_physical_resource("depth");
+
+        let (prepare_set, _) = JBDescriptorBuilder::new(
             &self.device.resource_manager,
             &mut self.descriptor_layout_cache,
             &mut self.frame_descriptor_allocator[resource_index],
         )
         .bind_image(ImageDescriptorInfo {
             binding: 0,
-            image: vertical_image,
+            image: forward,
             sampler: self.device.ui_sampler(),
             desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
         })
-        .build()
-        .unwrap();
-        let (bloom_set_two, _) = JBDescriptorBuilder::new(
-            &self.device.resource_manager,
-            &mut self.descriptor_layout_cache,
-            &mut self.frame_descriptor_allocator[resource_index],
-        )
         .bind_image(ImageDescriptorInfo {
-            binding: 0,
-            image: horizontal_image,
+            binding: 1,
+            image: depth,
             sampler: self.device.ui_sampler(),
             desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
         })
         .build()
         .unwrap();
 
-        for i in 0..10 {
-            let pass = {
-                if i == 0 {
-                    self.bloom_initial
-                } else if i == 9 {
-                    self.bloom_final
-                } else if horizontal {
-                    self.bloom_horizontal
-                } else {
-                    self.bloom_vertical
-                }
-            };
+        let push_constants = DofPushConstants {
+            focus_distance: self.dof.focus_distance,
+            focus_range: self.dof.focus_range.max(0.001),
+            padding: [0.0f32; 2],
+        };
+        self.list.run_pass(self.dof_prepare, |_, cmd| {
+            if !self.dof.enabled {
+                return;
+            }
 
-            let set = {
-                if i == 0 {
-                    first_bloom_set
-                } else if horizontal {
-                    bloom_set
-                } else {
-                    bloom_set_two
+            let pipeline = self
+                .pipeline_manager
+                .get_pipeline(self.dof_pass.prepare_pso);
+            unsafe {
+                self.device.vk_device.cmd_bind_pipeline(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.dof_pass.prepare_pso_layout,
+                    0u32,
+                    &[
+                        self.device.bindless_descriptor_set(),
+                        self.descriptor_set[resource_index],
+                        prepare_set,
+                    ],
+                    &[],
+                );
+                self.device.vk_device.cmd_push_constants(
+                    cmd,
+                    self.dof_pass.prepare_pso_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
+                    0u32,
+                    bytemuck::cast_slice(&[push_constants]),
+                );
+                self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+            }
+        });
+        self.run_custom_passes_after(self.dof_prepare);
+
+        // The blur kernel reaches four taps either side, so spacing the taps out widens it to the
+        // requested radius. Linear filtering fills in the texels between taps.
+        let texel_step = (self.dof.max_blur / 4.0).max(0.0);
+        let blurs = [
+            (self.dof_far_blur_horizontal, "dof_far", true),
+            (self.dof_far_blur_vertical, "dof_far_blur", false),
+            (self.dof_near_blur_horizontal, "dof_near", true),
+            (self.dof_near_blur_vertical, "dof_near_blur", false),
+        ];
+        for (pass, input, horizontal) in blurs {
+            let image = self.list.get_physical_resource(input);
+            let (set, _) = JBDescriptorBuilder::new(
+                &self.device.resource_manager,
+                &mut self.descriptor_layout_cache,
+                &mut self.frame_descriptor_allocator[resource_index],
+            )
+            .bind_image(ImageDescriptorInfo {
+                binding: 0,
+                image,
+                sampler: self.device.linear_clamp_sampler(),
+                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            })
+            .build()
+            .unwrap();
+
+            self.list.run_pass(pass, |_, cmd| {
+                if !self.dof.enabled {
+                    return;
                 }
-            };
 
-            self.list.run_pass(pass, |list, cmd| {
                 let pipeline = self
                     .pipeline_manager
                     .get_pipeline(self.bloom_pass.bloom_pso);
-
                 unsafe {
                     self.device.vk_device.cmd_bind_pipeline(
-                        self.device.graphics_command_buffer(),
+                        cmd,
                         vk::PipelineBindPoint::GRAPHICS,
                         pipeline,
                     );
                     self.device.vk_device.cmd_bind_descriptor_sets(
-                        self.device.graphics_command_buffer(),
+                        cmd,
                         vk::PipelineBindPoint::GRAPHICS,
                         self.bloom_pass.bloom_pso_layout,
                         0u32,
                         &[set],
                         &[],
                     );
-                };
-
-                // Draw commands
-
-                unsafe {
                     self.device.vk_device.cmd_push_constants(
-                        self.device.graphics_command_buffer(),
+                        cmd,
                         self.bloom_pass.bloom_pso_layout,
                         vk::ShaderStageFlags::FRAGMENT,
                         0u32,
-                        bytemuck::cast_slice(&[horizontal as i32]),
-                    );
-                    self.device.vk_device.cmd_draw(
-                        self.device.graphics_command_buffer(),
-                        6u32,
-                        1u32,
-                        0u32,
-                        0u32,
+                        bytemuck::cast_slice(&[BlurPushConstants {
+                            horizontal: horizontal as i32,
+                            texel_step,
+                        }]),
                     );
-                };
+                    self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
+                }
             });
-            horizontal = !horizontal;
+            self.run_custom_passes_after(pass);
         }
-        // Bloom pass
-        let bloom_pass_end = self.device.write_timestamp(
-            self.device.graphics_command_buffer(),
-            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-        );
-        self.list.run_pass(self.combine, |list, cmd| {
-            let forward = list.get_physical_resource("forward");
-            let bloom_result = list.get_physical_resource("bloom_vertical");
 
-            let (combine_set, _) = JBDescriptorBuilder::new(
-                &self.device.resource_manager,
-                &mut self.descriptor_layout_cache,
-                &mut self.frame_descriptor_allocator[resource_index],
-            )
-            .bind_image(ImageDescriptorInfo {
-                binding: 0,
-                image: forward,
-                sampler: self.device.ui_sampler(),
-                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-            })
-            .bind_image(ImageDescriptorInfo {
-                binding: 1,
-                image: bloom_result,
-                sampler: self.device.ui_sampler(),
-                desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-            })
-            .build()
-            .unwrap();
+        let far_blurred = self.list.get_physical_resource("dof_far_blurred");
+        let near_blurred = self.list.get_physical_resource("dof_near_blurred");
+        let (composite_set, _) = JBDescriptorBuilder::new(
+            &self.device.resource_manager,
+            &mut self.descriptor_layout_cache,
+            &mut self.frame_descriptor_allocator[resource_index],
+        )
+        .bind_image(ImageDescriptorInfo {
+            binding: 0,
+            image: depth,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_image(ImageDescriptorInfo {
+            binding: 1,
+            image: far_blurred,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_image(ImageDescriptorInfo {
+            binding: 2,
+            image: near_blurred,
+            sampler: self.device.ui_sampler(),
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .build()
+        .unwrap();
 
-            let pipeline = self.pipeline_manager.get_pipeline(self.combine_pso);
+        // Leaves the sharp scene untouched while disabled
+        self.list.run_pass(self.dof_composite, |_, cmd| {
+            if !self.dof.enabled {
+                return;
+            }
 
+            let pipeline = self
+                .pipeline_manager
+                .get_pipeline(self.dof_pass.composite_pso);
             unsafe {
                 self.device.vk_device.cmd_bind_pipeline(
-                    self.device.graphics_command_buffer(),
+                    cmd,
                     vk::PipelineBindPoint::GRAPHICS,
                     pipeline,
                 );
                 self.device.vk_device.cmd_bind_descriptor_sets(
-                    self.device.graphics_command_buffer(),
+                    cmd,
                     vk::PipelineBindPoint::GRAPHICS,
-                    self.combine_pso_layout,
+                    self.dof_pass.composite_pso_layout,
                     0u32,
-                    &[combine_set],
+                    &[
+                        self.device.bindless_descriptor_set(),
+                        self.descriptor_set[resource_index],
+                        composite_set,
+                    ],
                     &[],
                 );
-            };
-
-            // Draw commands
-
-            unsafe {
-                self.device.vk_device.cmd_draw(
-                    self.device.graphics_command_buffer(),
-                    6u32,
-                    1u32,
-                    0u32,
+                self.device.vk_device.cmd_push_constants(
+                    cmd,
+                    self.dof_pass.composite_pso_layout,
+                    vk::ShaderStageFlags::FRAGMENT,
                     0u32,
+                    bytemuck::cast_slice(&[push_constants]),
                 );
-            };
-        });
-        let combine_pass_end = self.device.write_timestamp(
-            self.device.graphics_command_buffer(),
-            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-        );
-        self.list.run_pass(self.ui, |list, cmd| {
-            if self.draw_debug_ui {
-                let pipeline = self.pipeline_manager.get_pipeline(self.world_debug_pso);
-
-                unsafe {
-                    self.device.vk_device.cmd_bind_pipeline(
-                        self.device.graphics_command_buffer(),
-                        vk::PipelineBindPoint::GRAPHICS,
-                        pipeline,
-                    );
-                    self.device.vk_device.cmd_bind_descriptor_sets(
-                        self.device.graphics_command_buffer(),
-                        vk::PipelineBindPoint::GRAPHICS,
-                        self.world_debug_pso_layout,
-                        0u32,
-                        &[
-                            self.device.bindless_descriptor_set(),
-                            self.world_debug_desc_set[resource_index],
-                        ],
-                        &[],
-                    );
-                };
-
-                unsafe {
-                    self.device.vk_device.cmd_draw(
-                        self.device.graphics_command_buffer(),
-                        6u32 * debug_ui_draw_amount as u32,
-                        1u32,
-                        0u32,
-                        0u32,
-                    );
-                };
+                self.device.vk_device.cmd_draw(cmd, 6u32, 1u32, 0u32, 0u32);
             }
+        });
+        self.run_custom_passes_after(self.dof_composite);
+    }
 
-            let pipeline = self.pipeline_manager.get_pipeline(self.ui_pass.pso);
+    /// Draws this frame's debug lines over the scene, hidden behind its geometry.
+    fn draw_debug_lines(&mut self, global_set: vk::DescriptorSet) {
+        self.list.run_pass(self.debug_lines, |_, cmd| {
+            if self.debug_line_vertex_count == 0 {
+                return;
+            }
 
+            let pipeline = self.pipeline_manager.get_pipeline(self.debug_line_pso);
             unsafe {
                 self.device.vk_device.cmd_bind_pipeline(
-                    self.device.graphics_command_buffer(),
+                    cmd,
                     vk::PipelineBindPoint::GRAPHICS,
                     pipeline,
                 );
                 self.device.vk_device.cmd_bind_descriptor_sets(
-                    self.device.graphics_command_buffer(),
+                    cmd,
                     vk::PipelineBindPoint::GRAPHICS,
-                    self.ui_pass.pso_layout,
+                    self.debug_line_pso_layout,
                     0u32,
                     &[
                         self.device.bindless_descriptor_set(),
-                        self.ui_pass.desc_set[resource_index],
+                        global_set,
+                        self.debug_line_desc_set,
                     ],
                     &[],
                 );
-            };
-
-            let index_buffer = self
-                .device
-                .resource_manager
-                .get_buffer(self.ui_pass.index_buffer[resource_index])
-                .unwrap();
-
-            unsafe {
-                self.device.vk_device.cmd_bind_index_buffer(
-                    self.device.graphics_command_buffer(),
-                    index_buffer.buffer(),
-                    0u64,
-                    vk::IndexType::UINT32,
+                self.device.vk_device.cmd_draw(
+                    cmd,
+                    self.debug_line_vertex_count as u32,
+                    1u32,
+                    0u32,
+                    0u32,
                 );
             }
+        });
+        self.run_custom_passes_after(self.debug_lines);
+    }
 
-            for draw in ui_draw_calls.iter() {
-                let y = self.device.size().height as i32;
-                let height = self.device.size().height as i32;
+    /// Returns the shadow casting point lights closest to the camera.
+    fn get_point_shadow_casters(&self) -> Vec<LightHandle> {
+        let camera_position = Point3::new(
+            self.camera_uniform.position[0],
+            self.camera_uniform.position[1],
+            self.camera_uniform.position[2],
+        );
 
-                let max = [
-                    draw.scissor.1[0] - draw.scissor.0[0],
-                    draw.scissor.1[1] - draw.scissor.0[1],
-                ];
+        let mut casters: Vec<(LightHandle, f32)> = self
+            .stored_lights
+            .iter()
+            .filter(|(_, light)| light.casts_shadows)
+            .map(|(handle, light)| (handle, light.position.distance2(camera_position)))
+            .collect();
+        casters.sort_by(|a, b| a.1.total_cmp(&b.1));
+        casters.truncate(MAX_SHADOWED_POINT_LIGHTS);
 
-                let scissor = vk::Rect2D::builder()
-                    .offset(vk::Offset2D {
-                        x: draw.scissor.0[0] as i32,
-                        y: draw.scissor.0[1] as i32, //y: height - (draw.scissor.0[1] as i32 + max[1] as i32),
-                    })
-                    .extent(vk::Extent2D {
-                        width: max[0] as u32,
-                        height: max[1] as u32,
-                    });
+        casters.into_iter().map(|(handle, _)| handle).collect()
+    }
 
-                unsafe {
-                    self.device
-                        .vk_device
-                        .cmd_set_scissor(cmd, 0u32, &[*scissor]);
+    /// Renders the six faces of each of the given point shadow maps from the light they belong to,
+    /// storing the distance to the light divided by the far plane.
+    fn draw_point_light_shadows(
+        &self,
+        shadow_maps: &[usize],
+        draw_commands: &[DrawCommand],
+        resource_index: usize,
+    ) -> Result<()> {
+        let cmd = self.device.graphics_command_buffer();
+        let pipeline = self.pipeline_manager.get_pipeline(self.point_shadow_pso);
+        let projection = cgmath::perspective(Deg(90.0f32), 1.0f32, 0.1f32, POINT_SHADOW_FAR_PLANE);
+        // Cube faces in +X, -X, +Y, -Y, +Z, -Z order, with up vectors matching the cube map layout
+        let faces = [
+            (Vector3::unit_x(), -Vector3::unit_y()),
+            (-Vector3::unit_x(), -Vector3::unit_y()),
+            (Vector3::unit_y(), Vector3::unit_z()),
+            (-Vector3::unit_y(), -Vector3::unit_z()),
+            (Vector3::unit_z(), -Vector3::unit_y()),
+            (-Vector3::unit_z(), -Vector3::unit_y()),
+        ];
+
+        for &index in shadow_maps.iter() {
+            let shadow_map = &self.point_shadow_maps[index];
+            let image =
+                AttachmentHandle::Image(self.render_targets.get(shadow_map.target).unwrap());
+            let render_area = vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: vk::Extent2D {
+                    width: shadow_map.resolution,
+                    height: shadow_map.resolution,
+                },
+            };
+            let viewport = vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: shadow_map.resolution as f32,
+                height: shadow_map.resolution as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            };
+
+            ImageBarrierBuilder::default()
+                .add_image_barrier(
+                    ImageBarrier::new(image)
+                        .new_usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+                        .image_layers(6),
+                )
+                .build(&self.device, &cmd)?;
+
+            self.device
+                .cmd_begin_label(cmd, [0.4, 0.4, 0.4, 1.0], "point_shadow");
+            for (face, (direction, up)) in faces.iter().enumerate() {
+                let view = Matrix4::look_to_rh(shadow_map.light_position, *direction, *up);
+                let push_constants = PointShadowPushConstants {
+                    view_proj: (projection * view).into(),
+                    light_position: shadow_map
+                        .light_position
+                        .to_vec()
+                        .extend(POINT_SHADOW_FAR_PLANE)
+                        .into(),
                 };
 
-                //render_pass.set_scissor(draw.scissor.0, max);
-                // Draw commands
+                let depth_attachment = vk::RenderingAttachmentInfo {
+                    image_view: self
+                        .render_targets
+                        .get_layer_view(shadow_map.target, face)
+                        .unwrap(),
+                    image_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
+                    load_op: vk::AttachmentLoadOp::CLEAR,
+                    store_op: vk::AttachmentStoreOp::STORE,
+                    clear_value: vk::ClearValue {
+                        depth_stencil: ClearDepthStencilValue {
+                            depth: 1.0,
+                            stencil: 0,
+                        },
+                    },
+                    ..Default::default()
+                };
+                let render_info = vk::RenderingInfo::builder()
+                    .render_area(render_area)
+                    .layer_count(1u32)
+                    .depth_attachment(&depth_attachment);
+
                 unsafe {
-                    self.device.vk_device.cmd_draw_indexed(
-                        self.device.graphics_command_buffer(),
-                        draw.amount as u32,
-                        1u32,
-                        draw.index_offset as u32,
-                        draw.vertex_offset as i32,
+                    self.device.vk_device.cmd_begin_rendering(cmd, &render_info);
+                    self.device
+                        .vk_device
+                        .cmd_set_viewport(cmd, 0u32, &[viewport]);
+                    self.device
+                        .vk_device
+                        .cmd_set_scissor(cmd, 0u32, &[render_area]);
+                    self.device.vk_device.cmd_bind_pipeline(
+                        cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    self.device.vk_device.cmd_bind_descriptor_sets(
+                        cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.point_shadow_pso_layout,
                         0u32,
+                        &[
+                            self.device.bindless_descriptor_set(),
+                            self.descriptor_set[resource_index],
+                        ],
+                        &[],
                     );
-                };
-            }
-        });
+                    self.device.vk_device.cmd_push_constants(
+                        cmd,
+                        self.point_shadow_pso_layout,
+                        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                        0u32,
+                        bytemuck::cast_slice(&[push_constants]),
+                    );
+                }
 
-        let ui_pass_end = self.device.write_timestamp(
-            self.device.graphics_command_buffer(),
-            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-        );
+                Self::draw_objects_free(draw_commands, &self.device.vk_device, &cmd)?;
 
-        ImageBarrierBuilder::default()
-            .add_image_barrier(ImageBarrier {
-                image: AttachmentHandle::SwapchainImage,
-                src_stage_mask: PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-                src_access_mask: AccessFlags2::COLOR_ATTACHMENT_WRITE,
-                old_layout: ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-                new_layout: ImageLayout::PRESENT_SRC_KHR,
-                ..Default::default()
-            })
-            .build(&self.device, &self.device.graphics_command_buffer())?;
+                unsafe { self.device.vk_device.cmd_end_rendering(cmd) };
+            }
+            self.device.cmd_end_label(cmd);
+
+            ImageBarrierBuilder::default()
+                .add_image_barrier(
+                    ImageBarrier::new(image)
+                        .old_usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+                        .new_usage(vk::ImageUsageFlags::SAMPLED)
+                        .image_layers(6),
+                )
+                .build(&self.device, &cmd)?;
+        }
 
-        self.device.end_frame()?;
+        Ok(())
+    }
 
-        if let Some(time) = self
-            .device
-            .get_timestamp_result(shadow_pass_start, shadow_pass_end)
-        {
-            self.timestamps.shadow_pass = time;
-        }
-        if let Some(time) = self.device.get_timestamp_result(shadow_pass_end, gbuffer) {
-            self.timestamps.deferred_fill_pass = time;
-        }
-        if let Some(time) = self
-            .device
-            .get_timestamp_result(gbuffer, deferred_lighting_end)
-        {
-            self.timestamps.deferred_lighting_pass = time;
-        }
-        if let Some(time) = self
-            .device
-            .get_timestamp_result(deferred_lighting_end, forward_pass_end)
-        {
-            self.timestamps.forward_pass = time;
-        }
-        if let Some(time) = self
-            .device
-            .get_timestamp_result(forward_pass_end, bloom_pass_end)
-        {
-            self.timestamps.bloom_pass = time;
-        }
-        if let Some(time) = self
-            .device
-            .get_timestamp_result(bloom_pass_end, combine_pass_end)
-        {
-            self.timestamps.combine_pass = time;
-        }
-        if let Some(time) = self
-            .device
-            .get_timestamp_result(combine_pass_end, ui_pass_end)
-        {
-            self.timestamps.ui_pass = time;
-        }
-        if let Some(time) = self
-            .device
-            .get_timestamp_result(shadow_pass_start, ui_pass_end)
+    /// Draws the transparent draw commands, which come last, in order. Pipelines are only
+    /// switched when the cull mode changes.
+    fn draw_transparent_objects(
+        device: &ash::Device,
+        cmd: vk::CommandBuffer,
+        pso_layout: vk::PipelineLayout,
+        descriptor_sets: &[vk::DescriptorSet],
+        (pipeline, double_sided_pipeline): (vk::Pipeline, vk::Pipeline),
+        indirect_buffer: vk::Buffer,
+        draw_commands: &[DrawCommand],
+    ) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                pso_layout,
+                0u32,
+                descriptor_sets,
+                &[],
+            );
+        };
+
+        let mut bound_double_sided = None;
+        for (i, draw) in draw_commands
+            .iter()
+            .enumerate()
+            .filter(|(_, draw)| draw.transparent)
         {
-            self.timestamps.total = time;
-        }
+            if bound_double_sided != Some(draw.double_sided) {
+                let pipeline = if draw.double_sided {
+                    double_sided_pipeline
+                } else {
+                    pipeline
+                };
+                unsafe {
+                    device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                }
+                bound_double_sided = Some(draw.double_sided);
+            }
 
-        Ok(())
+            unsafe {
+                device.cmd_draw_indexed_indirect(
+                    cmd,
+                    indirect_buffer,
+                    (i * size_of::<vk::DrawIndexedIndirectCommand>()) as vk::DeviceSize,
+                    1u32,
+                    size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+                );
+            }
+        }
     }
 
     fn draw_objects_free(
@@ -2153,48 +5902,6 @@ impl Renderer {
         Ok(())
     }
 
-    fn draw_skybox_free(
-        device: &GraphicsDevice,
-        mesh_pool: &MeshPool,
-        cube_mesh: MeshHandle,
-        skybox_texture: ImageHandle,
-        command_buffer: &vk::CommandBuffer,
-        psolayout: &vk::PipelineLayout,
-    ) -> Result<()> {
-        let push_constants = device.get_descriptor_index(&skybox_texture).unwrap() as i32;
-        unsafe {
-            device.vk_device.cmd_push_constants(
-                *command_buffer,
-                *psolayout,
-                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                0u32,
-                bytemuck::cast_slice(&[push_constants]),
-            )
-        };
-
-        let mesh = mesh_pool.get(cube_mesh).unwrap();
-        let index_count = {
-            if mesh.index_count == 0 {
-                mesh.vertex_count as u32
-            } else {
-                mesh.index_count as u32
-            }
-        };
-
-        unsafe {
-            device.vk_device.cmd_draw_indexed(
-                *command_buffer,
-                index_count as u32,
-                1u32,
-                mesh.index_offset as u32,
-                mesh.vertex_offset as i32,
-                0u32,
-            );
-        }
-
-        Ok(())
-    }
-
     /// Loads a texture into GPU memory and returns back a Texture or an error.
     ///
     /// # Arguments
@@ -2212,35 +5919,200 @@ impl Renderer {
         &mut self,
         file_location: &str,
         image_type: &ImageFormatType,
+    ) -> Result<ImageHandle> {
+        self.load_texture_with_mip_filter(file_location, image_type, MipFilter::default())
+    }
+
+    /// Loads a texture like [Renderer::load_texture], generating its mips with `mip_filter`.
+    /// Formats that the compute filters can't write are blitted instead. sRGB textures are
+    /// filtered in linear space.
+    pub fn load_texture_with_mip_filter(
+        &mut self,
+        file_location: &str,
+        image_type: &ImageFormatType,
+        mip_filter: MipFilter,
     ) -> Result<ImageHandle> {
         profiling::scope!("Renderer: Load Texture");
 
-        let img = {
-            profiling::scope!("image::open");
-            image::open(file_location)
-        };
+        let img = {
+            profiling::scope!("image::open");
+            image::open(file_location)
+        };
+
+        if let Err(error) = img {
+            return Err(anyhow!(error.to_string()));
+        }
+
+        let img = img?;
+        let img_bytes = image_type.image_bytes(&img);
+        let mip_levels = (img.width().max(img.height()) as f32).log2().floor() as u32 + 1u32;
+
+        let image = self.device.load_image(
+            &img_bytes,
+            img.width(),
+            img.height(),
+            image_type,
+            mip_levels,
+            1,
+            mip_filter,
+        )?;
+
+        // Debug name image
+        {
+            let image_name = file_location.rsplit_once('/').unwrap().1;
+            let name = "Image: ".to_string() + image_name;
+            let image_handle = self
+                .device
+                .resource_manager
+                .get_image(image)
+                .unwrap()
+                .image()
+                .as_raw();
+            self.device
+                .set_vulkan_debug_name(image_handle, ObjectType::IMAGE, &name)?;
+
+            trace!(
+                "Texture Loaded: {} | Size: [{},{}] | Mip Levels:[{}]",
+                image_name,
+                img.width(),
+                img.height(),
+                mip_levels
+            );
+        }
+
+        Ok(image)
+    }
+
+    /// Starts loading a texture on a background thread, returning a handle that can be used
+    /// straight away. The handle shows a single placeholder texel until the image is decoded,
+    /// after which the real texture is uploaded and takes over the handle. Textures that fail to
+    /// load keep the placeholder.
+    pub fn load_texture_async(
+        &mut self,
+        file_location: &str,
+        image_type: &ImageFormatType,
+    ) -> Result<ImageHandle> {
+        profiling::scope!("Renderer: Load Texture(Async)");
+
+        let image =
+            self.load_texture_from_bytes(&image_type.placeholder_texel(), 1, 1, image_type, 1, 1)?;
+
+        let (sender, receiver) = mpsc::channel();
+        let path = file_location.to_string();
+        let decode_type = *image_type;
+        thread::spawn(move || {
+            profiling::scope!("Decode Texture");
+            let decoded = image::open(&path)
+                .map(|img| (decode_type.image_bytes(&img), img.width(), img.height()))
+                .map_err(|error| anyhow!(error.to_string()));
+            // The renderer may have been dropped while decoding, in which case nobody needs the result
+            let _ = sender.send(decoded);
+        });
+        self.streamed_textures.push(StreamedTexture {
+            image,
+            file_location: file_location.to_string(),
+            image_type: *image_type,
+            receiver,
+        });
+
+        Ok(image)
+    }
+
+    /// Uploads textures from [Renderer::load_texture_async] that have finished decoding and swaps
+    /// them in for their placeholders. The placeholders are unloaded once no frame in flight can
+    /// be sampling them.
+    fn poll_streamed_textures(&mut self) -> Result<()> {
+        let mut finished = Vec::new();
+        let mut decoding = Vec::new();
+        for texture in self.streamed_textures.drain(..) {
+            match texture.receiver.try_recv() {
+                Ok(decoded) => finished.push((texture, decoded)),
+                Err(TryRecvError::Empty) => decoding.push(texture),
+                Err(TryRecvError::Disconnected) => {}
+            }
+        }
+        self.streamed_textures = decoding;
+
+        for (texture, decoded) in finished.into_iter() {
+            // Unloaded while it was decoding
+            if self.device.get_descriptor_index(&texture.image).is_none() {
+                continue;
+            }
+            let (img_bytes, width, height) = match decoded {
+                Ok(decoded) => decoded,
+                Err(error) => {
+                    warn!(
+                        "Failed to stream texture {}: {}",
+                        texture.file_location, error
+                    );
+                    continue;
+                }
+            };
+
+            let mip_levels = (width.max(height) as f32).log2().floor() as u32 + 1u32;
+            let loaded = self.load_texture_from_bytes(
+                &img_bytes,
+                width,
+                height,
+                &texture.image_type,
+                mip_levels,
+                1,
+            )?;
+
+            let image_name = texture
+                .file_location
+                .rsplit_once('/')
+                .map_or(texture.file_location.as_str(), |(_, name)| name);
+            let image_handle = self
+                .device
+                .resource_manager
+                .get_image(loaded)
+                .unwrap()
+                .image()
+                .as_raw();
+            self.device.set_vulkan_debug_name(
+                image_handle,
+                ObjectType::IMAGE,
+                &("Image: ".to_string() + image_name),
+            )?;
+
+            // The placeholder ends up behind `loaded`
+            self.device.swap_images(texture.image, loaded);
+            self.device.unload_image(loaded)?;
 
-        if let Err(error) = img {
-            return Err(anyhow!(error.to_string()));
+            trace!(
+                "Texture Streamed: {} | Size: [{},{}] | Mip Levels:[{}]",
+                image_name,
+                width,
+                height,
+                mip_levels
+            );
         }
 
-        let img = img?;
-        let rgba_img = img.to_rgba8();
-        let img_bytes = rgba_img.as_bytes();
-        let mip_levels = (img.width().max(img.height()) as f32).log2().floor() as u32 + 1u32;
+        Ok(())
+    }
 
-        let image = self.load_texture_from_bytes(
-            img_bytes,
-            img.width(),
-            img.height(),
-            image_type,
-            mip_levels,
-            1,
+    /// Loads a KTX2 file, using its stored format and mip chain as is. This is the way to load
+    /// block compressed (e.g. BC7, BC5) textures, which fail to load if the device can't sample
+    /// them.
+    pub fn load_texture_ktx2(&mut self, file_location: &str) -> Result<ImageHandle> {
+        profiling::scope!("Renderer: Load Texture(KTX2)");
+
+        let bytes = std::fs::read(file_location)?;
+        let texture = Ktx2Texture::parse(&bytes)?;
+        let image = self.device.load_image_with_mips(
+            &texture.levels,
+            texture.width,
+            texture.height,
+            texture.format,
+            texture.layers,
         )?;
 
         // Debug name image
         {
-            let image_name = file_location.rsplit_once('/').unwrap().1;
+            let image_name = file_location
+                .rsplit_once('/')
+                .map_or(file_location, |(_, name)| name);
             let name = "Image: ".to_string() + image_name;
             let image_handle = self
                 .device
@@ -2253,11 +6125,12 @@ impl Renderer {
                 .set_vulkan_debug_name(image_handle, ObjectType::IMAGE, &name)?;
 
             trace!(
-                "Texture Loaded: {} | Size: [{},{}] | Mip Levels:[{}]",
+                "Texture Loaded: {} | Size: [{},{}] | Mip Levels:[{}] | Format: {:?}",
                 image_name,
-                img.width(),
-                img.height(),
-                mip_levels
+                texture.width,
+                texture.height,
+                texture.levels.len(),
+                texture.format
             );
         }
 
@@ -2271,19 +6144,24 @@ impl Renderer {
     ) -> Result<()> {
         profiling::scope!("Renderer: Load Texture");
 
+        self.release_equirect_skybox()?;
+
         let img = {
             profiling::scope!("image::open");
             [
-                image::open(file_location[0]).unwrap().to_rgba8(),
-                image::open(file_location[1]).unwrap().to_rgba8(),
-                image::open(file_location[2]).unwrap().to_rgba8(),
-                image::open(file_location[3]).unwrap().to_rgba8(),
-                image::open(file_location[4]).unwrap().to_rgba8(),
-                image::open(file_location[5]).unwrap().to_rgba8(),
+                image::open(file_location[0]).unwrap(),
+                image::open(file_location[1]).unwrap(),
+                image::open(file_location[2]).unwrap(),
+                image::open(file_location[3]).unwrap(),
+                image::open(file_location[4]).unwrap(),
+                image::open(file_location[5]).unwrap(),
             ]
         };
 
-        let img_bytes: Vec<u8> = img.iter().flat_map(|img| img.as_bytes().to_vec()).collect();
+        let img_bytes: Vec<u8> = img
+            .iter()
+            .flat_map(|img| image_type.image_bytes(img))
+            .collect();
         let mip_levels = (img[0].width().max(img[0].height()) as f32).log2().floor() as u32 + 1u32;
 
         let image = self.load_texture_from_bytes(
@@ -2319,9 +6197,395 @@ impl Renderer {
         }
 
         self.skybox = Some(image);
+        self.replace_ibl_maps()?;
+        Ok(())
+    }
+
+    /// Loads a single equirectangular panorama, such as an `.hdr` file, as the skybox. It is
+    /// projected onto a cubemap on the GPU at the start of the next frame, then used like a skybox
+    /// from [Renderer::load_skybox], including for image based lighting.
+    pub fn load_skybox_hdr(&mut self, file_location: &str) -> Result<()> {
+        profiling::scope!("Renderer: Load HDR Skybox");
+
+        self.release_equirect_skybox()?;
+
+        let img = {
+            profiling::scope!("image::open");
+            image::open(file_location)?
+        };
+        let img_bytes = ImageFormatType::Hdr.image_bytes(&img);
+        let mip_levels = (img.width().max(img.height()) as f32).log2().floor() as u32 + 1u32;
+
+        let equirect = self.load_texture_from_bytes(
+            &img_bytes,
+            img.width(),
+            img.height(),
+            &ImageFormatType::Hdr,
+            mip_levels,
+            1,
+        )?;
+        let skybox = self.ibl_generator.create_equirect_skybox(
+            &self.device,
+            equirect,
+            img.width(),
+            &mut self.descriptor_layout_cache,
+            &mut self.descriptor_allocator,
+        )?;
+
+        // Debug name image
+        {
+            let image_name = file_location
+                .rsplit_once('/')
+                .map_or(file_location, |(_, name)| name);
+            let name = "Image:".to_string() + image_name;
+            let image_handle = self
+                .device
+                .resource_manager
+                .get_image(skybox.cubemap)
+                .unwrap()
+                .image()
+                .as_raw();
+            self.device
+                .set_vulkan_debug_name(image_handle, ObjectType::IMAGE, &name)?;
+
+            trace!(
+                "HDR Skybox Loaded: {} | Size: [{},{}]",
+                image_name,
+                img.width(),
+                img.height()
+            );
+        }
+
+        self.skybox = Some(skybox.cubemap);
+        self.equirect_skybox = Some(skybox);
+        self.replace_ibl_maps()?;
+        Ok(())
+    }
+
+    /// Frees the skybox loaded by [Renderer::load_skybox_hdr], if it is still the current one.
+    fn release_equirect_skybox(&mut self) -> Result<()> {
+        if let Some(skybox) = self.equirect_skybox.take() {
+            if self.skybox == Some(skybox.cubemap) {
+                self.skybox = None;
+                self.device.unload_image(skybox.cubemap)?;
+            }
+            skybox.destroy(&self.device)?;
+        }
+        Ok(())
+    }
+
+    /// Swaps in new lighting maps for the current skybox. They are generated at the start of the
+    /// next frame, after the skybox has been uploaded.
+    fn replace_ibl_maps(&mut self) -> Result<()> {
+        if let Some(maps) = self.ibl_maps.take() {
+            maps.destroy(&self.device)?;
+        }
+        if self.skybox.is_some() {
+            self.ibl_maps = Some(self.ibl_generator.create_maps(
+                &self.device,
+                &mut self.descriptor_layout_cache,
+                &mut self.descriptor_allocator,
+            )?);
+        }
+        Ok(())
+    }
+
+    fn generate_pending_ibl_maps(&mut self) -> Result<()> {
+        let skybox_index = self
+            .skybox
+            .and_then(|skybox| self.device.get_descriptor_index(&skybox));
+        if let (Some(maps), Some(skybox_index)) = (&mut self.ibl_maps, skybox_index) {
+            if !maps.generated {
+                self.ibl_generator.record(
+                    &self.device,
+                    &self.pipeline_manager,
+                    maps,
+                    skybox_index as i32,
+                )?;
+                maps.generated = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Scales the ambient light taken from the skybox by image based lighting. Has no effect until
+    /// a skybox is loaded with [Renderer::load_skybox].
+    pub fn set_ibl_intensity(&mut self, intensity: f32) {
+        self.ibl_intensity = intensity.max(0.0f32);
+    }
+
+    pub fn ibl_intensity(&self) -> f32 {
+        self.ibl_intensity
+    }
+
+    /// Turns the skybox, along with the image based lighting taken from it. The procedural sky
+    /// follows the sun instead.
+    pub fn set_skybox_rotation(&mut self, rotation: Quaternion<f32>) {
+        self.skybox_rotation = rotation.normalize();
+    }
+
+    pub fn skybox_rotation(&self) -> Quaternion<f32> {
+        self.skybox_rotation
+    }
+
+    /// Scales the brightness of the skybox, both drawn in the background and lighting the scene
+    /// through image based lighting.
+    pub fn set_skybox_intensity(&mut self, intensity: f32) {
+        self.skybox_intensity = intensity.max(0.0f32);
+    }
+
+    pub fn skybox_intensity(&self) -> f32 {
+        self.skybox_intensity
+    }
+
+    /// Sets what is drawn behind the scene, wherever no geometry was rendered.
+    ///
+    /// The background is filled in by the deferred lighting pass, so no extra geometry is drawn.
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    pub fn background(&self) -> Background {
+        self.background
+    }
+
+    /// Switches the background to the procedural sky, lit by the sun so it follows
+    /// [Renderer::sun]'s direction.
+    pub fn set_procedural_sky(&mut self, sky: SkyParams) {
+        self.sky = SkyParams {
+            turbidity: sky.turbidity.clamp(MIN_SKY_TURBIDITY, MAX_SKY_TURBIDITY),
+            ..sky
+        };
+        self.background = Background::SkyboxProcedural;
+    }
+
+    pub fn sky_params(&self) -> SkyParams {
+        self.sky
+    }
+
+    fn get_background_push_constants(&self) -> BackgroundPushConstants {
+        let skybox_index = self
+            .skybox
+            .and_then(|skybox| self.device.get_descriptor_index(&skybox))
+            .unwrap_or(0usize) as i32;
+        let shadow_filter_radius = self.shadow_filter.radius();
+
+        // Only used once generated, before then the ambient light is flat
+        let ibl_maps = self.ibl_maps.as_ref().filter(|maps| maps.generated);
+        let ibl_index = |image: Option<ImageHandle>| {
+            image
+                .and_then(|image| self.device.get_descriptor_index(&image))
+                .unwrap_or(0usize) as i32
+        };
+        let irradiance_index = ibl_index(ibl_maps.map(|maps| maps.irradiance));
+        let prefiltered_index = ibl_index(ibl_maps.map(|maps| maps.prefiltered));
+        let brdf_lut_index = ibl_index(Some(self.brdf_lut));
+        let ltc_matrix_index = ibl_index(Some(self.ltc_tables.matrix));
+        let ltc_amplitude_index = ibl_index(Some(self.ltc_tables.amplitude));
+        let skybox_rotation = self.skybox_rotation.conjugate();
+
+        let (colour, background_type) = match self.background {
+            Background::Colour(colour) => (colour.to_linear().into(), 0i32),
+            Background::Skybox => ([0.0f32; 4], 1i32),
+            Background::SkyboxProcedural => ([0.0f32; 4], 2i32),
+        };
+
+        BackgroundPushConstants {
+            colour,
+            background_type,
+            skybox_index,
+            shadow_filter_radius,
+            shadow_bias: self.shadow_bias,
+            irradiance_index,
+            prefiltered_index,
+            brdf_lut_index,
+            ibl_intensity: self.ibl_intensity,
+            ltc_matrix_index,
+            ltc_amplitude_index,
+            skybox_intensity: self.skybox_intensity,
+            padding: 0,
+            skybox_rotation: [
+                skybox_rotation.v.x,
+                skybox_rotation.v.y,
+                skybox_rotation.v.z,
+                skybox_rotation.s,
+            ],
+            sky_params: Vector3::from(self.sky.ground_albedo.to_linear())
+                .extend(self.sky.turbidity)
+                .into(),
+        }
+    }
+
+    /// Sets the filter used when the scene is scaled to the swapchain in the combine pass.
+    /// Nearest keeps pixels crisp when upscaling, linear gives a smoother result.
+    pub fn set_upscale_filter(&mut self, filter: Filter) {
+        self.upscale_filter = filter;
+    }
+
+    pub fn upscale_filter(&self) -> Filter {
+        self.upscale_filter
+    }
+
+    /// Sets the distance fog applied to the scene in the deferred lighting pass. The background is
+    /// never fogged, and fog fades into it towards the far plane.
+    pub fn set_fog(&mut self, fog: FogParams) {
+        self.fog = fog;
+    }
+
+    pub fn fog(&self) -> FogParams {
+        self.fog
+    }
+
+    /// Outlines the models, for example to highlight a selection. Replaces the previously outlined
+    /// models, so an empty slice removes every outline. The width is in pixels.
+    pub fn set_outline(&mut self, handles: &[RenderModelHandle], colour: Colour, width: u32) {
+        self.outline_models = handles.iter().copied().collect();
+        self.outline_colour = colour;
+        self.outline_width = width;
+    }
+
+    /// Blurs the scene in front of and behind the focus distance. See [DofParams].
+    pub fn set_dof(&mut self, dof: DofParams) {
+        self.dof = dof;
+    }
+
+    pub fn dof(&self) -> DofParams {
+        self.dof
+    }
+
+    /// Controls which parts of the scene glow and how strongly. See [BloomParams]. Bloom is
+    /// turned off with [Renderer::enable_bloom_pass].
+    ///
+    /// Changing the mip count waits for the GPU to be idle and bakes the frame graph again, so
+    /// images from [Renderer::debug_shadow_map] and similar must be fetched again.
+    pub fn set_bloom(&mut self, bloom: BloomParams) -> Result<()> {
+        ensure!(
+            (MIN_BLOOM_MIPS..=MAX_BLOOM_MIPS).contains(&bloom.mip_count),
+            "Bloom mip count must be between {} and {}",
+            MIN_BLOOM_MIPS,
+            MAX_BLOOM_MIPS
+        );
+
+        let mip_count_changed = bloom.mip_count != self.bloom.mip_count;
+        self.bloom = BloomParams {
+            threshold: bloom.threshold.max(0.0),
+            knee: bloom.knee.max(0.0),
+            intensity: bloom.intensity.max(0.0),
+            mip_count: bloom.mip_count,
+        };
+        if mip_count_changed {
+            unsafe { self.device.vk_device.device_wait_idle() }?;
+            self.update_pass_order();
+            self.rebuild_render_list()?;
+        }
+
         Ok(())
     }
 
+    pub fn bloom(&self) -> BloomParams {
+        self.bloom
+    }
+
+    /// Adjusts the exposure over time to the brightness of the scene, like an eye adapting to the
+    /// dark. See [AutoExposureParams]. While disabled, the scene is shown at an exposure of 1.
+    pub fn set_auto_exposure(&mut self, params: AutoExposureParams) {
+        self.auto_exposure_params = params;
+    }
+
+    pub fn auto_exposure(&self) -> AutoExposureParams {
+        self.auto_exposure_params
+    }
+
+    /// Chooses how transparent materials are blended. See [TransparencyMode].
+    pub fn set_transparency_mode(&mut self, mode: TransparencyMode) {
+        self.transparency_mode = mode;
+    }
+
+    pub fn transparency_mode(&self) -> TransparencyMode {
+        self.transparency_mode
+    }
+
+    /// Smooths jagged edges in the combine pass with FXAA. Much cheaper than [Renderer::set_msaa],
+    /// but also softens texture detail.
+    pub fn set_fxaa(&mut self, enabled: bool) {
+        self.fxaa_enabled = enabled;
+    }
+
+    pub fn fxaa(&self) -> bool {
+        self.fxaa_enabled
+    }
+
+    pub fn set_fxaa_settings(&mut self, settings: FxaaSettings) {
+        self.fxaa_settings = settings;
+    }
+
+    pub fn fxaa_settings(&self) -> FxaaSettings {
+        self.fxaa_settings
+    }
+
+    /// Smooths edges by jittering the camera a fraction of a pixel every frame and blending each
+    /// frame with the previous ones. Motion vectors from the gbuffer follow moving objects, and
+    /// history that no longer matches the scene is clamped to the current frame.
+    pub fn set_taa(&mut self, enabled: bool) {
+        self.taa_enabled = enabled;
+    }
+
+    pub fn taa(&self) -> bool {
+        self.taa_enabled
+    }
+
+    /// Sub-pixel offset of this frame's projection in normalized device coordinates, following
+    /// the Halton (2, 3) sequence.
+    fn taa_jitter(&self) -> Vector2<f32> {
+        let index = (self.device.frame_number() % TAA_JITTER_SAMPLES) as u32 + 1;
+        let (width, height) = self.list.swapchain_size;
+        Vector2::new(
+            (halton(index, 2) - 0.5) * 2.0 / width as f32,
+            (halton(index, 3) - 0.5) * 2.0 / height as f32,
+        )
+    }
+
+    /// Restricts the scene to a sub-rectangle of the screen, for example to draw it inside a UI panel.
+    /// Pixels outside the rectangle keep the clear colour. Applies to every pass that draws the
+    /// scene, including outlines and custom passes that write "forward" or "output". UI is not
+    /// affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `scissor`: Rectangle in pixels from the top left of the screen, or None to use the whole
+    ///   screen.
+    pub fn set_scene_scissor(&mut self, scissor: Option<Rect>) {
+        self.scene_scissor = scissor.map(vk::Rect2D::from);
+
+        let custom_passes = self
+            .custom_passes
+            .iter()
+            .filter(|custom| custom.writes_scene)
+            .map(|custom| custom.handle);
+        let passes: Vec<VirtualRenderPassHandle> = [
+            self.gbuffer,
+            self.deferred_lighting,
+            self.transparent,
+            self.transparent_oit,
+            self.oit_composite,
+            self.particles,
+            self.taa_resolve,
+            self.taa_copy,
+            self.dof_prepare,
+            self.dof_composite,
+            self.debug_lines,
+            self.combine,
+            self.outline_mask,
+            self.outline,
+        ]
+        .into_iter()
+        .chain(custom_passes)
+        .collect();
+        for pass in passes {
+            self.list.set_scissor_override(pass, self.scene_scissor);
+        }
+    }
+
     pub fn load_texture_from_bytes(
         &self,
         img_bytes: &[u8],
@@ -2334,24 +6598,165 @@ impl Renderer {
         profiling::scope!("Renderer: Load Texture(From Bytes)");
 
         let image = self.device.load_image(
-            img_bytes, img_width, img_height, image_type, mip_levels, img_layers,
+            img_bytes,
+            img_width,
+            img_height,
+            image_type,
+            mip_levels,
+            img_layers,
+            MipFilter::Blit,
         )?;
 
         Ok(image)
     }
 
+    /// Frees a texture loaded with [`Renderer::load_texture`], [`Renderer::load_texture_async`] or
+    /// [`Renderer::load_texture_from_bytes`].
+    ///
+    /// The image is destroyed once the frames in flight that may sample it have finished, and its
+    /// bindless slot is then reused by later loads. Materials still pointing at the handle fall
+    /// back to the default texture.
+    pub fn unload_texture(&mut self, image: ImageHandle) -> Result<()> {
+        profiling::scope!("Renderer: Unload Texture");
+
+        ensure!(
+            self.device.get_descriptor_index(&image).is_some(),
+            "Image is not a loaded texture"
+        );
+        if self.skybox == Some(image) {
+            self.skybox = None;
+            self.release_equirect_skybox()?;
+            self.replace_ibl_maps()?;
+        }
+        if self.light_texture == Some(image) {
+            self.light_texture = None;
+        }
+        if let Some(handle) = self.render_textures.remove(&image) {
+            self.pending_render_textures
+                .retain(|&pending| pending != handle);
+            let viewport = self.viewports.remove(handle).unwrap();
+            self.destroy_scene_viewport(viewport)?;
+        }
+
+        self.device.unload_image(image)
+    }
+
+    /// Uploads a mesh. Normals are generated for meshes that have none.
     pub fn load_mesh(&mut self, mesh: &MeshData) -> Result<MeshHandle> {
+        if mesh.has_zero_normals() {
+            let mut mesh = mesh.clone();
+            mesh.recompute_normals();
+            return self.mesh_pool.add_mesh(&mesh);
+        }
+
         self.mesh_pool.add_mesh(mesh)
     }
 
+    /// Returns the object space bounds of an uploaded mesh.
+    pub fn mesh_bounds(&self, mesh: MeshHandle) -> Option<MeshBounds> {
+        self.mesh_pool.get(mesh).map(|mesh| mesh.bounds)
+    }
+
+    /// Returns the directional light's shadow map (`D32_SFLOAT`).
+    ///
+    /// After the deferred lighting pass has run the image is in `SHADER_READ_ONLY_OPTIMAL`,
+    /// and stays there until the shadow pass of the next frame. The handle changes when the
+    /// render graph is rebaked, such as when MSAA is changed, but not on resize.
+    pub fn debug_shadow_map(&self) -> ImageHandle {
+        self.list.get_physical_resource("scene_shadow")
+    }
+
+    /// The graphics device used by the renderer, for access to its raw Vulkan handles.
+    pub fn device(&self) -> &Arc<GraphicsDevice> {
+        &self.device
+    }
+
+    pub fn descriptor_layout_cache_stats(&self) -> LayoutCacheStats {
+        self.descriptor_layout_cache.cache_stats()
+    }
+
+    pub fn pipeline_layout_cache_stats(&self) -> LayoutCacheStats {
+        self.pipeline_layout_cache.cache_stats()
+    }
+
     pub fn timestamps(&self) -> TimeStamp {
         self.timestamps
     }
 
+    /// GPU time in milliseconds of every render graph pass run last frame, keyed by pass name.
+    /// Includes custom passes. Skipped passes are left out.
+    pub fn pass_timings(&self) -> HashMap<String, f64> {
+        self.list.timings()
+    }
+
+    /// GPU memory currently allocated for buffers and images, including render targets.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.device.memory_stats()
+    }
+
+    /// Records a layout transition for a user created image on the current frame's command buffer.
+    ///
+    /// Must be called while a frame is being recorded. All mip levels and array layers of the
+    /// image are transitioned.
+    ///
+    /// Supported usages and the stage/access/layout inferred from them:
+    ///
+    /// * `COLOR_ATTACHMENT` - `COLOR_ATTACHMENT_OUTPUT`, `COLOR_ATTACHMENT_WRITE`, `ATTACHMENT_OPTIMAL`
+    /// * `DEPTH_STENCIL_ATTACHMENT` - `LATE_FRAGMENT_TESTS`, `DEPTH_STENCIL_ATTACHMENT_WRITE`, `ATTACHMENT_OPTIMAL`
+    /// * `SAMPLED` - `FRAGMENT_SHADER`, `SHADER_READ`, `SHADER_READ_ONLY_OPTIMAL`
+    /// * `TRANSFER_SRC` - `TRANSFER`, `TRANSFER_READ`, `TRANSFER_SRC_OPTIMAL`
+    /// * `TRANSFER_DST` - `TRANSFER`, `TRANSFER_WRITE`, `TRANSFER_DST_OPTIMAL`
+    ///
+    /// An empty `from_usage` transitions from `UNDEFINED`, discarding the image contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `image`: Image to transition.
+    /// * `from_usage`: How the image was last used.
+    /// * `to_usage`: How the image will be used next.
+    pub fn transition_image(
+        &self,
+        image: ImageHandle,
+        from_usage: vk::ImageUsageFlags,
+        to_usage: vk::ImageUsageFlags,
+    ) -> Result<()> {
+        ensure!(
+            self.device.is_recording_frame(),
+            "Images can only be transitioned while a frame is being recorded"
+        );
+        ensure!(
+            from_usage.is_empty() || is_supported_transition_usage(from_usage),
+            "Unsupported usage to transition from: {:?}",
+            from_usage
+        );
+        ensure!(
+            is_supported_transition_usage(to_usage),
+            "Unsupported usage to transition to: {:?}",
+            to_usage
+        );
+
+        let image_info = self
+            .device
+            .resource_manager
+            .get_image(image)
+            .ok_or_else(|| anyhow!("No image exists"))?;
+
+        ImageBarrierBuilder::default()
+            .add_image_barrier(
+                ImageBarrier::new(AttachmentHandle::Image(image))
+                    .old_usage(from_usage)
+                    .new_usage(to_usage)
+                    .level_count(image_info.mip_levels())
+                    .image_layers(image_info.array_layers()),
+            )
+            .build(&self.device, &self.device.graphics_command_buffer())
+    }
+
     fn get_material_ssbo_from_instance(&self, instance: &MaterialInstance) -> MaterialParamSSBO {
+        let uv_offset = instance.uv_offset(self.camera_uniform.time);
         let diffuse_tex = {
             if let Some(tex) = instance.diffuse_texture {
-                self.device.get_descriptor_index(&tex).unwrap()
+                self.device.get_descriptor_index(&tex).unwrap_or(0)
             } else {
                 0usize
             }
@@ -2359,7 +6764,7 @@ impl Renderer {
 
         let normal_tex = {
             if let Some(tex) = instance.normal_texture {
-                self.device.get_descriptor_index(&tex).unwrap()
+                self.device.get_descriptor_index(&tex).unwrap_or(0)
             } else {
                 0usize
             }
@@ -2367,7 +6772,7 @@ impl Renderer {
 
         let metallic_roughness_tex = {
             if let Some(tex) = instance.metallic_roughness_texture {
-                self.device.get_descriptor_index(&tex).unwrap()
+                self.device.get_descriptor_index(&tex).unwrap_or(0)
             } else {
                 0usize
             }
@@ -2375,7 +6780,7 @@ impl Renderer {
 
         let emissive_tex = {
             if let Some(tex) = instance.emissive_texture {
-                self.device.get_descriptor_index(&tex).unwrap()
+                self.device.get_descriptor_index(&tex).unwrap_or(0)
             } else {
                 0usize
             }
@@ -2383,56 +6788,139 @@ impl Renderer {
 
         let occlusion_tex = {
             if let Some(tex) = instance.occlusion_texture {
-                self.device.get_descriptor_index(&tex).unwrap()
+                self.device.get_descriptor_index(&tex).unwrap_or(0)
             } else {
                 0usize
             }
         };
 
+        let mut flags = 0i32;
+        if instance.use_normal_map && instance.normal_texture.is_some() {
+            flags |= MATERIAL_FLAG_NORMAL_MAP;
+        }
+        if instance.use_vertex_color {
+            flags |= MATERIAL_FLAG_VERTEX_COLOR;
+        }
+        if instance.double_sided {
+            flags |= MATERIAL_FLAG_DOUBLE_SIDED;
+        }
+
         MaterialParamSSBO {
             diffuse: instance.diffuse.into(),
-            emissive: instance.emissive.extend(0f32).into(),
+            emissive: instance
+                .emissive
+                .extend(instance.emissive_strength.max(0.0))
+                .into(),
             textures: [
                 diffuse_tex as i32,
                 normal_tex as i32,
                 metallic_roughness_tex as i32,
                 occlusion_tex as i32,
                 emissive_tex as i32,
+                flags,
+                self.device
+                    .material_sampler_index(instance.sampler, instance.max_anisotropy),
                 0,
-                0,
-                0,
+            ],
+            uv_scroll: [
+                uv_offset[0],
+                uv_offset[1],
+                instance.alpha_cutoff.unwrap_or(-1.0f32),
+                instance.lod_bias,
             ],
         }
     }
 
+    /// Adds a model to draw each frame. Models sharing a mesh are drawn together in a single instanced draw.
     pub fn add_render_model(
         &mut self,
         handle: MeshHandle,
         material_handle: MaterialInstanceHandle,
     ) -> RenderModelHandle {
+        let transform = from_transforms(
+            Vector3::from_value(0f32),
+            Quaternion::from_axis_angle(Vector3::new(0.0f32, 1.0f32, 0.0f32), Deg(0f32)),
+            Vector3::from_value(1f32),
+        );
         self.render_models.insert(RenderModel {
             mesh_handle: handle,
             material_instance: material_handle,
-            transform: from_transforms(
-                Vector3::from_value(0f32),
-                Quaternion::from_axis_angle(Vector3::new(0.0f32, 1.0f32, 0.0f32), Deg(0f32)),
-                Vector3::from_value(1f32),
-            ),
+            transform,
+            prev_transform: transform,
+            params: [0.0; 4],
         })
     }
 
+    /// Returns the model whose bounding box is hit first by a world space ray, for mouse selection.
+    ///
+    /// # Arguments
+    ///
+    /// * `ray_origin`: World space start of the ray, such as the camera position.
+    /// * `ray_dir`: Direction of the ray. Doesn't need to be normalised.
+    pub fn pick(
+        &self,
+        ray_origin: Vector3<f32>,
+        ray_dir: Vector3<f32>,
+    ) -> Option<RenderModelHandle> {
+        self.render_models
+            .iter()
+            .filter_map(|(handle, model)| {
+                let bounds = self.mesh_pool.get(model.mesh_handle)?.bounds;
+                let inverse = model.transform.invert()?;
+
+                // The ray stays parameterised the same way in object space, so distances can be compared
+                let origin = inverse.transform_point(Point3::from_vec(ray_origin));
+                let dir = inverse.transform_vector(ray_dir);
+                ray_box_intersection(origin, dir, bounds.min, bounds.max).map(|t| (handle, t))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(handle, _)| handle)
+    }
+
+    /// Adds `count` models of the same mesh and material, for repeated objects such as projectiles.
+    /// Each model has its own transform, and they are all drawn with a single instanced draw.
+    pub fn add_render_model_instanced(
+        &mut self,
+        handle: MeshHandle,
+        material_handle: MaterialInstanceHandle,
+        count: usize,
+    ) -> Vec<RenderModelHandle> {
+        (0..count)
+            .map(|_| self.add_render_model(handle, material_handle))
+            .collect()
+    }
+
     pub fn remove_render_model(&mut self, handle: RenderModelHandle) {
         self.render_models.remove(handle);
+        self.outline_models.remove(&handle);
+    }
+
+    pub fn set_render_model_transform(
+        &mut self,
+        handles: &[RenderModelHandle],
+        transform: Matrix4<f32>,
+    ) -> Result<()> {
+        for &handle in handles.iter() {
+            if let Some(model) = self.render_models.get_mut(handle) {
+                model.transform = transform;
+            } else {
+                bail!(anyhow!("Unable to find Render Model!"))
+            }
+        }
+        Ok(())
     }
 
-    pub fn set_render_model_transform(
+    /// Sets values passed to the shaders alongside each model's transform, read with `objectParams`
+    /// in object.glsl. Allows per-model effects such as a tint without a unique material
+    /// instance for every model. Zero by default.
+    pub fn set_render_model_params(
         &mut self,
         handles: &[RenderModelHandle],
-        transform: Matrix4<f32>,
+        params: [f32; 4],
     ) -> Result<()> {
         for &handle in handles.iter() {
             if let Some(model) = self.render_models.get_mut(handle) {
-                model.transform = transform;
+                model.params = params;
             } else {
                 bail!(anyhow!("Unable to find Render Model!"))
             }
@@ -2456,10 +6944,10 @@ impl Renderer {
     }
 
     pub fn create_light(&mut self, light: &Light) -> Option<LightHandle> {
-        if self.stored_lights.len() >= MAX_LIGHTS {
+        if self.stored_lights.len() >= self.max_lights {
             warn!(
                 "Tried to create light, but reached max limit of [{}].",
-                MAX_LIGHTS
+                self.max_lights
             );
             return None;
         }
@@ -2476,22 +6964,415 @@ impl Renderer {
         Err(anyhow!("No light exists"))
     }
 
+    /// Adds a rectangular area light. Every area light is shaded at every pixel, so they are capped
+    /// separately from point lights by [RendererSettings::max_area_lights].
+    pub fn create_area_light(&mut self, light: &AreaLight) -> Option<AreaLightHandle> {
+        if self.stored_area_lights.len() >= self.max_area_lights {
+            warn!(
+                "Tried to create area light, but reached max limit of [{}].",
+                self.max_area_lights
+            );
+            return None;
+        }
+
+        let handle = self.stored_area_lights.insert(*light);
+        Some(handle)
+    }
+
+    pub fn set_area_light(
+        &mut self,
+        light_handle: AreaLightHandle,
+        light: &AreaLight,
+    ) -> Result<()> {
+        if let Some(modified_light) = self.stored_area_lights.get_mut(light_handle) {
+            *modified_light = *light;
+            return Ok(());
+        }
+        Err(anyhow!("No area light exists"))
+    }
+
     pub fn set_camera<T: CameraTrait>(&mut self, camera: &T) {
         self.camera_uniform.update_proj(camera);
     }
 
+    /// Adds another camera, drawn over the main camera inside `rect`, for split screen or a
+    /// minimap. Everything from the gbuffer to the combine pass runs again for each viewport, so
+    /// each one costs about as much as the main camera. Screen space effects such as bloom can
+    /// bleed over the viewport's edges.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera`: Camera to draw from, its aspect ratio should match `rect`.
+    /// * `rect`: Rectangle in pixels from the top left of the screen. Fails unless it covers at
+    /// least one pixel and lies entirely on screen.
+    pub fn add_viewport<T: CameraTrait>(
+        &mut self,
+        camera: &T,
+        rect: Rect,
+    ) -> Result<ViewportHandle> {
+        self.validate_viewport_rect(rect)?;
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_proj(camera);
+
+        let viewport = self.create_scene_viewport(camera_uniform, rect, None);
+        Ok(self.viewports.insert(viewport))
+    }
+
+    /// Fails when `rect` is empty, as the viewport's projection is scaled down to it, or when it
+    /// isn't entirely on screen.
+    fn validate_viewport_rect(&self, rect: Rect) -> Result<()> {
+        ensure!(
+            rect.width > 0 && rect.height > 0,
+            "Viewport must be at least one pixel wide and high"
+        );
+        let size = self.device.size();
+        ensure!(
+            rect.is_within(size.width, size.height),
+            "Viewport {:?} is not inside the {}x{} screen",
+            rect,
+            size.width,
+            size.height
+        );
+        Ok(())
+    }
+
+    fn create_scene_viewport(
+        &self,
+        camera_uniform: CameraUniform,
+        rect: Rect,
+        target: Option<RenderTextureTarget>,
+    ) -> SceneViewport {
+        let create_buffers = |buffer_create_info: BufferCreateInfo| {
+            (0..self.device.frames_in_flight())
+                .map(|_| {
+                    self.device
+                        .resource_manager
+                        .create_buffer(&buffer_create_info)
+                })
+                .collect::<Vec<_>>()
+        };
+        let camera_buffer = create_buffers(BufferCreateInfo {
+            size: size_of::<CameraUniform>(),
+            usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
+            storage_type: BufferStorageType::HostLocal,
+        });
+        let draw_indirect_buffer = create_buffers(BufferCreateInfo {
+            size: size_of::<vk::DrawIndexedIndirectCommand>() * self.max_objects,
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            storage_type: BufferStorageType::HostLocal,
+        });
+        let visible_instance_buffer = create_buffers(BufferCreateInfo {
+            size: size_of::<u32>() * self.max_objects,
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+            storage_type: BufferStorageType::Device,
+        });
+        let light_cluster_buffer = create_buffers(BufferCreateInfo {
+            size: LIGHT_CLUSTER_BUFFER_SIZE,
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+            storage_type: BufferStorageType::Device,
+        });
+
+        SceneViewport {
+            camera_uniform,
+            rect,
+            target,
+            camera_buffer,
+            draw_indirect_buffer,
+            visible_instance_buffer,
+            light_cluster_buffer,
+        }
+    }
+
+    pub fn set_viewport_camera<T: CameraTrait>(
+        &mut self,
+        handle: ViewportHandle,
+        camera: &T,
+    ) -> Result<()> {
+        let viewport = self
+            .viewports
+            .get_mut(handle)
+            .ok_or_else(|| anyhow!("No viewport exists"))?;
+        viewport.camera_uniform.update_proj(camera);
+        Ok(())
+    }
+
+    /// Moves a viewport to another rectangle of the screen, in pixels from the top left. Fails
+    /// unless the rectangle covers at least one pixel and lies entirely on screen.
+    pub fn set_viewport_rect(&mut self, handle: ViewportHandle, rect: Rect) -> Result<()> {
+        self.validate_viewport_rect(rect)?;
+        let viewport = self
+            .viewports
+            .get_mut(handle)
+            .ok_or_else(|| anyhow!("No viewport exists"))?;
+        viewport.rect = rect;
+        Ok(())
+    }
+
+    pub fn remove_viewport(&mut self, handle: ViewportHandle) -> Result<()> {
+        let viewport = self
+            .viewports
+            .remove(handle)
+            .ok_or_else(|| anyhow!("No viewport exists"))?;
+        self.destroy_scene_viewport(viewport)
+    }
+
+    fn destroy_scene_viewport(&self, viewport: SceneViewport) -> Result<()> {
+        // Buffers may still be used by frames in flight
+        unsafe { self.device.vk_device.device_wait_idle() }?;
+        for buffer in viewport
+            .camera_buffer
+            .into_iter()
+            .chain(viewport.draw_indirect_buffer)
+            .chain(viewport.visible_instance_buffer)
+            .chain(viewport.light_cluster_buffer)
+        {
+            self.device.resource_manager.destroy_buffer(buffer);
+        }
+        Ok(())
+    }
+
+    /// Creates a texture the scene can be drawn into with [Renderer::render_to_texture], for example a screen
+    /// showing a security camera. The texture is in the bindless set, so materials can use it like a loaded
+    /// texture, and is unloaded with [Renderer::unload_texture].
+    ///
+    /// The scene is drawn into the backbuffer before the main camera and copied out, so textures larger than
+    /// the window are drawn at a lower resolution and scaled up.
+    pub fn create_render_texture(&mut self, width: u32, height: u32) -> Result<ImageHandle> {
+        ensure!(
+            width > 0 && height > 0,
+            "Render texture must not be empty. [Width: {}, Height: {}]",
+            width,
+            height
+        );
+
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .format(self.device.surface_format().format)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .extent(vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            })
+            .image_type(vk::ImageType::TYPE_2D)
+            .array_layers(1u32)
+            .mip_levels(1u32)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL);
+        let image = self
+            .device
+            .resource_manager
+            .create_image(&image_create_info);
+
+        // Sampled straight away, so leave it readable until it is first drawn
+        self.device.immediate_submit(|device, cmd| {
+            ImageBarrierBuilder::default()
+                .add_image_barrier(
+                    ImageBarrier::new(AttachmentHandle::Image(image))
+                        .new_usage(vk::ImageUsageFlags::SAMPLED),
+                )
+                .build(device, cmd)
+        })?;
+        self.device.add_image_to_bindless(&image)?;
+
+        let target = RenderTextureTarget {
+            image,
+            width,
+            height,
+        };
+        let viewport = self.create_scene_viewport(
+            self.camera_uniform,
+            target.backbuffer_rect(width, height),
+            Some(target),
+        );
+        let handle = self.viewports.insert(viewport);
+        self.render_textures.insert(image, handle);
+
+        Ok(image)
+    }
+
+    /// Draws the scene from `camera` into a texture made with [Renderer::create_render_texture], during the next
+    /// rendered frame. The texture keeps its contents until it is drawn again.
+    ///
+    /// Drawing is never recursive: a render texture visible to its own camera, or to another render texture's
+    /// camera, shows what it held the last time it was drawn. At most four textures are drawn
+    /// each frame, the rest are drawn in the following frames.
+    pub fn render_to_texture<T: CameraTrait>(
+        &mut self,
+        texture: ImageHandle,
+        camera: &T,
+    ) -> Result<()> {
+        let handle = *self
+            .render_textures
+            .get(&texture)
+            .ok_or_else(|| anyhow!("Image is not a render texture"))?;
+        self.viewports
+            .get_mut(handle)
+            .unwrap()
+            .camera_uniform
+            .update_proj(camera);
+        if !self.pending_render_textures.contains(&handle) {
+            self.pending_render_textures.push(handle);
+        }
+        Ok(())
+    }
+
     pub fn draw_ui(&mut self, ui: UIMesh) -> Result<()> {
         self.ui_to_draw.push(ui);
         Ok(())
     }
 
+    /// Draws text on top of the screen this frame, with the top left of the first glyph at pixel
+    /// (`x`, `y`). Uses a built in 8x8 pixel ASCII font, multiplied in size by `scale`. Newlines
+    /// start a new row and anything off screen is clipped.
+    pub fn draw_debug_text(&mut self, x: f32, y: f32, text: &str, colour: Colour, scale: f32) {
+        let size = self.device.size();
+        let screen_size = [size.width as f32, size.height as f32];
+        let (vertices, indices) = layout_text([x, y], text, colour, scale, screen_size);
+        if indices.is_empty() {
+            return;
+        }
+
+        self.ui_to_draw.push(UIMesh {
+            indices,
+            vertices,
+            texture_id: self.debug_font,
+            scissor: ([0.0f32, 0.0f32], screen_size),
+        });
+    }
+
+    /// Draws a world space line this frame, in the main camera and every viewport. Lines are hidden
+    /// behind scene geometry.
+    pub fn draw_line(&mut self, start: Vector3<f32>, end: Vector3<f32>, colour: Colour) {
+        let colour: [f32; 4] = colour.to_linear().into();
+        self.lines_to_draw.push(DebugLineVertex {
+            position: start.extend(1.0f32).into(),
+            colour,
+        });
+        self.lines_to_draw.push(DebugLineVertex {
+            position: end.extend(1.0f32).into(),
+            colour,
+        });
+    }
+
+    /// Draws the edges of an axis aligned box this frame.
+    pub fn draw_box(&mut self, min: Vector3<f32>, max: Vector3<f32>, colour: Colour) {
+        let corner = |x: bool, y: bool, z: bool| {
+            Vector3::new(
+                if x { max.x } else { min.x },
+                if y { max.y } else { min.y },
+                if z { max.z } else { min.z },
+            )
+        };
+
+        for a in [false, true] {
+            for b in [false, true] {
+                self.draw_line(corner(false, a, b), corner(true, a, b), colour);
+                self.draw_line(corner(a, false, b), corner(a, true, b), colour);
+                self.draw_line(corner(a, b, false), corner(a, b, true), colour);
+            }
+        }
+    }
+
+    /// Draws a circle around `normal` this frame, made of straight segments.
+    pub fn draw_circle(
+        &mut self,
+        centre: Vector3<f32>,
+        normal: Vector3<f32>,
+        radius: f32,
+        colour: Colour,
+    ) {
+        const SEGMENTS: usize = 32;
+
+        let normal = normal.normalize();
+        let tangent = perpendicular(normal);
+        let bitangent = normal.cross(tangent);
+        let point = |segment: usize| {
+            let angle = segment as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+            centre + (tangent * angle.cos() + bitangent * angle.sin()) * radius
+        };
+
+        for segment in 0..SEGMENTS {
+            self.draw_line(point(segment), point(segment + 1), colour);
+        }
+    }
+
+    fn draw_light_gizmos(&mut self) {
+        let lights: Vec<Light> = self.stored_lights.values().copied().collect();
+        for light in lights {
+            let position = light.position.to_vec();
+            let colour = Colour::from(light.colour);
+            match light.light_type {
+                LightType::Point => {
+                    for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+                        self.draw_circle(position, axis, light.range, colour);
+                    }
+                }
+                LightType::Spot {
+                    direction,
+                    outer_cone,
+                    ..
+                } => {
+                    let direction = direction.normalize();
+                    let base = position + direction * light.range * outer_cone.0.cos();
+                    let radius = light.range * outer_cone.0.sin();
+                    self.draw_circle(base, direction, radius, colour);
+
+                    let side = perpendicular(direction);
+                    let side_up = direction.cross(side);
+                    for edge in [side, -side, side_up, -side_up] {
+                        self.draw_line(position, base + edge * radius, colour);
+                    }
+                }
+            }
+        }
+
+        let area_lights: Vec<AreaLight> = self.stored_area_lights.values().copied().collect();
+        for light in area_lights {
+            let centre = light.position.to_vec();
+            let colour = Colour::from(light.colour);
+            let (half_u, half_v) = (light.edge_u * 0.5, light.edge_v * 0.5);
+            let corners = [
+                centre - half_u - half_v,
+                centre + half_u - half_v,
+                centre + half_u + half_v,
+                centre - half_u + half_v,
+            ];
+            for (i, &corner) in corners.iter().enumerate() {
+                self.draw_line(corner, corners[(i + 1) % corners.len()], colour);
+            }
+            // Points out of the lit side
+            let normal = light.edge_u.cross(light.edge_v).normalize();
+            self.draw_line(centre, centre + normal, colour);
+        }
+
+        // The sun has no position, so its arrow starts at the origin
+        let direction = self.sun.direction.normalize();
+        let colour = Colour::from(self.sun.colour);
+        let tip = direction * 5.0;
+        self.draw_line(Vector3::zero(), tip, colour);
+        let side = perpendicular(direction);
+        for edge in [side, -side] {
+            self.draw_line(tip, tip - direction + edge * 0.5, colour);
+        }
+    }
+
+    /// Adds a material, failing when the material buffer would grow past the device's storage
+    /// buffer range.
     pub fn add_material_instance(
         &mut self,
         material_instance: MaterialInstance,
-    ) -> MaterialInstanceHandle {
-        assert!(self.material_instances.len() <= MAX_MATERIAL_INSTANCES);
+    ) -> Result<MaterialInstanceHandle> {
+        let max_material_instances = self
+            .max_material_buffer_capacity
+            .saturating_sub(self.max_lights);
+        ensure!(
+            self.material_instances.len() < max_material_instances,
+            "Tried to add material instance, but reached max limit of [{}]",
+            max_material_instances
+        );
 
-        self.material_instances.insert(material_instance)
+        Ok(self.material_instances.insert(material_instance))
     }
 
     pub fn set_material_instance(
@@ -2521,6 +7402,331 @@ impl Renderer {
         self.stored_particle_systems.insert(system)
     }
 
+    /// Creates a particle system that starts emitting straight away. Particles are simulated by
+    /// [Renderer::tick_particle_systems], on the CPU or GPU depending on
+    /// [ParticleSystemDesc::simulation], and drawn as camera facing quads.
+    pub fn create_particle_system(
+        &mut self,
+        desc: ParticleSystemDesc,
+    ) -> Result<ParticleSystemHandle> {
+        ensure!(
+            desc.particle_limit > 0,
+            "Particle systems need room for at least one particle"
+        );
+
+        let mut system = ParticleSystem::from(desc);
+        system.set_state(ParticleSystemState::Running);
+        let handle = self.stored_particle_systems.insert(system);
+
+        if desc.simulation == ParticleSimulation::Gpu {
+            let buffers = self.create_gpu_particle_buffers(desc.particle_limit)?;
+            self.gpu_particle_buffers.insert(handle, buffers);
+        }
+
+        Ok(handle)
+    }
+
+    fn create_gpu_particle_buffers(&mut self, particle_limit: usize) -> Result<GpuParticleBuffers> {
+        let particles = self
+            .device
+            .resource_manager
+            .create_buffer(&BufferCreateInfo {
+                size: size_of::<GpuParticle>() * particle_limit,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                storage_type: BufferStorageType::Device,
+            });
+        let alive = self
+            .device
+            .resource_manager
+            .create_buffer(&BufferCreateInfo {
+                size: size_of::<u32>() * particle_limit,
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+                storage_type: BufferStorageType::Device,
+            });
+        let draw = self
+            .device
+            .resource_manager
+            .create_buffer(&BufferCreateInfo {
+                size: size_of::<vk::DrawIndexedIndirectCommand>(),
+                usage: vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::INDIRECT_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_DST,
+                storage_type: BufferStorageType::HostLocal,
+            });
+        let params_create_info = BufferCreateInfo {
+            size: size_of::<GpuParticleParams>(),
+            usage: vk::BufferUsageFlags::STORAGE_BUFFER,
+            storage_type: BufferStorageType::HostLocal,
+        };
+        let params: Vec<BufferHandle> = (0..self.device.frames_in_flight())
+            .map(|_| {
+                self.device
+                    .resource_manager
+                    .create_buffer(&params_create_info)
+            })
+            .collect();
+
+        // The instance count is reset and counted up by every simulation dispatch
+        let quad = self
+            .mesh_pool
+            .get(self.quad_mesh)
+            .ok_or_else(|| anyhow!("Unable to find particle quad mesh"))?;
+        let draw_command = vk::DrawIndexedIndirectCommand {
+            index_count: quad.index_count as u32,
+            instance_count: 0,
+            first_index: quad.index_offset as u32,
+            vertex_offset: quad.vertex_offset as i32,
+            first_instance: 0,
+        };
+        self.device
+            .resource_manager
+            .get_buffer(draw)
+            .unwrap()
+            .view_custom(0, 1)?
+            .mapped_slice()?
+            .copy_from_slice(&[draw_command]);
+
+        let stage_flags = vk::ShaderStageFlags::COMPUTE
+            | vk::ShaderStageFlags::VERTEX
+            | vk::ShaderStageFlags::FRAGMENT;
+        let mut sets = vec![vk::DescriptorSet::null(); self.device.frames_in_flight()];
+        for (i, set) in sets.iter_mut().enumerate() {
+            let (built_set, _) = JBDescriptorBuilder::new(
+                &self.device.resource_manager,
+                &mut self.descriptor_layout_cache,
+                &mut self.descriptor_allocator,
+            )
+            .bind_buffer(BufferDescriptorInfo {
+                binding: 0,
+                buffer: params[i],
+                desc_type: vk::DescriptorType::STORAGE_BUFFER,
+                stage_flags,
+            })
+            .bind_buffer(BufferDescriptorInfo {
+                binding: 1,
+                buffer: particles,
+                desc_type: vk::DescriptorType::STORAGE_BUFFER,
+                stage_flags,
+            })
+            .bind_buffer(BufferDescriptorInfo {
+                binding: 2,
+                buffer: alive,
+                desc_type: vk::DescriptorType::STORAGE_BUFFER,
+                stage_flags,
+            })
+            .bind_buffer(BufferDescriptorInfo {
+                binding: 3,
+                buffer: draw,
+                desc_type: vk::DescriptorType::STORAGE_BUFFER,
+                stage_flags,
+            })
+            .build()?;
+            *set = built_set;
+        }
+
+        Ok(GpuParticleBuffers {
+            particles,
+            alive,
+            draw,
+            params,
+            sets,
+            next_spawn: 0,
+            cleared: false,
+        })
+    }
+
+    /// Dispatches the simulation of every GPU particle system, using the time and spawns queued
+    /// by [Renderer::tick_particle_systems] since the last frame.
+    fn simulate_gpu_particles(&mut self, resource_index: usize) -> Result<()> {
+        if self.gpu_particle_buffers.is_empty() {
+            return Ok(());
+        }
+
+        let cmd = self.device.graphics_command_buffer();
+        self.device
+            .cmd_begin_label(cmd, [0.6, 0.6, 0.6, 1.0], "particle_simulation");
+
+        // Last frame's particle draws read the buffers being reset and simulated
+        unsafe {
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(
+                    PipelineStageFlags2::DRAW_INDIRECT
+                        | PipelineStageFlags2::VERTEX_SHADER
+                        | PipelineStageFlags2::FRAGMENT_SHADER,
+                )
+                .src_access_mask(
+                    AccessFlags2::INDIRECT_COMMAND_READ | AccessFlags2::SHADER_STORAGE_READ,
+                )
+                .dst_stage_mask(PipelineStageFlags2::TRANSFER | PipelineStageFlags2::COMPUTE_SHADER)
+                .dst_access_mask(
+                    AccessFlags2::TRANSFER_WRITE
+                        | AccessFlags2::SHADER_STORAGE_READ
+                        | AccessFlags2::SHADER_STORAGE_WRITE,
+                );
+            let memory_barriers = [*memory_barrier];
+            let dependency_info = vk::DependencyInfo::builder().memory_barriers(&memory_barriers);
+            self.device
+                .vk_device
+                .cmd_pipeline_barrier2(cmd, &dependency_info);
+        }
+
+        for (handle, buffers) in self.gpu_particle_buffers.iter_mut() {
+            let system = match self.stored_particle_systems.get_mut(*handle) {
+                Some(system) => system,
+                None => continue,
+            };
+            let step = system.take_gpu_step();
+            let capacity = system.particle_limit() as u32;
+
+            let (velocity_min, velocity_max) = match system.velocity {
+                VectorParameter::Static(velocity) => (velocity, velocity),
+                VectorParameter::Random { min, max } => (min, max),
+            };
+            let texture_index = system
+                .texture
+                .and_then(|texture| self.device.get_descriptor_index(&texture))
+                .unwrap_or(0) as i32;
+            let params = GpuParticleParams {
+                emitter: (system.transform() * Matrix4::from_translation(system.spawn_position))
+                    .into(),
+                velocity_min: velocity_min.extend(0.0).into(),
+                velocity_max: velocity_max.extend(0.0).into(),
+                gravity: system.gravity.extend(step.delta_time).into(),
+                start_colour: system.initial_colour.into(),
+                end_colour: system.final_colour.into(),
+                start_size: system.scale,
+                end_size: system.final_scale,
+                lifetime: system.life,
+                texture_index,
+                spawn_start: buffers.next_spawn,
+                spawn_count: step.spawn_count,
+                capacity,
+                seed: self.device.frame_number() as u32,
+            };
+            buffers.next_spawn = (buffers.next_spawn + step.spawn_count) % capacity;
+
+            self.device
+                .resource_manager
+                .get_buffer(buffers.params[resource_index])
+                .unwrap()
+                .view_custom(0, 1)?
+                .mapped_slice()?
+                .copy_from_slice(&[params]);
+
+            let particle_buffer = self
+                .device
+                .resource_manager
+                .get_buffer(buffers.particles)
+                .unwrap()
+                .buffer();
+            let draw_buffer = self
+                .device
+                .resource_manager
+                .get_buffer(buffers.draw)
+                .unwrap()
+                .buffer();
+            unsafe {
+                // Zeroed particles have no life left
+                if !buffers.cleared {
+                    self.device.vk_device.cmd_fill_buffer(
+                        cmd,
+                        particle_buffer,
+                        0,
+                        vk::WHOLE_SIZE,
+                        0,
+                    );
+                    buffers.cleared = true;
+                }
+                self.device.vk_device.cmd_fill_buffer(
+                    cmd,
+                    draw_buffer,
+                    offset_of!(vk::DrawIndexedIndirectCommand, instance_count) as vk::DeviceSize,
+                    size_of::<u32>() as vk::DeviceSize,
+                    0,
+                );
+            }
+        }
+
+        unsafe {
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(PipelineStageFlags2::TRANSFER)
+                .src_access_mask(AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(PipelineStageFlags2::COMPUTE_SHADER)
+                .dst_access_mask(
+                    AccessFlags2::SHADER_STORAGE_READ | AccessFlags2::SHADER_STORAGE_WRITE,
+                );
+            let memory_barriers = [*memory_barrier];
+            let dependency_info = vk::DependencyInfo::builder().memory_barriers(&memory_barriers);
+            self.device
+                .vk_device
+                .cmd_pipeline_barrier2(cmd, &dependency_info);
+
+            self.device.vk_device.cmd_bind_pipeline(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_manager
+                    .get_compute_pipeline(self.gpu_particles.simulate_pso),
+            );
+        }
+        for (handle, buffers) in self.gpu_particle_buffers.iter() {
+            let capacity = match self.stored_particle_systems.get(*handle) {
+                Some(system) => system.particle_limit() as u32,
+                None => continue,
+            };
+            unsafe {
+                self.device.vk_device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.gpu_particles.pso_layout,
+                    0u32,
+                    &[
+                        self.device.bindless_descriptor_set(),
+                        self.descriptor_set[resource_index],
+                        buffers.sets[resource_index],
+                    ],
+                    &[],
+                );
+                self.device
+                    .vk_device
+                    .cmd_dispatch(cmd, (capacity + 63) / 64, 1, 1);
+            }
+        }
+
+        unsafe {
+            let memory_barrier = vk::MemoryBarrier2::builder()
+                .src_stage_mask(PipelineStageFlags2::COMPUTE_SHADER)
+                .src_access_mask(AccessFlags2::SHADER_STORAGE_WRITE)
+                .dst_stage_mask(
+                    PipelineStageFlags2::DRAW_INDIRECT | PipelineStageFlags2::VERTEX_SHADER,
+                )
+                .dst_access_mask(
+                    AccessFlags2::INDIRECT_COMMAND_READ | AccessFlags2::SHADER_STORAGE_READ,
+                );
+            let memory_barriers = [*memory_barrier];
+            let dependency_info = vk::DependencyInfo::builder().memory_barriers(&memory_barriers);
+            self.device
+                .vk_device
+                .cmd_pipeline_barrier2(cmd, &dependency_info);
+        }
+        self.device.cmd_end_label(cmd);
+
+        Ok(())
+    }
+
+    /// Moves the emitter. Particles that have already spawned stay where they are.
+    pub fn set_particle_system_transform(
+        &mut self,
+        handle: ParticleSystemHandle,
+        transform: Matrix4<f32>,
+    ) -> Result<()> {
+        if let Some(system) = self.stored_particle_systems.get_mut(handle) {
+            system.set_transform(transform);
+        } else {
+            bail!(anyhow!("Unable to find Particle System!"))
+        }
+        Ok(())
+    }
+
     pub fn get_particle_system(
         &mut self,
         system: ParticleSystemHandle,
@@ -2532,17 +7738,104 @@ impl Renderer {
         }
     }
 
-    pub fn tick_particle_systems(&mut self, delta_time: f32) {
-        for (_, system) in self.stored_particle_systems.iter_mut() {
-            system.tick(delta_time)
+    /// Advances every particle system. Live CPU particles are packed into the buffer uploaded by
+    /// the next [Renderer::render], which also dispatches the GPU simulations for the time
+    /// queued here.
+    pub fn tick_particle_systems(&mut self, delta_time: f32) {
+        for (_, system) in self.stored_particle_systems.iter_mut() {
+            system.tick(delta_time)
+        }
+
+        self.particle_draw_data.clear();
+        self.particle_draw_commands.clear();
+        for (_, system) in self.stored_particle_systems.iter() {
+            let billboard = system.mesh.is_none();
+            let remaining = MAX_PARTICLES - self.particle_draw_data.len();
+            let particle_data: Vec<ParticleDrawData> = system
+                .particles()
+                .iter()
+                .take(remaining)
+                .map(|particle| {
+                    // Billboards are only rolled, and are turned to face the camera in particle.vert
+                    let rotation_euler = {
+                        if billboard {
+                            Euler {
+                                x: Deg(0.0),
+                                y: Deg(0.0),
+                                z: Deg(particle.rotation.z),
+                            }
+                        } else {
+                            Euler {
+                                x: Deg(particle.rotation.x),
+                                y: Deg(particle.rotation.y),
+                                z: Deg(particle.rotation.z),
+                            }
+                        }
+                    };
+                    let model = Matrix4::from_translation(particle.position)
+                        * Matrix4::from(Quaternion::from(rotation_euler))
+                        * Matrix4::from_scale(particle.size);
+
+                    ParticleDrawData {
+                        model: model.into(),
+                        colour: particle.colour.into(),
+                        texture_index: {
+                            if let Some(tex) = particle.texture_index {
+                                self.device.get_descriptor_index(&tex).unwrap_or(0) as i32
+                            } else {
+                                0
+                            }
+                        },
+                        billboard: billboard as i32,
+                        padding_one: 0.0,
+                        padding_two: 0.0,
+                    }
+                })
+                .collect();
+            if particle_data.is_empty() {
+                continue;
+            }
+
+            let mesh = system.mesh.unwrap_or(self.quad_mesh);
+            if let Some(mesh) = self.mesh_pool.get(mesh) {
+                let index_count = {
+                    if mesh.index_count == 0 {
+                        mesh.vertex_count
+                    } else {
+                        mesh.index_count
+                    }
+                };
+
+                self.particle_draw_commands.push(DrawCommand {
+                    vertex_offset: mesh.vertex_offset,
+                    index_offset: mesh.index_offset,
+                    index_count,
+                    instance_offset: self.particle_draw_data.len(),
+                    instance_count: particle_data.len(),
+                    double_sided: false,
+                    transparent: false,
+                });
+                self.particle_draw_data.extend(particle_data);
+            }
         }
     }
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
+        // Captures still being encoded would otherwise be lost when the program exits
+        for writer in self.capture_writers.drain(..) {
+            let _ = writer.join();
+        }
         unsafe {
             self.device.vk_device.device_wait_idle().unwrap();
+            if let Some(maps) = self.ibl_maps.take() {
+                maps.destroy(&self.device).unwrap();
+            }
+            if let Some(skybox) = self.equirect_skybox.take() {
+                skybox.destroy(&self.device).unwrap();
+            }
+            self.mip_generator.destroy(&self.device);
             for cache in self.frame_descriptor_allocator.iter_mut() {
                 cache.cleanup();
             }
@@ -2606,12 +7899,340 @@ impl Vertex {
     }
 }
 
+/// Options used when creating a [Renderer].
+#[derive(Clone, Debug)]
+pub struct RendererSettings {
+    /// File compiled pipelines are cached in between runs, to speed up startup. [None] disables the cache.
+    pub pipeline_cache_path: Option<String>,
+    /// Anisotropic filtering of the material texture samplers. Can be changed later with
+    /// [Renderer::set_anisotropy].
+    pub anisotropy: AnisotropyLevel,
+    /// Validation and debug naming, see [GraphicsDeviceConfig].
+    pub device: GraphicsDeviceConfig,
+    /// Render models that can be drawn each frame, which sizes the per-object buffers.
+    pub max_objects: usize,
+    /// Lights that can be created with [Renderer::create_light].
+    pub max_lights: usize,
+    /// Area lights that can be created with [Renderer::create_area_light]. They aren't culled, so
+    /// each one adds to the cost of every lit pixel.
+    pub max_area_lights: usize,
+    /// Vertices of UI drawn each frame. The UI index buffer holds three times as many indices.
+    pub max_quads: usize,
+    /// Materials the material buffer starts with room for. It grows when more are added.
+    pub initial_material_capacity: usize,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            pipeline_cache_path: Some("pipeline_cache.bin".to_string()),
+            anisotropy: AnisotropyLevel::default(),
+            device: GraphicsDeviceConfig::default(),
+            max_objects: 10000,
+            max_lights: 64,
+            max_area_lights: 8,
+            max_quads: 100000,
+            initial_material_capacity: 128,
+        }
+    }
+}
+
+/// What the renderer shows where no geometry was drawn.
+#[derive(Copy, Clone)]
+pub enum Background {
+    /// A flat colour. Unlike [Renderer::clear_colour], this is drawn behind the scene's geometry.
+    Colour(Colour),
+    /// The skybox loaded with [Renderer::load_skybox]. Black if no skybox is loaded.
+    Skybox,
+    /// A physically based sky generated from the sun direction, see [Renderer::set_procedural_sky].
+    SkyboxProcedural,
+}
+
+/// Rectangle in pixels.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Whether the rectangle lies entirely inside a screen of the given size.
+    pub fn is_within(&self, width: u32, height: u32) -> bool {
+        self.x >= 0
+            && self.y >= 0
+            && self.x as u64 + self.width as u64 <= width as u64
+            && self.y as u64 + self.height as u64 <= height as u64
+    }
+}
+
+impl From<Rect> for vk::Rect2D {
+    fn from(rect: Rect) -> Self {
+        vk::Rect2D {
+            offset: vk::Offset2D {
+                x: rect.x,
+                y: rect.y,
+            },
+            extent: vk::Extent2D {
+                width: rect.width,
+                height: rect.height,
+            },
+        }
+    }
+}
+
+/// Filter used when scaling the rendered scene to the output.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+impl Filter {
+    /// Picks whichever of the samplers filters this way.
+    fn sampler(self, nearest: vk::Sampler, linear: vk::Sampler) -> vk::Sampler {
+        match self {
+            Filter::Nearest => nearest,
+            Filter::Linear => linear,
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::Linear
+    }
+}
+
+/// How materials with [MaterialInstance::transparent] are blended.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TransparencyMode {
+    /// Drawn one at a time from back to front. Exact for separate objects, but overlapping
+    /// triangles within a mesh can blend in the wrong order.
+    Sorted,
+    /// Weighted blended order independent transparency. Needs no sorting and lets instances be
+    /// drawn together, but is an approximation that loses accuracy with many overlapping layers.
+    WeightedOIT,
+}
+
+impl Default for TransparencyMode {
+    fn default() -> Self {
+        Self::Sorted
+    }
+}
+
+/// Edge detection tunables for [Renderer::set_fxaa].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FxaaSettings {
+    /// Minimum local contrast, relative to the brightest neighbour, for a pixel to count as an edge.
+    pub edge_threshold: f32,
+    /// Contrast below which a pixel is never treated as an edge, so dark areas are left alone.
+    pub edge_threshold_min: f32,
+    /// Amount of subpixel smoothing, from 0 (off) to 1 (softest).
+    pub subpixel_quality: f32,
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self {
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            subpixel_quality: 0.75,
+        }
+    }
+}
+
+/// How fog thickens with distance from the camera.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FogMode {
+    /// No fog.
+    None,
+    /// Increases evenly from [FogParams::start] to [FogParams::end].
+    Linear,
+    /// Exponential falloff by [FogParams::density].
+    Exp,
+    /// Exponential squared falloff by [FogParams::density], clearer near the camera than Exp.
+    Exp2,
+}
+
+/// Distance fog settings for [Renderer::set_fog].
+#[derive(Copy, Clone)]
+pub struct FogParams {
+    pub colour: Colour,
+    /// Used by the exponential modes.
+    pub density: f32,
+    pub mode: FogMode,
+    /// Distance fog starts at in linear mode.
+    pub start: f32,
+    /// Distance fog is fully opaque at in linear mode.
+    pub end: f32,
+}
+
+impl Default for FogParams {
+    fn default() -> Self {
+        Self {
+            colour: Colour::new(0.6, 0.65, 0.7),
+            density: 0.02,
+            mode: FogMode::None,
+            start: 10.0,
+            end: 100.0,
+        }
+    }
+}
+
+/// Procedural sky settings for [Renderer::set_procedural_sky].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SkyParams {
+    /// Haziness of the air, from about 2 for a clear sky to 10 for a hazy one.
+    pub turbidity: f32,
+    /// Colour of the ground below the horizon.
+    pub ground_albedo: Colour,
+}
+
+impl Default for SkyParams {
+    fn default() -> Self {
+        Self {
+            turbidity: 2.5,
+            ground_albedo: Colour::new(0.3, 0.3, 0.3),
+        }
+    }
+}
+
+/// Depth of field settings for [Renderer::set_dof].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DofParams {
+    pub enabled: bool,
+    /// Distance from the camera that is perfectly sharp.
+    pub focus_distance: f32,
+    /// Distance either side of the focus distance over which the scene goes from sharp to fully blurred.
+    pub focus_range: f32,
+    /// Blur radius, in pixels, of fully out of focus areas.
+    pub max_blur: f32,
+}
+
+impl Default for DofParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            focus_distance: 10.0,
+            focus_range: 10.0,
+            max_blur: 8.0,
+        }
+    }
+}
+
+/// Bloom settings for [Renderer::set_bloom].
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BloomParams {
+    /// Luminance above which the scene blooms.
+    pub threshold: f32,
+    /// Range below the threshold over which colours fade into the bloom, rather than cutting
+    /// off sharply.
+    pub knee: f32,
+    /// Multiplies the bloom added over the scene.
+    pub intensity: f32,
+    /// Levels of the downsampled mip chain, from 2 to 8. Each level doubles the radius of the
+    /// glow, at little cost as the levels get smaller.
+    pub mip_count: usize,
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 1.0,
+            mip_count: 6,
+        }
+    }
+}
+
+/// Eye adaptation settings for [Renderer::set_auto_exposure]. Exposure values are EV100, where
+/// each step up halves the exposure.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct AutoExposureParams {
+    pub enabled: bool,
+    /// Exposure value of the darkest scenes, which are brightened no further.
+    pub min_ev: f32,
+    /// Exposure value of the brightest scenes, which are darkened no further.
+    pub max_ev: f32,
+    /// How quickly the exposure adapts, where higher is faster. About 1 / speed seconds gets
+    /// two thirds of the way to a new brightness.
+    pub speed: f32,
+}
+
+impl Default for AutoExposureParams {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_ev: -4.0,
+            max_ev: 8.0,
+            speed: 1.5,
+        }
+    }
+}
+
 new_key_type! {
     pub struct RenderModelHandle;
     pub struct LightHandle;
+    pub struct AreaLightHandle;
     pub struct CameraHandle;
     pub struct MaterialInstanceHandle;
     pub struct ParticleSystemHandle;
+    pub struct ViewportHandle;
+}
+
+/// A unit vector at right angles to the unit vector `direction`.
+fn perpendicular(direction: Vector3<f32>) -> Vector3<f32> {
+    let up = if direction.y.abs() < 0.99 {
+        Vector3::unit_y()
+    } else {
+        Vector3::unit_x()
+    };
+    direction.cross(up).normalize()
+}
+
+/// Element `index` of the low discrepancy sequence in `base`, between 0 and 1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Returns the distance along the ray to the first hit of an axis aligned box, using the slab method.
+/// Rays starting inside the box hit at 0.
+fn ray_box_intersection(
+    origin: Point3<f32>,
+    dir: Vector3<f32>,
+    min: Point3<f32>,
+    max: Point3<f32>,
+) -> Option<f32> {
+    let mut t_min = 0.0f32;
+    let mut t_max = f32::MAX;
+    for axis in 0..3 {
+        if dir[axis].abs() < f32::EPSILON {
+            // Parallel to the slab, so must start between its planes
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let t1 = (min[axis] - origin[axis]) / dir[axis];
+        let t2 = (max[axis] - origin[axis]) / dir[axis];
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+        if t_min > t_max {
+            return None;
+        }
+    }
+    Some(t_min)
 }
 
 fn from_transforms(
@@ -2634,12 +8255,40 @@ fn from_transforms(
 pub struct MaterialInstance {
     pub diffuse: Vector4<f32>,
     pub emissive: Vector3<f32>,
+    /// Multiplies the emissive colour. Values above 1 push emissive surfaces into the bloom
+    /// without washing out their colour.
+    pub emissive_strength: f32,
 
     pub diffuse_texture: Option<ImageHandle>,
     pub normal_texture: Option<ImageHandle>,
     pub metallic_roughness_texture: Option<ImageHandle>,
     pub emissive_texture: Option<ImageHandle>,
     pub occlusion_texture: Option<ImageHandle>,
+
+    /// Scrolls the diffuse and emissive textures, in UV units per second.
+    pub uv_scroll: [f32; 2],
+    /// Perturbs the surface normal with [MaterialInstance::normal_texture] in tangent space.
+    /// Has no effect without a normal texture.
+    pub use_normal_map: bool,
+    /// Multiplies the diffuse colour by the interpolated [Vertex::color](crate::Vertex::color).
+    pub use_vertex_color: bool,
+    /// Draws both sides of each triangle, for thin geometry like foliage. Back faces are lit with
+    /// the normal flipped.
+    pub double_sided: bool,
+    /// Discards fragments where the diffuse texture alpha is below the cutoff, including in
+    /// shadows. Has no effect without a diffuse texture.
+    pub alpha_cutoff: Option<f32>,
+    /// Blends with what's behind it using the diffuse alpha. Transparent models are lit in a
+    /// forward pass after the opaque scene, sorted back to front, and don't cast shadows.
+    pub transparent: bool,
+    /// Filtering used for all of the material's textures.
+    pub sampler: SamplerKind,
+    /// Caps the anisotropic filtering of the material's textures below the renderer's
+    /// [anisotropy](Renderer::set_anisotropy). None follows the renderer.
+    pub max_anisotropy: Option<AnisotropyLevel>,
+    /// Added to the mip level the material's textures are sampled at. Negative values sharpen,
+    /// positive values blur. The device clamps it to its sampler LOD bias limit.
+    pub lod_bias: f32,
 }
 
 impl Default for MaterialInstance {
@@ -2647,19 +8296,44 @@ impl Default for MaterialInstance {
         Self {
             diffuse: Vector4::from_value(1.0f32),
             emissive: Vector3::from_value(0.0f32),
+            emissive_strength: 1.0f32,
             diffuse_texture: None,
             normal_texture: None,
             metallic_roughness_texture: None,
             emissive_texture: None,
             occlusion_texture: None,
+            uv_scroll: [0.0f32; 2],
+            use_normal_map: true,
+            use_vertex_color: false,
+            double_sided: false,
+            alpha_cutoff: None,
+            transparent: false,
+            sampler: SamplerKind::default(),
+            max_anisotropy: None,
+            lod_bias: 0.0f32,
         }
     }
 }
 
+impl MaterialInstance {
+    /// How far [MaterialInstance::uv_scroll] has moved the texture coordinates after `time`
+    /// seconds. Wrapped to within one repeat of the texture, so precision holds however long the
+    /// scene has run.
+    pub fn uv_offset(&self, time: f32) -> [f32; 2] {
+        [
+            (self.uv_scroll[0] * time).rem_euclid(1.0),
+            (self.uv_scroll[1] * time).rem_euclid(1.0),
+        ]
+    }
+}
+
 struct RenderModel {
     mesh_handle: MeshHandle,
     material_instance: MaterialInstanceHandle,
     transform: Matrix4<f32>,
+    /// Transform the model was last drawn with, for motion vectors.
+    prev_transform: Matrix4<f32>,
+    params: [f32; 4],
 }
 
 struct DrawCommand {
@@ -2668,6 +8342,17 @@ struct DrawCommand {
     index_count: usize,
     instance_count: usize,
     instance_offset: usize,
+    /// Drawn without backface culling.
+    double_sided: bool,
+    /// Drawn in the transparent pass instead of the gbuffer and shadow passes.
+    transparent: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct DrawKey {
+    mesh: MeshHandle,
+    double_sided: bool,
+    transparent: bool,
 }
 
 pub struct UIVertex {
@@ -2702,13 +8387,45 @@ pub struct TimeStamp {
     pub total: f64,
 }
 
+/// Simulates and draws [ParticleSimulation::Gpu] particle systems.
+struct GpuParticlePass {
+    pso_layout: vk::PipelineLayout,
+    simulate_pso: ComputePipelineHandle,
+    draw_pso: PipelineHandle,
+}
+
+/// Buffers a GPU simulated particle system keeps between frames.
+struct GpuParticleBuffers {
+    particles: BufferHandle,
+    /// Indices of the live particles, written by the simulation.
+    alive: BufferHandle,
+    /// Indirect draw of the quad mesh, with the live particle count as its instance count.
+    draw: BufferHandle,
+    params: Vec<BufferHandle>,
+    sets: Vec<vk::DescriptorSet>,
+    /// Slot the next spawned particle goes in.
+    next_spawn: u32,
+    /// Whether the particle buffer has been cleared, so its particles start dead.
+    cleared: bool,
+}
+
+/// Lights and blends transparent materials.
 struct ForwardPass {
     pso_layout: vk::PipelineLayout,
     pso: PipelineHandle,
+    double_sided_pso: PipelineHandle,
+    /// Writes to the weighted blended OIT targets instead, for [TransparencyMode::WeightedOIT].
+    oit_pso: PipelineHandle,
+    double_sided_oit_pso: PipelineHandle,
 }
 
 struct DeferredPass {
     pso: PipelineHandle,
+    /// Same as [DeferredPass::pso] without backface culling, for double-sided materials.
+    double_sided_pso: PipelineHandle,
+    /// Draws the gbuffer with [vk::PolygonMode::LINE], if the device supports it.
+    wireframe_pso: Option<PipelineHandle>,
+    double_sided_wireframe_pso: Option<PipelineHandle>,
     pso_layout: vk::PipelineLayout,
 }
 
@@ -2720,13 +8437,470 @@ struct DeferredLightingCombinePass {
 struct UiPass {
     pso_layout: vk::PipelineLayout,
     pso: PipelineHandle,
-    desc_set: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
-    vertex_data_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
-    index_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
-    uniform_buffer: [BufferHandle; FRAMES_IN_FLIGHT],
+    desc_set: Vec<vk::DescriptorSet>,
+    vertex_data_buffer: Vec<BufferHandle>,
+    index_buffer: Vec<BufferHandle>,
+    uniform_buffer: Vec<BufferHandle>,
+}
+
+type CustomPassCommands = Box<dyn FnOnce(&mut RenderList, vk::CommandBuffer)>;
+
+/// A swapchain image copied into a readback buffer, waiting for its frame to finish on the GPU.
+struct PendingCapture {
+    path: String,
+    buffer: BufferHandle,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    resource_index: usize,
+}
+
+/// Encodes the pixels read back for a capture as a PNG at the capture's path.
+fn save_capture(capture: &PendingCapture, mut pixels: Vec<u8>) -> Result<()> {
+    match capture.format {
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => pixels
+            .chunks_exact_mut(4)
+            .for_each(|pixel| pixel.swap(0, 2)),
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => {}
+        format => bail!("Unable to capture swapchain format {:?}", format),
+    }
+
+    let image = image::RgbaImage::from_raw(capture.extent.width, capture.extent.height, pixels)
+        .ok_or_else(|| anyhow!("Capture buffer is smaller than the image"))?;
+    image.save(&capture.path)?;
+    Ok(())
+}
+
+struct CustomPass {
+    handle: VirtualRenderPassHandle,
+    /// Pass this one runs straight after.
+    after: VirtualRenderPassHandle,
+    /// Draws to the scene, so follows [Renderer::set_scene_scissor].
+    writes_scene: bool,
+}
+
+struct SsaoPass {
+    pso: PipelineHandle,
+    pso_layout: vk::PipelineLayout,
+    blur_pso: PipelineHandle,
 }
 
 struct BloomPass {
+    /// Separable blur, also used by the SSAO and depth of field passes.
     bloom_pso: PipelineHandle,
     bloom_pso_layout: vk::PipelineLayout,
+    downsample_pso: PipelineHandle,
+    downsample_pso_layout: vk::PipelineLayout,
+    upsample_pso: PipelineHandle,
+    upsample_pso_layout: vk::PipelineLayout,
+    /// One per level of the mip chain, each halving the level before, starting from the bright
+    /// image.
+    downsample_passes: Vec<VirtualRenderPassHandle>,
+    /// One per level but the smallest, each adding the level below it, upsampled, to its own.
+    /// The first holds the bloom added over the scene.
+    upsample_passes: Vec<VirtualRenderPassHandle>,
+}
+
+impl BloomPass {
+    /// Passes of the levels past the mip count, which are left out of the render list.
+    fn unused_passes(&self, mip_count: usize) -> Vec<VirtualRenderPassHandle> {
+        unused_bloom_passes(&self.downsample_passes, &self.upsample_passes, mip_count)
+    }
+}
+
+fn unused_bloom_passes(
+    downsample_passes: &[VirtualRenderPassHandle],
+    upsample_passes: &[VirtualRenderPassHandle],
+    mip_count: usize,
+) -> Vec<VirtualRenderPassHandle> {
+    downsample_passes[mip_count..]
+        .iter()
+        .chain(upsample_passes[mip_count - 1..].iter())
+        .copied()
+        .collect()
+}
+
+/// Settings of point lights that haven't been given any with [Renderer::set_light_shadow_settings].
+fn default_point_shadow_settings() -> ShadowSettings {
+    ShadowSettings {
+        resolution: POINT_SHADOW_SIZE,
+        ..Default::default()
+    }
+}
+
+/// Checks that a shadow map can be created at the settings' resolution, no larger than
+/// `max_resolution`, and that it is redrawn at some point.
+fn validate_shadow_settings(settings: &ShadowSettings, max_resolution: u32) -> Result<()> {
+    ensure!(
+        settings.resolution > 0,
+        "Shadow map resolution must be greater than zero"
+    );
+    ensure!(
+        settings.resolution <= max_resolution,
+        "Shadow map resolution {} is larger than the device maximum of {}",
+        settings.resolution,
+        max_resolution
+    );
+    if let ShadowUpdateMode::EveryNFrames(frames) = settings.update_mode {
+        ensure!(
+            frames > 0,
+            "Shadow update interval must be at least one frame"
+        );
+    }
+
+    Ok(())
+}
+
+/// Counts another frame since a cached shadow map was drawn and works out if it needs drawing now,
+/// either because its update mode says so or because it was invalidated. Restarts the count when it
+/// does.
+fn shadow_needs_redraw(
+    update_mode: ShadowUpdateMode,
+    frames_since_update: &mut u32,
+    invalidated: bool,
+) -> bool {
+    *frames_since_update = frames_since_update.saturating_add(1);
+    let scheduled = match update_mode {
+        ShadowUpdateMode::EveryFrame => true,
+        ShadowUpdateMode::EveryNFrames(frames) => *frames_since_update >= frames,
+        ShadowUpdateMode::Once => false,
+    };
+
+    if scheduled || invalidated {
+        *frames_since_update = 0;
+        true
+    } else {
+        false
+    }
+}
+
+/// Capacity to grow a material buffer holding `capacity` materials to, so it fits `required`, or
+/// None when it already does. Grows in powers of two, up to `max`.
+fn grown_material_capacity(capacity: usize, required: usize, max: usize) -> Option<usize> {
+    (required > capacity).then(|| required.next_power_of_two().min(max))
+}
+
+/// Render graph resource holding a level of the downsampled bloom chain.
+fn bloom_mip_name(level: usize) -> String {
+    format!("bloom_mip_{}", level)
+}
+
+/// Render graph resource holding a level of the bloom chain with every smaller level added in.
+fn bloom_upsampled_name(level: usize) -> String {
+    format!("bloom_upsampled_{}", level)
+}
+
+struct TaaPass {
+    resolve_pso: PipelineHandle,
+    resolve_pso_layout: vk::PipelineLayout,
+    copy_pso: PipelineHandle,
+    copy_pso_layout: vk::PipelineLayout,
+}
+
+struct OutlinePass {
+    mask_pso: PipelineHandle,
+    mask_pso_layout: vk::PipelineLayout,
+    composite_pso: PipelineHandle,
+    composite_pso_layout: vk::PipelineLayout,
+}
+
+/// Buffers bound to the global descriptor set, set 1 of the scene pipelines.
+struct GlobalSetBuffers {
+    camera: BufferHandle,
+    light: BufferHandle,
+    transform: BufferHandle,
+    material: BufferHandle,
+    instance: BufferHandle,
+    cull_object: BufferHandle,
+    draw_indirect: BufferHandle,
+    visible_instance: BufferHandle,
+    light_clusters: BufferHandle,
+    area_light: BufferHandle,
+}
+
+fn build_global_descriptor_set(
+    resource_manager: &ResourceManager,
+    layout_cache: &mut DescriptorLayoutCache,
+    allocator: &mut DescriptorAllocator,
+    buffers: &GlobalSetBuffers,
+    shadow_map: ImageHandle,
+    shadow_sampler: vk::Sampler,
+) -> Result<(vk::DescriptorSet, vk::DescriptorSetLayout)> {
+    JBDescriptorBuilder::new(resource_manager, layout_cache, allocator)
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 0,
+            buffer: buffers.camera,
+            desc_type: vk::DescriptorType::UNIFORM_BUFFER,
+            stage_flags: vk::ShaderStageFlags::VERTEX
+                | vk::ShaderStageFlags::FRAGMENT
+                | vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 1,
+            buffer: buffers.light,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::VERTEX
+                | vk::ShaderStageFlags::FRAGMENT
+                | vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 2,
+            buffer: buffers.transform,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::VERTEX
+                | vk::ShaderStageFlags::FRAGMENT
+                | vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 3,
+            buffer: buffers.material,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_image(ImageDescriptorInfo {
+            binding: 4,
+            image: shadow_map,
+            sampler: shadow_sampler,
+            desc_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 5,
+            buffer: buffers.instance,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 6,
+            buffer: buffers.cull_object,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 7,
+            buffer: buffers.draw_indirect,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 8,
+            buffer: buffers.visible_instance,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 9,
+            buffer: buffers.light_clusters,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE,
+        })
+        .bind_buffer(BufferDescriptorInfo {
+            binding: 10,
+            buffer: buffers.area_light,
+            desc_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+        })
+        .build()
+}
+
+/// An extra camera drawn into part of the screen, see [Renderer::add_viewport].
+struct SceneViewport {
+    camera_uniform: CameraUniform,
+    rect: Rect,
+    target: Option<RenderTextureTarget>,
+    camera_buffer: Vec<BufferHandle>,
+    /// Culled separately from the main camera, so each viewport has its own draws, visible instances
+    /// and light clusters.
+    draw_indirect_buffer: Vec<BufferHandle>,
+    visible_instance_buffer: Vec<BufferHandle>,
+    light_cluster_buffer: Vec<BufferHandle>,
+}
+
+/// Texture a viewport is copied into instead of being left on screen, see [Renderer::create_render_texture].
+#[derive(Copy, Clone)]
+struct RenderTextureTarget {
+    image: ImageHandle,
+    width: u32,
+    height: u32,
+}
+
+impl RenderTextureTarget {
+    /// Part of the backbuffer the texture is drawn into before being copied out, shrunk to fit if the texture
+    /// is larger than the backbuffer.
+    fn backbuffer_rect(&self, backbuffer_width: u32, backbuffer_height: u32) -> Rect {
+        let scale = (backbuffer_width as f32 / self.width as f32)
+            .min(backbuffer_height as f32 / self.height as f32)
+            .min(1.0);
+        Rect {
+            x: 0,
+            y: 0,
+            width: ((self.width as f32 * scale) as u32).max(1),
+            height: ((self.height as f32 * scale) as u32).max(1),
+        }
+    }
+}
+
+/// A single instance of an outlined model, drawn directly instead of through the culled draws.
+struct OutlineDraw {
+    vertex_offset: usize,
+    index_offset: usize,
+    index_count: usize,
+    instance: usize,
+}
+
+struct DofPass {
+    prepare_pso: PipelineHandle,
+    prepare_pso_layout: vk::PipelineLayout,
+    composite_pso: PipelineHandle,
+    composite_pso_layout: vk::PipelineLayout,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_uv_close(actual: [f32; 2], expected: [f32; 2]) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn uv_offset_advances_with_time() {
+        let material = MaterialInstance {
+            uv_scroll: [1.0, 0.0],
+            ..Default::default()
+        };
+        assert_uv_close(material.uv_offset(0.0), [0.0, 0.0]);
+        assert_uv_close(material.uv_offset(0.25), [0.25, 0.0]);
+        assert_uv_close(material.uv_offset(0.75), [0.75, 0.0]);
+        // A whole repeat later the texture lines up again
+        assert_uv_close(material.uv_offset(2.5), [0.5, 0.0]);
+    }
+
+    #[test]
+    fn uv_offset_wraps_negative_scrolling() {
+        let material = MaterialInstance {
+            uv_scroll: [0.0, -0.5],
+            ..Default::default()
+        };
+        assert_uv_close(material.uv_offset(1.0), [0.0, 0.5]);
+    }
+
+    #[test]
+    fn default_material_does_not_scroll() {
+        assert_uv_close(MaterialInstance::default().uv_offset(123.0), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn material_buffer_grows_past_initial_capacity() {
+        assert_eq!(grown_material_capacity(128, 128, 4096), None);
+        assert_eq!(grown_material_capacity(128, 129, 4096), Some(256));
+        assert_eq!(grown_material_capacity(128, 300, 4096), Some(512));
+        assert_eq!(grown_material_capacity(512, 300, 4096), None);
+    }
+
+    #[test]
+    fn light_materials_count_towards_material_capacity() {
+        // 126 materials fit on their own, but not with the 4 appended for lights
+        let materials = 126;
+        let lights = 4;
+        assert_eq!(grown_material_capacity(128, materials, 4096), None);
+        assert_eq!(
+            grown_material_capacity(128, materials + lights, 4096),
+            Some(256)
+        );
+    }
+
+    #[test]
+    fn material_buffer_growth_stops_at_the_storage_buffer_range() {
+        assert_eq!(grown_material_capacity(128, 1000, 600), Some(600));
+    }
+
+    #[test]
+    fn rect_within_the_screen_includes_its_edges() {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 1280,
+            height: 720,
+        };
+        assert!(rect.is_within(1280, 720));
+        assert!(!rect.is_within(1279, 720));
+        assert!(!rect.is_within(1280, 719));
+    }
+
+    #[test]
+    fn rect_off_the_screen_is_not_within_it() {
+        let screen = (1280, 720);
+        let rect = |x, y| Rect {
+            x,
+            y,
+            width: 100,
+            height: 100,
+        };
+
+        assert!(rect(1180, 620).is_within(screen.0, screen.1));
+        assert!(!rect(-1, 0).is_within(screen.0, screen.1));
+        assert!(!rect(0, -1).is_within(screen.0, screen.1));
+        assert!(!rect(1181, 0).is_within(screen.0, screen.1));
+        assert!(!rect(0, i32::MAX).is_within(screen.0, screen.1));
+    }
+
+    /// Whether the shadow map is drawn in each frame, given which frames it was invalidated in.
+    fn shadow_redraws(update_mode: ShadowUpdateMode, invalidated: &[bool]) -> Vec<bool> {
+        let mut frames_since_update = 0;
+        invalidated
+            .iter()
+            .map(|&invalidated| {
+                shadow_needs_redraw(update_mode, &mut frames_since_update, invalidated)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn once_shadow_is_only_redrawn_when_invalidated() {
+        // A light is invalidated in the frame it gets a shadow map, then by invalidate_shadow
+        let invalidated = [true, false, false, true, false];
+        assert_eq!(
+            shadow_redraws(ShadowUpdateMode::Once, &invalidated),
+            [true, false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn every_n_frames_shadow_is_redrawn_on_schedule() {
+        let invalidated = [true, false, false, false, false, false, false];
+        assert_eq!(
+            shadow_redraws(ShadowUpdateMode::EveryNFrames(3), &invalidated),
+            [true, false, false, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn invalidating_restarts_the_shadow_schedule() {
+        let invalidated = [true, false, true, false, false, false];
+        assert_eq!(
+            shadow_redraws(ShadowUpdateMode::EveryNFrames(3), &invalidated),
+            [true, false, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn every_frame_shadow_is_always_redrawn() {
+        assert_eq!(
+            shadow_redraws(ShadowUpdateMode::EveryFrame, &[false, false]),
+            [true, true]
+        );
+    }
+
+    #[test]
+    fn upscale_filter_defaults_to_linear() {
+        use ash::vk::Handle;
+
+        let nearest = vk::Sampler::from_raw(1);
+        let linear = vk::Sampler::from_raw(2);
+
+        assert_eq!(Filter::default().sampler(nearest, linear), linear);
+        assert_eq!(Filter::Nearest.sampler(nearest, linear), nearest);
+    }
 }
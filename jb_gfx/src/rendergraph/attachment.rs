@@ -1,17 +1,51 @@
 use ash::vk;
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct AttachmentInfo {
     pub size: SizeClass,
     pub format: vk::Format,
+    /// Samples per pixel. A multisampled attachment is resolved at the end of the first pass that
+    /// writes it, and every other pass uses the resolved image.
+    pub samples: vk::SampleCountFlags,
+}
+
+impl Default for AttachmentInfo {
+    fn default() -> Self {
+        Self {
+            size: SizeClass::default(),
+            format: vk::Format::default(),
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
 pub enum SizeClass {
     SwapchainRelative,
+    /// The swapchain size divided by the factor, rounded down to at least one pixel.
+    SwapchainDivided(u32),
     Custom(u32, u32),
 }
 
+impl SizeClass {
+    /// Width and height in pixels, for a swapchain of the given size.
+    pub fn extent(&self, swapchain_size: (u32, u32)) -> (u32, u32) {
+        match *self {
+            SizeClass::SwapchainRelative => swapchain_size,
+            SizeClass::SwapchainDivided(factor) => (
+                (swapchain_size.0 / factor).max(1),
+                (swapchain_size.1 / factor).max(1),
+            ),
+            SizeClass::Custom(width, height) => (width, height),
+        }
+    }
+
+    /// Whether the size changes with the swapchain, so the image has to be recreated on resize.
+    pub fn follows_swapchain(&self) -> bool {
+        !matches!(self, SizeClass::Custom(..))
+    }
+}
+
 impl Default for SizeClass {
     fn default() -> Self {
         Self::SwapchainRelative
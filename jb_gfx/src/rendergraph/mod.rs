@@ -1,22 +1,26 @@
+use anyhow::{ensure, Context, Result};
 use ash::vk;
 use ash::vk::Handle;
 use log::info;
 use rand::thread_rng;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::sync::Arc;
 
+use crate::core::device::TimeStampIndex;
 use crate::rendergraph::attachment::{AttachmentInfo, SizeClass};
 use crate::rendergraph::resource_tracker::{RenderPassTracker, RenderResourceTracker};
+use crate::rendergraph::tracy_gpu::TracyGpuZones;
 use crate::rendergraph::virtual_resource::{
     VirtualRenderPassHandle, VirtualResource, VirtualTextureResourceHandle,
 };
 use crate::renderpass::barrier::{ImageBarrier, ImageBarrierBuilder};
-use crate::{AttachmentHandle, GraphicsDevice, ImageHandle};
+use crate::{AttachmentHandle, Colour, GraphicsDevice, ImageHandle};
 
 pub mod attachment;
 pub mod physical_resource;
 pub mod resource_tracker;
+mod tracy_gpu;
 pub mod virtual_resource;
 
 pub struct RenderList {
@@ -25,14 +29,28 @@ pub struct RenderList {
     resource: RenderResourceTracker,
     order_of_passes: Vec<VirtualRenderPassHandle>,
     physical_passes: HashMap<VirtualRenderPassHandle, PhysicalRenderPass>,
-    physical_barriers: HashMap<VirtualRenderPassHandle, Vec<ImageBarrier>>,
+    physical_barriers: HashMap<VirtualRenderPassHandle, Vec<PhysicalBarrier>>,
+    skipped_passes: HashSet<VirtualRenderPassHandle>,
+    /// Resources written since the list was last baked, so holding contents a history input can read.
+    written_resources: HashSet<VirtualTextureResourceHandle>,
     physical_images: HashMap<VirtualTextureResourceHandle, ImageHandle>,
+    msaa_images: HashMap<VirtualTextureResourceHandle, ImageHandle>,
+    scissor_overrides: HashMap<VirtualRenderPassHandle, vk::Rect2D>,
+    /// Rectangle of the backbuffer every pass is restricted to, set with [RenderList::set_view_rect].
+    view_rect: Option<vk::Rect2D>,
+    /// Layout each image was left in by the passes run so far this frame.
+    frame_layouts: HashMap<AttachmentHandle, vk::ImageLayout>,
     pub swapchain_size: (u32, u32),
     backbuffer_source: String,
+    /// Timestamps written around each pass run this frame, waiting to be resolved.
+    pass_timestamps: Vec<(VirtualRenderPassHandle, TimeStampIndex, TimeStampIndex)>,
+    timings: HashMap<String, f64>,
+    tracy_zones: TracyGpuZones,
 }
 
 impl RenderList {
     pub fn new(device: Arc<GraphicsDevice>, swapchain_size: (u32, u32)) -> Self {
+        let tracy_zones = TracyGpuZones::new(&device);
         Self {
             device,
             passes: RenderPassTracker::default(),
@@ -40,9 +58,18 @@ impl RenderList {
             order_of_passes: Vec::default(),
             physical_passes: HashMap::default(),
             physical_barriers: HashMap::default(),
+            skipped_passes: HashSet::default(),
+            written_resources: HashSet::default(),
             physical_images: HashMap::default(),
+            msaa_images: HashMap::default(),
+            scissor_overrides: HashMap::default(),
+            view_rect: None,
+            frame_layouts: HashMap::default(),
             swapchain_size,
             backbuffer_source: String::default(),
+            pass_timestamps: Vec::default(),
+            timings: HashMap::default(),
+            tracy_zones,
         }
     }
 
@@ -73,6 +100,12 @@ impl RenderList {
             resource.read_in_pass(pass_handle);
             render_pass.texture_inputs.push(resource_handle);
         }
+        for input in pass_layout.history_inputs {
+            let (resource_handle, resource) = self.resource.get_texture_resource(&input);
+            resource.set_image_usage(vk::ImageUsageFlags::SAMPLED);
+            resource.read_in_pass(pass_handle);
+            render_pass.history_inputs.push(resource_handle);
+        }
 
         render_pass.clear_colour = pass_layout.clear_colour;
         render_pass.depth_clear = pass_layout.depth_clear;
@@ -82,59 +115,209 @@ impl RenderList {
         pass_handle
     }
 
+    /// Returns the handle of a pass previously added with [RenderList::add_pass].
+    pub fn find_pass(&self, name: &str) -> Option<VirtualRenderPassHandle> {
+        self.passes.find_render_pass(name)
+    }
+
+    /// Sets the order passes run in. Takes effect the next time the list is baked.
     pub fn set_pass_order(&mut self, passes: &[VirtualRenderPassHandle]) {
         self.order_of_passes = passes.to_vec();
     }
 
+    /// Restricts drawing in a pass to a sub-rectangle of its attachments.
+    /// The render area is unchanged, so attachments are still cleared in full.
+    ///
+    /// # Arguments
+    ///
+    /// * `pass`: Pass to restrict.
+    /// * `scissor`: Rectangle in pixels from the top left of the attachments, or None to draw to the whole attachment.
+    pub fn set_scissor_override(
+        &mut self,
+        pass: VirtualRenderPassHandle,
+        scissor: Option<vk::Rect2D>,
+    ) {
+        match scissor {
+            Some(scissor) => {
+                self.scissor_overrides.insert(pass, scissor);
+            }
+            None => {
+                self.scissor_overrides.remove(&pass);
+            }
+        }
+    }
+
+    /// Restricts every pass run afterwards to a rectangle of the backbuffer, replacing any scissor overrides,
+    /// so passes can be run again in the same frame to draw another view. Clears only touch the rectangle.
+    ///
+    /// # Arguments
+    ///
+    /// * `rect`: Rectangle in pixels from the top left of the backbuffer, or None to draw to the whole attachment.
+    pub fn set_view_rect(&mut self, rect: Option<vk::Rect2D>) {
+        self.view_rect = rect;
+    }
+
+    /// Changes the colour a pass clears its colour attachments to.
+    /// Takes effect the next time attachments are set up, so doesn't need the list to be baked again.
+    pub fn set_pass_clear_colour(&mut self, pass: VirtualRenderPassHandle, colour: ClearColour) {
+        self.passes.retrieve_render_pass_mut(pass).clear_colour = colour;
+    }
+
+    /// Stops a pass from running until it is enabled again, keeping the previous contents of its attachments.
+    ///
+    /// Only skip passes whose attachments are sampled, and never written, by the rest of the frame.
+    /// Attachments are then left in the sampled layout at the end of each frame, which is the layout
+    /// later passes expect when the transition from the skipped pass is left out.
+    /// The pass must have run at least once since the last [RenderList::bake].
+    pub fn set_pass_skipped(&mut self, pass: VirtualRenderPassHandle, skipped: bool) {
+        if skipped {
+            self.skipped_passes.insert(pass);
+        } else {
+            self.skipped_passes.remove(&pass);
+        }
+    }
+
+    /// Changes the samples per pixel of a resource. Takes effect the next time the list is baked.
+    pub fn set_resource_samples(&mut self, name: &str, samples: vk::SampleCountFlags) {
+        let (_, resource) = self.resource.get_texture_resource(name);
+        let mut info = resource.get_attachment_info().clone();
+        info.samples = samples;
+        resource.set_attachment_info(info);
+    }
+
+    /// Changes the size of a resource. Takes effect the next time the list is baked.
+    pub fn set_resource_size(&mut self, name: &str, size: SizeClass) {
+        let (_, resource) = self.resource.get_texture_resource(name);
+        let mut info = resource.get_attachment_info().clone();
+        info.size = size;
+        resource.set_attachment_info(info);
+    }
+
     pub fn set_backbuffer(&mut self, name: &str) {
         self.backbuffer_source = name.to_string();
     }
 
-    pub fn bake(&mut self) {
-        // Create physical images
-        for (handle, resource) in self.resource.get_resources() {
-            if resource.name() != self.backbuffer_source {
-                let size = {
-                    match resource.get_attachment_info().size {
-                        SizeClass::SwapchainRelative => self.swapchain_size,
-                        SizeClass::Custom(width, height) => (width, height),
-                    }
-                };
+    /// Creates the images and barriers for the current passes.
+    ///
+    /// Baking again after adding passes or changing resources destroys the images from the previous
+    /// bake first, so the device must be idle and any descriptors referencing them must be rewritten.
+    pub fn bake(&mut self) -> Result<()> {
+        self.reset();
+
+        let resources: Vec<VirtualTextureResourceHandle> = self
+            .resource
+            .get_resources()
+            .filter(|(_, resource)| resource.name() != self.backbuffer_source)
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in resources {
+            self.create_physical_image(handle)?;
+        }
 
-                let image_create_info = vk::ImageCreateInfo::builder()
-                    .format(resource.get_attachment_info().format)
-                    .usage(resource.get_image_usage())
-                    .extent(vk::Extent3D {
-                        width: size.0,
-                        height: size.1,
-                        depth: 1,
-                    })
-                    .image_type(vk::ImageType::TYPE_2D)
-                    .array_layers(1)
-                    .mip_levels(1)
-                    .samples(vk::SampleCountFlags::TYPE_1)
-                    .tiling(vk::ImageTiling::OPTIMAL);
-
-                let image = self
-                    .device
-                    .resource_manager
-                    .create_image(&image_create_info);
+        self.generate_barriers()
+    }
 
-                {
-                    let image = self.device.resource_manager.get_image(image).unwrap();
-                    let name = "Image: ".to_string() + resource.name();
+    /// Recreates the images sized relative to the swapchain, for example after the window has been
+    /// resized. Images with a custom size, such as shadow maps, are kept along with their contents.
+    ///
+    /// The device must be idle and any descriptors referencing the recreated images must be rewritten.
+    pub fn resize(&mut self, swapchain_size: (u32, u32)) -> Result<()> {
+        self.swapchain_size = swapchain_size;
+
+        let resources = self
+            .resource
+            .swapchain_sized_resources(&self.backbuffer_source);
+        for handle in resources {
+            if let Some(image) = self.physical_images.remove(&handle) {
+                self.device.resource_manager.destroy_image(image);
+            }
+            if let Some(image) = self.msaa_images.remove(&handle) {
+                self.device.resource_manager.destroy_image(image);
+            }
+            self.written_resources.remove(&handle);
+            self.create_physical_image(handle)?;
+        }
 
-                    self.device
-                        .set_vulkan_debug_name(image.image().as_raw(), vk::ObjectType::IMAGE, &name)
-                        .unwrap();
-                }
+        // Barriers refer to the images they transition
+        self.physical_barriers.clear();
+        self.generate_barriers()
+    }
+
+    fn create_physical_image(&mut self, handle: VirtualTextureResourceHandle) -> Result<()> {
+        let resource = self.resource.retrieve_resource(handle);
+        let size = resource
+            .get_attachment_info()
+            .size
+            .extent(self.swapchain_size);
+
+        self.device
+            .validate_render_target_format(
+                resource.get_attachment_info().format,
+                resource.get_image_usage(),
+            )
+            .with_context(|| format!("Render graph resource: {}", resource.name()))?;
+
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .format(resource.get_attachment_info().format)
+            .usage(resource.get_image_usage())
+            .extent(vk::Extent3D {
+                width: size.0,
+                height: size.1,
+                depth: 1,
+            })
+            .image_type(vk::ImageType::TYPE_2D)
+            .array_layers(1)
+            .mip_levels(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL);
+
+        let image = self
+            .device
+            .resource_manager
+            .create_image(&image_create_info);
+
+        {
+            let image = self.device.resource_manager.get_image(image).unwrap();
+            let name = "Image: ".to_string() + resource.name();
+
+            self.device
+                .set_vulkan_debug_name(image.image().as_raw(), vk::ObjectType::IMAGE, &name)
+                .unwrap();
+        }
 
-                self.physical_images.insert(handle, image);
-                info!("Image Created: {}", resource.name());
+        self.physical_images.insert(handle, image);
+        info!("Image Created: {}", resource.name());
+
+        // Multisampled resources are drawn to this image, then resolved into the one above
+        let samples = resource.get_attachment_info().samples;
+        if samples != vk::SampleCountFlags::TYPE_1 {
+            let msaa_usage = resource.get_image_usage()
+                & (vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT);
+            let msaa_create_info = image_create_info.samples(samples).usage(msaa_usage);
+            let msaa_image = self.device.resource_manager.create_image(&msaa_create_info);
+
+            {
+                let image = self.device.resource_manager.get_image(msaa_image).unwrap();
+                let name = "Image: ".to_string() + resource.name() + " (MSAA)";
+
+                self.device
+                    .set_vulkan_debug_name(image.image().as_raw(), vk::ObjectType::IMAGE, &name)
+                    .unwrap();
             }
+
+            self.msaa_images.insert(handle, msaa_image);
+            info!(
+                "Image Created: {} ({}x MSAA)",
+                resource.name(),
+                samples.as_raw()
+            );
         }
 
-        // for each renderpass, generate barriers
+        Ok(())
+    }
+
+    fn generate_barriers(&mut self) -> Result<()> {
         for (i, virtual_pass_handle) in self.order_of_passes.iter().enumerate() {
             let renderpass = self.passes.retrieve_render_pass(*virtual_pass_handle);
 
@@ -173,13 +356,30 @@ impl RenderList {
                         let barrier = ImageBarrier::new(image)
                             .old_usage(vk::ImageUsageFlags::SAMPLED)
                             .new_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
-                        barriers.push(barrier);
+                        barriers.push(PhysicalBarrier {
+                            barrier,
+                            written_by: None,
+                            history_of: None,
+                        });
                         info!("BARRIER: {},{}", resource.name(), last_operation,);
                     }
                     LastUsage::None => {
                         let barrier = ImageBarrier::new(image)
                             .new_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
-                        barriers.push(barrier);
+                        barriers.push(PhysicalBarrier {
+                            barrier,
+                            written_by: None,
+                            history_of: None,
+                        });
+                        if let Some(msaa_image) = self.msaa_images.get(attachment) {
+                            let barrier = ImageBarrier::new(AttachmentHandle::Image(*msaa_image))
+                                .new_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT);
+                            barriers.push(PhysicalBarrier {
+                                barrier,
+                                written_by: None,
+                                history_of: None,
+                            });
+                        }
                         info!("BARRIER: {},{}", resource.name(), last_operation,);
                     }
                 }
@@ -211,13 +411,30 @@ impl RenderList {
                         let barrier = ImageBarrier::new(AttachmentHandle::Image(*image))
                             .old_usage(vk::ImageUsageFlags::SAMPLED)
                             .new_usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT);
-                        barriers.push(barrier);
+                        barriers.push(PhysicalBarrier {
+                            barrier,
+                            written_by: None,
+                            history_of: None,
+                        });
                         info!("BARRIER: {},{}", resource.name(), last_operation,);
                     }
                     LastUsage::None => {
                         let barrier = ImageBarrier::new(AttachmentHandle::Image(*image))
                             .new_usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT);
-                        barriers.push(barrier);
+                        barriers.push(PhysicalBarrier {
+                            barrier,
+                            written_by: None,
+                            history_of: None,
+                        });
+                        if let Some(msaa_image) = self.msaa_images.get(&attachment) {
+                            let barrier = ImageBarrier::new(AttachmentHandle::Image(*msaa_image))
+                                .new_usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT);
+                            barriers.push(PhysicalBarrier {
+                                barrier,
+                                written_by: None,
+                                history_of: None,
+                            });
+                        }
                         info!("BARRIER: {},{}", resource.name(), last_operation,);
                     }
                 }
@@ -231,6 +448,7 @@ impl RenderList {
                 // Get last operation that occured
                 let mut last_operation = LastUsage::None;
                 let mut last_usage = vk::ImageUsageFlags::empty();
+                let mut last_writer = None;
                 for j in 0..i {
                     let previous_pass = self.order_of_passes[j];
                     let previous_virtual_pass = self.passes.retrieve_render_pass(previous_pass);
@@ -238,9 +456,11 @@ impl RenderList {
                     if previous_virtual_pass.color_attachments.contains(input) {
                         last_operation = LastUsage::Write;
                         last_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT;
+                        last_writer = Some(previous_pass);
                     } else if previous_virtual_pass.depth_attachment == Some(*input) {
                         last_operation = LastUsage::Write;
                         last_usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+                        last_writer = Some(previous_pass);
                     } else if previous_virtual_pass.texture_inputs.contains(input) {
                         last_operation = LastUsage::Read;
                         last_usage = vk::ImageUsageFlags::SAMPLED;
@@ -250,21 +470,55 @@ impl RenderList {
                 let image = self.physical_images.get(&input).unwrap();
                 match last_operation {
                     LastUsage::Write => {
-                        let barrier = ImageBarrier::new(AttachmentHandle::Image(*image))
+                        let mut barrier = ImageBarrier::new(AttachmentHandle::Image(*image))
                             .old_usage(last_usage)
                             .new_usage(vk::ImageUsageFlags::SAMPLED);
-                        barriers.push(barrier);
+                        // Resolves, including depth resolves, write in the colour attachment output stage
+                        if self.msaa_images.contains_key(input) {
+                            barrier.src_stage_mask |=
+                                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT;
+                            barrier.src_access_mask |= vk::AccessFlags2::COLOR_ATTACHMENT_WRITE;
+                        }
+                        barriers.push(PhysicalBarrier {
+                            barrier,
+                            written_by: last_writer,
+                            history_of: None,
+                        });
                         info!("BARRIER: {},{}", resource.name(), last_operation,);
                     }
                     LastUsage::Read => {}
                     LastUsage::None => {
                         let barrier = ImageBarrier::new(AttachmentHandle::Image(*image))
                             .new_usage(vk::ImageUsageFlags::SAMPLED);
-                        barriers.push(barrier);
+                        barriers.push(PhysicalBarrier {
+                            barrier,
+                            written_by: None,
+                            history_of: None,
+                        });
                         info!("BARRIER: {},{}", resource.name(), last_operation,);
                     }
                 }
             }
+            // The image was last written by a later pass in the previous frame
+            for input in renderpass.history_inputs.iter() {
+                let resource = self.resource.retrieve_resource(*input);
+                ensure!(
+                    !resource.get_write_passes().is_empty(),
+                    "History input {} is never written",
+                    resource.name()
+                );
+
+                let image = self.physical_images.get(input).unwrap();
+                let barrier = ImageBarrier::new(AttachmentHandle::Image(*image))
+                    .old_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+                    .new_usage(vk::ImageUsageFlags::SAMPLED);
+                barriers.push(PhysicalBarrier {
+                    barrier,
+                    written_by: None,
+                    history_of: Some(*input),
+                });
+                info!("BARRIER: {},History", resource.name());
+            }
 
             let virtual_pass = self.passes.retrieve_render_pass(*virtual_pass_handle);
             info!(
@@ -275,19 +529,32 @@ impl RenderList {
             self.physical_barriers
                 .insert(*virtual_pass_handle, barriers);
         }
+
+        Ok(())
     }
 
     pub fn setup_attachments(&mut self, swapchain_image: vk::ImageView) {
         self.physical_passes.clear();
+        self.frame_layouts.clear();
 
         for &pass in self.order_of_passes.iter() {
             let mut physical_render_pass = PhysicalRenderPass::default();
 
             let renderpass = self.passes.retrieve_render_pass(pass);
 
+            let clear_format = renderpass
+                .color_attachments
+                .first()
+                .map(|&color| {
+                    self.resource
+                        .retrieve_resource(color)
+                        .get_attachment_info()
+                        .format
+                })
+                .unwrap_or(vk::Format::UNDEFINED);
             physical_render_pass.clear_color = vk::ClearValue {
                 color: vk::ClearColorValue {
-                    float32: renderpass.clear_colour,
+                    float32: renderpass.clear_colour.to_float_array(clear_format),
                 },
             };
             physical_render_pass.depth_stencil_clear = vk::ClearValue {
@@ -325,7 +592,7 @@ impl RenderList {
                     }
                 };
 
-                let physical_attachment_info = vk::RenderingAttachmentInfo {
+                let mut physical_attachment_info = vk::RenderingAttachmentInfo {
                     image_view: physical_image_view,
                     image_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
                     load_op,
@@ -333,11 +600,18 @@ impl RenderList {
                     clear_value: physical_render_pass.clear_color,
                     ..Default::default()
                 };
+                if let Some(msaa_view) = self.get_multisampled_view(color, pass) {
+                    physical_attachment_info.resolve_mode = vk::ResolveModeFlags::AVERAGE;
+                    physical_attachment_info.resolve_image_view = physical_image_view;
+                    physical_attachment_info.resolve_image_layout =
+                        vk::ImageLayout::ATTACHMENT_OPTIMAL;
+                    physical_attachment_info.image_view = msaa_view;
+                }
 
-                let size = match resource.get_attachment_info().size {
-                    SizeClass::SwapchainRelative => self.swapchain_size,
-                    SizeClass::Custom(width, height) => (width, height),
-                };
+                let size = resource
+                    .get_attachment_info()
+                    .size
+                    .extent(self.swapchain_size);
                 let viewport = {
                     if resource.name() == self.backbuffer_source {
                         get_viewport_info(size, true)
@@ -376,7 +650,7 @@ impl RenderList {
                     }
                 };
 
-                let physical_attachment_info = vk::RenderingAttachmentInfo {
+                let mut physical_attachment_info = vk::RenderingAttachmentInfo {
                     image_view: physical_image_view,
                     image_layout: vk::ImageLayout::ATTACHMENT_OPTIMAL,
                     load_op, // TODO : Do this based on past usage
@@ -384,12 +658,20 @@ impl RenderList {
                     clear_value: physical_render_pass.depth_stencil_clear,
                     ..Default::default()
                 };
+                if let Some(msaa_view) = self.get_multisampled_view(depth, pass) {
+                    // Averaging depth isn't supported everywhere, sample zero always is
+                    physical_attachment_info.resolve_mode = vk::ResolveModeFlags::SAMPLE_ZERO;
+                    physical_attachment_info.resolve_image_view = physical_image_view;
+                    physical_attachment_info.resolve_image_layout =
+                        vk::ImageLayout::ATTACHMENT_OPTIMAL;
+                    physical_attachment_info.image_view = msaa_view;
+                }
 
                 let resource = self.resource.retrieve_resource(depth);
-                let size = match resource.get_attachment_info().size {
-                    SizeClass::SwapchainRelative => self.swapchain_size,
-                    SizeClass::Custom(width, height) => (width, height),
-                };
+                let size = resource
+                    .get_attachment_info()
+                    .size
+                    .extent(self.swapchain_size);
 
                 if physical_render_pass.viewport.is_none() {
                     let viewport = get_viewport_info(size, false);
@@ -411,12 +693,39 @@ impl RenderList {
         }
     }
 
+    /// Returns the multisampled image view to draw to if the pass resolves the resource.
+    fn get_multisampled_view(
+        &self,
+        resource: VirtualTextureResourceHandle,
+        pass: VirtualRenderPassHandle,
+    ) -> Option<vk::ImageView> {
+        let msaa_image = self.msaa_images.get(&resource)?;
+        let virtual_resource = self.resource.retrieve_resource(resource);
+        if virtual_resource.get_write_passes().first() != Some(&pass) {
+            return None;
+        }
+
+        Some(
+            self.device
+                .resource_manager
+                .get_image(*msaa_image)
+                .unwrap()
+                .image_view(),
+        )
+    }
+
     pub fn reset(&mut self) {
         self.physical_passes.clear();
+        self.physical_barriers.clear();
+        self.written_resources.clear();
         for image in self.physical_images.iter() {
             self.device.resource_manager.destroy_image(*image.1);
         }
         self.physical_images.clear();
+        for image in self.msaa_images.iter() {
+            self.device.resource_manager.destroy_image(*image.1);
+        }
+        self.msaa_images.clear();
     }
 
     pub fn run_pass<F>(&mut self, render_pass: VirtualRenderPassHandle, commands: F)
@@ -426,17 +735,60 @@ impl RenderList {
         // DO IMAGE BARRIERS NEEDED
         // START RENDERPASS
 
-        let physical_render_pass = self.get_physical_pass(render_pass);
+        if self.skipped_passes.contains(&render_pass) {
+            return;
+        }
+
+        let start_timestamp = self.device.write_timestamp(
+            self.device.graphics_command_buffer(),
+            vk::PipelineStageFlags2::TOP_OF_PIPE,
+        );
+
         let barriers = self.physical_barriers.get(&render_pass).unwrap();
 
         let mut barrier_builder = ImageBarrierBuilder::default();
         for barrier in barriers.iter() {
-            barrier_builder = barrier_builder.add_image_barrier(barrier.clone());
+            // Images written by a skipped pass are still in the layout the previous frame left them in,
+            // unless an earlier pass this frame already moved them
+            let written_by_skipped_pass = barrier
+                .written_by
+                .map_or(false, |pass| self.skipped_passes.contains(&pass))
+                && !self.frame_layouts.contains_key(&barrier.barrier.image);
+            if written_by_skipped_pass {
+                continue;
+            }
+            let mut image_barrier = barrier.barrier.clone();
+            // Nothing to keep until the resource is first written, and the image is still undefined
+            if let Some(resource) = barrier.history_of {
+                if !self.written_resources.contains(&resource) {
+                    image_barrier.old_layout = vk::ImageLayout::UNDEFINED;
+                }
+            }
+            // Passes run more than once a frame find their images where the last run left them, not undefined
+            if let Some(&layout) = self.frame_layouts.get(&image_barrier.image) {
+                if layout != image_barrier.old_layout {
+                    image_barrier.old_layout = layout;
+                    image_barrier.src_stage_mask = vk::PipelineStageFlags2::ALL_COMMANDS;
+                    image_barrier.src_access_mask = vk::AccessFlags2::MEMORY_WRITE;
+                }
+            }
+            self.frame_layouts
+                .insert(image_barrier.image, image_barrier.new_layout);
+            barrier_builder = barrier_builder.add_image_barrier(image_barrier);
         }
         barrier_builder
             .build(&self.device, &self.device.graphics_command_buffer())
             .unwrap();
 
+        let physical_render_pass = self.get_physical_pass(render_pass);
+        let view_area = self.view_rect.map(|rect| {
+            intersect_rect(
+                &physical_render_pass.scissor,
+                &view_rect_to_pass_area(&rect, self.swapchain_size, physical_render_pass),
+            )
+        });
+        let render_area = view_area.unwrap_or(physical_render_pass.scissor);
+
         unsafe {
             self.device.vk_device.cmd_set_viewport(
                 self.device.graphics_command_buffer(),
@@ -444,11 +796,17 @@ impl RenderList {
                 &[physical_render_pass.viewport.unwrap()],
             )
         };
+        let scissor = match (view_area, self.scissor_overrides.get(&render_pass)) {
+            (None, Some(scissor_override)) => {
+                intersect_rect(&physical_render_pass.scissor, scissor_override)
+            }
+            _ => render_area,
+        };
         unsafe {
             self.device.vk_device.cmd_set_scissor(
                 self.device.graphics_command_buffer(),
                 0u32,
-                &[physical_render_pass.scissor],
+                &[scissor],
             )
         };
 
@@ -456,13 +814,13 @@ impl RenderList {
         let render_info = {
             if physical_render_pass.depth_attachment.is_some() {
                 vk::RenderingInfo::builder()
-                    .render_area(physical_render_pass.scissor)
+                    .render_area(render_area)
                     .layer_count(1u32)
                     .color_attachments(&physical_render_pass.attachments)
                     .depth_attachment(depth_attachment.unwrap())
             } else {
                 vk::RenderingInfo::builder()
-                    .render_area(physical_render_pass.scissor)
+                    .render_area(render_area)
                     .layer_count(1u32)
                     .color_attachments(&physical_render_pass.attachments)
             }
@@ -501,14 +859,52 @@ impl RenderList {
 
         self.device
             .cmd_end_label(self.device.graphics_command_buffer());
+
+        let end_timestamp = self.device.write_timestamp(
+            self.device.graphics_command_buffer(),
+            vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+        );
+        self.tracy_zones.record_zone(
+            &self.passes.retrieve_render_pass(render_pass).name,
+            start_timestamp,
+            end_timestamp,
+        );
+        self.pass_timestamps
+            .push((render_pass, start_timestamp, end_timestamp));
+
+        let written = &self
+            .passes
+            .retrieve_render_pass(render_pass)
+            .color_attachments;
+        self.written_resources.extend(written.iter().copied());
+    }
+
+    /// Reads back the timestamps written around each pass this frame. Must be called after the
+    /// frame has been submitted, once per frame.
+    pub fn resolve_timings(&mut self) {
+        let mut timings = HashMap::new();
+        for (pass, start, end) in self.pass_timestamps.drain(..) {
+            if let Some(time) = self.device.get_timestamp_result(start, end) {
+                let name = &self.passes.retrieve_render_pass(pass).name;
+                *timings.entry(name.clone()).or_insert(0.0f64) += time;
+            }
+        }
+        self.timings = timings;
+        self.tracy_zones.upload(&self.device);
+    }
+
+    /// GPU time in milliseconds of each pass run last frame, keyed by pass name. Passes run more
+    /// than once are summed.
+    pub fn timings(&self) -> HashMap<String, f64> {
+        self.timings.clone()
     }
 
     fn get_physical_pass(&self, handle: VirtualRenderPassHandle) -> &PhysicalRenderPass {
         self.physical_passes.get(&handle).unwrap()
     }
 
-    pub fn get_physical_resource(&mut self, name: &str) -> ImageHandle {
-        let (handle, _) = self.resource.get_texture_resource(name);
+    pub fn get_physical_resource(&self, name: &str) -> ImageHandle {
+        let handle = self.resource.find_texture_resource(name).unwrap();
         *self.physical_images.get(&handle).unwrap()
     }
 }
@@ -519,7 +915,8 @@ pub struct RenderPassLayout {
     pub color_attachments: Vec<(String, AttachmentInfo)>,
     pub depth_attachment: Option<(String, AttachmentInfo)>,
     pub texture_inputs: Vec<String>,
-    clear_colour: [f32; 4],
+    pub history_inputs: Vec<String>,
+    clear_colour: ClearColour,
     depth_clear: f32,
     stencil_clear: u32,
 }
@@ -541,8 +938,27 @@ impl RenderPassLayout {
         self
     }
 
+    /// Samples the contents a later pass wrote to a colour attachment in the previous frame, for
+    /// example to accumulate results over several frames. The contents are undefined until the
+    /// attachment has been written once after the list is baked.
+    pub fn add_history_input(mut self, name: &str) -> Self {
+        self.history_inputs.push(name.to_string());
+        self
+    }
+
+    /// Sets the raw clear value written to the colour attachments, with no colour space conversion.
     pub fn set_clear_colour(mut self, colour: [f32; 4]) -> Self {
-        self.clear_colour = colour;
+        self.clear_colour = ClearColour::Raw(colour);
+        self
+    }
+
+    /// Sets the clear colour from an sRGB [Colour].
+    ///
+    /// The colour is converted for the format of the pass's first colour attachment when it is
+    /// baked. sRGB and floating point attachments receive the linear colour, as sRGB attachments
+    /// encode on write. UNORM attachments receive the colour unchanged.
+    pub fn set_clear_colour_typed(mut self, colour: Colour) -> Self {
+        self.clear_colour = ClearColour::Colour(colour);
         self
     }
 
@@ -553,6 +969,72 @@ impl RenderPassLayout {
     }
 }
 
+/// Clear colour of a pass, resolved against the attachment format when the pass is set up.
+#[derive(Copy, Clone)]
+pub enum ClearColour {
+    Raw([f32; 4]),
+    Colour(Colour),
+}
+
+impl ClearColour {
+    pub fn to_float_array(&self, format: vk::Format) -> [f32; 4] {
+        match self {
+            ClearColour::Raw(colour) => *colour,
+            ClearColour::Colour(colour) => {
+                let colour = if is_unorm_format(format) {
+                    *colour
+                } else {
+                    colour.to_linear()
+                };
+                colour.into()
+            }
+        }
+    }
+}
+
+impl Default for ClearColour {
+    fn default() -> Self {
+        ClearColour::Raw([0.0f32; 4])
+    }
+}
+
+/// Uncompressed UNORM colour formats, which store the value written unchanged. Their sRGB
+/// counterparts and the floating point formats aren't included.
+fn is_unorm_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R4G4_UNORM_PACK8
+            | vk::Format::R4G4B4A4_UNORM_PACK16
+            | vk::Format::B4G4R4A4_UNORM_PACK16
+            | vk::Format::R5G6B5_UNORM_PACK16
+            | vk::Format::B5G6R5_UNORM_PACK16
+            | vk::Format::R5G5B5A1_UNORM_PACK16
+            | vk::Format::B5G5R5A1_UNORM_PACK16
+            | vk::Format::A1R5G5B5_UNORM_PACK16
+            | vk::Format::R8_UNORM
+            | vk::Format::R8G8_UNORM
+            | vk::Format::R8G8B8_UNORM
+            | vk::Format::B8G8R8_UNORM
+            | vk::Format::R8G8B8A8_UNORM
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::A8B8G8R8_UNORM_PACK32
+            | vk::Format::A2R10G10B10_UNORM_PACK32
+            | vk::Format::A2B10G10R10_UNORM_PACK32
+            | vk::Format::R16_UNORM
+            | vk::Format::R16G16_UNORM
+            | vk::Format::R16G16B16_UNORM
+            | vk::Format::R16G16B16A16_UNORM
+    )
+}
+
+struct PhysicalBarrier {
+    barrier: ImageBarrier,
+    /// Pass that last wrote the image, when the barrier makes that write visible.
+    written_by: Option<VirtualRenderPassHandle>,
+    /// Resource whose previous frame contents the barrier keeps, for history inputs.
+    history_of: Option<VirtualTextureResourceHandle>,
+}
+
 #[derive(Default)]
 struct PhysicalRenderPass {
     attachments: Vec<vk::RenderingAttachmentInfo>,
@@ -585,6 +1067,51 @@ fn get_viewport_info(size: (u32, u32), flipped: bool) -> vk::Viewport {
     }
 }
 
+/// Maps a rectangle in backbuffer pixels onto a pass's attachments. Attachments other than the
+/// backbuffer are drawn without the flipped viewport, so are upside down compared to it.
+fn view_rect_to_pass_area(
+    rect: &vk::Rect2D,
+    swapchain_size: (u32, u32),
+    pass: &PhysicalRenderPass,
+) -> vk::Rect2D {
+    let scale_x = pass.scissor.extent.width as f32 / swapchain_size.0 as f32;
+    let scale_y = pass.scissor.extent.height as f32 / swapchain_size.1 as f32;
+    let flipped = pass
+        .viewport
+        .map_or(false, |viewport| viewport.height < 0.0);
+    let y = if flipped {
+        rect.offset.y
+    } else {
+        swapchain_size.1 as i32 - rect.offset.y - rect.extent.height as i32
+    };
+
+    vk::Rect2D {
+        offset: vk::Offset2D {
+            x: (rect.offset.x as f32 * scale_x).floor() as i32,
+            y: (y as f32 * scale_y).floor() as i32,
+        },
+        extent: vk::Extent2D {
+            width: (rect.extent.width as f32 * scale_x).ceil() as u32,
+            height: (rect.extent.height as f32 * scale_y).ceil() as u32,
+        },
+    }
+}
+
+fn intersect_rect(a: &vk::Rect2D, b: &vk::Rect2D) -> vk::Rect2D {
+    let min_x = a.offset.x.max(b.offset.x);
+    let min_y = a.offset.y.max(b.offset.y);
+    let max_x = (a.offset.x + a.extent.width as i32).min(b.offset.x + b.extent.width as i32);
+    let max_y = (a.offset.y + a.extent.height as i32).min(b.offset.y + b.extent.height as i32);
+
+    vk::Rect2D {
+        offset: vk::Offset2D { x: min_x, y: min_y },
+        extent: vk::Extent2D {
+            width: (max_x - min_x).max(0) as u32,
+            height: (max_y - min_y).max(0) as u32,
+        },
+    }
+}
+
 enum LastUsage {
     Write,
     Read,
@@ -601,3 +1128,56 @@ impl Display for LastUsage {
         write!(f, "{}", display)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 4], expected: [f32; 4]) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn raw_clear_colour_is_never_converted() {
+        let clear = ClearColour::Raw([0.5, 0.25, 0.125, 0.0]);
+        for format in [
+            vk::Format::R8G8B8A8_UNORM,
+            vk::Format::R8G8B8A8_SRGB,
+            vk::Format::R16G16B16A16_SFLOAT,
+        ] {
+            assert_eq!(clear.to_float_array(format), [0.5, 0.25, 0.125, 0.0]);
+        }
+    }
+
+    #[test]
+    fn colour_is_unchanged_for_unorm_attachments() {
+        let clear = ClearColour::Colour(Colour::new(0.5, 0.25, 1.0));
+        for format in [
+            vk::Format::R8_UNORM,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::Format::B8G8R8A8_UNORM,
+            vk::Format::A8B8G8R8_UNORM_PACK32,
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::Format::R16G16B16A16_UNORM,
+        ] {
+            assert_close(clear.to_float_array(format), [0.5, 0.25, 1.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn colour_is_linear_for_srgb_and_float_attachments() {
+        let clear = ClearColour::Colour(Colour::new(0.5, 0.0, 1.0));
+        // 0.5 in sRGB is 0.214 in linear space
+        let linear = [0.21404114, 0.0, 1.0, 1.0];
+        for format in [
+            vk::Format::R8G8B8A8_SRGB,
+            vk::Format::B8G8R8A8_SRGB,
+            vk::Format::R16G16B16A16_SFLOAT,
+            vk::Format::R32G32B32A32_SFLOAT,
+        ] {
+            assert_close(clear.to_float_array(format), linear);
+        }
+    }
+}
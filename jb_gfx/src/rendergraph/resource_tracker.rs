@@ -6,6 +6,7 @@ use slotmap::SlotMap;
 use crate::rendergraph::virtual_resource::{
     VirtualRenderPassHandle, VirtualResource, VirtualTextureResource, VirtualTextureResourceHandle,
 };
+use crate::rendergraph::ClearColour;
 
 #[derive(Default)]
 pub struct RenderPassTracker {
@@ -32,12 +33,23 @@ impl RenderPassTracker {
         }
     }
 
+    pub(crate) fn find_render_pass(&self, name: &str) -> Option<VirtualRenderPassHandle> {
+        self.pass_to_handle.get(name).copied()
+    }
+
     pub(crate) fn retrieve_render_pass(
         &self,
         handle: VirtualRenderPassHandle,
     ) -> &VirtualRenderPass {
         self.passes.get(handle).unwrap()
     }
+
+    pub(crate) fn retrieve_render_pass_mut(
+        &mut self,
+        handle: VirtualRenderPassHandle,
+    ) -> &mut VirtualRenderPass {
+        self.passes.get_mut(handle).unwrap()
+    }
 }
 
 /// Internal RenderPass used for tracking resources
@@ -47,7 +59,8 @@ pub struct VirtualRenderPass {
     pub color_attachments: Vec<VirtualTextureResourceHandle>,
     pub depth_attachment: Option<VirtualTextureResourceHandle>,
     pub texture_inputs: Vec<VirtualTextureResourceHandle>,
-    pub clear_colour: [f32; 4],
+    pub history_inputs: Vec<VirtualTextureResourceHandle>,
+    pub clear_colour: ClearColour,
     pub depth_clear: f32,
     pub stencil_clear: u32,
 }
@@ -59,6 +72,10 @@ pub struct RenderResourceTracker {
 }
 
 impl RenderResourceTracker {
+    pub(crate) fn find_texture_resource(&self, name: &str) -> Option<VirtualTextureResourceHandle> {
+        self.resource_to_handle.get(name).copied()
+    }
+
     pub(crate) fn get_texture_resource(
         &mut self,
         name: &str,
@@ -87,4 +104,66 @@ impl RenderResourceTracker {
     pub fn get_resources(&self) -> Iter<VirtualTextureResourceHandle, VirtualTextureResource> {
         self.resources.iter()
     }
+
+    /// Resources sized relative to the swapchain, leaving out the backbuffer, which is the
+    /// swapchain image itself.
+    pub(crate) fn swapchain_sized_resources(
+        &self,
+        backbuffer: &str,
+    ) -> Vec<VirtualTextureResourceHandle> {
+        self.resources
+            .iter()
+            .filter(|(_, resource)| {
+                resource.name() != backbuffer
+                    && resource.get_attachment_info().size.follows_swapchain()
+            })
+            .map(|(handle, _)| handle)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendergraph::attachment::SizeClass;
+
+    fn add_resource(
+        tracker: &mut RenderResourceTracker,
+        name: &str,
+        size: SizeClass,
+    ) -> VirtualTextureResourceHandle {
+        let (handle, resource) = tracker.get_texture_resource(name);
+        let mut info = resource.get_attachment_info().clone();
+        info.size = size;
+        resource.set_attachment_info(info);
+        handle
+    }
+
+    #[test]
+    fn swapchain_sized_resources_are_recreated_on_resize() {
+        let mut tracker = RenderResourceTracker::default();
+        let colour = add_resource(&mut tracker, "colour", SizeClass::SwapchainRelative);
+        let bloom = add_resource(&mut tracker, "bloom", SizeClass::SwapchainDivided(2));
+        add_resource(&mut tracker, "shadow", SizeClass::Custom(2048, 2048));
+        add_resource(&mut tracker, "backbuffer", SizeClass::SwapchainRelative);
+
+        let mut resized = tracker.swapchain_sized_resources("backbuffer");
+        resized.sort();
+        let mut expected = vec![colour, bloom];
+        expected.sort();
+        assert_eq!(resized, expected);
+    }
+
+    #[test]
+    fn resized_resources_follow_size_changes() {
+        let mut tracker = RenderResourceTracker::default();
+        let shadow = add_resource(&mut tracker, "shadow", SizeClass::SwapchainRelative);
+        assert_eq!(
+            tracker.swapchain_sized_resources("backbuffer"),
+            vec![shadow]
+        );
+
+        add_resource(&mut tracker, "shadow", SizeClass::Custom(1024, 1024));
+        assert!(tracker.swapchain_sized_resources("backbuffer").is_empty());
+    }
 }
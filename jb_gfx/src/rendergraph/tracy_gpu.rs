@@ -0,0 +1,87 @@
+use crate::core::device::TimeStampIndex;
+use crate::GraphicsDevice;
+
+/// Sends the timestamps written around each render pass to Tracy as GPU zones. Without the
+/// `tracy` feature this does nothing.
+#[cfg(feature = "tracy")]
+pub(crate) struct TracyGpuZones {
+    context: Option<profiling::tracy_client::GpuContext>,
+    pending: Vec<(
+        profiling::tracy_client::GpuSpan,
+        TimeStampIndex,
+        TimeStampIndex,
+    )>,
+}
+
+#[cfg(feature = "tracy")]
+impl TracyGpuZones {
+    pub fn new(device: &GraphicsDevice) -> Self {
+        use profiling::tracy_client::{Client, GpuContextType};
+
+        // Tracy lines GPU zones up with the CPU timeline using a timestamp taken now
+        let context = Client::running().and_then(|client| {
+            let gpu_timestamp = device
+                .query_gpu_timestamp()
+                .map_err(|error| log::warn!("Couldn't calibrate Tracy GPU context: {}", error))
+                .ok()?;
+            client
+                .new_gpu_context(
+                    Some("Graphics Queue"),
+                    GpuContextType::Vulkan,
+                    gpu_timestamp as i64,
+                    device.timestamp_period(),
+                )
+                .map_err(|error| log::warn!("Couldn't create Tracy GPU context: {}", error))
+                .ok()
+        });
+
+        Self {
+            context,
+            pending: Vec::default(),
+        }
+    }
+
+    pub fn record_zone(&mut self, name: &str, start: TimeStampIndex, end: TimeStampIndex) {
+        // A zone Tracy is never given timestamps for stalls its GPU timeline
+        if !start.has_result() || !end.has_result() {
+            return;
+        }
+        if let Some(context) = &self.context {
+            if let Ok(mut span) = context.span_alloc(name, "", file!(), line!()) {
+                span.end_zone();
+                self.pending.push((span, start, end));
+            }
+        }
+    }
+
+    /// Uploads the zones recorded this frame. The frame's timestamps must have been read back.
+    ///
+    /// Tracy wants timestamps in increasing order. Passes don't nest, so each zone's start and
+    /// end are uploaded together in the order the passes were recorded.
+    pub fn upload(&mut self, device: &GraphicsDevice) {
+        for (span, start, end) in self.pending.drain(..) {
+            let (Some(start), Some(end)) = (
+                device.get_raw_timestamp(start),
+                device.get_raw_timestamp(end),
+            ) else {
+                continue;
+            };
+            span.upload_timestamp_start(start as i64);
+            span.upload_timestamp_end(end as i64);
+        }
+    }
+}
+
+#[cfg(not(feature = "tracy"))]
+pub(crate) struct TracyGpuZones;
+
+#[cfg(not(feature = "tracy"))]
+impl TracyGpuZones {
+    pub fn new(_device: &GraphicsDevice) -> Self {
+        Self
+    }
+
+    pub fn record_zone(&mut self, _name: &str, _start: TimeStampIndex, _end: TimeStampIndex) {}
+
+    pub fn upload(&mut self, _device: &GraphicsDevice) {}
+}
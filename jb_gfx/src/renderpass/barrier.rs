@@ -17,6 +17,8 @@ pub struct ImageBarrier {
     pub base_mip_level: u32,
     pub level_count: u32,
     pub image_layers: u32,
+    pub src_queue_family_index: u32,
+    pub dst_queue_family_index: u32,
 }
 
 impl ImageBarrier {
@@ -70,6 +72,8 @@ impl Default for ImageBarrier {
             base_mip_level: 0,
             level_count: 1,
             image_layers: 1,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
         }
     }
 }
@@ -115,6 +119,8 @@ impl ImageBarrierBuilder {
                 .dst_access_mask(image_barrier.dst_access_mask)
                 .old_layout(image_barrier.old_layout)
                 .new_layout(image_barrier.new_layout)
+                .src_queue_family_index(image_barrier.src_queue_family_index)
+                .dst_queue_family_index(image_barrier.dst_queue_family_index)
                 .image(image_handle)
                 .subresource_range(vk::ImageSubresourceRange {
                     aspect_mask,
@@ -146,6 +152,10 @@ fn get_stage_flag_from_usage(flags: vk::ImageUsageFlags) -> vk::PipelineStageFla
         vk::PipelineStageFlags2::FRAGMENT_SHADER
     } else if flags == vk::ImageUsageFlags::COLOR_ATTACHMENT {
         vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+    } else if flags == vk::ImageUsageFlags::TRANSFER_SRC {
+        vk::PipelineStageFlags2::TRANSFER
+    } else if flags == vk::ImageUsageFlags::TRANSFER_DST {
+        vk::PipelineStageFlags2::TRANSFER
     } else {
         vk::PipelineStageFlags2::empty()
     }
@@ -158,6 +168,10 @@ fn get_access_flag_from_usage(flags: vk::ImageUsageFlags) -> vk::AccessFlags2 {
         vk::AccessFlags2::SHADER_READ
     } else if flags == vk::ImageUsageFlags::COLOR_ATTACHMENT {
         vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+    } else if flags == vk::ImageUsageFlags::TRANSFER_SRC {
+        vk::AccessFlags2::TRANSFER_READ
+    } else if flags == vk::ImageUsageFlags::TRANSFER_DST {
+        vk::AccessFlags2::TRANSFER_WRITE
     } else {
         vk::AccessFlags2::empty()
     }
@@ -170,7 +184,20 @@ fn get_image_layout_from_usage(flags: vk::ImageUsageFlags) -> vk::ImageLayout {
         vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
     } else if flags == vk::ImageUsageFlags::COLOR_ATTACHMENT {
         vk::ImageLayout::ATTACHMENT_OPTIMAL
+    } else if flags == vk::ImageUsageFlags::TRANSFER_SRC {
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+    } else if flags == vk::ImageUsageFlags::TRANSFER_DST {
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL
     } else {
         vk::ImageLayout::UNDEFINED
     }
 }
+
+/// Returns true if the usage maps to a known stage, access mask and layout for a barrier.
+pub(crate) fn is_supported_transition_usage(flags: vk::ImageUsageFlags) -> bool {
+    flags == vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+        || flags == vk::ImageUsageFlags::SAMPLED
+        || flags == vk::ImageUsageFlags::COLOR_ATTACHMENT
+        || flags == vk::ImageUsageFlags::TRANSFER_SRC
+        || flags == vk::ImageUsageFlags::TRANSFER_DST
+}
@@ -12,6 +12,41 @@ pub struct ResourceManager {
     allocator: vk_mem_alloc::Allocator,
     buffers: RefCell<SlotMap<BufferHandle, Buffer>>,
     images: RefCell<SlotMap<ImageHandle, Image>>,
+    transient: RefCell<TransientBuffers>,
+}
+
+/// Size of each frame's transient buffer, see [ResourceManager::allocate_transient].
+const TRANSIENT_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+/// Covers the largest uniform and storage buffer offset alignment a device may require.
+const TRANSIENT_ALIGNMENT: usize = 256;
+
+/// One large mapped buffer per frame in flight, handed out in pieces and reset once the frame's fence is
+/// waited on.
+#[derive(Default)]
+struct TransientBuffers {
+    buffers: Vec<BufferHandle>,
+    frame: usize,
+    offset: usize,
+}
+
+impl TransientBuffers {
+    fn allocate(&mut self, size: usize) -> Result<(BufferHandle, usize)> {
+        let buffer = *self
+            .buffers
+            .get(self.frame)
+            .ok_or_else(|| anyhow!("Transient buffers have not been created"))?;
+        let offset = (self.offset + TRANSIENT_ALIGNMENT - 1) & !(TRANSIENT_ALIGNMENT - 1);
+        ensure!(
+            offset + size <= TRANSIENT_BUFFER_SIZE,
+            "Transient buffer is full. [Requested: {} bytes, Used: {} bytes, Capacity: {} bytes]",
+            size,
+            self.offset,
+            TRANSIENT_BUFFER_SIZE
+        );
+
+        self.offset = offset + size;
+        Ok((buffer, offset))
+    }
 }
 
 impl ResourceManager {
@@ -28,9 +63,48 @@ impl ResourceManager {
             allocator,
             buffers: RefCell::new(SlotMap::default()),
             images: RefCell::new(SlotMap::default()),
+            transient: RefCell::new(TransientBuffers::default()),
         }
     }
 
+    /// Creates the backing buffers for [ResourceManager::allocate_transient], one per frame in flight.
+    pub(crate) fn create_transient_buffers(&self, frames_in_flight: usize) {
+        let buffer_create_info = BufferCreateInfo {
+            size: TRANSIENT_BUFFER_SIZE,
+            usage: vk::BufferUsageFlags::UNIFORM_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::VERTEX_BUFFER
+                | vk::BufferUsageFlags::INDEX_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            storage_type: BufferStorageType::HostLocal,
+        };
+        let buffers = (0..frames_in_flight)
+            .map(|_| self.create_buffer(&buffer_create_info))
+            .collect();
+        *self.transient.borrow_mut() = TransientBuffers {
+            buffers,
+            frame: 0,
+            offset: 0,
+        };
+    }
+
+    /// Hands out `size` bytes of mapped memory for data that only lives for the current frame, such as small
+    /// uniforms or vertices, without a buffer and allocation of its own. Returns the shared buffer and the byte
+    /// offset into it, aligned for use as a uniform or storage buffer.
+    ///
+    /// Everything handed out is reused once the frame is next started, so it must not be kept across frames.
+    pub fn allocate_transient(&self, size: usize) -> Result<(BufferHandle, usize)> {
+        self.transient.borrow_mut().allocate(size)
+    }
+
+    /// Starts handing out transient memory from the frame's own buffer, reusing everything handed out the last
+    /// time the frame was started. The frame's fence must have been waited on.
+    pub(crate) fn reset_transient(&self, frame: usize) {
+        let mut transient = self.transient.borrow_mut();
+        transient.frame = frame;
+        transient.offset = 0;
+    }
+
     /// Creates a buffer on the GPU and returns a handle([`BufferHandle`] to it.
     ///
     /// # Arguments
@@ -125,7 +199,11 @@ impl ResourceManager {
             }
         };
 
-        let default_image_view_create_info = vk::ImageViewCreateInfo::builder()
+        // Images with extended usage can have usages their own format doesn't support, e.g. storage
+        // on sRGB images written through a UNORM view, so the default view leaves them out
+        let mut view_usage_info = vk::ImageViewUsageCreateInfo::builder()
+            .usage(image_create_info.usage & !vk::ImageUsageFlags::STORAGE);
+        let mut default_image_view_create_info = vk::ImageViewCreateInfo::builder()
             .format(image_create_info.format)
             .image(vk_image)
             .view_type(view_type)
@@ -134,8 +212,14 @@ impl ResourceManager {
                 level_count: image_create_info.mip_levels,
                 layer_count: image_create_info.array_layers,
                 ..Default::default()
-            })
-            .build();
+            });
+        if image_create_info
+            .flags
+            .contains(vk::ImageCreateFlags::EXTENDED_USAGE)
+        {
+            default_image_view_create_info =
+                default_image_view_create_info.push_next(&mut view_usage_info);
+        }
 
         let default_view = {
             unsafe {
@@ -151,6 +235,7 @@ impl ResourceManager {
             image_usage: image_create_info.usage,
             image_format: image_create_info.format,
             mip_levels: image_create_info.mip_levels,
+            array_layers: image_create_info.array_layers,
             allocation,
             allocation_info,
         };
@@ -168,6 +253,14 @@ impl ResourceManager {
         self.images.borrow().get(handle).cloned()
     }
 
+    /// Exchanges the images behind two handles, so each handle refers to the other's image.
+    pub(crate) fn swap_images(&self, a: ImageHandle, b: ImageHandle) {
+        let mut images = self.images.borrow_mut();
+        let image_a = images[a].clone();
+        images[a] = images[b].clone();
+        images[b] = image_a;
+    }
+
     pub fn destroy_image(&self, handle: ImageHandle) {
         let image = self.images.borrow_mut().remove(handle).unwrap();
         unsafe {
@@ -175,6 +268,31 @@ impl ResourceManager {
             vk_mem_alloc::destroy_image(self.allocator, image.image, image.allocation)
         };
     }
+
+    /// Returns how much GPU memory is held by live buffers and images, using the allocation sizes
+    /// reported by the allocator.
+    pub fn memory_stats(&self) -> MemoryStats {
+        let buffers = self.buffers.borrow();
+        let images = self.images.borrow();
+        let buffer_bytes = buffers
+            .values()
+            .map(|buffer| buffer.allocation_info.size)
+            .sum();
+        let image_bytes = images
+            .values()
+            .map(|image| image.allocation_info.size)
+            .sum();
+
+        MemoryStats {
+            total_bytes: buffer_bytes + image_bytes,
+            buffer_bytes,
+            image_bytes,
+            allocation_count: buffers.len() + images.len(),
+            buffer_count: buffers.len(),
+            image_count: images.len(),
+        }
+    }
+
     pub fn destroy_resources(&self) {
         unsafe {
             for buffer in self.buffers.borrow_mut().iter_mut() {
@@ -190,6 +308,21 @@ impl ResourceManager {
     }
 }
 
+/// GPU memory usage of a [`ResourceManager`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes allocated for buffers and images combined.
+    pub total_bytes: vk::DeviceSize,
+    pub buffer_bytes: vk::DeviceSize,
+    pub image_bytes: vk::DeviceSize,
+    /// Number of allocations. Every buffer and image has its own allocation.
+    pub allocation_count: usize,
+    /// Number of live [`BufferHandle`]s.
+    pub buffer_count: usize,
+    /// Number of live [`ImageHandle`]s.
+    pub image_count: usize,
+}
+
 /// A buffer and it's memory allocation.
 #[derive(Copy, Clone)]
 pub struct Buffer {
@@ -299,6 +432,8 @@ impl<'a, T> BufferView<'a, T> {
 pub enum BufferStorageType {
     Device,
     HostLocal,
+    /// Host visible memory for reading back data written by the GPU.
+    Readback,
 }
 
 #[derive(Copy, Clone)]
@@ -326,10 +461,20 @@ impl From<BufferCreateInfo> for vk_mem_alloc::AllocationCreateInfo {
                 vk_mem_alloc::AllocationCreateFlags::MAPPED
                     | vk_mem_alloc::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE
             }
+            BufferStorageType::Readback => {
+                vk_mem_alloc::AllocationCreateFlags::MAPPED
+                    | vk_mem_alloc::AllocationCreateFlags::HOST_ACCESS_RANDOM
+            }
+        };
+        // Readback memory is coherent so GPU writes are visible without invalidating the allocation
+        let required_flags = match value.storage_type {
+            BufferStorageType::Readback => vk::MemoryPropertyFlags::HOST_COHERENT,
+            _ => vk::MemoryPropertyFlags::empty(),
         };
         Self {
             flags,
             usage: vk_mem_alloc::MemoryUsage::AUTO,
+            required_flags,
             ..Default::default()
         }
     }
@@ -343,6 +488,7 @@ pub struct Image {
     image_format: vk::Format,
     image_view: vk::ImageView,
     mip_levels: u32,
+    array_layers: u32,
     allocation: vk_mem_alloc::Allocation,
     allocation_info: vk_mem_alloc::AllocationInfo,
 }
@@ -364,6 +510,10 @@ impl Image {
         self.mip_levels
     }
 
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
     pub fn usage(&self) -> vk::ImageUsageFlags {
         self.image_usage
     }
@@ -377,14 +527,15 @@ fn get_image_aspect_flags_from_format(format: vk::Format) -> vk::ImageAspectFlag
     let mut flags = vk::ImageAspectFlags::empty();
 
     match format {
-        vk::Format::R8G8B8A8_SRGB
-        | vk::Format::R8G8B8A8_UNORM
-        | vk::Format::R16G16B16A16_SFLOAT
-        | vk::Format::R32G32B32A32_SFLOAT => flags |= vk::ImageAspectFlags::COLOR,
-        vk::Format::D32_SFLOAT => flags |= vk::ImageAspectFlags::DEPTH,
-        _ => {
-            todo!()
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT => flags |= vk::ImageAspectFlags::DEPTH,
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            flags |= vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
         }
+        vk::Format::S8_UINT => flags |= vk::ImageAspectFlags::STENCIL,
+        // Everything else (including the mask, offscreen and block compressed formats) is colour
+        _ => flags |= vk::ImageAspectFlags::COLOR,
     }
 
     flags
@@ -396,3 +547,66 @@ new_key_type! {
     /// Used to access images in a ResourceManager.
     pub struct ImageHandle;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transient_buffers() -> TransientBuffers {
+        let mut handles = SlotMap::<BufferHandle, ()>::with_key();
+        TransientBuffers {
+            buffers: vec![handles.insert(()), handles.insert(())],
+            frame: 0,
+            offset: 0,
+        }
+    }
+
+    #[test]
+    fn transient_allocations_are_aligned() {
+        let mut transient = transient_buffers();
+
+        assert_eq!(transient.allocate(10).unwrap().1, 0);
+        assert_eq!(transient.allocate(1).unwrap().1, TRANSIENT_ALIGNMENT);
+        assert_eq!(
+            transient.allocate(TRANSIENT_ALIGNMENT).unwrap().1,
+            TRANSIENT_ALIGNMENT * 2
+        );
+        assert_eq!(transient.allocate(0).unwrap().1, TRANSIENT_ALIGNMENT * 3);
+    }
+
+    #[test]
+    fn transient_allocations_come_from_the_frames_buffer() {
+        let mut transient = transient_buffers();
+        transient.frame = 1;
+
+        assert_eq!(transient.allocate(16).unwrap().0, transient.buffers[1]);
+    }
+
+    #[test]
+    fn transient_allocation_fills_the_buffer_exactly() {
+        let mut transient = transient_buffers();
+
+        assert_eq!(transient.allocate(TRANSIENT_BUFFER_SIZE).unwrap().1, 0);
+        assert!(transient.allocate(1).is_err());
+    }
+
+    #[test]
+    fn transient_overflow_fails_without_using_space() {
+        let mut transient = transient_buffers();
+        transient
+            .allocate(TRANSIENT_BUFFER_SIZE - TRANSIENT_ALIGNMENT * 2 + 1)
+            .unwrap();
+
+        // Fits in the free space, but not once its offset is aligned
+        assert!(transient.allocate(TRANSIENT_ALIGNMENT + 1).is_err());
+        assert_eq!(
+            transient.allocate(TRANSIENT_ALIGNMENT).unwrap().1,
+            TRANSIENT_BUFFER_SIZE - TRANSIENT_ALIGNMENT
+        );
+    }
+
+    #[test]
+    fn transient_allocation_fails_before_the_buffers_are_created() {
+        assert!(TransientBuffers::default().allocate(16).is_err());
+    }
+}
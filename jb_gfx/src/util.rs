@@ -1,4 +1,6 @@
 pub mod bindless;
+pub mod debug_text;
 pub mod descriptor;
+pub mod ktx2;
 pub mod meshpool;
 pub mod targets;
@@ -1,71 +1,108 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use ash::prelude::VkResult;
 use ash::vk;
 use ash::vk::ImageLayout;
+use log::info;
 
+use crate::core::device::BINDLESS_SAMPLER_COUNT;
 use crate::resource::{ImageHandle, ResourceManager};
-use crate::FRAMES_IN_FLIGHT;
 
 pub struct BindlessManager {
     device: Arc<ash::Device>,
     resource_manager: Arc<ResourceManager>,
     bindless_textures: Vec<ImageHandle>,
     bindless_indexes: HashMap<ImageHandle, usize>,
-    pub descriptor_set: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
+    free_indexes: Vec<usize>,
+    layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+    /// Textures the current sets have room for.
+    capacity: u32,
+    /// Texture count the layout was created with, which the sets can't grow past.
+    max_capacity: u32,
+    samplers: Vec<vk::Sampler>,
+    /// Pools replaced by growing, with the frames left until no frame in flight can be using them.
+    retired_pools: Vec<(vk::DescriptorPool, usize)>,
+    frames_in_flight: usize,
+    /// One set per frame in flight.
+    pub descriptor_set: Vec<vk::DescriptorSet>,
 }
 
 impl BindlessManager {
+    /// Allocates a set per frame in flight with room for `capacity` textures. `layout` must have
+    /// a variable count texture binding of at least `max_capacity`.
     pub fn new(
         device: Arc<ash::Device>,
         resource_manager: Arc<ResourceManager>,
-        descriptor_set: [vk::DescriptorSet; FRAMES_IN_FLIGHT],
-    ) -> Self {
-        Self {
+        layout: vk::DescriptorSetLayout,
+        frames_in_flight: usize,
+        capacity: u32,
+        max_capacity: u32,
+    ) -> Result<Self> {
+        let (pool, descriptor_set) = allocate_sets(&device, layout, frames_in_flight, capacity)?;
+
+        Ok(Self {
             device,
             resource_manager,
             descriptor_set,
             bindless_textures: Vec::default(),
             bindless_indexes: HashMap::default(),
-        }
+            free_indexes: Vec::default(),
+            layout,
+            pool,
+            capacity,
+            max_capacity,
+            samplers: Vec::default(),
+            retired_pools: Vec::default(),
+            frames_in_flight,
+        })
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
     }
 
     pub fn get_bindless_index(&self, image: &ImageHandle) -> Option<usize> {
         self.bindless_indexes.get(image).cloned()
     }
 
-    pub fn setup_samplers(&self, samplers: &[vk::Sampler], device: &ash::Device) -> Result<()> {
+    pub fn setup_samplers(&mut self, samplers: &[vk::Sampler], device: &ash::Device) -> Result<()> {
         for (i, sampler) in samplers.iter().enumerate() {
-            let sampler_info = vk::DescriptorImageInfo::builder().sampler(*sampler);
-
-            let image_info = [*sampler_info];
-            let desc_write = vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_set[0])
-                .dst_binding(0u32)
-                .dst_array_element(i as u32)
-                .descriptor_type(vk::DescriptorType::SAMPLER)
-                .image_info(&image_info);
-            let desc_write_two = vk::WriteDescriptorSet::builder()
-                .dst_set(self.descriptor_set[1])
-                .dst_binding(0u32)
-                .dst_array_element(i as u32)
-                .descriptor_type(vk::DescriptorType::SAMPLER)
-                .image_info(&image_info);
-
-            unsafe {
-                device.update_descriptor_sets(&[*desc_write, *desc_write_two], &[]);
-            }
+            write_sampler(&self.descriptor_set, device, i, *sampler);
         }
+        self.samplers = samplers.to_vec();
 
         Ok(())
     }
 
-    pub fn add_image_to_bindless(&mut self, image: &ImageHandle) {
-        self.bindless_textures.push(*image);
-        let bindless_index = self.bindless_textures.len();
+    /// Replaces a sampler in every frame's set. Neither set can be in use by the GPU.
+    pub fn set_sampler(&mut self, index: usize, sampler: vk::Sampler) {
+        write_sampler(&self.descriptor_set, &self.device, index, sampler);
+        self.samplers[index] = sampler;
+    }
+
+    /// Gives the image a slot in the bindless sets. When every slot is taken the sets are
+    /// reallocated with double the capacity, up to the capacity the layout was created with.
+    pub fn add_image_to_bindless(&mut self, image: &ImageHandle) -> Result<()> {
+        let bindless_index = if let Some(index) = self.free_indexes.pop() {
+            self.bindless_textures[index - 1] = *image;
+            index
+        } else {
+            if self.bindless_textures.len() as u32 == self.capacity {
+                self.grow()?;
+            }
+            self.bindless_textures.push(*image);
+            self.bindless_textures.len()
+        };
         self.bindless_indexes.insert(*image, bindless_index);
 
+        self.write_image(&self.descriptor_set, bindless_index, image);
+        Ok(())
+    }
+
+    fn write_image(&self, descriptor_set: &[vk::DescriptorSet], index: usize, image: &ImageHandle) {
         let image_view = self
             .resource_manager
             .get_image(*image)
@@ -77,22 +114,167 @@ impl BindlessManager {
             .image_layout(ImageLayout::SHADER_READ_ONLY_OPTIMAL);
 
         let image_info = [*bindless_image_info];
-        let desc_write = vk::WriteDescriptorSet::builder()
-            .dst_set(self.descriptor_set[0])
-            .dst_binding(1u32)
-            .dst_array_element(bindless_index as u32 - 1u32)
-            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
-            .image_info(&image_info);
-        let desc_write_two = vk::WriteDescriptorSet::builder()
-            .dst_set(self.descriptor_set[1])
-            .dst_binding(1u32)
-            .dst_array_element(bindless_index as u32 - 1u32)
-            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
-            .image_info(&image_info);
+        let desc_writes: Vec<vk::WriteDescriptorSet> = descriptor_set
+            .iter()
+            .map(|&set| {
+                *vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(1u32)
+                    .dst_array_element(index as u32 - 1u32)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .image_info(&image_info)
+            })
+            .collect();
+
+        unsafe {
+            self.device.update_descriptor_sets(&desc_writes, &[]);
+        }
+    }
+
+    /// Moves every descriptor into new sets with double the capacity. The old sets may still be
+    /// bound by frames in flight, or the frame being recorded, so their pool is kept until
+    /// [`BindlessManager::release_retired_pools`] has been called once for each frame in flight.
+    fn grow(&mut self) -> Result<()> {
+        if self.capacity >= self.max_capacity {
+            bail!(
+                "Bindless texture capacity of {} is full. Raise GraphicsDeviceConfig::max_bindless_capacity or unload textures",
+                self.max_capacity
+            );
+        }
+
+        let capacity = (self.capacity * 2).min(self.max_capacity);
+        let (pool, descriptor_set) =
+            allocate_sets(&self.device, self.layout, self.frames_in_flight, capacity)?;
+        for (i, sampler) in self.samplers.iter().enumerate() {
+            write_sampler(&descriptor_set, &self.device, i, *sampler);
+        }
+        for (image, index) in self.bindless_indexes.iter() {
+            self.write_image(&descriptor_set, *index, image);
+        }
+
+        let old_pool = std::mem::replace(&mut self.pool, pool);
+        self.retired_pools.push((old_pool, self.frames_in_flight));
+        self.descriptor_set = descriptor_set;
+        info!(
+            "Bindless texture capacity grown from {} to {}.",
+            self.capacity, capacity
+        );
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Called at the start of each frame, destroys the pools of sets no frame in flight can be
+    /// using.
+    pub fn release_retired_pools(&mut self) {
+        let device = &self.device;
+        self.retired_pools.retain_mut(|(pool, frames_remaining)| {
+            *frames_remaining -= 1;
+            if *frames_remaining > 0 {
+                return true;
+            }
 
+            unsafe { device.destroy_descriptor_pool(*pool, None) };
+            false
+        });
+    }
+
+    /// Destroys the current and retired pools. The GPU must be idle.
+    pub fn destroy(&mut self) {
         unsafe {
-            self.device
-                .update_descriptor_sets(&[*desc_write, *desc_write_two], &[]);
+            self.device.destroy_descriptor_pool(self.pool, None);
+            for (pool, _) in self.retired_pools.drain(..) {
+                self.device.destroy_descriptor_pool(pool, None);
+            }
         }
     }
+
+    /// Removes the image from the bindless lookup so it can no longer be referenced by new draws.
+    /// The returned index is still written in the descriptor sets and must be handed back with
+    /// [`BindlessManager::release_bindless_index`] once no frame in flight can be reading it.
+    pub fn remove_image_from_bindless(&mut self, image: &ImageHandle) -> Option<usize> {
+        self.bindless_indexes.remove(image)
+    }
+
+    /// Exchanges the slots of two images. The descriptors are left as they are, so this follows
+    /// the images behind the handles being swapped.
+    pub fn swap_images(&mut self, a: &ImageHandle, b: &ImageHandle) {
+        let index_a = self.bindless_indexes.remove(a);
+        let index_b = self.bindless_indexes.remove(b);
+        if let Some(index) = index_b {
+            self.bindless_indexes.insert(*a, index);
+            self.bindless_textures[index - 1] = *a;
+        }
+        if let Some(index) = index_a {
+            self.bindless_indexes.insert(*b, index);
+            self.bindless_textures[index - 1] = *b;
+        }
+    }
+
+    pub fn release_bindless_index(&mut self, index: usize) {
+        self.free_indexes.push(index);
+    }
+}
+
+fn allocate_sets(
+    device: &ash::Device,
+    layout: vk::DescriptorSetLayout,
+    frames_in_flight: usize,
+    capacity: u32,
+) -> Result<(vk::DescriptorPool, Vec<vk::DescriptorSet>)> {
+    let pool_sizes = [
+        *vk::DescriptorPoolSize::builder()
+            .descriptor_count(BINDLESS_SAMPLER_COUNT as u32 * frames_in_flight as u32)
+            .ty(vk::DescriptorType::SAMPLER),
+        *vk::DescriptorPoolSize::builder()
+            .descriptor_count(capacity * frames_in_flight as u32)
+            .ty(vk::DescriptorType::SAMPLED_IMAGE),
+    ];
+    let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+        .max_sets(frames_in_flight as u32)
+        .pool_sizes(&pool_sizes);
+    let pool = unsafe { device.create_descriptor_pool(&pool_create_info, None) }?;
+
+    let descriptor_counts = [capacity];
+    let mut descriptor_set_counts = vk::DescriptorSetVariableDescriptorCountAllocateInfo::builder()
+        .descriptor_counts(&descriptor_counts);
+    let set_layouts = [layout];
+    let create_info = vk::DescriptorSetAllocateInfo::builder()
+        .push_next(&mut descriptor_set_counts)
+        .descriptor_pool(pool)
+        .set_layouts(&set_layouts);
+
+    let descriptor_set = (0..frames_in_flight)
+        .map(|_| {
+            unsafe { device.allocate_descriptor_sets(&create_info) }
+                .map(|descriptor_sets| descriptor_sets[0])
+        })
+        .collect::<VkResult<Vec<_>>>()?;
+
+    Ok((pool, descriptor_set))
+}
+
+fn write_sampler(
+    descriptor_set: &[vk::DescriptorSet],
+    device: &ash::Device,
+    index: usize,
+    sampler: vk::Sampler,
+) {
+    let sampler_info = vk::DescriptorImageInfo::builder().sampler(sampler);
+
+    let image_info = [*sampler_info];
+    let desc_writes: Vec<vk::WriteDescriptorSet> = descriptor_set
+        .iter()
+        .map(|&set| {
+            *vk::WriteDescriptorSet::builder()
+                .dst_set(set)
+                .dst_binding(0u32)
+                .dst_array_element(index as u32)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(&image_info)
+        })
+        .collect();
+
+    unsafe {
+        device.update_descriptor_sets(&desc_writes, &[]);
+    }
 }
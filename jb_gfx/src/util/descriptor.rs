@@ -160,9 +160,20 @@ impl Default for PoolSizes {
     }
 }
 
+/// Counters for a layout cache.
+/// A miss count that keeps growing usually means layouts are being built that differ slightly.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LayoutCacheStats {
+    pub cached_layouts: usize,
+    pub hits: usize,
+    pub misses: usize,
+}
+
 pub struct DescriptorLayoutCache {
     device: Arc<ash::Device>,
     layout_cache: HashMap<DescriptorLayoutInfo, vk::DescriptorSetLayout>,
+    hits: usize,
+    misses: usize,
 }
 
 impl DescriptorLayoutCache {
@@ -170,6 +181,8 @@ impl DescriptorLayoutCache {
         Self {
             device,
             layout_cache: HashMap::default(),
+            hits: 0,
+            misses: 0,
         }
     }
 
@@ -179,6 +192,14 @@ impl DescriptorLayoutCache {
         }
     }
 
+    pub fn cache_stats(&self) -> LayoutCacheStats {
+        LayoutCacheStats {
+            cached_layouts: self.layout_cache.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+
     pub fn create_descriptor_layout(
         &mut self,
         create_info: vk::DescriptorSetLayoutCreateInfo,
@@ -197,8 +218,10 @@ impl DescriptorLayoutCache {
         }
 
         return if let Some(layout) = self.layout_cache.get(&layout_info) {
+            self.hits += 1;
             *layout
         } else {
+            self.misses += 1;
             let layout =
                 unsafe { self.device.create_descriptor_set_layout(&create_info, None) }.unwrap();
             self.layout_cache.insert(layout_info, layout);
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, bail, ensure, Result};
+use ash::vk;
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// Texture data read from a KTX2 file, with every mip level already present.
+pub struct Ktx2Texture {
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+    /// 6 for cubemaps, otherwise 1.
+    pub layers: u32,
+    /// Mip levels ordered from the base level down. Each holds all the faces of that level.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl Ktx2Texture {
+    /// Parses a KTX2 file. Supercompressed (e.g. Basis) files, 3D textures and texture arrays
+    /// aren't supported.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() >= HEADER_SIZE && bytes[..IDENTIFIER.len()] == IDENTIFIER,
+            "Not a KTX2 file"
+        );
+
+        let format = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?;
+        let depth = read_u32(bytes, 28)?;
+        let layer_count = read_u32(bytes, 32)?;
+        let face_count = read_u32(bytes, 36)?;
+        let level_count = read_u32(bytes, 40)?;
+        let supercompression = read_u32(bytes, 44)?;
+
+        if format == 0 {
+            bail!("KTX2 files without a Vulkan format (e.g. Basis Universal) aren't supported");
+        }
+        ensure!(
+            supercompression == 0,
+            "Supercompressed KTX2 files aren't supported"
+        );
+        ensure!(depth <= 1, "3D KTX2 textures aren't supported");
+        ensure!(layer_count <= 1, "KTX2 texture arrays aren't supported");
+        ensure!(
+            face_count == 1 || face_count == 6,
+            "KTX2 file has {} faces",
+            face_count
+        );
+        ensure!(width > 0 && height > 0, "KTX2 file has no size");
+        let format = vk::Format::from_raw(format as i32);
+        let (block_size, block_bytes) = block_layout(format)
+            .ok_or_else(|| anyhow!("KTX2 files in {:?} aren't supported", format))?;
+
+        // A level count of 0 asks for mips to be generated, which compressed data can't do
+        let level_count = level_count.max(1) as usize;
+        let max_levels = (u32::BITS - width.max(height).leading_zeros()) as usize;
+        ensure!(
+            level_count <= max_levels,
+            "KTX2 file has {} mip levels, more than the {} a {}x{} image can have",
+            level_count,
+            max_levels,
+            width,
+            height
+        );
+        let mut levels = Vec::with_capacity(level_count);
+        for level in 0..level_count {
+            let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+            let offset = read_u64(bytes, entry)? as usize;
+            let length = read_u64(bytes, entry + 8)? as usize;
+            let end = offset
+                .checked_add(length)
+                .filter(|end| *end <= bytes.len())
+                .ok_or_else(|| anyhow!("KTX2 mip level {} is out of bounds", level))?;
+
+            let blocks_wide = (width >> level).max(1).div_ceil(block_size);
+            let blocks_high = (height >> level).max(1).div_ceil(block_size);
+            let expected = (blocks_wide * blocks_high * block_bytes) as usize * face_count as usize;
+            ensure!(
+                length == expected,
+                "KTX2 mip level {} has {} bytes, but should have {}",
+                level,
+                length,
+                expected
+            );
+            levels.push(bytes[offset..end].to_vec());
+        }
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            layers: face_count,
+            levels,
+        })
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let value = bytes
+        .get(offset..offset + 4)
+        .ok_or_else(|| anyhow!("KTX2 file is truncated"))?;
+    Ok(u32::from_le_bytes(value.try_into()?))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    let value = bytes
+        .get(offset..offset + 8)
+        .ok_or_else(|| anyhow!("KTX2 file is truncated"))?;
+    Ok(u64::from_le_bytes(value.try_into()?))
+}
+
+/// Width and height in texels of the format's blocks, and the bytes each block takes. Uncompressed
+/// formats have single texel blocks. None for formats KTX2 files can't be loaded in.
+fn block_layout(format: vk::Format) -> Option<(u32, u32)> {
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC4_SNORM_BLOCK => Some((4, 8)),
+        format if is_bc_format(format) => Some((4, 16)),
+        vk::Format::R8_UNORM | vk::Format::R8_SRGB => Some((1, 1)),
+        vk::Format::R8G8_UNORM | vk::Format::R8G8_SRGB | vk::Format::R16_SFLOAT => Some((1, 2)),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_SFLOAT => Some((1, 4)),
+        vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32_SFLOAT => Some((1, 8)),
+        vk::Format::R32G32B32A32_SFLOAT => Some((1, 16)),
+        _ => None,
+    }
+}
+
+/// Whether the format is one of the BCn block compressed formats.
+pub fn is_bc_format(format: vk::Format) -> bool {
+    (vk::Format::BC1_RGB_UNORM_BLOCK.as_raw()..=vk::Format::BC7_SRGB_BLOCK.as_raw())
+        .contains(&format.as_raw())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A KTX2 file with the given header values and one level index entry per level, each
+    /// followed by that many bytes of data.
+    fn ktx2_file(format: vk::Format, width: u32, height: u32, level_lengths: &[usize]) -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        let header = [
+            format.as_raw() as u32,
+            1,
+            width,
+            height,
+            0,
+            0,
+            1,
+            level_lengths.len() as u32,
+            0,
+        ];
+        for value in header {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.resize(HEADER_SIZE, 0);
+
+        let mut offset = HEADER_SIZE + level_lengths.len() * LEVEL_INDEX_ENTRY_SIZE;
+        for &length in level_lengths {
+            for value in [offset, length, length] {
+                bytes.extend_from_slice(&(value as u64).to_le_bytes());
+            }
+            offset += length;
+        }
+        bytes.resize(offset, 0);
+        bytes
+    }
+
+    #[test]
+    fn parses_a_full_bc7_mip_chain() {
+        // 8x8, 4x4, 2x2 and 1x1 levels, the last two still taking a whole block
+        let file = ktx2_file(vk::Format::BC7_SRGB_BLOCK, 8, 8, &[64, 16, 16, 16]);
+        let texture = Ktx2Texture::parse(&file).unwrap();
+        assert_eq!(texture.levels.len(), 4);
+        assert_eq!(texture.levels[0].len(), 64);
+    }
+
+    #[test]
+    fn rejects_levels_shorter_than_their_blocks() {
+        let file = ktx2_file(vk::Format::BC1_RGBA_UNORM_BLOCK, 8, 8, &[16]);
+        assert!(Ktx2Texture::parse(&file).is_err());
+        let file = ktx2_file(vk::Format::BC1_RGBA_UNORM_BLOCK, 8, 8, &[1]);
+        assert!(Ktx2Texture::parse(&file).is_err());
+    }
+
+    #[test]
+    fn rejects_more_levels_than_the_image_has() {
+        let file = ktx2_file(vk::Format::R8G8B8A8_UNORM, 2, 2, &[16, 4, 4]);
+        assert!(Ktx2Texture::parse(&file).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        let file = ktx2_file(vk::Format::ASTC_4X4_UNORM_BLOCK, 4, 4, &[16]);
+        assert!(Ktx2Texture::parse(&file).is_err());
+    }
+}
@@ -9,7 +9,7 @@ use log::trace;
 use slotmap::{new_key_type, SlotMap};
 
 use crate::core::device::cmd_copy_buffer;
-use crate::mesh::Index;
+use crate::mesh::{Index, MeshBounds};
 use crate::resource::{BufferCreateInfo, BufferStorageType};
 use crate::{BufferHandle, GraphicsDevice, MeshData, Vertex};
 
@@ -27,6 +27,8 @@ pub struct PooledMesh {
     pub vertex_count: usize,
     pub index_offset: usize,
     pub index_count: usize,
+    /// Object space bounds, computed once when the mesh is added.
+    pub bounds: MeshBounds,
 }
 
 impl MeshPool {
@@ -129,6 +131,7 @@ impl MeshPool {
                     vertex_count: mesh.vertices.len(),
                     index_offset: 0,
                     index_count: 0,
+                    bounds: mesh.bounds(),
                 };
                 trace!(
                     "Mesh Loaded. Vertex Count:{}|Faces:{}",
@@ -184,6 +187,7 @@ impl MeshPool {
                     vertex_count: mesh.vertices.len(),
                     index_offset: index_buffer_offset,
                     index_count: indices.len(),
+                    bounds: mesh.bounds(),
                 };
                 trace!(
                     "Mesh Loaded. Vertex Count:{}|Index Count:{}|Faces:{}",
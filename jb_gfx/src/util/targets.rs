@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, ensure, Result};
 use ash::vk;
 use log::{info, trace};
 use slotmap::{new_key_type, SlotMap};
@@ -11,6 +11,8 @@ use crate::GraphicsDevice;
 pub struct RenderTargets {
     device: Arc<GraphicsDevice>,
     targets: SlotMap<RenderTargetHandle, RenderTarget>,
+    /// Layer views of resized targets, kept until no frame in flight can still render to them.
+    retired_views: Vec<RetiredViews>,
 }
 
 impl RenderTargets {
@@ -18,6 +20,7 @@ impl RenderTargets {
         Self {
             device,
             targets: SlotMap::default(),
+            retired_views: Vec::new(),
         }
     }
 
@@ -29,19 +32,29 @@ impl RenderTargets {
     ) -> Result<RenderTargetHandle> {
         profiling::scope!("Create Render Target");
 
+        self.device
+            .validate_render_target_format(format, get_render_target_usage(image_type))?;
+
         let actual_size = match size {
             RenderTargetSize::Static(width, height) => (width, height),
+            RenderTargetSize::StaticCube(size) => (size, size),
             RenderTargetSize::Fullscreen => (self.device.size().width, self.device.size().height),
         };
+        let layers = size.layers();
 
         let render_image = create_render_target_image(
             &self.device.resource_manager,
             format,
             actual_size,
+            layers,
             image_type,
         )?;
+
+        let layer_views = create_layer_views(&self.device, render_image, format, layers)?;
+
         let render_target = RenderTarget {
             image: render_image,
+            layer_views,
             size,
             format,
             image_type,
@@ -59,6 +72,76 @@ impl RenderTargets {
         self.targets.get(render_target).map(|render| render.image)
     }
 
+    /// Returns a view of a single layer of the render target, such as one face of a cube target.
+    pub fn get_layer_view(
+        &self,
+        render_target: RenderTargetHandle,
+        layer: usize,
+    ) -> Option<vk::ImageView> {
+        self.targets
+            .get(render_target)
+            .and_then(|render| render.layer_views.get(layer).copied())
+    }
+
+    /// Replaces the render target's image with a new one of `size`, for use while frames are in
+    /// flight. The old image and its layer views are destroyed once those frames have finished,
+    /// and the new image has to be added to the bindless set again if it is sampled that way.
+    pub fn resize_render_target(
+        &mut self,
+        render_target: RenderTargetHandle,
+        size: RenderTargetSize,
+    ) -> Result<()> {
+        profiling::scope!("Resize Render Target");
+
+        let actual_size = match size {
+            RenderTargetSize::Static(width, height) => (width, height),
+            RenderTargetSize::StaticCube(size) => (size, size),
+            RenderTargetSize::Fullscreen => (self.device.size().width, self.device.size().height),
+        };
+        let target = self
+            .targets
+            .get_mut(render_target)
+            .ok_or_else(|| anyhow!("Render target does not exist"))?;
+        ensure!(
+            size.layers() == target.size.layers(),
+            "Render target can not change its number of layers"
+        );
+
+        let image = create_render_target_image(
+            &self.device.resource_manager,
+            target.format,
+            actual_size,
+            size.layers(),
+            target.image_type,
+        )?;
+        let layer_views = create_layer_views(&self.device, image, target.format, size.layers())?;
+
+        self.device.unload_image(target.image)?;
+        self.retired_views.push(RetiredViews {
+            views: std::mem::replace(&mut target.layer_views, layer_views),
+            frames_remaining: self.device.frames_in_flight(),
+        });
+        target.image = image;
+        target.size = size;
+
+        Ok(())
+    }
+
+    /// Destroys the layer views of resized targets once no frame in flight can still use them.
+    /// Call once a frame.
+    pub fn release_retired_views(&mut self) {
+        let device = &self.device;
+        self.retired_views.retain_mut(|retired| {
+            retired.frames_remaining -= 1;
+            if retired.frames_remaining > 0 {
+                return true;
+            }
+
+            destroy_layer_views(device, &retired.views);
+            false
+        });
+    }
+
     pub fn recreate_render_targets(&mut self) -> Result<()> {
         profiling::scope!("Recreate Render Targets");
 
@@ -81,6 +164,7 @@ impl RenderTargets {
                 "Test", size.0, size.1,
             );
 
+            destroy_layer_views(&self.device, &render_target.layer_views);
             self.device
                 .resource_manager
                 .destroy_image(render_target.image);
@@ -88,8 +172,15 @@ impl RenderTargets {
                 &self.device.resource_manager,
                 render_target.format,
                 size,
+                render_target.size.layers(),
                 render_target.image_type,
             )?;
+            render_target.layer_views = create_layer_views(
+                &self.device,
+                render_target.image,
+                render_target.format,
+                render_target.size.layers(),
+            )?;
         }
 
         info!("Render Targets recreated successfully.");
@@ -97,14 +188,36 @@ impl RenderTargets {
     }
 }
 
+impl Drop for RenderTargets {
+    fn drop(&mut self) {
+        for (_, render_target) in self.targets.iter() {
+            destroy_layer_views(&self.device, &render_target.layer_views);
+        }
+        for retired in self.retired_views.iter() {
+            destroy_layer_views(&self.device, &retired.views);
+        }
+    }
+}
+
 new_key_type! {pub struct RenderTargetHandle;}
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum RenderTargetSize {
     Static(u32, u32),
+    /// A cube map with faces of the given width and height.
+    StaticCube(u32),
     Fullscreen,
 }
 
+impl RenderTargetSize {
+    fn layers(&self) -> u32 {
+        match self {
+            RenderTargetSize::StaticCube(_) => 6,
+            _ => 1,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum RenderImageType {
     Colour,
@@ -113,15 +226,22 @@ pub enum RenderImageType {
 
 pub struct RenderTarget {
     image: ImageHandle,
+    layer_views: Vec<vk::ImageView>,
     size: RenderTargetSize,
     format: vk::Format,
     image_type: RenderImageType,
 }
 
+struct RetiredViews {
+    views: Vec<vk::ImageView>,
+    frames_remaining: usize,
+}
+
 fn create_render_target_image(
     resource_manager: &ResourceManager,
     format: vk::Format,
     size: (u32, u32),
+    layers: u32,
     image_type: RenderImageType,
 ) -> Result<ImageHandle> {
     let extent = vk::Extent3D {
@@ -130,17 +250,11 @@ fn create_render_target_image(
         depth: 1,
     };
 
-    let usage = match image_type {
-        RenderImageType::Colour => {
-            vk::ImageUsageFlags::SAMPLED
-                | vk::ImageUsageFlags::COLOR_ATTACHMENT
-                | vk::ImageUsageFlags::TRANSFER_SRC
-        }
-        RenderImageType::Depth => {
-            vk::ImageUsageFlags::SAMPLED
-                | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
-                | vk::ImageUsageFlags::TRANSFER_SRC
-        }
+    let usage = get_render_target_usage(image_type);
+    let flags = if layers == 6 {
+        vk::ImageCreateFlags::CUBE_COMPATIBLE
+    } else {
+        vk::ImageCreateFlags::empty()
     };
 
     let render_image = {
@@ -149,7 +263,8 @@ fn create_render_target_image(
             .usage(usage)
             .extent(extent)
             .image_type(vk::ImageType::TYPE_2D)
-            .array_layers(1u32)
+            .flags(flags)
+            .array_layers(layers)
             .mip_levels(1u32)
             .samples(vk::SampleCountFlags::TYPE_1)
             .tiling(vk::ImageTiling::OPTIMAL);
@@ -159,3 +274,57 @@ fn create_render_target_image(
 
     Ok(render_image)
 }
+
+/// Creates a view of each layer of a layered target, as each needs its own view to be rendered to.
+/// Single layer targets are rendered through the image's own view and get none.
+fn create_layer_views(
+    device: &GraphicsDevice,
+    render_image: ImageHandle,
+    format: vk::Format,
+    layers: u32,
+) -> Result<Vec<vk::ImageView>> {
+    let mut layer_views = Vec::new();
+    if layers == 1 {
+        return Ok(layer_views);
+    }
+
+    let image = device.resource_manager.get_image(render_image).unwrap();
+    for layer in 0..layers {
+        let view_create_info = vk::ImageViewCreateInfo::builder()
+            .format(format)
+            .image(image.image())
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: image.aspect_flags(),
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: layer,
+                layer_count: 1,
+            });
+        let view = unsafe { device.vk_device.create_image_view(&view_create_info, None) }?;
+        layer_views.push(view);
+    }
+
+    Ok(layer_views)
+}
+
+fn destroy_layer_views(device: &GraphicsDevice, layer_views: &[vk::ImageView]) {
+    for view in layer_views.iter() {
+        unsafe { device.vk_device.destroy_image_view(*view, None) };
+    }
+}
+
+fn get_render_target_usage(image_type: RenderImageType) -> vk::ImageUsageFlags {
+    match image_type {
+        RenderImageType::Colour => {
+            vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+        }
+        RenderImageType::Depth => {
+            vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Renders frames on a headless device. These need a Vulkan driver with the validation layers
+//! installed, so are skipped unless run with `cargo test -p jb_gfx -- --ignored`.
+
+use cgmath::Point3;
+use jb_gfx::{Colour, Light, MaterialInstance, MeshData, Rect, Renderer, RendererSettings};
+use winit::dpi::PhysicalSize;
+
+const FRAMES: usize = 4;
+
+fn headless_renderer() -> Renderer {
+    // Shaders and textures are loaded relative to the workspace root
+    std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/..")).unwrap();
+
+    let mut settings = RendererSettings::default();
+    settings.device.validation = true;
+    let mut renderer = Renderer::headless_with_settings(320, 180, settings).unwrap();
+
+    let mesh = renderer.load_mesh(&MeshData::cube()).unwrap();
+    let material = renderer
+        .add_material_instance(MaterialInstance::default())
+        .unwrap();
+    renderer.add_render_model(mesh, material);
+    renderer.create_light(&Light {
+        position: Point3::new(0.0, 3.0, 0.0),
+        casts_shadows: true,
+        ..Default::default()
+    });
+
+    renderer
+}
+
+fn render_frames(renderer: &mut Renderer) {
+    for frame in 0..FRAMES {
+        // Goes through the UI pass and its scissor
+        renderer.draw_debug_text(8.0, 8.0, &format!("Frame {}", frame), Colour::red(), 1.0);
+        renderer.render().unwrap();
+    }
+    renderer.flush_captures().unwrap();
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn renders_frames_without_validation_errors() {
+    let mut renderer = headless_renderer();
+    render_frames(&mut renderer);
+
+    assert_eq!(renderer.device().validation_error_count(), 0);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn renders_frames_with_bloom_disabled_without_validation_errors() {
+    let mut renderer = headless_renderer();
+    render_frames(&mut renderer);
+    renderer.enable_bloom_pass = false;
+    render_frames(&mut renderer);
+
+    assert_eq!(renderer.device().validation_error_count(), 0);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn renders_frames_after_resizing_without_validation_errors() {
+    let mut renderer = headless_renderer();
+    render_frames(&mut renderer);
+    renderer.resize(PhysicalSize::new(640, 360)).unwrap();
+    render_frames(&mut renderer);
+    renderer.resize(PhysicalSize::new(200, 120)).unwrap();
+
+    let path = std::env::temp_dir().join("jb_gfx_resize.png");
+    renderer.capture_frame(path.to_str().unwrap()).unwrap();
+    render_frames(&mut renderer);
+    let capture = image::open(&path).unwrap();
+
+    assert_eq!((capture.width(), capture.height()), (200, 120));
+    assert_eq!(renderer.device().validation_error_count(), 0);
+}
+
+#[test]
+#[ignore = "needs a Vulkan device"]
+fn scene_scissor_leaves_pixels_outside_untouched() {
+    let mut renderer = headless_renderer();
+    let mesh = renderer.load_mesh(&MeshData::cube()).unwrap();
+    let material = renderer
+        .add_material_instance(MaterialInstance::default())
+        .unwrap();
+    let model = renderer.add_render_model(mesh, material);
+    renderer.set_outline(&[model], Colour::green(), 4);
+    renderer.draw_debug_ui = false;
+    renderer.clear_colour = Colour::new(1.0, 0.0, 1.0);
+    let scissor = Rect {
+        x: 80,
+        y: 45,
+        width: 160,
+        height: 90,
+    };
+    renderer.set_scene_scissor(Some(scissor));
+
+    let path = std::env::temp_dir().join("jb_gfx_scene_scissor.png");
+    renderer.capture_frame(path.to_str().unwrap()).unwrap();
+    // No debug text, which is UI and so drawn outside the scissor
+    for _ in 0..FRAMES {
+        renderer.render().unwrap();
+    }
+    renderer.flush_captures().unwrap();
+    let capture = image::open(&path).unwrap().to_rgba8();
+
+    let clear = [255, 0, 255, 255];
+    let inside = |x: u32, y: u32| {
+        x >= scissor.x as u32
+            && x < scissor.x as u32 + scissor.width
+            && y >= scissor.y as u32
+            && y < scissor.y as u32 + scissor.height
+    };
+    for (x, y, pixel) in capture.enumerate_pixels() {
+        if !inside(x, y) {
+            assert_eq!(pixel.0, clear, "pixel ({}, {}) outside the scissor", x, y);
+        }
+    }
+    assert!(capture
+        .enumerate_pixels()
+        .any(|(x, y, pixel)| inside(x, y) && pixel.0 != clear));
+    assert_eq!(renderer.device().validation_error_count(), 0);
+}